@@ -0,0 +1,8 @@
+CREATE TABLE sessions(
+    token CHAR(64) PRIMARY KEY,
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_sessions_user ON sessions(uid);
@@ -0,0 +1,36 @@
+CREATE TYPE role_n AS ENUM ('admin', 'developer', 'player');
+CREATE TYPE permission_n AS ENUM ('manage_users', 'manage_roles', 'manage_games', 'manage_purchases', 'view_all_transactions');
+
+CREATE TABLE roles(
+    role role_n PRIMARY KEY,
+    descr TEXT
+);
+
+CREATE TABLE role_permissions(
+    role role_n NOT NULL REFERENCES roles(role) ON DELETE CASCADE,
+    permission permission_n NOT NULL,
+    PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE user_roles(
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    role role_n NOT NULL REFERENCES roles(role) ON DELETE CASCADE,
+    granted_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (uid, role)
+);
+
+INSERT INTO roles (role, descr) VALUES
+    ('admin', 'Manages users, roles and the whole catalog'),
+    ('developer', 'Publishes games and their purchases'),
+    ('player', 'Buys and plays games');
+
+INSERT INTO role_permissions (role, permission) VALUES
+    ('admin', 'manage_users'),
+    ('admin', 'manage_roles'),
+    ('admin', 'manage_games'),
+    ('admin', 'manage_purchases'),
+    ('admin', 'view_all_transactions'),
+    ('developer', 'manage_games'),
+    ('developer', 'manage_purchases');
+
+INSERT INTO user_roles (uid, role) SELECT uid, 'player' FROM users;
@@ -0,0 +1,5 @@
+ALTER TABLE transactions
+ADD COLUMN quantity int NOT NULL DEFAULT 1 CHECK (quantity > 0);
+
+ALTER TABLE transactions
+ALTER COLUMN amount TYPE float;
@@ -0,0 +1,12 @@
+-- Prices and amounts are stored in minor units (e.g. cents) of their ISO 4217 currency.
+ALTER TABLE purchases
+ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE purchases
+ALTER COLUMN price TYPE BIGINT USING ROUND(price * 100)::BIGINT;
+
+ALTER TABLE transactions
+ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE transactions
+ALTER COLUMN amount TYPE BIGINT USING ROUND(amount * 100)::BIGINT;
@@ -0,0 +1,22 @@
+CREATE TABLE cart_items(
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    quantity int NOT NULL DEFAULT 1 CHECK (quantity > 0),
+    added_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (uid, pid)
+);
+
+CREATE TABLE orders(
+    oid serial PRIMARY KEY,
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    receiver_uid int REFERENCES users(uid) ON DELETE SET NULL,
+    payment_method payment_n NOT NULL,
+    total BIGINT NOT NULL CHECK (total >= 0),
+    currency CHAR(3) NOT NULL DEFAULT 'USD',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE transactions
+ADD COLUMN oid int REFERENCES orders(oid) ON DELETE SET NULL;
+
+CREATE INDEX idx_transactions_order ON transactions(oid);
@@ -0,0 +1,23 @@
+CREATE TYPE discount_n AS ENUM ('percentage', 'fixed');
+
+-- A sale applies to exactly one of: a purchase, every purchase of games with a tag,
+-- or every purchase of games by a developer.
+CREATE TABLE discounts(
+    dcid serial PRIMARY KEY,
+    kind discount_n NOT NULL,
+    percent int CHECK (percent > 0 AND percent <= 100),
+    amount BIGINT CHECK (amount > 0),
+    currency CHAR(3) NOT NULL DEFAULT 'USD',
+    pid int REFERENCES purchases(pid) ON DELETE CASCADE,
+    tname VARCHAR(50) REFERENCES tags(tname) ON DELETE CASCADE,
+    did int REFERENCES developers(did) ON DELETE CASCADE,
+    descr TEXT,
+    starts_at TIMESTAMP NOT NULL,
+    ends_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    CHECK (ends_at > starts_at),
+    CHECK (num_nonnulls(pid, tname, did) = 1),
+    CHECK ((kind = 'percentage' AND percent IS NOT NULL) OR (kind = 'fixed' AND amount IS NOT NULL))
+);
+
+CREATE INDEX idx_discounts_period ON discounts(starts_at, ends_at);
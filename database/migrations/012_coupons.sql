@@ -0,0 +1,42 @@
+-- Scope columns left NULL match anything; a line is eligible when it matches every set column.
+CREATE TABLE coupons(
+    code VARCHAR(32) PRIMARY KEY,
+    kind discount_n NOT NULL,
+    percent int CHECK (percent > 0 AND percent <= 100),
+    amount BIGINT CHECK (amount > 0),
+    currency CHAR(3) NOT NULL DEFAULT 'USD',
+    min_spend BIGINT NOT NULL DEFAULT 0 CHECK (min_spend >= 0),
+    max_uses int CHECK (max_uses > 0),
+    max_uses_per_user int CHECK (max_uses_per_user > 0),
+    gid int REFERENCES games(gid) ON DELETE CASCADE,
+    did int REFERENCES developers(did) ON DELETE CASCADE,
+    purchase_type purchase_n,
+    expires_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((kind = 'percentage' AND percent IS NOT NULL) OR (kind = 'fixed' AND amount IS NOT NULL))
+);
+
+CREATE TABLE coupon_redemptions(
+    code VARCHAR(32) NOT NULL REFERENCES coupons(code) ON DELETE CASCADE,
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    oid int NOT NULL REFERENCES orders(oid) ON DELETE CASCADE,
+    redeemed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (code, oid)
+);
+
+CREATE INDEX idx_coupon_redemptions_user ON coupon_redemptions(code, uid);
+
+ALTER TABLE orders
+ADD COLUMN subtotal BIGINT NOT NULL DEFAULT 0 CHECK (subtotal >= 0),
+ADD COLUMN discount BIGINT NOT NULL DEFAULT 0 CHECK (discount >= 0),
+ADD COLUMN coupon_code VARCHAR(32) REFERENCES coupons(code) ON DELETE SET NULL;
+
+UPDATE orders SET subtotal = total;
+
+ALTER TABLE transactions
+ADD COLUMN discount BIGINT NOT NULL DEFAULT 0 CHECK (discount >= 0);
+
+-- a fully discounted line is charged nothing
+ALTER TABLE transactions
+DROP CONSTRAINT transactions_amount_check,
+ADD CHECK (amount >= 0);
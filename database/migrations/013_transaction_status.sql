@@ -0,0 +1,23 @@
+CREATE TYPE transaction_status_n AS ENUM ('pending', 'completed', 'refund_requested', 'refunded', 'failed');
+
+ALTER TABLE transactions
+ALTER COLUMN status DROP DEFAULT;
+
+ALTER TABLE transactions
+ALTER COLUMN status TYPE transaction_status_n USING (
+    CASE WHEN status IN ('pending', 'completed', 'refund_requested', 'refunded', 'failed')
+    THEN status::transaction_status_n
+    ELSE 'completed' END
+);
+
+UPDATE transactions SET status = 'completed' WHERE status IS NULL;
+
+ALTER TABLE transactions
+ALTER COLUMN status SET DEFAULT 'completed',
+ALTER COLUMN status SET NOT NULL,
+ADD COLUMN refund_reason TEXT,
+ADD COLUMN refund_requested_at TIMESTAMP,
+ADD COLUMN refunded_at TIMESTAMP;
+
+CREATE INDEX idx_transactions_refund_requested ON transactions(refund_requested_at)
+WHERE status = 'refund_requested';
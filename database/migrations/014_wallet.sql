@@ -0,0 +1,34 @@
+ALTER TYPE payment_n ADD VALUE 'wallet';
+
+CREATE TYPE ledger_account_n AS ENUM ('wallet', 'funding', 'sales');
+CREATE TYPE ledger_journal_n AS ENUM ('top_up', 'purchase', 'refund');
+
+-- Every movement of money is a journal of entries that sum to zero, so a wallet balance is
+-- always backed by a matching entry on a funding or sales account.
+CREATE TABLE ledger_accounts(
+    aid serial PRIMARY KEY,
+    kind ledger_account_n NOT NULL,
+    uid int REFERENCES users(uid) ON DELETE CASCADE,
+    currency CHAR(3) NOT NULL,
+    balance BIGINT NOT NULL DEFAULT 0,
+    CHECK ((kind = 'wallet') = (uid IS NOT NULL)),
+    CHECK (kind <> 'wallet' OR balance >= 0),
+    UNIQUE NULLS NOT DISTINCT (kind, uid, currency)
+);
+
+CREATE TABLE ledger_journals(
+    jid serial PRIMARY KEY,
+    kind ledger_journal_n NOT NULL,
+    oid int REFERENCES orders(oid) ON DELETE SET NULL,
+    tid int REFERENCES transactions(tid) ON DELETE SET NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE ledger_entries(
+    jid int NOT NULL REFERENCES ledger_journals(jid) ON DELETE CASCADE,
+    aid int NOT NULL REFERENCES ledger_accounts(aid) ON DELETE CASCADE,
+    amount BIGINT NOT NULL CHECK (amount <> 0),
+    PRIMARY KEY (jid, aid)
+);
+
+CREATE INDEX idx_ledger_entries_account ON ledger_entries(aid);
@@ -0,0 +1,31 @@
+CREATE TYPE gift_status_n AS ENUM ('pending', 'accepted', 'declined');
+
+CREATE TABLE gifts(
+    tid int PRIMARY KEY REFERENCES transactions(tid) ON DELETE CASCADE,
+    sender_uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    receiver_uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    message TEXT,
+    status gift_status_n NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    responded_at TIMESTAMP
+);
+
+CREATE INDEX idx_gifts_receiver ON gifts(receiver_uid, created_at);
+CREATE INDEX idx_gifts_sender ON gifts(sender_uid, created_at);
+
+-- gifts sent before the inbox existed went straight into the receiver's library
+INSERT INTO gifts (tid, sender_uid, receiver_uid, status, created_at, responded_at)
+SELECT tid, uid, receiver_uid, 'accepted', bought_at, bought_at
+FROM transactions
+WHERE receiver_uid IS NOT NULL AND receiver_uid <> uid;
+
+CREATE TABLE notifications(
+    nid serial PRIMARY KEY,
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    message TEXT NOT NULL,
+    tid int REFERENCES transactions(tid) ON DELETE SET NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    read_at TIMESTAMP
+);
+
+CREATE INDEX idx_notifications_uid ON notifications(uid, created_at);
@@ -0,0 +1,18 @@
+CREATE TABLE key_batches(
+    kbid serial PRIMARY KEY,
+    pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    descr TEXT,
+    created_by int REFERENCES users(uid) ON DELETE SET NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE product_keys(
+    code TEXT PRIMARY KEY,
+    kbid int NOT NULL REFERENCES key_batches(kbid) ON DELETE CASCADE,
+    redeemed_by int REFERENCES users(uid) ON DELETE SET NULL,
+    redeemed_at TIMESTAMP,
+    CHECK (redeemed_by IS NULL OR redeemed_at IS NOT NULL)
+);
+
+CREATE INDEX idx_product_keys_batch ON product_keys(kbid);
+CREATE INDEX idx_product_keys_redeemed_by ON product_keys(redeemed_by);
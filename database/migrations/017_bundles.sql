@@ -0,0 +1,17 @@
+ALTER TYPE purchase_n ADD VALUE 'bundle';
+
+CREATE TABLE bundle_items(
+    bundle_pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    PRIMARY KEY (bundle_pid, pid),
+    CHECK (bundle_pid <> pid)
+);
+
+CREATE INDEX idx_bundle_items_pid ON bundle_items(pid);
+
+-- which games a bundle actually added to a library, so a refund only takes those back out
+CREATE TABLE bundle_grants(
+    tid int NOT NULL REFERENCES transactions(tid) ON DELETE CASCADE,
+    gid int NOT NULL REFERENCES games(gid) ON DELETE CASCADE,
+    PRIMARY KEY (tid, gid)
+);
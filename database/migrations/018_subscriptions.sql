@@ -0,0 +1,48 @@
+CREATE TYPE billing_period_n AS ENUM ('monthly', 'yearly');
+CREATE TYPE subscription_status_n AS ENUM ('active', 'cancelled', 'expired');
+
+CREATE FUNCTION billing_interval(period billing_period_n) RETURNS INTERVAL
+LANGUAGE SQL IMMUTABLE
+RETURN CASE period WHEN 'monthly' THEN INTERVAL '1 month' ELSE INTERVAL '1 year' END;
+
+-- a subscription purchase is a plan, billed once per period
+CREATE TABLE subscription_plans(
+    pid int PRIMARY KEY REFERENCES purchases(pid) ON DELETE CASCADE,
+    billing_period billing_period_n NOT NULL DEFAULT 'monthly'
+);
+
+-- games a plan lets its holders play without owning them
+CREATE TABLE subscription_catalog(
+    pid int NOT NULL REFERENCES subscription_plans(pid) ON DELETE CASCADE,
+    gid int NOT NULL REFERENCES games(gid) ON DELETE CASCADE,
+    PRIMARY KEY (pid, gid)
+);
+
+CREATE INDEX idx_subscription_catalog_gid ON subscription_catalog(gid);
+
+-- existing subscription purchases become monthly plans covering their own game
+INSERT INTO subscription_plans (pid)
+SELECT pid FROM purchases WHERE purchase_type = 'subscriptions';
+INSERT INTO subscription_catalog (pid, gid)
+SELECT pid, gid FROM purchases WHERE purchase_type = 'subscriptions';
+
+CREATE TABLE subscriptions(
+    sid serial PRIMARY KEY,
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    pid int NOT NULL REFERENCES subscription_plans(pid) ON DELETE CASCADE,
+    -- what renewals are charged to; gifted subscriptions have none and lapse at expiry
+    payment_method payment_n,
+    status subscription_status_n NOT NULL DEFAULT 'active',
+    started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    cancelled_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_subscriptions_running ON subscriptions(uid, pid) WHERE status <> 'expired';
+CREATE INDEX idx_subscriptions_due ON subscriptions(expires_at) WHERE status <> 'expired';
+
+-- every transaction that paid for a period of a subscription
+CREATE TABLE subscription_payments(
+    tid int PRIMARY KEY REFERENCES transactions(tid) ON DELETE CASCADE,
+    sid int NOT NULL REFERENCES subscriptions(sid) ON DELETE CASCADE
+);
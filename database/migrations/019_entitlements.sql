@@ -0,0 +1,24 @@
+-- the DLC and in-game purchases a user holds, one row per grant so that a refund takes back
+-- exactly what its transaction gave
+CREATE TABLE entitlements(
+    eid serial PRIMARY KEY,
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    -- the purchase, or bundle purchase, that granted it; none for a redeemed key
+    tid int REFERENCES transactions(tid) ON DELETE CASCADE,
+    quantity int NOT NULL DEFAULT 1 CHECK (quantity > 0),
+    granted_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_entitlements_uid ON entitlements(uid, pid);
+CREATE INDEX idx_entitlements_tid ON entitlements(tid);
+
+-- past purchases that still stand, skipping gifts that never reached the receiver
+INSERT INTO entitlements (uid, pid, tid, quantity, granted_at)
+SELECT COALESCE(t.receiver_uid, t.uid), t.pid, t.tid, t.quantity, t.bought_at
+FROM transactions t
+JOIN purchases p ON t.pid = p.pid
+LEFT JOIN gifts gf ON t.tid = gf.tid
+WHERE p.purchase_type IN ('dlc', 'in_game_purchase')
+    AND t.status IN ('completed', 'refund_requested')
+    AND (gf.status IS NULL OR gf.status = 'accepted');
@@ -0,0 +1,16 @@
+-- how many of each in-game purchase a user has left to use
+CREATE TABLE inventory(
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    quantity int NOT NULL DEFAULT 0 CHECK (quantity >= 0),
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (uid, pid)
+);
+
+-- nothing has been used up before
+INSERT INTO inventory (uid, pid, quantity)
+SELECT e.uid, e.pid, SUM(e.quantity)
+FROM entitlements e
+JOIN purchases p ON e.pid = p.pid
+WHERE p.purchase_type = 'in_game_purchase'
+GROUP BY e.uid, e.pid;
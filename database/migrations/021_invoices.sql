@@ -0,0 +1,21 @@
+-- invoice numbers must not have gaps, which a sequence cannot promise across rolled back
+-- checkouts, so the last issued number is kept in a single locked row instead
+CREATE TABLE invoice_counter(
+    id boolean PRIMARY KEY DEFAULT TRUE CHECK (id),
+    last_number int NOT NULL CHECK (last_number >= 0)
+);
+
+CREATE TABLE invoices(
+    number int PRIMARY KEY CHECK (number > 0),
+    tid int NOT NULL UNIQUE REFERENCES transactions(tid) ON DELETE CASCADE,
+    issued_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- past transactions are numbered in the order they were placed
+INSERT INTO invoices (number, tid, issued_at)
+SELECT ROW_NUMBER() OVER (ORDER BY bought_at, tid), tid, COALESCE(bought_at, CURRENT_TIMESTAMP)
+FROM transactions
+WHERE status <> 'failed';
+
+INSERT INTO invoice_counter (last_number)
+SELECT COUNT(*) FROM invoices;
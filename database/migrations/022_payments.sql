@@ -0,0 +1,5 @@
+-- how the payment gateway knows the payment of an order, once it authorized it
+ALTER TABLE orders
+ADD COLUMN payment_ref VARCHAR(64) UNIQUE,
+-- kept until a pending payment settles and the gifts go out
+ADD COLUMN gift_message TEXT;
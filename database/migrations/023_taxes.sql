@@ -0,0 +1,23 @@
+-- ISO 3166-1 alpha-2 code of where a user is billed, taxes are only levied when it is known
+ALTER TABLE users
+ADD COLUMN billing_country CHAR(2) CHECK (billing_country ~ '^[A-Z]{2}$');
+
+CREATE TABLE tax_rates(
+    country CHAR(2) NOT NULL CHECK (country ~ '^[A-Z]{2}$'),
+    purchase_type purchase_n NOT NULL,
+    -- in basis points, hundredths of a percent
+    rate_bp int NOT NULL CHECK (rate_bp BETWEEN 0 AND 10000),
+    -- prices already include the tax, as with VAT, rather than it being added at checkout
+    inclusive boolean NOT NULL DEFAULT TRUE,
+    PRIMARY KEY (country, purchase_type)
+);
+
+-- what was levied, as part of `amount`; nothing was before
+ALTER TABLE transactions
+ADD COLUMN tax BIGINT NOT NULL DEFAULT 0 CHECK (tax >= 0),
+ADD COLUMN tax_country CHAR(2),
+ADD COLUMN tax_rate_bp int NOT NULL DEFAULT 0,
+ADD COLUMN tax_inclusive boolean NOT NULL DEFAULT TRUE;
+
+ALTER TABLE orders
+ADD COLUMN tax BIGINT NOT NULL DEFAULT 0 CHECK (tax >= 0);
@@ -0,0 +1,29 @@
+-- every price a purchase has had, so changing `purchases.price` no longer loses the old one
+CREATE TABLE price_history(
+    phid serial PRIMARY KEY,
+    pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    price BIGINT NOT NULL,
+    currency CHAR(3) NOT NULL,
+    changed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_price_history_pid ON price_history(pid, changed_at);
+
+-- recorded by a trigger, so prices edited outside the store are tracked as well
+CREATE FUNCTION record_price_change() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_OP = 'INSERT' OR (OLD.price, OLD.currency) IS DISTINCT FROM (NEW.price, NEW.currency) THEN
+        INSERT INTO price_history (pid, price, currency) VALUES (NEW.pid, NEW.price, NEW.currency);
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER purchases_price_history
+AFTER INSERT OR UPDATE OF price, currency ON purchases
+FOR EACH ROW EXECUTE FUNCTION record_price_change();
+
+-- the price a purchase has now is all that is known of its past
+INSERT INTO price_history (pid, price, currency, changed_at)
+SELECT pid, price, currency, COALESCE(created_at, CURRENT_TIMESTAMP) FROM purchases;
@@ -0,0 +1,3 @@
+-- bought before the game was released; a pre-order can be cancelled for a full refund until then
+ALTER TABLE transactions
+ADD COLUMN preorder boolean NOT NULL DEFAULT FALSE;
@@ -0,0 +1,12 @@
+-- the age a player must have reached to see, buy and play games of a rating
+CREATE TABLE rating_ages(
+    rating rating_n PRIMARY KEY,
+    min_age int NOT NULL CHECK (min_age BETWEEN 0 AND 99)
+);
+
+INSERT INTO rating_ages (rating, min_age)
+VALUES ('general', 0), ('mature', 17), ('sensitive', 18);
+
+-- the highest age a user without a birth date declared to have reached
+ALTER TABLE users
+ADD COLUMN confirmed_age int CHECK (confirmed_age BETWEEN 0 AND 99);
@@ -0,0 +1,17 @@
+-- an owner lends the games of their library to the members of their family, and a member
+-- borrows from one family only
+CREATE TABLE family_members(
+    member_uid int PRIMARY KEY REFERENCES users(uid) ON DELETE CASCADE,
+    owner_uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE CHECK (owner_uid <> member_uid),
+    added_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_family_members_owner ON family_members(owner_uid);
+
+-- whether the members of the owner's family may borrow the game
+ALTER TABLE game_user
+ADD COLUMN shared boolean NOT NULL DEFAULT TRUE;
+
+-- whose library the game of a play session was borrowed from
+ALTER TABLE game_interaction
+ADD COLUMN lender_uid int REFERENCES users(uid) ON DELETE SET NULL;
@@ -0,0 +1,53 @@
+-- in-game items players may sell to each other on the marketplace
+ALTER TABLE purchases
+ADD COLUMN tradeable boolean NOT NULL DEFAULT FALSE;
+
+-- money held back for open buy orders, paid out to sellers as the orders are filled
+ALTER TYPE ledger_account_n ADD VALUE 'market';
+ALTER TYPE ledger_journal_n ADD VALUE 'market_hold';
+ALTER TYPE ledger_journal_n ADD VALUE 'market_release';
+ALTER TYPE ledger_journal_n ADD VALUE 'market_sale';
+ALTER TYPE ledger_journal_n ADD VALUE 'market_fee';
+
+CREATE TYPE market_order_status_n AS ENUM ('active', 'completed', 'cancelled');
+
+-- items offered for a price per unit, taken out of the seller's inventory while listed
+CREATE TABLE market_listings(
+    lid serial PRIMARY KEY,
+    seller_uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    quantity int NOT NULL CHECK (quantity > 0),
+    sold int NOT NULL DEFAULT 0 CHECK (sold BETWEEN 0 AND quantity),
+    price BIGINT NOT NULL CHECK (price > 0),
+    currency CHAR(3) NOT NULL,
+    status market_order_status_n NOT NULL DEFAULT 'active',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    closed_at TIMESTAMP
+);
+
+CREATE INDEX idx_market_listings_active ON market_listings(pid, price) WHERE status = 'active';
+
+-- offers to buy items for up to a price per unit, held back from the buyer's wallet while open
+CREATE TABLE market_buy_orders(
+    boid serial PRIMARY KEY,
+    buyer_uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    pid int NOT NULL REFERENCES purchases(pid) ON DELETE CASCADE,
+    quantity int NOT NULL CHECK (quantity > 0),
+    filled int NOT NULL DEFAULT 0 CHECK (filled BETWEEN 0 AND quantity),
+    max_price BIGINT NOT NULL CHECK (max_price > 0),
+    currency CHAR(3) NOT NULL,
+    status market_order_status_n NOT NULL DEFAULT 'active',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    closed_at TIMESTAMP
+);
+
+CREATE INDEX idx_market_buy_orders_active ON market_buy_orders(pid, max_price)
+WHERE status = 'active';
+
+-- a trade between players, bought by `uid` from `seller_uid`, who was paid `amount` less the fee
+ALTER TABLE transactions
+ADD COLUMN seller_uid int REFERENCES users(uid) ON DELETE SET NULL,
+ADD COLUMN lid int REFERENCES market_listings(lid) ON DELETE SET NULL,
+ADD COLUMN market_fee BIGINT NOT NULL DEFAULT 0 CHECK (market_fee >= 0);
+
+CREATE INDEX idx_transactions_seller ON transactions(seller_uid) WHERE seller_uid IS NOT NULL;
@@ -0,0 +1,2 @@
+-- store-wide settings only admins may change; added on its own so later migrations can use it
+ALTER TYPE permission_n ADD VALUE 'manage_store';
@@ -0,0 +1,11 @@
+INSERT INTO role_permissions (role, permission) VALUES ('admin', 'manage_store');
+
+-- the studios a developer account works for, which bounds the games and purchases it manages
+CREATE TABLE developer_members(
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    did int NOT NULL REFERENCES developers(did) ON DELETE CASCADE,
+    added_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (uid, did)
+);
+
+CREATE INDEX idx_developer_members_did ON developer_members(did);
@@ -0,0 +1,14 @@
+-- A top-up is recorded before its payment is charged and only credited to the wallet once the
+-- payment was captured.
+CREATE TABLE wallet_top_ups(
+    wtid serial PRIMARY KEY,
+    uid int NOT NULL REFERENCES users(uid) ON DELETE CASCADE,
+    payment_method payment_n NOT NULL CHECK (payment_method <> 'wallet'),
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    currency CHAR(3) NOT NULL,
+    status transaction_status_n NOT NULL DEFAULT 'pending',
+    payment_ref VARCHAR(64) UNIQUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_wallet_top_ups_uid ON wallet_top_ups(uid);
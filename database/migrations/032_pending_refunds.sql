@@ -0,0 +1,7 @@
+-- A refund is committed before its payment is paid back through the payment gateway. Until the
+-- gateway took it, the refund stays pending and is retried once the last attempt is old enough.
+ALTER TABLE transactions
+ADD COLUMN refund_pending BOOLEAN NOT NULL DEFAULT FALSE,
+ADD COLUMN refund_attempted_at TIMESTAMP;
+
+CREATE INDEX idx_transactions_refund_pending ON transactions(refunded_at) WHERE refund_pending;
@@ -0,0 +1,6 @@
+-- A pending order is settled by capturing its payment before anything is handed out. The capture
+-- is remembered so an interrupted settlement is retried without capturing twice, and each attempt
+-- is claimed so two of them never run at once.
+ALTER TABLE orders
+ADD COLUMN captured_at TIMESTAMP,
+ADD COLUMN settle_attempted_at TIMESTAMP;
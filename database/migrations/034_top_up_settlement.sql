@@ -0,0 +1,6 @@
+-- A pending top-up is settled like a pending order: its capture is remembered so an interrupted
+-- settlement is retried without capturing twice, and each attempt is claimed so two of them never
+-- run at once.
+ALTER TABLE wallet_top_ups
+ADD COLUMN captured_at TIMESTAMP,
+ADD COLUMN settle_attempted_at TIMESTAMP;
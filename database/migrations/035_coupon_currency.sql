@@ -0,0 +1,10 @@
+-- A percentage coupon applies to orders in any currency, so it only has one when it sets a
+-- minimum spend.
+ALTER TABLE coupons
+ALTER COLUMN currency DROP NOT NULL,
+ALTER COLUMN currency DROP DEFAULT;
+
+UPDATE coupons SET currency = NULL WHERE kind = 'percentage' AND min_spend = 0;
+
+ALTER TABLE coupons
+ADD CHECK (currency IS NOT NULL OR (kind = 'percentage' AND min_spend = 0));
@@ -1,8 +1,28 @@
 use sqlx::postgres::types::PgInterval;
+use thiserror::Error;
 use time::{Duration, PrimitiveDateTime};
 use zenki_util::usize_to_i32;
 
-use crate::State;
+use crate::{
+    State,
+    age::{AgeError, age_check},
+    family::{end_borrowed_sessions, lent_copy_in_use, lock_lender},
+    subscription::entitled,
+};
+
+#[derive(Error, Debug)]
+pub enum PlayError {
+    #[error("game is neither owned nor included in a subscription")]
+    NotEntitled,
+    #[error("game is being played by someone else in the family")]
+    InUse,
+    #[error("game is not released until {0}")]
+    NotReleased(PrimitiveDateTime),
+    #[error(transparent)]
+    Age(#[from] AgeError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
 
 pub struct GameActivity {
     pub uid: i32,
@@ -14,17 +34,68 @@ pub struct GameActivity {
 }
 
 impl State {
+    /// Starts a play session of a released game `uid` owns, plays through a subscription or
+    /// borrows from their family, and is old enough for.
+    ///
+    /// A borrowed game is played by one member at a time and only while its owner is not
+    /// playing it, while the owner starting it ends the session of whoever borrowed it.
+    ///
     /// # Errors
-    /// when connecting to the database failed
-    pub async fn start_playing(&self, uid: usize, gid: usize) -> sqlx::Result<()> {
+    /// when the user is not entitled to the game, the copy they would borrow is in use, it is
+    /// not released yet, the user is too young or has yet to confirm their age, or connecting to
+    /// the database failed
+    pub async fn start_playing(&self, uid: usize, gid: usize) -> Result<(), PlayError> {
+        let mut tx = self.db.begin().await?;
+        let lender_uid = if entitled(&mut *tx, uid, gid).await? {
+            None
+        } else {
+            let owner_uid = lock_lender(&mut tx, usize_to_i32(uid), usize_to_i32(gid))
+                .await?
+                .ok_or(PlayError::NotEntitled)?;
+            if lent_copy_in_use(&mut tx, usize_to_i32(uid), owner_uid, usize_to_i32(gid)).await? {
+                return Err(PlayError::InUse);
+            }
+            Some(owner_uid)
+        };
+        if let Some(x) = sqlx::query!(
+            r#"SELECT release_at AS "release_at!" FROM games WHERE gid = $1 AND release_at > NOW()"#,
+            usize_to_i32(gid),
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        {
+            return Err(PlayError::NotReleased(x.release_at));
+        }
+        age_check(&mut *tx, Some(uid), usize_to_i32(gid))
+            .await?
+            .ensure()?;
+        if lender_uid.is_none() {
+            // the owner takes priority over whoever borrowed their copy
+            sqlx::query!(
+                r"SELECT uid FROM game_user WHERE uid = $1 AND gid = $2 FOR UPDATE",
+                usize_to_i32(uid),
+                usize_to_i32(gid),
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+            end_borrowed_sessions(
+                &mut tx,
+                None,
+                Some(usize_to_i32(uid)),
+                Some(usize_to_i32(gid)),
+            )
+            .await?;
+        }
         sqlx::query!(
-            r#"INSERT INTO game_interaction (uid, gid, startplay_at)
-            VALUES ($1, $2, NOW())"#,
+            r#"INSERT INTO game_interaction (uid, gid, startplay_at, lender_uid)
+            VALUES ($1, $2, NOW(), $3)"#,
             usize_to_i32(uid),
             usize_to_i32(gid),
+            lender_uid,
         )
-        .execute(&self.db)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -0,0 +1,187 @@
+use sqlx::{PgConnection, PgExecutor};
+use thiserror::Error;
+use zenki_util::usize_to_i32;
+
+use crate::{GameRating, State};
+
+#[derive(Error, Debug)]
+pub enum AgeError {
+    #[error("game is only available to players aged {0} or older")]
+    TooYoung(i32),
+    #[error("confirm being aged {0} or older to continue")]
+    ConfirmationRequired(i32),
+    #[error("minimum age must be between 0 and 99")]
+    InvalidAge,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// Whether a user may see, buy and play a game, going by its rating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgeCheck {
+    Allowed,
+    /// Without a birth date, the user has to declare being old enough first.
+    ConfirmationRequired {
+        min_age: i32,
+    },
+    TooYoung {
+        min_age: i32,
+    },
+}
+
+impl AgeCheck {
+    /// # Errors
+    /// when the user is too young or has yet to confirm their age
+    pub const fn ensure(self) -> Result<(), AgeError> {
+        match self {
+            Self::Allowed => Ok(()),
+            Self::ConfirmationRequired { min_age } => Err(AgeError::ConfirmationRequired(min_age)),
+            Self::TooYoung { min_age } => Err(AgeError::TooYoung(min_age)),
+        }
+    }
+}
+
+/// The age a player must have reached for games of a rating.
+pub struct RatingAge {
+    pub rating: GameRating,
+    pub min_age: i32,
+}
+
+impl State {
+    /// Checks whether `uid`, or an anonymous visitor without one, may access game `gid`.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_age_check(&self, uid: Option<usize>, gid: usize) -> sqlx::Result<AgeCheck> {
+        age_check(&self.db, uid, usize_to_i32(gid)).await
+    }
+
+    /// Records that `uid` declared being old enough for game `gid`, which only matters while they
+    /// have no birth date.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn confirm_age(&self, uid: usize, gid: usize) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"UPDATE users
+            SET confirmed_age = GREATEST(confirmed_age, (
+                SELECT ra.min_age
+                FROM games g
+                JOIN rating_ages ra ON g.rating = ra.rating
+                WHERE g.gid = $2
+            ))
+            WHERE uid = $1",
+            usize_to_i32(uid),
+            usize_to_i32(gid),
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_rating_ages(&self) -> sqlx::Result<Vec<RatingAge>> {
+        sqlx::query_as!(
+            RatingAge,
+            r#"SELECT rating AS "rating: _", min_age FROM rating_ages ORDER BY rating"#
+        )
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// # Errors
+    /// when the age is out of range or querying the database failed
+    pub async fn set_rating_age(&self, rating: GameRating, min_age: i32) -> Result<(), AgeError> {
+        if !(0..=99).contains(&min_age) {
+            return Err(AgeError::InvalidAge);
+        }
+        sqlx::query!(
+            r"INSERT INTO rating_ages (rating, min_age) VALUES ($1, $2)
+            ON CONFLICT (rating) DO UPDATE SET min_age = EXCLUDED.min_age",
+            rating as GameRating,
+            min_age,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Checks the age of `uid` against the minimum age of the rating of game `gid`, going by their
+/// birth date, or else by the age they declared.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn age_check<'e>(
+    executor: impl PgExecutor<'e>,
+    uid: Option<usize>,
+    gid: i32,
+) -> sqlx::Result<AgeCheck> {
+    let Some(x) = sqlx::query!(
+        r#"SELECT
+            COALESCE(ra.min_age, 0) AS "min_age!",
+            EXTRACT(YEAR FROM AGE(CURRENT_DATE, u.birth_date))::int AS age,
+            u.confirmed_age
+        FROM games g
+        LEFT JOIN rating_ages ra ON g.rating = ra.rating
+        LEFT JOIN users u ON u.uid = $2
+        WHERE g.gid = $1"#,
+        gid,
+        uid.map(usize_to_i32),
+    )
+    .fetch_optional(executor)
+    .await?
+    else {
+        return Ok(AgeCheck::Allowed);
+    };
+
+    let min_age = x.min_age;
+    Ok(match (x.age, x.confirmed_age) {
+        _ if min_age == 0 => AgeCheck::Allowed,
+        (Some(age), _) if age < min_age => AgeCheck::TooYoung { min_age },
+        (Some(_), _) => AgeCheck::Allowed,
+        (None, Some(confirmed)) if confirmed >= min_age => AgeCheck::Allowed,
+        (None, _) => AgeCheck::ConfirmationRequired { min_age },
+    })
+}
+
+/// Checks the age of `uid` against every game purchase `pid` of game `gid` grants, i.e. against
+/// the games of the items of a bundle as well, returning the strictest check.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn purchase_age_check(
+    tx: &mut PgConnection,
+    uid: Option<usize>,
+    pid: i32,
+    gid: i32,
+) -> sqlx::Result<AgeCheck> {
+    let gids = sqlx::query_scalar!(
+        r#"SELECT $2::int AS "gid!"
+        UNION
+        SELECT p.gid FROM bundle_items bi JOIN purchases p ON bi.pid = p.pid
+        WHERE bi.bundle_pid = $1"#,
+        pid,
+        gid,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut strictest = AgeCheck::Allowed;
+    for gid in gids {
+        match age_check(&mut *tx, uid, gid).await? {
+            AgeCheck::Allowed => {}
+            check @ AgeCheck::TooYoung { .. } => return Ok(check),
+            AgeCheck::ConfirmationRequired { min_age } => {
+                if !matches!(
+                    strictest,
+                    AgeCheck::ConfirmationRequired { min_age: x } if x >= min_age
+                ) {
+                    strictest = AgeCheck::ConfirmationRequired { min_age };
+                }
+            }
+        }
+    }
+    Ok(strictest)
+}
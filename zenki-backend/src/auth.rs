@@ -5,7 +5,7 @@ use argon2::{
 use thiserror::Error;
 use zenki_util::{i32_to_usize, usize_to_i32};
 
-use crate::State;
+use crate::{State, session::revoke_user_sessions};
 
 #[derive(Error, Debug)]
 #[error(transparent)]
@@ -22,9 +22,7 @@ impl State {
         username: &str,
         password: &str,
     ) -> Result<Option<usize>, VerifyPasswordError> {
-        let user = sqlx::query!(r"SELECT passwd, uid FROM users WHERE uname = $1", username)
-            .fetch_one(&self.db)
-            .await?;
+        let user = self.query_credential(username).await?;
 
         let parsed_hash = argon2::PasswordHash::new(&user.passwd)?;
         Ok(self
@@ -41,12 +39,7 @@ impl State {
         uid: usize,
         password: &str,
     ) -> Result<bool, VerifyPasswordError> {
-        let user = sqlx::query!(
-            r"SELECT passwd FROM users WHERE uid = $1",
-            usize_to_i32(uid)
-        )
-        .fetch_one(&self.db)
-        .await?;
+        let user = self.query_credential_by_id(uid).await?;
 
         let parsed_hash = argon2::PasswordHash::new(&user.passwd)?;
         Ok(self
@@ -69,7 +62,10 @@ impl State {
             .to_string();
 
         sqlx::query!(
-            r"INSERT INTO users (uname, passwd) VALUES ($1, $2)",
+            r"WITH new_user AS (
+                INSERT INTO users (uname, passwd) VALUES ($1, $2) RETURNING uid
+            )
+            INSERT INTO user_roles (uid, role) SELECT uid, 'player' FROM new_user",
             username,
             password_hash
         )
@@ -79,6 +75,8 @@ impl State {
         Ok(())
     }
 
+    /// Replaces the password of user `id` once `old_passwd` matches, ending all their sessions.
+    ///
     /// # Errors
     /// when querying the database failed
     pub async fn change_password(
@@ -87,9 +85,7 @@ impl State {
         old_passwd: &str,
         passwd: &str,
     ) -> Result<bool, VerifyPasswordError> {
-        let user = sqlx::query!(r"SELECT passwd FROM users WHERE uid = $1", usize_to_i32(id))
-            .fetch_one(&self.db)
-            .await?;
+        let user = self.query_credential_by_id(id).await?;
 
         let parsed_hash = argon2::PasswordHash::new(&user.passwd)?;
         if self
@@ -105,13 +101,16 @@ impl State {
             .argon2
             .hash_password(passwd.as_bytes(), &salt)?
             .to_string();
+        let mut tx = self.db.begin().await?;
         sqlx::query!(
             r"UPDATE users SET passwd = $1 WHERE uid = $2",
             password_hash,
             usize_to_i32(id),
         )
-        .execute(&self.db)
+        .execute(&mut *tx)
         .await?;
+        revoke_user_sessions(&mut *tx, id).await?;
+        tx.commit().await?;
         Ok(true)
     }
 }
@@ -0,0 +1,316 @@
+use sqlx::{PgConnection, PgExecutor};
+use thiserror::Error;
+use zenki_util::{Money, i32_to_usize, usize_to_i32};
+
+use crate::{
+    PurchaseType, State,
+    entitlement::{grant_entitlement, is_add_on},
+    game::claim_game,
+    purchase::decode_money,
+    transaction::CheckoutError,
+};
+
+#[derive(Error, Debug)]
+pub enum BundleError {
+    #[error("a bundle must contain at least two items")]
+    TooFewItems,
+    #[error("purchase not found")]
+    PurchaseNotFound,
+    #[error("bundles can only contain games, DLC and in-game purchases")]
+    UnbundleableItem,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct BundleItem {
+    pub pid: i32,
+    pub gid: i32,
+    pub gname: String,
+    pub descr: Option<String>,
+    pub purchase_type: PurchaseType,
+    /// List price, which weighs the item when completing the bundle.
+    pub price: Money,
+    pub owned: bool,
+}
+
+impl State {
+    /// Lists what a bundle contains, marking what `uid` already owns.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_bundle_items(
+        &self,
+        bundle_pid: usize,
+        uid: usize,
+    ) -> sqlx::Result<Vec<BundleItem>> {
+        bundle_items(&self.db, usize_to_i32(bundle_pid), uid).await
+    }
+
+    /// Creates a bundle of existing purchases for a game, returning its purchase id.
+    ///
+    /// # Errors
+    /// when fewer than two items are given, an item does not exist or is not a game or add-on, or
+    /// querying the database failed
+    pub async fn create_bundle(
+        &self,
+        gid: usize,
+        price: Money,
+        descr: Option<&str>,
+        pids: &[usize],
+    ) -> Result<i32, BundleError> {
+        let mut pids = pids.iter().copied().map(usize_to_i32).collect::<Vec<_>>();
+        pids.sort_unstable();
+        pids.dedup();
+        if pids.len() < 2 {
+            return Err(BundleError::TooFewItems);
+        }
+
+        let mut tx = self.db.begin().await?;
+
+        let items = sqlx::query!(
+            r#"SELECT purchase_type AS "purchase_type: PurchaseType"
+            FROM purchases WHERE pid = ANY($1)"#,
+            &pids,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        if items.len() != pids.len() {
+            return Err(BundleError::PurchaseNotFound);
+        }
+        // only games and add-ons are granted by `grant_bundle`
+        if items
+            .iter()
+            .any(|x| x.purchase_type != PurchaseType::GamePurchase && !is_add_on(x.purchase_type))
+        {
+            return Err(BundleError::UnbundleableItem);
+        }
+
+        let pid = sqlx::query!(
+            r"INSERT INTO purchases (gid, purchase_type, price, currency, descr)
+            VALUES ($1, 'bundle', $2, $3, $4)
+            RETURNING pid",
+            usize_to_i32(gid),
+            price.minor(),
+            price.currency().to_string(),
+            descr.map(str::trim).filter(|x| !x.is_empty()),
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .pid;
+        sqlx::query!(
+            r"INSERT INTO bundle_items (bundle_pid, pid) SELECT $1, UNNEST($2::int[])",
+            pid,
+            &pids,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(pid)
+    }
+}
+
+/// # Errors
+/// when querying the database failed
+pub async fn bundle_items<'e>(
+    executor: impl PgExecutor<'e>,
+    bundle_pid: i32,
+    uid: usize,
+) -> sqlx::Result<Vec<BundleItem>> {
+    sqlx::query!(
+        r#"SELECT
+            p.pid,
+            p.gid,
+            g.gname,
+            p.descr,
+            p.purchase_type AS "purchase_type: PurchaseType",
+            p.price,
+            p.currency,
+            CASE p.purchase_type
+                WHEN 'game_purchase' THEN EXISTS (
+                    SELECT 1 FROM game_user gu
+                    WHERE gu.uid = $2 AND gu.gid = p.gid AND gu.wishlist = FALSE
+                )
+                WHEN 'dlc' THEN EXISTS (
+                    SELECT 1 FROM entitlements e WHERE e.uid = $2 AND e.pid = p.pid
+                )
+                ELSE FALSE
+            END AS "owned!"
+        FROM bundle_items bi
+        JOIN purchases p ON bi.pid = p.pid
+        JOIN games g ON p.gid = g.gid
+        WHERE bi.bundle_pid = $1
+        ORDER BY p.purchase_type <> 'game_purchase', p.pid"#,
+        bundle_pid,
+        usize_to_i32(uid),
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|x| {
+        Ok(BundleItem {
+            pid: x.pid,
+            gid: x.gid,
+            gname: x.gname,
+            descr: x.descr,
+            purchase_type: x.purchase_type,
+            price: decode_money(x.price, &x.currency)?,
+            owned: x.owned,
+        })
+    })
+    .collect()
+}
+
+/// Prices a bundle for someone who already owns some of it.
+///
+/// The bundle keeps its discount over the items: `price` is scaled by the list price share of
+/// the items not owned yet, rounded half up.
+///
+/// # Errors
+/// when every item is owned already or the items are priced in another currency
+pub fn complete_the_bundle(price: Money, items: &[BundleItem]) -> Result<Money, CheckoutError> {
+    if !items.is_empty() && items.iter().all(|item| item.owned) {
+        return Err(CheckoutError::AlreadyOwned);
+    }
+    let mut total = 0_i128;
+    let mut unowned = 0_i128;
+    for item in items {
+        if item.price.currency() != price.currency() {
+            return Err(CheckoutError::CurrencyMismatch);
+        }
+        total += i128::from(item.price.minor());
+        if !item.owned {
+            unowned += i128::from(item.price.minor());
+        }
+    }
+    if total == 0 || unowned == total {
+        return Ok(price);
+    }
+    let minor = (i128::from(price.minor()) * unowned + total / 2) / total;
+    Ok(Money::new(
+        i64::try_from(minor).map_err(|_| CheckoutError::AmountOverflow)?,
+        price.currency(),
+    ))
+}
+
+/// Adds every game and add-on of a bundle that `uid` does not own yet to their library and
+/// entitlements, remembering what a bought bundle's transaction `tid` granted.
+///
+/// # Errors
+/// when `uid` lacks the base game of an add-on or querying the database failed
+pub async fn grant_bundle(
+    tx: &mut PgConnection,
+    uid: usize,
+    tid: Option<i32>,
+    bundle_pid: i32,
+) -> Result<(), CheckoutError> {
+    for item in bundle_items(&mut *tx, bundle_pid, uid).await? {
+        if item.owned {
+            continue;
+        }
+        if is_add_on(item.purchase_type) {
+            grant_entitlement(tx, uid, tid, (item.pid, item.gid, item.purchase_type), 1).await?;
+            continue;
+        }
+        if item.purchase_type != PurchaseType::GamePurchase {
+            continue;
+        }
+        // only what this bundle added is taken back on a refund
+        if !claim_game(&mut *tx, uid, i32_to_usize(item.gid)).await? {
+            continue;
+        }
+        let Some(tid) = tid else {
+            continue;
+        };
+        sqlx::query!(
+            r"INSERT INTO bundle_grants (tid, gid) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            tid,
+            item.gid,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use zenki_util::Currency;
+
+    use super::*;
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::USD)
+    }
+
+    fn item(price: Money, owned: bool) -> BundleItem {
+        BundleItem {
+            pid: 1,
+            gid: 1,
+            gname: "Game".to_string(),
+            descr: None,
+            purchase_type: PurchaseType::GamePurchase,
+            price,
+            owned,
+        }
+    }
+
+    #[test]
+    fn charges_the_full_price_when_nothing_is_owned() {
+        let items = [item(usd(1_000), false), item(usd(2_000), false)];
+        assert_eq!(
+            complete_the_bundle(usd(2_400), &items).ok(),
+            Some(usd(2_400))
+        );
+        assert_eq!(complete_the_bundle(usd(2_400), &[]).ok(), Some(usd(2_400)));
+    }
+
+    #[test]
+    fn scales_the_price_by_the_share_not_owned() {
+        let items = [item(usd(1_000), true), item(usd(2_000), false)];
+        assert_eq!(
+            complete_the_bundle(usd(2_400), &items).ok(),
+            Some(usd(1_600))
+        );
+
+        // a third of a cent rounds down, two thirds round up
+        let items = [item(usd(1), true), item(usd(1), true), item(usd(1), false)];
+        assert_eq!(complete_the_bundle(usd(1), &items).ok(), Some(usd(0)));
+        let items = [item(usd(1), true), item(usd(1), false), item(usd(1), false)];
+        assert_eq!(complete_the_bundle(usd(1), &items).ok(), Some(usd(1)));
+    }
+
+    #[test]
+    fn keeps_the_price_of_free_items() {
+        let items = [item(usd(0), true), item(usd(0), false)];
+        assert_eq!(complete_the_bundle(usd(500), &items).ok(), Some(usd(500)));
+    }
+
+    #[test]
+    fn refuses_a_bundle_owned_entirely() {
+        let items = [item(usd(1_000), true), item(usd(2_000), true)];
+        assert!(matches!(
+            complete_the_bundle(usd(2_400), &items),
+            Err(CheckoutError::AlreadyOwned)
+        ));
+    }
+
+    #[test]
+    fn refuses_items_priced_in_another_currency() {
+        let eur = Money::new(1_000, "EUR".parse().expect("EUR is a currency code"));
+        let items = [item(eur, true), item(usd(1_000), false)];
+        assert!(matches!(
+            complete_the_bundle(usd(1_500), &items),
+            Err(CheckoutError::CurrencyMismatch)
+        ));
+    }
+
+    #[test]
+    fn does_not_overflow_on_large_prices() {
+        let items = [item(usd(i64::MAX), true), item(usd(i64::MAX), false)];
+        assert_eq!(
+            complete_the_bundle(usd(i64::MAX), &items).ok(),
+            Some(usd(i64::MAX / 2 + 1))
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use time::PrimitiveDateTime;
+use zenki_util::{i32_to_usize, usize_to_i32};
+
+use crate::{
+    Purchase, PurchaseType, State,
+    discount::attach_sales,
+    purchase::decode_money,
+    transaction::{CheckoutError, PaymentMethod, Receipt},
+};
+
+pub struct CartItem {
+    pub purchase: Purchase,
+    pub gname: String,
+    pub quantity: i32,
+    pub added_at: Option<PrimitiveDateTime>,
+}
+
+impl State {
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_cart(&self, uid: usize) -> sqlx::Result<Vec<CartItem>> {
+        let rows = sqlx::query!(
+            r#"SELECT
+                p.pid,
+                p.gid,
+                g.gname,
+                p.purchase_type AS "purchase_type: PurchaseType",
+                p.price,
+                p.currency,
+                p.descr,
+                p.created_at,
+                c.quantity,
+                c.added_at
+            FROM cart_items c
+            JOIN purchases p ON c.pid = p.pid
+            JOIN games g ON p.gid = g.gid
+            WHERE c.uid = $1
+            ORDER BY c.added_at"#,
+            usize_to_i32(uid)
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut purchases = rows
+            .iter()
+            .map(|x| {
+                Ok(Purchase {
+                    pid: x.pid,
+                    gid: x.gid,
+                    purchase_type: x.purchase_type,
+                    price: decode_money(x.price, &x.currency)?,
+                    sale: None,
+                    descr: x.descr.clone(),
+                    created_at: x.created_at,
+                })
+            })
+            .collect::<sqlx::Result<Vec<_>>>()?;
+        attach_sales(&self.db, &mut purchases).await?;
+
+        Ok(purchases
+            .into_iter()
+            .zip(rows)
+            .map(|(purchase, x)| CartItem {
+                purchase,
+                gname: x.gname,
+                quantity: x.quantity,
+                added_at: x.added_at,
+            })
+            .collect())
+    }
+
+    /// Adds `quantity` of a purchase to the cart, on top of what is already in it.
+    ///
+    /// # Errors
+    /// when the quantity is out of range or querying the database failed
+    pub async fn add_to_cart(
+        &self,
+        uid: usize,
+        pid: usize,
+        quantity: usize,
+    ) -> Result<(), CheckoutError> {
+        let quantity = i32::try_from(quantity).map_err(|_| CheckoutError::InvalidQuantity)?;
+        sqlx::query!(
+            r"INSERT INTO cart_items (uid, pid, quantity) VALUES ($1, $2, $3)
+            ON CONFLICT (uid, pid) DO UPDATE SET quantity = cart_items.quantity + EXCLUDED.quantity",
+            usize_to_i32(uid),
+            usize_to_i32(pid),
+            quantity,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// # Errors
+    /// when the quantity is out of range or querying the database failed
+    pub async fn update_cart_quantity(
+        &self,
+        uid: usize,
+        pid: usize,
+        quantity: usize,
+    ) -> Result<(), CheckoutError> {
+        let quantity = i32::try_from(quantity).map_err(|_| CheckoutError::InvalidQuantity)?;
+        if quantity == 0 {
+            return Ok(self.remove_from_cart(uid, pid).await?);
+        }
+        sqlx::query!(
+            r"UPDATE cart_items SET quantity = $3 WHERE uid = $1 AND pid = $2",
+            usize_to_i32(uid),
+            usize_to_i32(pid),
+            quantity,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn remove_from_cart(&self, uid: usize, pid: usize) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"DELETE FROM cart_items WHERE uid = $1 AND pid = $2",
+            usize_to_i32(uid),
+            usize_to_i32(pid),
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Lines of the cart of `uid` as `(pid, quantity)`, e.g. to preview its checkout.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_cart_lines(&self, uid: usize) -> sqlx::Result<Vec<(usize, usize)>> {
+        Ok(sqlx::query!(
+            r"SELECT pid, quantity FROM cart_items WHERE uid = $1 ORDER BY added_at",
+            usize_to_i32(uid)
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| (i32_to_usize(x.pid), i32_to_usize(x.quantity)))
+        .collect())
+    }
+
+    /// Turns the whole cart of `uid` into one order for `ruid` and empties the cart, unless the
+    /// payment was declined.
+    ///
+    /// # Errors
+    /// when the cart is empty, any line cannot be sold, the payment was declined, or querying the
+    /// database failed
+    pub async fn checkout_cart(
+        &self,
+        uid: usize,
+        ruid: usize,
+        payment_method: PaymentMethod,
+        coupon: Option<&str>,
+        gift_message: Option<&str>,
+    ) -> Result<Receipt, CheckoutError> {
+        let mut tx = self.db.begin().await?;
+
+        let lines = sqlx::query!(
+            r"SELECT pid, quantity FROM cart_items WHERE uid = $1 ORDER BY added_at FOR UPDATE",
+            usize_to_i32(uid)
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|x| (i32_to_usize(x.pid), i32_to_usize(x.quantity)))
+        .collect::<Vec<_>>();
+
+        let receipt = self
+            .checkout(tx, uid, ruid, payment_method, &lines, coupon, gift_message)
+            .await?;
+
+        sqlx::query!(r"DELETE FROM cart_items WHERE uid = $1", usize_to_i32(uid))
+            .execute(&self.db)
+            .await?;
+        Ok(receipt)
+    }
+}
@@ -0,0 +1,457 @@
+use sqlx::PgConnection;
+use time::PrimitiveDateTime;
+use zenki_util::{Money, usize_to_i32};
+
+use crate::{
+    DiscountValue, Permission, PurchaseType, State,
+    discount::DiscountKind,
+    purchase::decode_money,
+    transaction::{CheckoutError, PricedLine},
+};
+
+/// A coupon that passed its expiry and usage checks.
+pub struct Coupon {
+    pub code: String,
+    pub value: DiscountValue,
+    pub min_spend: Option<Money>,
+}
+
+/// Coupon codes are case-insensitive and stored upper case.
+#[must_use]
+pub fn normalize_coupon_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+impl Coupon {
+    /// Splits the coupon discount over the eligible lines, returning one discount per line.
+    ///
+    /// Percentages are rounded per line; a fixed amount is split pro rata by largest remainder, so
+    /// the parts add up to exactly the coupon value and no part exceeds its line total.
+    ///
+    /// # Errors
+    /// when no line is eligible, the eligible lines do not reach the minimum spend, or they are
+    /// priced in another currency than a fixed amount or minimum spend
+    pub fn allocate(
+        &self,
+        lines: &[PricedLine],
+        eligible: &[bool],
+    ) -> Result<Vec<Money>, CheckoutError> {
+        // a percentage without a minimum spend applies to any currency
+        let currency = match (&self.value, self.min_spend) {
+            (DiscountValue::Fixed(amount), _) => Some(amount.currency()),
+            (DiscountValue::Percentage(_), min_spend) => min_spend.map(Money::currency),
+        };
+        let Some(currency) = currency.or_else(|| lines.first().map(|x| x.line_total.currency()))
+        else {
+            return Err(CheckoutError::CouponNotApplicable);
+        };
+        let eligible_total = lines
+            .iter()
+            .zip(eligible)
+            .filter(|&(_, &eligible)| eligible)
+            .try_fold(Money::zero(currency), |total, (line, _)| {
+                total.checked_add(line.line_total)
+            })
+            .ok_or(CheckoutError::CurrencyMismatch)?;
+        if eligible_total.is_zero()
+            || self
+                .min_spend
+                .is_some_and(|min_spend| eligible_total.minor() < min_spend.minor())
+        {
+            return Err(CheckoutError::CouponNotApplicable);
+        }
+
+        let mut discounts = Vec::with_capacity(lines.len());
+        match self.value {
+            DiscountValue::Percentage(percent) => {
+                for (line, &eligible) in lines.iter().zip(eligible) {
+                    let discount = if eligible {
+                        line.line_total
+                            .checked_percent_off(i64::from(percent))
+                            .and_then(|rest| line.line_total.checked_sub(rest))
+                            .ok_or(CheckoutError::AmountOverflow)?
+                    } else {
+                        Money::zero(line.line_total.currency())
+                    };
+                    discounts.push(discount);
+                }
+            }
+            DiscountValue::Fixed(amount) => {
+                let amount = i128::from(amount.minor().min(eligible_total.minor()));
+                let total = i128::from(eligible_total.minor());
+                // (share, remainder) of each line, rounded down
+                let mut shares = lines
+                    .iter()
+                    .zip(eligible)
+                    .map(|(line, &eligible)| {
+                        let exact = if eligible {
+                            amount * i128::from(line.line_total.minor())
+                        } else {
+                            0
+                        };
+                        (exact / total, exact % total)
+                    })
+                    .collect::<Vec<_>>();
+                // hand the rounded off units to the largest remainders, which all lie below their
+                // line total as the amount never exceeds the eligible total
+                let left =
+                    usize::try_from(amount - shares.iter().map(|&(share, _)| share).sum::<i128>())
+                        .map_err(|_| CheckoutError::AmountOverflow)?;
+                let mut order = (0..shares.len()).collect::<Vec<_>>();
+                order.sort_by_key(|&i| std::cmp::Reverse(shares[i].1));
+                for i in order.into_iter().take(left) {
+                    shares[i].0 += 1;
+                }
+                for (line, (share, _)) in lines.iter().zip(shares) {
+                    let minor = i64::try_from(share).map_err(|_| CheckoutError::AmountOverflow)?;
+                    discounts.push(Money::new(minor, line.line_total.currency()));
+                }
+            }
+        }
+        Ok(discounts)
+    }
+}
+
+/// Loads a coupon for `uid` to redeem and locks it until the end of the transaction.
+///
+/// # Errors
+/// when the coupon does not exist, has expired or is used up, or querying the database failed
+pub async fn redeemable_coupon(
+    tx: &mut PgConnection,
+    uid: usize,
+    code: &str,
+) -> Result<Coupon, CheckoutError> {
+    let x = sqlx::query!(
+        r#"SELECT
+            code,
+            kind AS "kind: DiscountKind",
+            percent,
+            amount,
+            currency,
+            min_spend,
+            max_uses,
+            max_uses_per_user,
+            expires_at IS NOT NULL AND expires_at <= NOW() AS "expired!",
+            (SELECT COUNT(*) FROM coupon_redemptions r WHERE r.code = c.code) AS "uses!",
+            (SELECT COUNT(*) FROM coupon_redemptions r WHERE r.code = c.code AND r.uid = $2)
+                AS "user_uses!"
+        FROM coupons c
+        WHERE code = $1
+        FOR UPDATE"#,
+        normalize_coupon_code(code),
+        usize_to_i32(uid),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(CheckoutError::CouponNotFound)?;
+
+    if x.expired {
+        return Err(CheckoutError::CouponExpired);
+    }
+    if x.max_uses.is_some_and(|max| x.uses >= i64::from(max))
+        || x.max_uses_per_user
+            .is_some_and(|max| x.user_uses >= i64::from(max))
+    {
+        return Err(CheckoutError::CouponUsedUp);
+    }
+
+    let value = match (x.kind, x.percent, x.amount, x.currency.as_deref()) {
+        (DiscountKind::Percentage, Some(percent), _, _) => DiscountValue::Percentage(percent),
+        (DiscountKind::Fixed, _, Some(amount), Some(currency)) => {
+            DiscountValue::Fixed(decode_money(amount, currency)?)
+        }
+        _ => return Err(CheckoutError::CouponNotFound),
+    };
+    let min_spend = match x.currency.as_deref() {
+        Some(currency) if x.min_spend > 0 => Some(decode_money(x.min_spend, currency)?),
+        _ => None,
+    };
+    Ok(Coupon {
+        code: x.code,
+        value,
+        min_spend,
+    })
+}
+
+/// Which of the purchases fall within the scope of the coupon.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn coupon_eligibility(
+    tx: &mut PgConnection,
+    code: &str,
+    pids: &[i32],
+) -> sqlx::Result<Vec<bool>> {
+    let eligible = sqlx::query!(
+        r#"SELECT p.pid
+        FROM purchases p, coupons c
+        WHERE c.code = $1
+            AND p.pid = ANY($2)
+            AND (c.gid IS NULL OR c.gid = p.gid)
+            AND (c.purchase_type IS NULL OR c.purchase_type = p.purchase_type)
+            AND (c.did IS NULL OR EXISTS (
+                SELECT 1 FROM developer_game dg WHERE dg.gid = p.gid AND dg.did = c.did
+            ))"#,
+        code,
+        pids,
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|x| x.pid)
+    .collect::<Vec<_>>();
+    Ok(pids.iter().map(|pid| eligible.contains(pid)).collect())
+}
+
+/// Scope a new coupon is limited to; `None` fields match anything.
+#[derive(Default)]
+pub struct CouponScope {
+    pub gid: Option<usize>,
+    pub did: Option<usize>,
+    pub purchase_type: Option<PurchaseType>,
+}
+
+/// Usage limits of a new coupon; `None` fields are unlimited.
+#[derive(Default)]
+pub struct CouponLimits {
+    pub max_uses: Option<usize>,
+    pub max_uses_per_user: Option<usize>,
+    pub min_spend: Option<Money>,
+    pub expires_at: Option<PrimitiveDateTime>,
+}
+
+impl State {
+    /// Whether `uid` may create coupons of `scope`: store managers any, developers only those
+    /// bound to their own games or studios.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn manages_coupon_scope(
+        &self,
+        uid: usize,
+        scope: &CouponScope,
+    ) -> sqlx::Result<bool> {
+        if scope.gid.is_none() && scope.did.is_none() {
+            return self.has_permission(uid, Permission::ManageStore).await;
+        }
+        if let Some(gid) = scope.gid
+            && !self.manages_game(uid, gid).await?
+        {
+            return Ok(false);
+        }
+        if let Some(did) = scope.did
+            && !self.manages_developer(uid, did).await?
+        {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// # Errors
+    /// when the code is already taken or querying the database failed
+    pub async fn create_coupon(
+        &self,
+        code: &str,
+        value: DiscountValue,
+        scope: CouponScope,
+        limits: CouponLimits,
+    ) -> sqlx::Result<()> {
+        let (kind, percent, amount) = match value {
+            DiscountValue::Percentage(percent) => (DiscountKind::Percentage, Some(percent), None),
+            DiscountValue::Fixed(amount) => (DiscountKind::Fixed, None, Some(amount.minor())),
+        };
+        // a percentage only has a currency when it sets a minimum spend
+        let min_spend = limits.min_spend.filter(|x| !x.is_zero());
+        let currency = match value {
+            DiscountValue::Fixed(amount) => Some(amount.currency()),
+            DiscountValue::Percentage(_) => min_spend.map(Money::currency),
+        };
+        sqlx::query!(
+            r"INSERT INTO coupons
+            (code, kind, percent, amount, currency, min_spend, max_uses, max_uses_per_user,
+             gid, did, purchase_type, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+            normalize_coupon_code(code),
+            kind as DiscountKind,
+            percent,
+            amount,
+            currency.map(|x| x.to_string()),
+            min_spend.map_or(0, Money::minor),
+            limits.max_uses.map(usize_to_i32),
+            limits.max_uses_per_user.map(usize_to_i32),
+            scope.gid.map(usize_to_i32),
+            scope.did.map(usize_to_i32),
+            scope.purchase_type as Option<PurchaseType>,
+            limits.expires_at,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zenki_util::Currency;
+
+    use super::*;
+    use crate::Purchase;
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::USD)
+    }
+
+    fn eur(minor: i64) -> Money {
+        Money::new(minor, "EUR".parse().expect("EUR is a currency code"))
+    }
+
+    fn line(line_total: Money) -> PricedLine {
+        PricedLine {
+            purchase: Purchase {
+                pid: 1,
+                gid: 1,
+                purchase_type: PurchaseType::InGamePurchase,
+                price: line_total,
+                sale: None,
+                descr: None,
+                created_at: None,
+            },
+            unit_price: line_total,
+            quantity: 1,
+            line_total,
+            discount: Money::zero(line_total.currency()),
+            tax: Money::zero(line_total.currency()),
+            tax_rate: None,
+        }
+    }
+
+    fn allocate(
+        value: DiscountValue,
+        min_spend: Option<Money>,
+        lines: &[Money],
+        eligible: &[bool],
+    ) -> Result<Vec<i64>, CheckoutError> {
+        let coupon = Coupon {
+            code: "TEST".to_string(),
+            value,
+            min_spend,
+        };
+        let lines = lines.iter().copied().map(line).collect::<Vec<_>>();
+        Ok(coupon
+            .allocate(&lines, eligible)?
+            .into_iter()
+            .map(Money::minor)
+            .collect())
+    }
+
+    #[test]
+    fn splits_fixed_amounts_by_largest_remainder() {
+        // 100 cents over three equal lines: 33.33 each, the first remainder gets the extra cent
+        let discounts = allocate(
+            DiscountValue::Fixed(usd(100)),
+            None,
+            &[usd(300), usd(300), usd(300)],
+            &[true; 3],
+        );
+        assert_eq!(discounts.ok(), Some(vec![34, 33, 33]));
+
+        // exact shares are 50.5 and 49.5
+        let discounts = allocate(
+            DiscountValue::Fixed(usd(100)),
+            None,
+            &[usd(101), usd(99)],
+            &[true; 2],
+        );
+        assert_eq!(discounts.ok(), Some(vec![51, 49]));
+    }
+
+    #[test]
+    fn never_discounts_a_line_past_its_total() {
+        let discounts = allocate(
+            DiscountValue::Fixed(usd(1_000)),
+            None,
+            &[usd(1), usd(2), usd(3)],
+            &[true; 3],
+        );
+        assert_eq!(discounts.ok(), Some(vec![1, 2, 3]));
+
+        let discounts = allocate(DiscountValue::Fixed(usd(1)), None, &[usd(1)], &[true]);
+        assert_eq!(discounts.ok(), Some(vec![1]));
+    }
+
+    #[test]
+    fn leaves_ineligible_lines_alone() {
+        let discounts = allocate(
+            DiscountValue::Fixed(usd(50)),
+            None,
+            &[usd(100), usd(100)],
+            &[false, true],
+        );
+        assert_eq!(discounts.ok(), Some(vec![0, 50]));
+
+        let discounts = allocate(
+            DiscountValue::Percentage(10),
+            None,
+            &[usd(100), usd(100)],
+            &[true, false],
+        );
+        assert_eq!(discounts.ok(), Some(vec![10, 0]));
+    }
+
+    #[test]
+    fn rounds_percentages_per_line() {
+        // 15% of 5 cents is 0.75 cents, of 3 cents 0.45 cents
+        let discounts = allocate(
+            DiscountValue::Percentage(15),
+            None,
+            &[usd(5), usd(3)],
+            &[true; 2],
+        );
+        assert_eq!(discounts.ok(), Some(vec![1, 0]));
+    }
+
+    #[test]
+    fn applies_percentages_to_any_currency() {
+        let discounts = allocate(DiscountValue::Percentage(50), None, &[eur(200)], &[true]);
+        assert_eq!(discounts.ok(), Some(vec![100]));
+    }
+
+    #[test]
+    fn refuses_other_currencies_than_its_amount_or_minimum_spend() {
+        let discounts = allocate(DiscountValue::Fixed(usd(100)), None, &[eur(200)], &[true]);
+        assert!(matches!(discounts, Err(CheckoutError::CurrencyMismatch)));
+
+        let discounts = allocate(
+            DiscountValue::Percentage(50),
+            Some(usd(100)),
+            &[eur(200)],
+            &[true],
+        );
+        assert!(matches!(discounts, Err(CheckoutError::CurrencyMismatch)));
+    }
+
+    #[test]
+    fn requires_an_eligible_minimum_spend() {
+        let discounts = allocate(
+            DiscountValue::Percentage(10),
+            Some(usd(200)),
+            &[usd(150), usd(100)],
+            &[true, false],
+        );
+        assert!(matches!(discounts, Err(CheckoutError::CouponNotApplicable)));
+
+        let discounts = allocate(
+            DiscountValue::Percentage(10),
+            Some(usd(200)),
+            &[usd(200)],
+            &[true],
+        );
+        assert_eq!(discounts.ok(), Some(vec![20]));
+
+        let discounts = allocate(DiscountValue::Fixed(usd(10)), None, &[usd(0)], &[true]);
+        assert!(matches!(discounts, Err(CheckoutError::CouponNotApplicable)));
+
+        let discounts = allocate(DiscountValue::Percentage(10), None, &[], &[]);
+        assert!(matches!(discounts, Err(CheckoutError::CouponNotApplicable)));
+    }
+}
@@ -1,7 +1,7 @@
 use time::PrimitiveDateTime;
 use zenki_util::usize_to_i32;
 
-use crate::{Game, State};
+use crate::{Game, Permission, PublicProfile, State};
 
 pub struct Developer {
     pub did: i32,
@@ -46,4 +46,116 @@ impl State {
         .fetch_all(&self.db)
         .await
     }
+
+    /// The accounts working for developer `did`.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_developer_members(&self, did: usize) -> sqlx::Result<Vec<PublicProfile>> {
+        sqlx::query_as!(
+            PublicProfile,
+            r"SELECT u.uid, u.uname, u.bio, u.avatar, u.created_at
+            FROM developer_members dm
+            JOIN users u ON dm.uid = u.uid
+            WHERE dm.did = $1
+            ORDER BY dm.added_at, u.uid",
+            usize_to_i32(did)
+        )
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// Lets `uid` manage the games and purchases of developer `did`.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn add_developer_member(&self, did: usize, uid: usize) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"INSERT INTO developer_members (uid, did) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            usize_to_i32(uid),
+            usize_to_i32(did),
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn remove_developer_member(&self, did: usize, uid: usize) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"DELETE FROM developer_members WHERE uid = $1 AND did = $2",
+            usize_to_i32(uid),
+            usize_to_i32(did),
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Whether `uid` manages the whole store or works for developer `did`.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn manages_developer(&self, uid: usize, did: usize) -> sqlx::Result<bool> {
+        if self.has_permission(uid, Permission::ManageStore).await? {
+            return Ok(true);
+        }
+        Ok(sqlx::query!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM developer_members WHERE uid = $1 AND did = $2
+            ) AS "allowed!""#,
+            usize_to_i32(uid),
+            usize_to_i32(did),
+        )
+        .fetch_one(&self.db)
+        .await?
+        .allowed)
+    }
+
+    /// Whether `uid` manages the whole store or works for a developer of game `gid`.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn manages_game(&self, uid: usize, gid: usize) -> sqlx::Result<bool> {
+        if self.has_permission(uid, Permission::ManageStore).await? {
+            return Ok(true);
+        }
+        Ok(sqlx::query!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM developer_members dm
+                JOIN developer_game dg ON dm.did = dg.did
+                WHERE dm.uid = $1 AND dg.gid = $2
+            ) AS "allowed!""#,
+            usize_to_i32(uid),
+            usize_to_i32(gid),
+        )
+        .fetch_one(&self.db)
+        .await?
+        .allowed)
+    }
+
+    /// Whether `uid` manages the whole store or works for a developer of the game of purchase
+    /// `pid`.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn manages_purchase(&self, uid: usize, pid: usize) -> sqlx::Result<bool> {
+        if self.has_permission(uid, Permission::ManageStore).await? {
+            return Ok(true);
+        }
+        Ok(sqlx::query!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM purchases p
+                JOIN developer_game dg ON p.gid = dg.gid
+                JOIN developer_members dm ON dg.did = dm.did
+                WHERE dm.uid = $1 AND p.pid = $2
+            ) AS "allowed!""#,
+            usize_to_i32(uid),
+            usize_to_i32(pid),
+        )
+        .fetch_one(&self.db)
+        .await?
+        .allowed)
+    }
 }
@@ -0,0 +1,224 @@
+use std::fmt::Display;
+
+use sqlx::PgExecutor;
+use time::PrimitiveDateTime;
+use zenki_util::{Money, usize_to_i32};
+
+use crate::{Permission, Purchase, State, purchase::decode_money};
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "discount_n", rename_all = "snake_case")]
+pub enum DiscountKind {
+    Percentage,
+    Fixed,
+}
+
+impl Display for DiscountKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Percentage => "Percentage",
+            Self::Fixed => "Fixed",
+        })
+    }
+}
+
+pub enum DiscountValue {
+    /// Percent off, from 1 to 100.
+    Percentage(i32),
+    /// A fixed amount off, never going below zero.
+    Fixed(Money),
+}
+
+/// What a discount applies to.
+pub enum DiscountScope {
+    Purchase(usize),
+    Tag(String),
+    Developer(usize),
+}
+
+pub struct Discount {
+    pub value: DiscountValue,
+    pub descr: Option<String>,
+    pub ends_at: PrimitiveDateTime,
+}
+
+impl Discount {
+    /// The discounted price, or `None` when the discount cannot apply to a price in this currency.
+    #[must_use]
+    pub fn apply(&self, price: Money) -> Option<Money> {
+        self.value.apply(price)
+    }
+}
+
+impl DiscountValue {
+    /// The discounted price, or `None` when the discount cannot apply to a price in this currency.
+    #[must_use]
+    pub fn apply(&self, price: Money) -> Option<Money> {
+        match *self {
+            Self::Percentage(percent) => price.checked_percent_off(i64::from(percent)),
+            Self::Fixed(amount) => (amount.currency() == price.currency()).then(|| {
+                price
+                    .checked_sub(amount)
+                    .filter(|x| x.minor() > 0)
+                    .unwrap_or_else(|| Money::zero(price.currency()))
+            }),
+        }
+    }
+}
+
+/// A running discount applied to a purchase.
+pub struct Sale {
+    pub price: Money,
+    pub descr: Option<String>,
+    pub ends_at: PrimitiveDateTime,
+    /// The lowest price of the purchase in the last 30 days, not counting running sales.
+    /// Only known where purchases are shown, see [`attach_lowest_prices`].
+    ///
+    /// [`attach_lowest_prices`]: crate::price_history::attach_lowest_prices
+    pub lowest_price: Option<Money>,
+}
+
+/// Builds the value of a discount from its columns, `None` when they do not match its kind.
+///
+/// # Errors
+/// when the currency column is not a valid ISO 4217 code
+pub fn discount_value(
+    kind: DiscountKind,
+    percent: Option<i32>,
+    amount: Option<i64>,
+    currency: &str,
+) -> sqlx::Result<Option<DiscountValue>> {
+    Ok(match (kind, percent, amount) {
+        (DiscountKind::Percentage, Some(percent), _) => Some(DiscountValue::Percentage(percent)),
+        (DiscountKind::Fixed, _, Some(amount)) => {
+            Some(DiscountValue::Fixed(decode_money(amount, currency)?))
+        }
+        _ => None,
+    })
+}
+
+/// Attaches the best running discount, if any, to each purchase. Among equally good discounts the
+/// oldest one wins.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn attach_sales<'e>(
+    executor: impl PgExecutor<'e>,
+    purchases: &mut [Purchase],
+) -> sqlx::Result<()> {
+    let pids = purchases.iter().map(|p| p.pid).collect::<Vec<_>>();
+    let discounts = sqlx::query!(
+        r#"SELECT
+            p.pid,
+            d.kind AS "kind: DiscountKind",
+            d.percent,
+            d.amount,
+            d.currency,
+            d.descr,
+            d.ends_at
+        FROM purchases p
+        JOIN discounts d ON d.pid = p.pid
+            OR d.tname IN (SELECT gt.tname FROM game_tag gt WHERE gt.gid = p.gid)
+            OR d.did IN (SELECT dg.did FROM developer_game dg WHERE dg.gid = p.gid)
+        WHERE p.pid = ANY($1) AND d.starts_at <= NOW() AND NOW() < d.ends_at
+        ORDER BY d.dcid"#,
+        &pids
+    )
+    .fetch_all(executor)
+    .await?;
+
+    for purchase in purchases {
+        for x in discounts.iter().filter(|x| x.pid == purchase.pid) {
+            let Some(value) = discount_value(x.kind, x.percent, x.amount, &x.currency)? else {
+                continue;
+            };
+            let discount = Discount {
+                value,
+                descr: x.descr.clone(),
+                ends_at: x.ends_at,
+            };
+            let Some(price) = discount.apply(purchase.price) else {
+                continue;
+            };
+            if purchase
+                .sale
+                .as_ref()
+                .is_none_or(|sale| price.minor() < sale.price.minor())
+            {
+                purchase.sale = Some(Sale {
+                    price,
+                    descr: discount.descr,
+                    ends_at: discount.ends_at,
+                    lowest_price: None,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+impl State {
+    /// Whether `uid` may create discounts of `scope`: store managers any, developers only those on
+    /// their own purchases or studios.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn manages_discount_scope(
+        &self,
+        uid: usize,
+        scope: &DiscountScope,
+    ) -> sqlx::Result<bool> {
+        match scope {
+            DiscountScope::Purchase(pid) => self.manages_purchase(uid, *pid).await,
+            DiscountScope::Developer(did) => self.manages_developer(uid, *did).await,
+            // a tag spans the games of every studio
+            DiscountScope::Tag(_) => self.has_permission(uid, Permission::ManageStore).await,
+        }
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn create_discount(
+        &self,
+        value: DiscountValue,
+        scope: DiscountScope,
+        descr: Option<String>,
+        starts_at: PrimitiveDateTime,
+        ends_at: PrimitiveDateTime,
+    ) -> sqlx::Result<()> {
+        let (kind, percent, amount, currency) = match value {
+            DiscountValue::Percentage(percent) => {
+                (DiscountKind::Percentage, Some(percent), None, None)
+            }
+            DiscountValue::Fixed(amount) => (
+                DiscountKind::Fixed,
+                None,
+                Some(amount.minor()),
+                Some(amount.currency().to_string()),
+            ),
+        };
+        let (pid, tname, did) = match scope {
+            DiscountScope::Purchase(pid) => (Some(usize_to_i32(pid)), None, None),
+            DiscountScope::Tag(tname) => (None, Some(tname), None),
+            DiscountScope::Developer(did) => (None, None, Some(usize_to_i32(did))),
+        };
+        sqlx::query!(
+            r"INSERT INTO discounts
+            (kind, percent, amount, currency, pid, tname, did, descr, starts_at, ends_at)
+            VALUES ($1, $2, $3, COALESCE($4, 'USD'), $5, $6, $7, $8, $9, $10)",
+            kind as DiscountKind,
+            percent,
+            amount,
+            currency,
+            pid,
+            tname,
+            did,
+            descr,
+            starts_at,
+            ends_at,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use sqlx::PgConnection;
+use zenki_util::usize_to_i32;
+
+use crate::{
+    Purchase, PurchaseType, State, inventory::stock_inventory, transaction::CheckoutError,
+};
+
+/// A DLC or in-game purchase of a game, with how many of it a user holds, or has left to use for
+/// in-game items.
+pub struct AddOn {
+    pub purchase: Purchase,
+    pub quantity: i64,
+}
+
+impl State {
+    /// Lists the DLC and in-game purchases of a game, with what `uid` holds of each.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_add_ons(&self, gid: usize, uid: usize) -> sqlx::Result<Vec<AddOn>> {
+        let purchases = self
+            .query_purchases(gid)
+            .await?
+            .into_iter()
+            .filter(|x| is_add_on(x.purchase_type))
+            .collect::<Vec<_>>();
+        let held = sqlx::query!(
+            r#"SELECT pid AS "pid!", SUM(quantity) AS "quantity!"
+            FROM entitlements
+            WHERE uid = $1 AND pid = ANY($2)
+                AND pid NOT IN (SELECT pid FROM purchases WHERE purchase_type = 'in_game_purchase')
+            GROUP BY pid
+            UNION ALL
+            SELECT pid, quantity::bigint
+            FROM inventory
+            WHERE uid = $1 AND pid = ANY($2)"#,
+            usize_to_i32(uid),
+            &purchases.iter().map(|x| x.pid).collect::<Vec<_>>(),
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(purchases
+            .into_iter()
+            .map(|purchase| AddOn {
+                quantity: held
+                    .iter()
+                    .find(|x| x.pid == purchase.pid)
+                    .map_or(0, |x| x.quantity),
+                purchase,
+            })
+            .collect())
+    }
+}
+
+/// Whether a purchase is held as an entitlement rather than a library entry.
+#[must_use]
+pub const fn is_add_on(purchase_type: PurchaseType) -> bool {
+    matches!(
+        purchase_type,
+        PurchaseType::Dlc | PurchaseType::InGamePurchase
+    )
+}
+
+/// Checks that `uid` may be given a DLC or in-game purchase `pid` of game `gid`: they must own
+/// the game, and may hold a DLC only once.
+///
+/// # Errors
+/// when the base game is not owned, the DLC already is, or querying the database failed
+pub async fn ensure_entitleable(
+    tx: &mut PgConnection,
+    uid: usize,
+    (pid, gid, purchase_type): (i32, i32, PurchaseType),
+) -> Result<(), CheckoutError> {
+    let x = sqlx::query!(
+        r#"SELECT
+            EXISTS (
+                SELECT 1 FROM game_user
+                WHERE uid = $1 AND gid = $2 AND wishlist IS NOT TRUE
+            ) AS "owns_game!",
+            EXISTS (SELECT 1 FROM entitlements WHERE uid = $1 AND pid = $3) AS "held!""#,
+        usize_to_i32(uid),
+        gid,
+        pid,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if !x.owns_game {
+        return Err(CheckoutError::RequiresBaseGame);
+    }
+    if purchase_type == PurchaseType::Dlc && x.held {
+        return Err(CheckoutError::AlreadyOwned);
+    }
+    Ok(())
+}
+
+/// Gives `uid` `quantity` of a DLC or in-game purchase, remembering the transaction `tid` that
+/// paid for it, if any. In-game items also go into their inventory.
+///
+/// # Errors
+/// when the base game is not owned, the DLC already is, or querying the database failed
+pub async fn grant_entitlement(
+    tx: &mut PgConnection,
+    uid: usize,
+    tid: Option<i32>,
+    purchase: (i32, i32, PurchaseType),
+    quantity: i32,
+) -> Result<(), CheckoutError> {
+    ensure_entitleable(tx, uid, purchase).await?;
+    sqlx::query!(
+        r"INSERT INTO entitlements (uid, pid, tid, quantity) VALUES ($1, $2, $3, $4)",
+        usize_to_i32(uid),
+        purchase.0,
+        tid,
+        quantity,
+    )
+    .execute(&mut *tx)
+    .await?;
+    if purchase.2 == PurchaseType::InGamePurchase {
+        stock_inventory(tx, uid, purchase.0, quantity).await?;
+    }
+    Ok(())
+}
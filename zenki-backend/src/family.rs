@@ -0,0 +1,391 @@
+use sqlx::PgConnection;
+use thiserror::Error;
+use time::PrimitiveDateTime;
+use zenki_util::usize_to_i32;
+
+use crate::{Game, GameRating, State};
+
+/// The most accounts an owner may lend their library to.
+pub const MAX_FAMILY_MEMBERS: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum FamilyError {
+    #[error("a family has at most {MAX_FAMILY_MEMBERS} members")]
+    Full,
+    #[error("only friends can be added to a family")]
+    NotFriends,
+    #[error("user already belongs to a family")]
+    AlreadyInFamily,
+    #[error("members of a family cannot lend their own library")]
+    BorrowerCannotLend,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct FamilyMember {
+    pub uid: i32,
+    pub uname: String,
+    pub added_at: PrimitiveDateTime,
+}
+
+/// The family of a user, either as the owner lending their library or as a member borrowing it.
+pub struct Family {
+    /// Whom the user borrows from, with when they joined.
+    pub owner: Option<FamilyMember>,
+    pub members: Vec<FamilyMember>,
+}
+
+/// A game of the family owner's library that a member may play without owning it.
+pub struct BorrowedGame {
+    pub game: Game,
+    pub owner_uid: i32,
+    pub owner_uname: String,
+    /// Whether the owner or another member is playing it, so it cannot be borrowed right now.
+    pub in_use: bool,
+}
+
+impl State {
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_family(&self, uid: usize) -> sqlx::Result<Family> {
+        let owner = sqlx::query_as!(
+            FamilyMember,
+            r"SELECT u.uid, u.uname, fm.added_at
+            FROM family_members fm
+            JOIN users u ON fm.owner_uid = u.uid
+            WHERE fm.member_uid = $1",
+            usize_to_i32(uid),
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        let members = sqlx::query_as!(
+            FamilyMember,
+            r"SELECT u.uid, u.uname, fm.added_at
+            FROM family_members fm
+            JOIN users u ON fm.member_uid = u.uid
+            WHERE fm.owner_uid = $1
+            ORDER BY fm.added_at, u.uid",
+            usize_to_i32(uid),
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(Family { owner, members })
+    }
+
+    /// Lets friend `member_uid` borrow the shared games of the library of `owner_uid`.
+    ///
+    /// # Errors
+    /// when the two are not friends both ways, the family is full, either already belongs to
+    /// another family, or querying the database failed
+    pub async fn add_family_member(
+        &self,
+        owner_uid: usize,
+        member_uid: usize,
+    ) -> Result<(), FamilyError> {
+        let (owner_uid, member_uid) = (usize_to_i32(owner_uid), usize_to_i32(member_uid));
+        let mut tx = self.db.begin().await?;
+        // families of the same owner grow one member at a time
+        sqlx::query!(
+            r"SELECT uid FROM users WHERE uid = $1 FOR UPDATE",
+            owner_uid
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let x = sqlx::query!(
+            r#"SELECT
+                -- both sides accepted, so the member agreed to the friendship themselves
+                EXISTS (
+                    SELECT 1 FROM friends WHERE uid = $1 AND fid = $2 AND pending = FALSE
+                ) AND EXISTS (
+                    SELECT 1 FROM friends WHERE uid = $2 AND fid = $1 AND pending = FALSE
+                ) AS "friends!",
+                EXISTS (SELECT 1 FROM family_members WHERE member_uid = $1) AS "owner_borrows!",
+                EXISTS (
+                    SELECT 1 FROM family_members WHERE member_uid = $2 OR owner_uid = $2
+                ) AS "member_in_family!",
+                (SELECT COUNT(*) FROM family_members WHERE owner_uid = $1) AS "members!""#,
+            owner_uid,
+            member_uid,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if !x.friends {
+            return Err(FamilyError::NotFriends);
+        }
+        if x.owner_borrows {
+            return Err(FamilyError::BorrowerCannotLend);
+        }
+        if x.member_in_family {
+            return Err(FamilyError::AlreadyInFamily);
+        }
+        if usize::try_from(x.members).unwrap_or(usize::MAX) >= MAX_FAMILY_MEMBERS {
+            return Err(FamilyError::Full);
+        }
+        sqlx::query!(
+            r"INSERT INTO family_members (member_uid, owner_uid) VALUES ($1, $2)",
+            member_uid,
+            owner_uid,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Removes `member_uid` from the family of `owner_uid`, ending the sessions of games they
+    /// borrowed.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn remove_family_member(
+        &self,
+        owner_uid: usize,
+        member_uid: usize,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let removed = sqlx::query!(
+            r"DELETE FROM family_members WHERE owner_uid = $1 AND member_uid = $2",
+            usize_to_i32(owner_uid),
+            usize_to_i32(member_uid),
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if removed > 0 {
+            end_borrowed_sessions(&mut tx, Some(usize_to_i32(member_uid)), None, None).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Leaves the family `uid` borrows from, ending the sessions of games they borrowed.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn leave_family(&self, uid: usize) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        sqlx::query!(
+            r"DELETE FROM family_members WHERE member_uid = $1",
+            usize_to_i32(uid),
+        )
+        .execute(&mut *tx)
+        .await?;
+        end_borrowed_sessions(&mut tx, Some(usize_to_i32(uid)), None, None).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Lends game `gid` of the library of `uid` to their family or stops doing so, ending the
+    /// session of whoever borrowed it then.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn set_game_shared(&self, uid: usize, gid: usize, shared: bool) -> sqlx::Result<()> {
+        let (uid, gid) = (usize_to_i32(uid), usize_to_i32(gid));
+        let mut tx = self.db.begin().await?;
+        sqlx::query!(
+            r"UPDATE game_user SET shared = $3 WHERE uid = $1 AND gid = $2",
+            uid,
+            gid,
+            shared,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if !shared {
+            end_borrowed_sessions(&mut tx, None, Some(uid), Some(gid)).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// The games `uid` may borrow from the owner of their family, leaving out those they own.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_borrowed_games(&self, uid: usize) -> sqlx::Result<Vec<BorrowedGame>> {
+        Ok(sqlx::query!(
+            r#"SELECT
+                g.gid,
+                g.gname,
+                g.descr,
+                g.rating AS "rating: GameRating",
+                g.release_at,
+                g.created_at,
+                o.uid AS owner_uid,
+                o.uname AS owner_uname,
+                EXISTS (
+                    SELECT 1 FROM game_interaction gi
+                    WHERE gi.gid = g.gid AND gi.duration IS NULL AND gi.uid <> $1
+                    AND (gi.uid = fm.owner_uid OR gi.lender_uid = fm.owner_uid)
+                ) AS "in_use!"
+            FROM family_members fm
+            JOIN users o ON fm.owner_uid = o.uid
+            JOIN game_user gu ON gu.uid = fm.owner_uid AND gu.wishlist IS NOT TRUE AND gu.shared
+            JOIN games g ON gu.gid = g.gid
+            WHERE fm.member_uid = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM game_user mine
+                WHERE mine.uid = $1 AND mine.gid = g.gid AND mine.wishlist IS NOT TRUE
+            )
+            ORDER BY g.gname, g.gid"#,
+            usize_to_i32(uid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| BorrowedGame {
+            game: Game {
+                gid: x.gid,
+                gname: x.gname,
+                descr: x.descr,
+                rating: x.rating,
+                release_at: x.release_at,
+                created_at: x.created_at,
+            },
+            owner_uid: x.owner_uid,
+            owner_uname: x.owner_uname,
+            in_use: x.in_use,
+        })
+        .collect())
+    }
+}
+
+/// The owner `uid` borrows game `gid` from, locking the owner's copy so that only one member
+/// starts playing it at a time, or none when the game is not shared with them.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn lock_lender(tx: &mut PgConnection, uid: i32, gid: i32) -> sqlx::Result<Option<i32>> {
+    Ok(sqlx::query!(
+        r"SELECT fm.owner_uid
+        FROM family_members fm
+        JOIN game_user gu ON gu.uid = fm.owner_uid
+        WHERE fm.member_uid = $1 AND gu.gid = $2 AND gu.wishlist IS NOT TRUE AND gu.shared
+        FOR UPDATE OF gu",
+        uid,
+        gid,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(|x| x.owner_uid))
+}
+
+/// Whether someone other than `uid` is playing the copy of game `gid` owned by `owner_uid`,
+/// either the owner or a member who borrowed it.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn lent_copy_in_use(
+    tx: &mut PgConnection,
+    uid: i32,
+    owner_uid: i32,
+    gid: i32,
+) -> sqlx::Result<bool> {
+    Ok(sqlx::query!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM game_interaction
+            WHERE gid = $3 AND duration IS NULL AND uid <> $1
+            AND (uid = $2 OR lender_uid = $2)
+        ) AS "in_use!""#,
+        uid,
+        owner_uid,
+        gid,
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .in_use)
+}
+
+/// Ends the running sessions of borrowed games, narrowed down to those of borrower `uid`, lent
+/// by `lender_uid` or of game `gid`.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn end_borrowed_sessions(
+    tx: &mut PgConnection,
+    uid: Option<i32>,
+    lender_uid: Option<i32>,
+    gid: Option<i32>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r"UPDATE game_interaction
+        SET duration = NOW() - startplay_at
+        WHERE duration IS NULL AND lender_uid IS NOT NULL
+        AND ($1::int IS NULL OR uid = $1)
+        AND ($2::int IS NULL OR lender_uid = $2)
+        AND ($3::int IS NULL OR gid = $3)",
+        uid,
+        lender_uid,
+        gid,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::FriendshipError;
+
+    async fn user(db: &PgPool, uname: &str) -> sqlx::Result<usize> {
+        let uid = sqlx::query!(
+            r"INSERT INTO users (uname, passwd) VALUES ($1, '') RETURNING uid",
+            uname
+        )
+        .fetch_one(db)
+        .await?
+        .uid;
+        Ok(zenki_util::i32_to_usize(uid))
+    }
+
+    #[sqlx::test(migrations = "../database/migrations")]
+    async fn rejects_a_friendship_accepted_by_the_owner_alone(db: PgPool) -> sqlx::Result<()> {
+        let state = State::for_tests(db.clone());
+        let (owner, member) = (user(&db, "owner").await?, user(&db, "member").await?);
+
+        // the member never sent a request, so the owner cannot accept one
+        assert!(matches!(
+            state.accept_friend_request(owner, member).await,
+            Err(FriendshipError::RequestNotFound)
+        ));
+
+        sqlx::query!(
+            r"INSERT INTO friends (uid, fid, pending) VALUES ($1, $2, FALSE)",
+            usize_to_i32(owner),
+            usize_to_i32(member),
+        )
+        .execute(&db)
+        .await?;
+        assert!(matches!(
+            state.add_family_member(owner, member).await,
+            Err(FamilyError::NotFriends)
+        ));
+        assert!(state.query_family(member).await?.owner.is_none());
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "../database/migrations")]
+    async fn adds_a_friend_who_accepted(db: PgPool) -> sqlx::Result<()> {
+        let state = State::for_tests(db.clone());
+        let (owner, member) = (user(&db, "owner").await?, user(&db, "member").await?);
+
+        state.send_friend_request(owner, member).await?;
+        assert!(matches!(
+            state.add_family_member(owner, member).await,
+            Err(FamilyError::NotFriends)
+        ));
+        assert!(state.accept_friend_request(member, owner).await.is_ok());
+        assert!(state.add_family_member(owner, member).await.is_ok());
+        assert!(
+            state
+                .query_family(member)
+                .await?
+                .owner
+                .is_some_and(|x| x.uid == usize_to_i32(owner))
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,15 @@
+use thiserror::Error;
 use zenki_util::usize_to_i32;
 
-use crate::{State, user::User};
+use crate::{PublicProfile, State};
+
+#[derive(Error, Debug)]
+pub enum FriendshipError {
+    #[error("friend request not found")]
+    RequestNotFound,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
 
 pub enum FriendshipStatus {
     NotFriends,
@@ -48,24 +57,38 @@ impl State {
         Ok(())
     }
 
+    /// Accepts the pending friend request `fid` sent to `uid`, making them friends both ways.
+    ///
     /// # Errors
-    /// when querying the database failed
-    pub async fn accept_friend_request(&self, uid: usize, fid: usize) -> sqlx::Result<()> {
-        sqlx::query!(
-            r"UPDATE friends SET pending = FALSE, added_at = NOW() WHERE uid = $1 AND fid = $2",
+    /// when `fid` sent no pending request to `uid` or querying the database failed
+    pub async fn accept_friend_request(
+        &self,
+        uid: usize,
+        fid: usize,
+    ) -> Result<(), FriendshipError> {
+        let mut tx = self.db.begin().await?;
+        let accepted = sqlx::query!(
+            r"UPDATE friends SET pending = FALSE, added_at = NOW()
+            WHERE uid = $1 AND fid = $2 AND pending = TRUE",
             usize_to_i32(fid),
             usize_to_i32(uid),
         )
-        .execute(&self.db)
-        .await?;
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if accepted != 1 {
+            return Err(FriendshipError::RequestNotFound);
+        }
 
         sqlx::query!(
-            r"INSERT INTO friends (uid, fid, pending) VALUES ($1, $2, FALSE);",
+            r"INSERT INTO friends (uid, fid, pending) VALUES ($1, $2, FALSE)
+            ON CONFLICT (uid, fid) DO UPDATE SET pending = FALSE, added_at = NOW()",
             usize_to_i32(uid),
             usize_to_i32(fid),
         )
-        .execute(&self.db)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -110,10 +133,13 @@ impl State {
 
     /// # Errors
     /// when querying the database failed
-    pub async fn query_friends(&self, uid: usize) -> sqlx::Result<Vec<User>> {
+    pub async fn query_friends(&self, uid: usize) -> sqlx::Result<Vec<PublicProfile>> {
         sqlx::query_as!(
-            User,
-            r"SELECT users.* FROM friends INNER JOIN users ON friends.fid = users.uid WHERE friends.uid = $1 AND NOT friends.pending",
+            PublicProfile,
+            r"SELECT u.uid, u.uname, u.bio, u.created_at, u.avatar
+            FROM friends f
+            JOIN users u ON f.fid = u.uid
+            WHERE f.uid = $1 AND NOT f.pending",
             usize_to_i32(uid)
         )
         .fetch_all(&self.db)
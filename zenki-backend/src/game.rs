@@ -1,11 +1,17 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
+use sqlx::PgExecutor;
+use thiserror::Error;
 use time::PrimitiveDateTime;
 use zenki_util::usize_to_i32;
 
 use crate::{Developer, State};
 
-#[derive(sqlx::Type, Debug)]
+#[derive(Error, Debug)]
+#[error("error while parsing game rating via string")]
+pub struct ParseGameRatingError;
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
 #[sqlx(type_name = "rating_n", rename_all = "snake_case")]
 pub enum GameRating {
     General,
@@ -23,6 +29,31 @@ impl Display for GameRating {
     }
 }
 
+impl GameRating {
+    /// The name of the rating in the database, as parsed by [`FromStr`].
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::General => "general",
+            Self::Mature => "mature",
+            Self::Sensitive => "sensitive",
+        }
+    }
+}
+
+impl FromStr for GameRating {
+    type Err = ParseGameRatingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "general" => Ok(Self::General),
+            "mature" => Ok(Self::Mature),
+            "sensitive" => Ok(Self::Sensitive),
+            _ => Err(ParseGameRatingError),
+        }
+    }
+}
+
 pub struct Game {
     pub gid: i32,
     pub gname: String,
@@ -32,6 +63,15 @@ pub struct Game {
     pub created_at: Option<PrimitiveDateTime>,
 }
 
+/// A game in a library.
+pub struct LibraryGame {
+    pub game: Game,
+    /// When the game comes out, while it is pre-ordered and not released yet.
+    pub available_at: Option<PrimitiveDateTime>,
+    /// Whether the members of the owner's family may borrow it.
+    pub shared: bool,
+}
+
 pub struct GameRef {
     pub gid: i32,
     pub gname: String,
@@ -85,14 +125,7 @@ impl State {
     /// # Errors
     /// when querying the database failed
     pub async fn add_game_to_library(&self, uid: usize, gid: usize) -> sqlx::Result<()> {
-        sqlx::query!(
-            r#"INSERT INTO game_user (uid, gid, wishlist) VALUES ($1, $2, FALSE);"#,
-            usize_to_i32(uid),
-            usize_to_i32(gid)
-        )
-        .execute(&self.db)
-        .await?;
-        Ok(())
+        add_to_library(&self.db, uid, gid).await
     }
 
     /// # Errors
@@ -125,17 +158,31 @@ impl State {
 
     /// # Errors
     /// when querying the database failed
-    pub async fn query_library(&self, id: usize) -> sqlx::Result<Vec<Game>> {
-        sqlx::query_as!(
-            Game,
-            r#"SELECT g.gid, gname, descr, rating AS "rating: _", release_at, created_at
+    pub async fn query_library(&self, id: usize) -> sqlx::Result<Vec<LibraryGame>> {
+        Ok(sqlx::query!(
+            r#"SELECT g.gid, gname, descr, rating AS "rating: GameRating", release_at, created_at,
+                COALESCE(release_at > NOW(), FALSE) AS "unreleased!", gu.shared
             FROM games g
             JOIN game_user gu ON g.gid = gu.gid
             WHERE gu.uid = $1 AND gu.wishlist = FALSE;"#,
             usize_to_i32(id)
         )
         .fetch_all(&self.db)
-        .await
+        .await?
+        .into_iter()
+        .map(|x| LibraryGame {
+            available_at: x.release_at.filter(|_| x.unreleased),
+            shared: x.shared,
+            game: Game {
+                gid: x.gid,
+                gname: x.gname,
+                descr: x.descr,
+                rating: x.rating,
+                release_at: x.release_at,
+                created_at: x.created_at,
+            },
+        })
+        .collect())
     }
 
     /// # Errors
@@ -201,3 +248,45 @@ impl State {
         .await
     }
 }
+
+/// Moves a game into the library of `uid`, taking it off their wishlist.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn add_to_library<'e>(
+    executor: impl PgExecutor<'e>,
+    uid: usize,
+    gid: usize,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r"INSERT INTO game_user (uid, gid, wishlist) VALUES ($1, $2, FALSE)
+        ON CONFLICT (gid, uid) DO UPDATE SET wishlist = FALSE",
+        usize_to_i32(uid),
+        usize_to_i32(gid)
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Adds game `gid` to the library of `uid` unless they own it already, returning whether it was
+/// added. Claiming the row in one statement keeps concurrent orders from both granting it.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn claim_game<'e>(
+    executor: impl PgExecutor<'e>,
+    uid: usize,
+    gid: usize,
+) -> sqlx::Result<bool> {
+    Ok(sqlx::query!(
+        r"INSERT INTO game_user (uid, gid, wishlist) VALUES ($1, $2, FALSE)
+        ON CONFLICT (gid, uid) DO UPDATE SET wishlist = FALSE WHERE game_user.wishlist
+        RETURNING gid",
+        usize_to_i32(uid),
+        usize_to_i32(gid)
+    )
+    .fetch_optional(executor)
+    .await?
+    .is_some())
+}
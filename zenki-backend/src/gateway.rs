@@ -0,0 +1,171 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+use zenki_util::Money;
+
+use crate::transaction::PaymentMethod;
+
+#[derive(Error, Debug)]
+pub enum GatewayError {
+    #[error("payment was declined")]
+    Declined,
+    #[error("payment provider did not answer in time")]
+    Timeout,
+    #[error("webhook could not be verified")]
+    InvalidWebhook,
+}
+
+/// A payment the gateway is asked to authorize.
+pub struct PaymentRequest {
+    /// Our reference of the order, echoed back by webhooks about it.
+    pub reference: String,
+    pub uid: usize,
+    pub payment_method: PaymentMethod,
+    pub amount: Money,
+}
+
+/// What a payment provider tells us about a payment after the fact.
+pub enum WebhookEvent {
+    /// An authorization that timed out went through after all.
+    Authorized {
+        reference: String,
+        payment_ref: String,
+    },
+    /// An authorization that timed out was declined after all.
+    Declined { reference: String },
+}
+
+/// A payment provider that card and `PayPal` payments are made through.
+#[async_trait]
+pub trait PaymentGateway: Send + Sync {
+    /// Reserves the amount of a payment, returning the provider's reference to it.
+    ///
+    /// # Errors
+    /// when the payment was declined or the provider could not be reached
+    async fn authorize(&self, request: &PaymentRequest) -> Result<String, GatewayError>;
+
+    /// Collects `amount` of an authorized payment.
+    ///
+    /// # Errors
+    /// when the capture was declined or the provider could not be reached
+    async fn capture(&self, payment_ref: &str, amount: Money) -> Result<(), GatewayError>;
+
+    /// Releases an authorized payment that will never be captured.
+    ///
+    /// # Errors
+    /// when the provider refused to release it or could not be reached
+    async fn void(&self, payment_ref: &str) -> Result<(), GatewayError>;
+
+    /// Pays `amount` of a captured payment back.
+    ///
+    /// # Errors
+    /// when the refund was declined or the provider could not be reached
+    async fn refund(&self, payment_ref: &str, amount: Money) -> Result<(), GatewayError>;
+
+    /// Verifies and reads a webhook call of the provider.
+    ///
+    /// # Errors
+    /// when the signature does not match or the payload cannot be read
+    fn parse_webhook(&self, payload: &str, signature: &str) -> Result<WebhookEvent, GatewayError>;
+}
+
+#[derive(Error, Debug)]
+#[error("error while parsing mock outcome via string")]
+pub struct ParseMockOutcomeError;
+
+/// How every call to a [`MockGateway`] ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockOutcome {
+    Succeed,
+    Decline,
+    Timeout,
+}
+
+impl FromStr for MockOutcome {
+    type Err = ParseMockOutcomeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "succeed" => Ok(Self::Succeed),
+            "decline" => Ok(Self::Decline),
+            "timeout" => Ok(Self::Timeout),
+            _ => Err(ParseMockOutcomeError),
+        }
+    }
+}
+
+/// A local stand-in for a payment provider that never moves money.
+///
+/// Payment references are derived from the order reference, so the same order always gets the
+/// same reference. Webhooks are `<reference> authorized` or `<reference> declined`, signed with
+/// the hex encoded HMAC-SHA256 of the payload under the webhook secret.
+pub struct MockGateway {
+    outcome: MockOutcome,
+    webhook_mac: Option<Hmac<Sha256>>,
+}
+
+impl MockGateway {
+    /// A mock ending every call with `outcome`, accepting webhooks signed with `webhook_secret`,
+    /// or none without one.
+    #[must_use]
+    pub fn new(outcome: MockOutcome, webhook_secret: Option<&str>) -> Self {
+        Self {
+            outcome,
+            webhook_mac: webhook_secret
+                .and_then(|secret| Hmac::new_from_slice(secret.as_bytes()).ok()),
+        }
+    }
+
+    const fn result(&self) -> Result<(), GatewayError> {
+        match self.outcome {
+            MockOutcome::Succeed => Ok(()),
+            MockOutcome::Decline => Err(GatewayError::Declined),
+            MockOutcome::Timeout => Err(GatewayError::Timeout),
+        }
+    }
+}
+
+#[async_trait]
+impl PaymentGateway for MockGateway {
+    async fn authorize(&self, request: &PaymentRequest) -> Result<String, GatewayError> {
+        self.result()?;
+        Ok(format!("mock_{}", request.reference))
+    }
+
+    async fn capture(&self, _payment_ref: &str, _amount: Money) -> Result<(), GatewayError> {
+        self.result()
+    }
+
+    async fn void(&self, _payment_ref: &str) -> Result<(), GatewayError> {
+        self.result()
+    }
+
+    async fn refund(&self, _payment_ref: &str, _amount: Money) -> Result<(), GatewayError> {
+        self.result()
+    }
+
+    fn parse_webhook(&self, payload: &str, signature: &str) -> Result<WebhookEvent, GatewayError> {
+        let mut mac = self
+            .webhook_mac
+            .clone()
+            .ok_or(GatewayError::InvalidWebhook)?;
+        mac.update(payload.as_bytes());
+        let signature = hex::decode(signature).map_err(|_| GatewayError::InvalidWebhook)?;
+        mac.verify_slice(&signature)
+            .map_err(|_| GatewayError::InvalidWebhook)?;
+
+        match payload.trim().split_once(' ') {
+            Some((reference, "authorized")) => Ok(WebhookEvent::Authorized {
+                reference: reference.to_string(),
+                payment_ref: format!("mock_{reference}"),
+            }),
+            Some((reference, "declined")) => Ok(WebhookEvent::Declined {
+                reference: reference.to_string(),
+            }),
+            _ => Err(GatewayError::InvalidWebhook),
+        }
+    }
+}
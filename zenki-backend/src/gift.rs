@@ -0,0 +1,273 @@
+use std::fmt::Display;
+
+use sqlx::PgConnection;
+use thiserror::Error;
+use time::PrimitiveDateTime;
+use zenki_util::usize_to_i32;
+
+use crate::{
+    AgeError, CheckoutError, PurchaseType, RefundError, State, TransactionStatus,
+    age::purchase_age_check, notification::notify, refund::refund_transaction,
+    transaction::grant_purchase,
+};
+
+#[derive(Error, Debug)]
+pub enum GiftError {
+    #[error("gift not found")]
+    GiftNotFound,
+    #[error("gift has already been {0}")]
+    AlreadyResponded(GiftStatus),
+    #[error("gifted transaction is {0}")]
+    InvalidStatus(TransactionStatus),
+    #[error(transparent)]
+    Age(#[from] AgeError),
+    #[error(transparent)]
+    Checkout(#[from] CheckoutError),
+    #[error(transparent)]
+    Refund(#[from] RefundError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "gift_status_n", rename_all = "snake_case")]
+pub enum GiftStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
+
+impl Display for GiftStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Pending => "pending",
+            Self::Accepted => "accepted",
+            Self::Declined => "declined",
+        })
+    }
+}
+
+pub struct Gift {
+    pub tid: i32,
+    pub sender_uid: i32,
+    pub s_uname: String,
+    pub receiver_uid: i32,
+    pub r_uname: String,
+    pub pid: i32,
+    pub p_descr: Option<String>,
+    pub gid: i32,
+    pub gname: String,
+    pub message: Option<String>,
+    pub status: GiftStatus,
+    pub created_at: Option<PrimitiveDateTime>,
+    pub responded_at: Option<PrimitiveDateTime>,
+}
+
+impl State {
+    /// Lists the gifts `uid` has sent or received, newest first.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_gifts(&self, uid: usize) -> sqlx::Result<Vec<Gift>> {
+        sqlx::query_as!(
+            Gift,
+            r#"SELECT
+                gf.tid,
+                gf.sender_uid,
+                sender.uname AS s_uname,
+                gf.receiver_uid,
+                receiver.uname AS r_uname,
+                p.pid,
+                p.descr AS p_descr,
+                g.gid,
+                g.gname,
+                gf.message,
+                gf.status AS "status: _",
+                gf.created_at,
+                gf.responded_at
+            FROM gifts gf
+            JOIN users sender ON gf.sender_uid = sender.uid
+            JOIN users receiver ON gf.receiver_uid = receiver.uid
+            JOIN transactions t ON gf.tid = t.tid
+            JOIN purchases p ON t.pid = p.pid
+            JOIN games g ON p.gid = g.gid
+            WHERE gf.sender_uid = $1 OR gf.receiver_uid = $1
+            ORDER BY gf.created_at DESC, gf.tid DESC"#,
+            usize_to_i32(uid)
+        )
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// Accepts a pending gift sent to `uid`, adding what was gifted to their library.
+    ///
+    /// # Errors
+    /// when the gift is not a pending gift to `uid`, the purchase was refunded, `uid` is not old
+    /// enough for the game or has yet to confirm their age, the game is already owned, or
+    /// querying the database failed
+    pub async fn accept_gift(&self, uid: usize, tid: usize) -> Result<(), GiftError> {
+        let mut tx = self.db.begin().await?;
+        let gift = pending_gift(&mut tx, uid, tid).await?;
+        purchase_age_check(&mut tx, Some(uid), gift.pid, gift.gid)
+            .await?
+            .ensure()?;
+
+        grant_purchase(
+            &mut tx,
+            uid,
+            gift.tid,
+            (gift.pid, gift.gid, gift.purchase_type),
+        )
+        .await?;
+        respond(&mut tx, gift.tid, GiftStatus::Accepted).await?;
+        notify(
+            &mut tx,
+            gift.sender_uid,
+            &format!("{} accepted your gift of {}.", gift.r_uname, gift.gname),
+            Some(gift.tid),
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Declines a pending gift sent to `uid`, refunding the sender.
+    ///
+    /// # Errors
+    /// when the gift is not a pending gift to `uid`, the purchase was refunded, or querying the
+    /// database failed
+    pub async fn decline_gift(&self, uid: usize, tid: usize) -> Result<(), GiftError> {
+        let mut tx = self.db.begin().await?;
+        let gift = pending_gift(&mut tx, uid, tid).await?;
+
+        let refunded = refund_transaction(&mut tx, gift.tid, TransactionStatus::Completed)
+            .await?
+            .ok_or(GiftError::GiftNotFound)?;
+        respond(&mut tx, gift.tid, GiftStatus::Declined).await?;
+        notify(
+            &mut tx,
+            gift.sender_uid,
+            &format!(
+                "{} declined your gift of {}; it has been refunded.",
+                gift.r_uname, gift.gname
+            ),
+            Some(gift.tid),
+        )
+        .await?;
+        tx.commit().await?;
+
+        self.refund_payment(&refunded).await?;
+        Ok(())
+    }
+}
+
+/// Puts transaction `tid` into the gift inbox of `ruid` rather than their library.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn send_gift(
+    tx: &mut PgConnection,
+    tid: i32,
+    uid: usize,
+    ruid: usize,
+    message: Option<&str>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r"INSERT INTO gifts (tid, sender_uid, receiver_uid, message) VALUES ($1, $2, $3, $4)",
+        tid,
+        usize_to_i32(uid),
+        usize_to_i32(ruid),
+        message.map(str::trim).filter(|x| !x.is_empty()),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let x = sqlx::query!(
+        r"SELECT u.uname, g.gname
+        FROM transactions t
+        JOIN users u ON t.uid = u.uid
+        JOIN purchases p ON t.pid = p.pid
+        JOIN games g ON p.gid = g.gid
+        WHERE t.tid = $1",
+        tid,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    notify(
+        tx,
+        usize_to_i32(ruid),
+        &format!("{} sent you {} as a gift.", x.uname, x.gname),
+        Some(tid),
+    )
+    .await
+}
+
+struct PendingGift {
+    tid: i32,
+    pid: i32,
+    sender_uid: i32,
+    r_uname: String,
+    gid: i32,
+    gname: String,
+    purchase_type: PurchaseType,
+}
+
+/// Locks a pending gift to `uid` whose purchase still stands.
+async fn pending_gift(
+    tx: &mut PgConnection,
+    uid: usize,
+    tid: usize,
+) -> Result<PendingGift, GiftError> {
+    let x = sqlx::query!(
+        r#"SELECT
+            gf.tid,
+            gf.sender_uid,
+            gf.status AS "gift_status: GiftStatus",
+            receiver.uname AS r_uname,
+            p.pid,
+            g.gid,
+            g.gname,
+            p.purchase_type AS "purchase_type: PurchaseType",
+            t.status AS "status: TransactionStatus"
+        FROM gifts gf
+        JOIN users receiver ON gf.receiver_uid = receiver.uid
+        JOIN transactions t ON gf.tid = t.tid
+        JOIN purchases p ON t.pid = p.pid
+        JOIN games g ON p.gid = g.gid
+        WHERE gf.tid = $1 AND gf.receiver_uid = $2
+        FOR UPDATE OF gf, t"#,
+        usize_to_i32(tid),
+        usize_to_i32(uid),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(GiftError::GiftNotFound)?;
+
+    if x.gift_status != GiftStatus::Pending {
+        return Err(GiftError::AlreadyResponded(x.gift_status));
+    }
+    if x.status != TransactionStatus::Completed {
+        return Err(GiftError::InvalidStatus(x.status));
+    }
+    Ok(PendingGift {
+        tid: x.tid,
+        pid: x.pid,
+        sender_uid: x.sender_uid,
+        r_uname: x.r_uname,
+        gid: x.gid,
+        gname: x.gname,
+        purchase_type: x.purchase_type,
+    })
+}
+
+async fn respond(tx: &mut PgConnection, tid: i32, status: GiftStatus) -> sqlx::Result<()> {
+    sqlx::query!(
+        r"UPDATE gifts SET status = $2, responded_at = NOW() WHERE tid = $1",
+        tid,
+        status as GiftStatus,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
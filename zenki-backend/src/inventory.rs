@@ -0,0 +1,122 @@
+use sqlx::PgConnection;
+use thiserror::Error;
+use time::PrimitiveDateTime;
+use zenki_util::usize_to_i32;
+
+use crate::State;
+
+#[derive(Error, Debug)]
+pub enum InventoryError {
+    #[error("quantity must be at least one")]
+    InvalidQuantity,
+    #[error("not enough items left")]
+    NotEnoughItems,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct InventoryItem {
+    pub pid: i32,
+    pub descr: Option<String>,
+    pub gid: i32,
+    pub gname: String,
+    pub quantity: i32,
+    pub updated_at: Option<PrimitiveDateTime>,
+    /// Whether it may be sold to other players on the marketplace.
+    pub tradeable: bool,
+}
+
+impl State {
+    /// Lists the in-game items `uid` has left, of one game or of all of them.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_inventory(
+        &self,
+        uid: usize,
+        gid: Option<usize>,
+    ) -> sqlx::Result<Vec<InventoryItem>> {
+        sqlx::query_as!(
+            InventoryItem,
+            r"SELECT p.pid, p.descr, g.gid, g.gname, i.quantity, i.updated_at, p.tradeable
+            FROM inventory i
+            JOIN purchases p ON i.pid = p.pid
+            JOIN games g ON p.gid = g.gid
+            WHERE i.uid = $1 AND i.quantity > 0 AND ($2::int IS NULL OR g.gid = $2)
+            ORDER BY g.gname, p.pid",
+            usize_to_i32(uid),
+            gid.map(usize_to_i32),
+        )
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// Uses up `quantity` of an in-game item of `uid`, returning how many are left.
+    ///
+    /// # Errors
+    /// when the quantity is zero, fewer items are left, or querying the database failed
+    pub async fn consume_item(
+        &self,
+        uid: usize,
+        pid: usize,
+        quantity: usize,
+    ) -> Result<i32, InventoryError> {
+        let quantity = i32::try_from(quantity).map_err(|_| InventoryError::InvalidQuantity)?;
+        if quantity == 0 {
+            return Err(InventoryError::InvalidQuantity);
+        }
+        let mut tx = self.db.begin().await?;
+        let left = take_from_inventory(&mut tx, uid, usize_to_i32(pid), quantity)
+            .await?
+            .ok_or(InventoryError::NotEnoughItems)?;
+        tx.commit().await?;
+        Ok(left)
+    }
+}
+
+/// Adds `quantity` of in-game item `pid` to the inventory of `uid`.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn stock_inventory(
+    tx: &mut PgConnection,
+    uid: usize,
+    pid: i32,
+    quantity: i32,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r"INSERT INTO inventory (uid, pid, quantity) VALUES ($1, $2, $3)
+        ON CONFLICT (uid, pid) DO UPDATE
+        SET quantity = inventory.quantity + EXCLUDED.quantity, updated_at = NOW()",
+        usize_to_i32(uid),
+        pid,
+        quantity,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Takes `quantity` of in-game item `pid` out of the inventory of `uid`, returning how many are
+/// left, or `None` when there were not that many.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn take_from_inventory(
+    tx: &mut PgConnection,
+    uid: usize,
+    pid: i32,
+    quantity: i32,
+) -> sqlx::Result<Option<i32>> {
+    Ok(sqlx::query!(
+        r"UPDATE inventory SET quantity = quantity - $3, updated_at = NOW()
+        WHERE uid = $1 AND pid = $2 AND quantity >= $3
+        RETURNING quantity",
+        usize_to_i32(uid),
+        pid,
+        quantity,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(|x| x.quantity))
+}
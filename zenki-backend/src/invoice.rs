@@ -0,0 +1,302 @@
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+use sqlx::PgConnection;
+use time::PrimitiveDateTime;
+use zenki_util::{Money, usize_to_i32};
+
+use crate::{
+    PurchaseType, State,
+    purchase::decode_money,
+    tax::TaxRate,
+    transaction::{PaymentMethod, TransactionStatus},
+};
+
+pub struct InvoiceLine {
+    pub pid: i32,
+    pub descr: Option<String>,
+    pub purchase_type: PurchaseType,
+    pub unit_price: Money,
+    pub quantity: i32,
+    pub line_total: Money,
+    pub discount: Money,
+}
+
+/// The invoice issued for a transaction, numbered without gaps in the order of sale.
+pub struct Invoice {
+    pub number: i32,
+    pub issued_at: PrimitiveDateTime,
+    pub tid: i32,
+    pub oid: Option<i32>,
+    pub uid: i32,
+    pub s_uname: String,
+    pub receiver_uid: Option<i32>,
+    pub r_uname: Option<String>,
+    pub payment_method: PaymentMethod,
+    pub status: TransactionStatus,
+    pub lines: Vec<InvoiceLine>,
+    pub subtotal: Money,
+    pub discount: Money,
+    pub tax: Money,
+    pub tax_rate: Option<TaxRate>,
+    pub total: Money,
+    pub coupon_code: Option<String>,
+}
+
+impl State {
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_invoice(&self, tid: usize) -> sqlx::Result<Option<Invoice>> {
+        let Some(x) = sqlx::query!(
+            r#"SELECT
+                i.number,
+                i.issued_at,
+                t.tid,
+                t.oid,
+                t.uid,
+                sender.uname AS "s_uname",
+                t.receiver_uid,
+                receiver.uname AS "r_uname?",
+                t.payment_method AS "payment_method: PaymentMethod",
+                t.status AS "status: TransactionStatus",
+                t.pid,
+                p.descr,
+                p.purchase_type AS "purchase_type: PurchaseType",
+                t.quantity,
+                t.amount,
+                t.discount,
+                t.currency,
+                t.tax,
+                t.tax_country,
+                t.tax_rate_bp,
+                t.tax_inclusive,
+                o.coupon_code AS "coupon_code?"
+            FROM invoices i
+            JOIN transactions t ON i.tid = t.tid
+            JOIN purchases p ON t.pid = p.pid
+            JOIN users sender ON t.uid = sender.uid
+            LEFT JOIN users receiver ON t.receiver_uid = receiver.uid
+            LEFT JOIN orders o ON t.oid = o.oid
+            WHERE i.tid = $1"#,
+            usize_to_i32(tid),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let total = decode_money(x.amount, &x.currency)?;
+        let discount = Money::new(x.discount, total.currency());
+        let tax = Money::new(x.tax, total.currency());
+        // tax added on top of the price is not part of the line, only of the total
+        let added_tax = if x.tax_inclusive {
+            Money::zero(total.currency())
+        } else {
+            tax
+        };
+        let line_total = total
+            .checked_add(discount)
+            .and_then(|x| x.checked_sub(added_tax))
+            .ok_or_else(|| sqlx::Error::Decode("invoice line total out of range".into()))?;
+        Ok(Some(Invoice {
+            number: x.number,
+            issued_at: x.issued_at,
+            tid: x.tid,
+            oid: x.oid,
+            uid: x.uid,
+            s_uname: x.s_uname,
+            receiver_uid: x.receiver_uid,
+            r_uname: x.r_uname,
+            payment_method: x.payment_method,
+            status: x.status,
+            lines: vec![InvoiceLine {
+                pid: x.pid,
+                descr: x.descr,
+                purchase_type: x.purchase_type,
+                unit_price: Money::new(
+                    line_total.minor() / i64::from(x.quantity),
+                    total.currency(),
+                ),
+                quantity: x.quantity,
+                line_total,
+                discount,
+            }],
+            subtotal: line_total,
+            discount,
+            tax,
+            tax_rate: x.tax_country.map(|country| TaxRate {
+                country,
+                purchase_type: x.purchase_type,
+                rate_bp: x.tax_rate_bp,
+                inclusive: x.tax_inclusive,
+            }),
+            total,
+            coupon_code: x.coupon_code.filter(|_| !discount.is_zero()),
+        }))
+    }
+}
+
+impl Invoice {
+    /// The number as printed, e.g. `INV-000042`.
+    #[must_use]
+    pub fn display_number(&self) -> String {
+        format!("INV-{:06}", self.number)
+    }
+
+    /// Renders the invoice as a single page A4 PDF document.
+    #[must_use]
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let catalog_id = Ref::new(1);
+        let page_tree_id = Ref::new(2);
+        let page_id = Ref::new(3);
+        let font_id = Ref::new(4);
+        let bold_id = Ref::new(5);
+        let content_id = Ref::new(6);
+
+        let mut pdf = Pdf::new();
+        pdf.catalog(catalog_id).pages(page_tree_id);
+        pdf.pages(page_tree_id).kids([page_id]).count(1);
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, 595.0, 842.0));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        page.resources()
+            .fonts()
+            .pair(Name(b"F1"), font_id)
+            .pair(Name(b"F2"), bold_id);
+        page.finish();
+        pdf.type1_font(font_id)
+            .base_font(Name(b"Helvetica"))
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+        pdf.type1_font(bold_id)
+            .base_font(Name(b"Helvetica-Bold"))
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+        pdf.stream(content_id, &self.pdf_content()).finish();
+        pdf.finish()
+    }
+
+    fn pdf_content(&self) -> Vec<u8> {
+        const COLUMNS: [f32; 5] = [50.0, 250.0, 340.0, 400.0, 480.0];
+
+        let mut content = Content::new();
+        let mut y = 780.0;
+        pdf_text(&mut content, true, 20.0, 50.0, y, "Invoice");
+        pdf_text(&mut content, true, 12.0, 400.0, y, &self.display_number());
+        y -= 36.0;
+
+        let receiver = self.r_uname.clone().unwrap_or_else(|| self.s_uname.clone());
+        let details = [
+            ("Issued", self.issued_at.to_string()),
+            ("Transaction", format!("#{}", self.tid)),
+            (
+                "Order",
+                self.oid
+                    .map_or_else(|| "-".to_string(), |x| format!("#{x}")),
+            ),
+            ("Buyer", self.s_uname.clone()),
+            ("Receiver", receiver),
+            ("Payment Method", self.payment_method.to_string()),
+            ("Status", self.status.to_string()),
+        ];
+        for (label, value) in details {
+            pdf_text(&mut content, true, 10.0, 50.0, y, label);
+            pdf_text(&mut content, false, 10.0, 150.0, y, &value);
+            y -= 16.0;
+        }
+        y -= 20.0;
+
+        let header = ["Item", "Type", "Quantity", "Unit Price", "Line Total"];
+        for (x, label) in COLUMNS.into_iter().zip(header) {
+            pdf_text(&mut content, true, 10.0, x, y, label);
+        }
+        y -= 6.0;
+        content
+            .set_line_width(0.5)
+            .move_to(50.0, y)
+            .line_to(545.0, y)
+            .stroke();
+        y -= 14.0;
+        for line in &self.lines {
+            let descr = line
+                .descr
+                .clone()
+                .unwrap_or_else(|| format!("#{}", line.pid));
+            let cells = [
+                descr.chars().take(36).collect(),
+                line.purchase_type.to_string(),
+                line.quantity.to_string(),
+                line.unit_price.to_string(),
+                line.line_total.to_string(),
+            ];
+            for (x, cell) in COLUMNS.into_iter().zip(cells) {
+                pdf_text(&mut content, false, 10.0, x, y, &cell);
+            }
+            y -= 16.0;
+        }
+        y -= 10.0;
+
+        let mut totals = vec![("Subtotal".to_string(), self.subtotal.to_string())];
+        if !self.discount.is_zero() {
+            let label = self
+                .coupon_code
+                .as_ref()
+                .map_or_else(|| "Discount".to_string(), |code| format!("Coupon {code}"));
+            totals.push((label, format!("-{}", self.discount)));
+        }
+        let tax_label = self
+            .tax_rate
+            .as_ref()
+            .map_or_else(|| "Tax".to_string(), |rate| format!("Tax {rate}"));
+        totals.push((tax_label, self.tax.to_string()));
+        totals.push(("Total".to_string(), self.total.to_string()));
+        for (label, value) in totals {
+            let bold = label == "Total";
+            pdf_text(&mut content, bold, 10.0, COLUMNS[2] - 40.0, y, &label);
+            pdf_text(&mut content, bold, 10.0, COLUMNS[4], y, &value);
+            y -= 16.0;
+        }
+        content.finish()
+    }
+}
+
+/// Writes `text` at `(x, y)` in Helvetica, replacing what `WinAnsiEncoding` cannot show.
+fn pdf_text(content: &mut Content, bold: bool, size: f32, x: f32, y: f32, text: &str) {
+    let bytes = text
+        .chars()
+        .map(|c| {
+            u8::try_from(c)
+                .ok()
+                .filter(|b| (0x20..0x7f).contains(b) || *b >= 0xa0)
+        })
+        .map(|b| b.unwrap_or(b'?'))
+        .collect::<Vec<_>>();
+    content
+        .begin_text()
+        .set_font(Name(if bold { b"F2" } else { b"F1" }), size)
+        .set_text_matrix([1.0, 0.0, 0.0, 1.0, x, y])
+        .show(Str(&bytes))
+        .end_text();
+}
+
+/// Issues the next invoice number to transaction `tid`.
+///
+/// The counter row stays locked until the caller's database transaction ends, so checkouts
+/// take their numbers one after another and a rolled back checkout leaves no gap.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn issue_invoice(tx: &mut PgConnection, tid: i32) -> sqlx::Result<i32> {
+    let number = sqlx::query!(
+        r"UPDATE invoice_counter SET last_number = last_number + 1 RETURNING last_number"
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .last_number;
+    sqlx::query!(
+        r"INSERT INTO invoices (number, tid) VALUES ($1, $2)",
+        number,
+        tid,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(number)
+}
@@ -1,17 +1,39 @@
 mod activity;
+mod age;
 mod auth;
+mod bundle;
+mod cart;
+mod coupon;
 mod developer;
+mod discount;
+mod entitlement;
+mod family;
 mod friendship;
 mod game;
+mod gateway;
+mod gift;
+mod inventory;
+mod invoice;
+mod market;
+mod notification;
+mod price_history;
+mod product_key;
 mod purchase;
+mod refund;
 mod review;
+mod role;
+mod session;
+mod subscription;
 mod tag;
+mod tax;
 mod transaction;
 mod user;
+mod wallet;
 
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use argon2::Argon2;
+use hmac::Mac;
 use log::LevelFilter;
 use sqlx::{
     ConnectOptions, PgPool,
@@ -19,36 +41,103 @@ use sqlx::{
 };
 
 pub use {
-    activity::{GameActivity, pg_interval_to_time_duration},
+    activity::{GameActivity, PlayError, pg_interval_to_time_duration},
+    age::{AgeCheck, AgeError, RatingAge},
+    bundle::{BundleError, BundleItem},
+    cart::CartItem,
+    coupon::{CouponLimits, CouponScope},
     developer::Developer,
-    friendship::FriendshipStatus,
-    game::{Game, GameRef, WishlistStatus},
-    purchase::{Purchase, PurchaseType},
+    discount::{Discount, DiscountScope, DiscountValue, Sale},
+    entitlement::AddOn,
+    family::{BorrowedGame, Family, FamilyError, FamilyMember, MAX_FAMILY_MEMBERS},
+    friendship::{FriendshipError, FriendshipStatus},
+    game::{Game, GameRating, GameRef, LibraryGame, ParseGameRatingError, WishlistStatus},
+    gateway::{
+        GatewayError, MockGateway, MockOutcome, PaymentGateway, PaymentRequest, WebhookEvent,
+    },
+    gift::{Gift, GiftError, GiftStatus},
+    inventory::{InventoryError, InventoryItem},
+    invoice::{Invoice, InvoiceLine},
+    market::{
+        BuyOrder, MARKET_FEE_BP, Market, MarketError, MarketListing, MarketOrderStatus,
+        MarketTrade, market_fee,
+    },
+    notification::Notification,
+    price_history::PricePoint,
+    product_key::{KeyBatch, KeyError, MAX_KEYS_PER_BATCH, ProductKey, RedeemedKey},
+    purchase::{ParsePurchaseTypeError, Purchase, PurchaseType},
+    refund::{REFUND_MAX_PLAYTIME, REFUND_WINDOW, RefundError, RefundRequest},
     review::Review,
+    role::{Permission, Role},
+    session::SESSION_TTL,
+    subscription::{
+        BillingPeriod, RENEWAL_INTERVAL, RenewalReport, Subscription, SubscriptionError,
+        SubscriptionPlan, SubscriptionStatus,
+    },
     tag::Tag,
-    transaction::{RichTransaction, TransactionHistory},
-    user::{User, parse_html_date},
+    tax::{TaxError, TaxRate, format_tax_rate, parse_tax_rate},
+    transaction::{
+        CheckoutError, PricedLine, PricedOrder, Receipt, ReceiptLine, RichTransaction,
+        TransactionHistory, TransactionStatus,
+    },
+    user::{PrivateAccount, PublicProfile, parse_html_date, parse_html_datetime},
+    wallet::{LedgerJournalKind, TopUpError, Wallet, WalletEntry, WalletError},
 };
 
 #[derive(Clone)]
 pub struct State {
     db: PgPool,
     argon2: Argon2<'static>,
+    session_mac: session::SessionMac,
+    gateway: Arc<dyn PaymentGateway>,
 }
 
 impl State {
+    /// Connects to the database and sets up the mock payment gateway, ending every call as
+    /// `MOCK_PAYMENT_OUTCOME` says, `succeed` unless set.
+    ///
     /// # Errors
-    /// when connecting to the database failed
+    /// when connecting to the database failed, the session secret is missing or the mock outcome
+    /// is invalid
     pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
         let database_url = dotenvy::var("DATABASE_URL")?;
+        let session_secret = dotenvy::var("SESSION_SECRET")?;
         let options = PgConnectOptions::from_str(&database_url)?.log_statements(LevelFilter::Debug);
         let db = PgPoolOptions::new()
             .max_connections(5)
             .connect_with(options)
             .await?;
+        let outcome =
+            dotenvy::var("MOCK_PAYMENT_OUTCOME").map_or(Ok(MockOutcome::Succeed), |x| x.parse())?;
+        let webhook_secret = dotenvy::var("PAYMENT_WEBHOOK_SECRET").ok();
         Ok(Self {
             db,
             argon2: Argon2::default(),
+            session_mac: session::SessionMac::new_from_slice(session_secret.as_bytes())?,
+            gateway: Arc::new(MockGateway::new(outcome, webhook_secret.as_deref())),
         })
     }
+
+    /// Takes payments through `gateway` instead of the mock.
+    #[must_use]
+    pub fn with_gateway(self, gateway: impl PaymentGateway + 'static) -> Self {
+        Self {
+            gateway: Arc::new(gateway),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+impl State {
+    /// A state over the database of a test, taking payments through a mock that succeeds.
+    fn for_tests(db: PgPool) -> Self {
+        Self {
+            db,
+            argon2: Argon2::default(),
+            session_mac: session::SessionMac::new_from_slice(b"test")
+                .expect("HMAC takes keys of any length"),
+            gateway: Arc::new(MockGateway::new(MockOutcome::Succeed, None)),
+        }
+    }
 }
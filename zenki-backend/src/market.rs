@@ -0,0 +1,724 @@
+use std::fmt::Display;
+
+use sqlx::PgConnection;
+use thiserror::Error;
+use time::PrimitiveDateTime;
+use zenki_util::{Money, i32_to_usize, usize_to_i32};
+
+use crate::{
+    State,
+    age::{AgeError, age_check},
+    inventory::{stock_inventory, take_from_inventory},
+    purchase::decode_money,
+    wallet::{WalletError, hold_for_market, pay_for_trade, release_from_market},
+};
+
+/// The share of each trade the store keeps, in basis points.
+pub const MARKET_FEE_BP: i64 = 500;
+
+#[derive(Error, Debug)]
+pub enum MarketError {
+    #[error("item cannot be traded")]
+    NotTradeable,
+    #[error("quantity must be at least one")]
+    InvalidQuantity,
+    #[error("price must be positive")]
+    InvalidPrice,
+    #[error("not enough items left")]
+    NotEnoughItems,
+    #[error("listing not found")]
+    ListingNotFound,
+    #[error("buy order not found")]
+    BuyOrderNotFound,
+    #[error("own listings cannot be bought")]
+    OwnListing,
+    #[error("amount does not fit into the price range")]
+    AmountOverflow,
+    #[error(transparent)]
+    Age(#[from] AgeError),
+    #[error(transparent)]
+    Wallet(#[from] WalletError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "market_order_status_n", rename_all = "snake_case")]
+pub enum MarketOrderStatus {
+    Active,
+    Completed,
+    Cancelled,
+}
+
+impl Display for MarketOrderStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Active => "Active",
+            Self::Completed => "Completed",
+            Self::Cancelled => "Cancelled",
+        })
+    }
+}
+
+/// Items a player offers to other players for a price per unit.
+pub struct MarketListing {
+    pub lid: i32,
+    pub seller_uid: i32,
+    pub s_uname: String,
+    pub pid: i32,
+    pub descr: Option<String>,
+    pub quantity: i32,
+    pub sold: i32,
+    pub price: Money,
+    pub status: MarketOrderStatus,
+    pub created_at: PrimitiveDateTime,
+    pub closed_at: Option<PrimitiveDateTime>,
+}
+
+/// An offer to buy items for up to a price per unit.
+pub struct BuyOrder {
+    pub boid: i32,
+    pub buyer_uid: i32,
+    pub b_uname: String,
+    pub pid: i32,
+    pub descr: Option<String>,
+    pub quantity: i32,
+    pub filled: i32,
+    pub max_price: Money,
+    pub status: MarketOrderStatus,
+    pub created_at: PrimitiveDateTime,
+}
+
+/// The open listings and buy orders of the items of a game.
+pub struct Market {
+    pub listings: Vec<MarketListing>,
+    pub buy_orders: Vec<BuyOrder>,
+}
+
+/// Items sold from one player to another.
+pub struct MarketTrade {
+    pub tid: i32,
+    pub seller_uid: Option<i32>,
+    pub s_uname: Option<String>,
+    pub buyer_uid: i32,
+    pub b_uname: String,
+    pub quantity: i32,
+    pub unit_price: Money,
+    pub bought_at: Option<PrimitiveDateTime>,
+}
+
+/// A sale of `quantity` items of listing `lid` for `unit_price` each.
+struct Trade {
+    lid: i32,
+    seller_uid: i32,
+    buyer_uid: i32,
+    pid: i32,
+    quantity: i32,
+    unit_price: Money,
+}
+
+impl State {
+    /// The open listings and buy orders of the items of game `gid`, cheapest listings and
+    /// highest offers first.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_market(&self, gid: usize) -> sqlx::Result<Market> {
+        let listings = sqlx::query!(
+            r#"SELECT
+                l.lid,
+                l.seller_uid,
+                u.uname AS s_uname,
+                l.pid,
+                p.descr,
+                l.quantity,
+                l.sold,
+                l.price,
+                l.currency,
+                l.status AS "status: MarketOrderStatus",
+                l.created_at,
+                l.closed_at
+            FROM market_listings l
+            JOIN purchases p ON l.pid = p.pid
+            JOIN users u ON l.seller_uid = u.uid
+            WHERE p.gid = $1 AND l.status = 'active'
+            ORDER BY l.pid, l.price, l.lid"#,
+            usize_to_i32(gid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| {
+            Ok(MarketListing {
+                lid: x.lid,
+                seller_uid: x.seller_uid,
+                s_uname: x.s_uname,
+                pid: x.pid,
+                descr: x.descr,
+                quantity: x.quantity,
+                sold: x.sold,
+                price: decode_money(x.price, &x.currency)?,
+                status: x.status,
+                created_at: x.created_at,
+                closed_at: x.closed_at,
+            })
+        })
+        .collect::<sqlx::Result<_>>()?;
+
+        let buy_orders = sqlx::query!(
+            r#"SELECT
+                o.boid,
+                o.buyer_uid,
+                u.uname AS b_uname,
+                o.pid,
+                p.descr,
+                o.quantity,
+                o.filled,
+                o.max_price,
+                o.currency,
+                o.status AS "status: MarketOrderStatus",
+                o.created_at
+            FROM market_buy_orders o
+            JOIN purchases p ON o.pid = p.pid
+            JOIN users u ON o.buyer_uid = u.uid
+            WHERE p.gid = $1 AND o.status = 'active'
+            ORDER BY o.pid, o.max_price DESC, o.boid"#,
+            usize_to_i32(gid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| {
+            Ok(BuyOrder {
+                boid: x.boid,
+                buyer_uid: x.buyer_uid,
+                b_uname: x.b_uname,
+                pid: x.pid,
+                descr: x.descr,
+                quantity: x.quantity,
+                filled: x.filled,
+                max_price: decode_money(x.max_price, &x.currency)?,
+                status: x.status,
+                created_at: x.created_at,
+            })
+        })
+        .collect::<sqlx::Result<_>>()?;
+
+        Ok(Market {
+            listings,
+            buy_orders,
+        })
+    }
+
+    /// Every listing of item `pid`, newest first.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_listing_history(&self, pid: usize) -> sqlx::Result<Vec<MarketListing>> {
+        sqlx::query!(
+            r#"SELECT
+                l.lid,
+                l.seller_uid,
+                u.uname AS s_uname,
+                l.pid,
+                p.descr,
+                l.quantity,
+                l.sold,
+                l.price,
+                l.currency,
+                l.status AS "status: MarketOrderStatus",
+                l.created_at,
+                l.closed_at
+            FROM market_listings l
+            JOIN purchases p ON l.pid = p.pid
+            JOIN users u ON l.seller_uid = u.uid
+            WHERE l.pid = $1
+            ORDER BY l.created_at DESC, l.lid DESC"#,
+            usize_to_i32(pid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| {
+            Ok(MarketListing {
+                lid: x.lid,
+                seller_uid: x.seller_uid,
+                s_uname: x.s_uname,
+                pid: x.pid,
+                descr: x.descr,
+                quantity: x.quantity,
+                sold: x.sold,
+                price: decode_money(x.price, &x.currency)?,
+                status: x.status,
+                created_at: x.created_at,
+                closed_at: x.closed_at,
+            })
+        })
+        .collect()
+    }
+
+    /// Every trade of item `pid` between players, newest first.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_market_trades(&self, pid: usize) -> sqlx::Result<Vec<MarketTrade>> {
+        sqlx::query!(
+            r#"SELECT
+                t.tid,
+                t.seller_uid,
+                seller.uname AS "s_uname?",
+                t.uid AS buyer_uid,
+                buyer.uname AS b_uname,
+                t.quantity,
+                t.amount,
+                t.currency,
+                t.bought_at
+            FROM transactions t
+            JOIN users buyer ON t.uid = buyer.uid
+            LEFT JOIN users seller ON t.seller_uid = seller.uid
+            WHERE t.pid = $1 AND t.lid IS NOT NULL
+            ORDER BY t.bought_at DESC, t.tid DESC"#,
+            usize_to_i32(pid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| {
+            let amount = decode_money(x.amount, &x.currency)?;
+            Ok(MarketTrade {
+                tid: x.tid,
+                seller_uid: x.seller_uid,
+                s_uname: x.s_uname,
+                buyer_uid: x.buyer_uid,
+                b_uname: x.b_uname,
+                quantity: x.quantity,
+                unit_price: Money::new(amount.minor() / i64::from(x.quantity), amount.currency()),
+                bought_at: x.bought_at,
+            })
+        })
+        .collect()
+    }
+
+    /// Allows or forbids trading in-game item `pid` between players.
+    ///
+    /// # Errors
+    /// when the purchase is not an in-game item or querying the database failed
+    pub async fn set_tradeable(&self, pid: usize, tradeable: bool) -> Result<(), MarketError> {
+        let updated = sqlx::query!(
+            r"UPDATE purchases SET tradeable = $2
+            WHERE pid = $1 AND purchase_type = 'in_game_purchase'",
+            usize_to_i32(pid),
+            tradeable,
+        )
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(MarketError::NotTradeable);
+        }
+        Ok(())
+    }
+
+    /// Lists `quantity` items `pid` of the inventory of `uid` for `price` each, selling to the
+    /// highest open buy orders right away, at the prices they offered.
+    ///
+    /// # Errors
+    /// when the item is not tradeable, the quantity or price is invalid, the seller has fewer
+    /// items, or querying the database failed
+    pub async fn create_listing(
+        &self,
+        uid: usize,
+        pid: usize,
+        quantity: usize,
+        price: Money,
+    ) -> Result<i32, MarketError> {
+        let quantity = i32::try_from(quantity).map_err(|_| MarketError::InvalidQuantity)?;
+        if quantity == 0 {
+            return Err(MarketError::InvalidQuantity);
+        }
+        if price.minor() <= 0 {
+            return Err(MarketError::InvalidPrice);
+        }
+        let (seller_uid, pid) = (usize_to_i32(uid), usize_to_i32(pid));
+        let mut tx = self.db.begin().await?;
+        lock_market(&mut tx, pid).await?;
+        tradeable_game(&mut tx, pid).await?;
+        take_from_inventory(&mut tx, uid, pid, quantity)
+            .await?
+            .ok_or(MarketError::NotEnoughItems)?;
+        let lid = sqlx::query!(
+            r"INSERT INTO market_listings (seller_uid, pid, quantity, price, currency)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING lid",
+            seller_uid,
+            pid,
+            quantity,
+            price.minor(),
+            price.currency().to_string(),
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .lid;
+
+        let orders = sqlx::query!(
+            r#"SELECT boid, buyer_uid, quantity - filled AS "left!", max_price
+            FROM market_buy_orders
+            WHERE pid = $1 AND status = 'active' AND currency = $2 AND max_price >= $3
+                AND buyer_uid <> $4
+            ORDER BY max_price DESC, boid
+            FOR UPDATE"#,
+            pid,
+            price.currency().to_string(),
+            price.minor(),
+            seller_uid,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut left = quantity;
+        for order in orders {
+            if left == 0 {
+                break;
+            }
+            let filled = left.min(order.left);
+            let trade = Trade {
+                lid,
+                seller_uid,
+                buyer_uid: order.buyer_uid,
+                pid,
+                quantity: filled,
+                unit_price: Money::new(order.max_price, price.currency()),
+            };
+            record_trade(&mut tx, &trade, true).await?;
+            sqlx::query!(
+                r"UPDATE market_buy_orders
+                SET filled = filled + $2,
+                    status = CASE WHEN filled + $2 = quantity THEN 'completed' ELSE status END,
+                    closed_at = CASE WHEN filled + $2 = quantity THEN NOW() END
+                WHERE boid = $1",
+                order.boid,
+                filled,
+            )
+            .execute(&mut *tx)
+            .await?;
+            left -= filled;
+        }
+        tx.commit().await?;
+        Ok(lid)
+    }
+
+    /// Takes a listing of `uid` off the market, putting the items left back into their
+    /// inventory.
+    ///
+    /// # Errors
+    /// when `uid` has no such active listing or querying the database failed
+    pub async fn cancel_listing(&self, uid: usize, lid: usize) -> Result<(), MarketError> {
+        let mut tx = self.db.begin().await?;
+        let x = sqlx::query!(
+            r#"UPDATE market_listings SET status = 'cancelled', closed_at = NOW()
+            WHERE lid = $1 AND seller_uid = $2 AND status = 'active'
+            RETURNING pid, quantity - sold AS "left!""#,
+            usize_to_i32(lid),
+            usize_to_i32(uid),
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(MarketError::ListingNotFound)?;
+        stock_inventory(&mut tx, uid, x.pid, x.left).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Buys `quantity` items of listing `lid` for `uid`, paid out of their wallet.
+    ///
+    /// # Errors
+    /// when the listing is not active, belongs to `uid` or has fewer items left, the quantity is
+    /// invalid, the buyer is not old enough for the game, the wallet cannot cover the price, or
+    /// querying the database failed
+    pub async fn buy_listing(
+        &self,
+        uid: usize,
+        lid: usize,
+        quantity: usize,
+    ) -> Result<i32, MarketError> {
+        let quantity = i32::try_from(quantity).map_err(|_| MarketError::InvalidQuantity)?;
+        if quantity == 0 {
+            return Err(MarketError::InvalidQuantity);
+        }
+        let mut tx = self.db.begin().await?;
+        let x = sqlx::query!(
+            r#"SELECT seller_uid, pid, quantity - sold AS "left!", price, currency
+            FROM market_listings
+            WHERE lid = $1 AND status = 'active'
+            FOR UPDATE"#,
+            usize_to_i32(lid),
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(MarketError::ListingNotFound)?;
+        if x.seller_uid == usize_to_i32(uid) {
+            return Err(MarketError::OwnListing);
+        }
+        if quantity > x.left {
+            return Err(MarketError::NotEnoughItems);
+        }
+        let gid = tradeable_game(&mut tx, x.pid).await?;
+        age_check(&mut *tx, Some(uid), gid).await?.ensure()?;
+        let trade = Trade {
+            lid: usize_to_i32(lid),
+            seller_uid: x.seller_uid,
+            buyer_uid: usize_to_i32(uid),
+            pid: x.pid,
+            quantity,
+            unit_price: decode_money(x.price, &x.currency)?,
+        };
+        let tid = record_trade(&mut tx, &trade, false).await?;
+        tx.commit().await?;
+        Ok(tid)
+    }
+
+    /// Offers to buy `quantity` items `pid` for up to `max_price` each, buying from the cheapest
+    /// listings right away and holding back what the rest may cost from the wallet of `uid`.
+    ///
+    /// # Errors
+    /// when the item is not tradeable, the quantity or price is invalid, the buyer is not old
+    /// enough for the game, the wallet cannot cover the offer, or querying the database failed
+    pub async fn create_buy_order(
+        &self,
+        uid: usize,
+        pid: usize,
+        quantity: usize,
+        max_price: Money,
+    ) -> Result<i32, MarketError> {
+        let quantity = i32::try_from(quantity).map_err(|_| MarketError::InvalidQuantity)?;
+        if quantity == 0 {
+            return Err(MarketError::InvalidQuantity);
+        }
+        if max_price.minor() <= 0 {
+            return Err(MarketError::InvalidPrice);
+        }
+        let (buyer_uid, pid) = (usize_to_i32(uid), usize_to_i32(pid));
+        let mut tx = self.db.begin().await?;
+        lock_market(&mut tx, pid).await?;
+        let gid = tradeable_game(&mut tx, pid).await?;
+        age_check(&mut *tx, Some(uid), gid).await?.ensure()?;
+
+        let listings = sqlx::query!(
+            r#"SELECT lid, seller_uid, quantity - sold AS "left!", price
+            FROM market_listings
+            WHERE pid = $1 AND status = 'active' AND currency = $2 AND price <= $3
+                AND seller_uid <> $4
+            ORDER BY price, lid
+            FOR UPDATE"#,
+            pid,
+            max_price.currency().to_string(),
+            max_price.minor(),
+            buyer_uid,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut left = quantity;
+        for listing in listings {
+            if left == 0 {
+                break;
+            }
+            let bought = left.min(listing.left);
+            let trade = Trade {
+                lid: listing.lid,
+                seller_uid: listing.seller_uid,
+                buyer_uid,
+                pid,
+                quantity: bought,
+                unit_price: Money::new(listing.price, max_price.currency()),
+            };
+            record_trade(&mut tx, &trade, false).await?;
+            left -= bought;
+        }
+
+        let boid = sqlx::query!(
+            r#"INSERT INTO market_buy_orders
+                (buyer_uid, pid, quantity, filled, max_price, currency, status, closed_at)
+            VALUES ($1, $2, $3, $4, $5, $6,
+                CASE WHEN $7 THEN 'completed' ELSE 'active' END::market_order_status_n,
+                CASE WHEN $7 THEN NOW() END)
+            RETURNING boid"#,
+            buyer_uid,
+            pid,
+            quantity,
+            quantity - left,
+            max_price.minor(),
+            max_price.currency().to_string(),
+            left == 0,
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .boid;
+        if left > 0 {
+            let held = max_price
+                .checked_mul(i64::from(left))
+                .ok_or(MarketError::AmountOverflow)?;
+            hold_for_market(&mut tx, buyer_uid, held).await?;
+        }
+        tx.commit().await?;
+        Ok(boid)
+    }
+
+    /// Withdraws a buy order of `uid`, giving back what was held for the items not bought yet.
+    ///
+    /// # Errors
+    /// when `uid` has no such active buy order or querying the database failed
+    pub async fn cancel_buy_order(&self, uid: usize, boid: usize) -> Result<(), MarketError> {
+        let mut tx = self.db.begin().await?;
+        let x = sqlx::query!(
+            r#"UPDATE market_buy_orders SET status = 'cancelled', closed_at = NOW()
+            WHERE boid = $1 AND buyer_uid = $2 AND status = 'active'
+            RETURNING quantity - filled AS "left!", max_price, currency"#,
+            usize_to_i32(boid),
+            usize_to_i32(uid),
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(MarketError::BuyOrderNotFound)?;
+        let held = decode_money(x.max_price, &x.currency)?
+            .checked_mul(i64::from(x.left))
+            .ok_or(MarketError::AmountOverflow)?;
+        release_from_market(&mut tx, usize_to_i32(uid), held).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// The fee kept of a trade for `price`, rounded half up to the minor unit.
+#[must_use]
+pub fn market_fee(price: Money) -> Option<Money> {
+    let fee = price
+        .minor()
+        .checked_mul(MARKET_FEE_BP)?
+        .checked_add(5_000)?
+        / 10_000;
+    Some(Money::new(fee, price.currency()))
+}
+
+/// Waits for other listings and buy orders of item `pid` to be matched until the transaction
+/// ends, so that a listing and a buy order placed at the same time cannot miss each other.
+///
+/// # Errors
+/// when querying the database failed
+async fn lock_market(tx: &mut PgConnection, pid: i32) -> sqlx::Result<()> {
+    sqlx::query!(r"SELECT pg_advisory_xact_lock($1)", i64::from(pid))
+        .execute(&mut *tx)
+        .await?;
+    Ok(())
+}
+
+/// The game of in-game item `pid`, as long as players may trade it.
+///
+/// # Errors
+/// when the item does not exist or is not tradeable, or querying the database failed
+async fn tradeable_game(tx: &mut PgConnection, pid: i32) -> Result<i32, MarketError> {
+    sqlx::query!(
+        r"SELECT gid FROM purchases
+        WHERE pid = $1 AND tradeable AND purchase_type = 'in_game_purchase'",
+        pid,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(|x| x.gid)
+    .ok_or(MarketError::NotTradeable)
+}
+
+/// Records `trade` as a completed transaction of the buyer, pays the seller and hands the items
+/// over, paid out of the buyer's wallet or out of what was held back for their buy order.
+///
+/// # Errors
+/// when the amount does not fit into the price range, the buyer's wallet cannot cover it, or
+/// querying the database failed
+async fn record_trade(
+    tx: &mut PgConnection,
+    trade: &Trade,
+    from_buy_order: bool,
+) -> Result<i32, MarketError> {
+    let price = trade
+        .unit_price
+        .checked_mul(i64::from(trade.quantity))
+        .ok_or(MarketError::AmountOverflow)?;
+    let fee = market_fee(price).ok_or(MarketError::AmountOverflow)?;
+    let tid = sqlx::query!(
+        r"INSERT INTO transactions
+            (uid, pid, payment_method, amount, currency, quantity, status, seller_uid, lid,
+            market_fee)
+        VALUES ($1, $2, 'wallet', $3, $4, $5, 'completed', $6, $7, $8)
+        RETURNING tid",
+        trade.buyer_uid,
+        trade.pid,
+        price.minor(),
+        price.currency().to_string(),
+        trade.quantity,
+        trade.seller_uid,
+        trade.lid,
+        fee.minor(),
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .tid;
+    pay_for_trade(
+        tx,
+        (!from_buy_order).then_some(trade.buyer_uid),
+        trade.seller_uid,
+        (price, fee),
+        tid,
+    )
+    .await?;
+    stock_inventory(tx, i32_to_usize(trade.buyer_uid), trade.pid, trade.quantity).await?;
+    sqlx::query!(
+        r"UPDATE market_listings
+        SET sold = sold + $2,
+            status = CASE WHEN sold + $2 = quantity THEN 'completed' ELSE status END,
+            closed_at = CASE WHEN sold + $2 = quantity THEN NOW() END
+        WHERE lid = $1",
+        trade.lid,
+        trade.quantity,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(tid)
+}
+
+#[cfg(test)]
+mod tests {
+    use zenki_util::Currency;
+
+    use super::*;
+
+    fn fee(minor: i64) -> Option<i64> {
+        market_fee(Money::new(minor, Currency::USD)).map(Money::minor)
+    }
+
+    #[test]
+    fn rounds_the_fee_half_up() {
+        assert_eq!(fee(100), Some(5));
+        assert_eq!(fee(10), Some(1));
+        assert_eq!(fee(9), Some(0));
+        assert_eq!(fee(30), Some(2));
+        assert_eq!(fee(1_999), Some(100));
+    }
+
+    #[test]
+    fn keeps_nothing_of_the_smallest_trades() {
+        assert_eq!(fee(0), Some(0));
+        assert_eq!(fee(1), Some(0));
+    }
+
+    #[test]
+    fn always_leaves_the_seller_something() {
+        for price in 1..1_000 {
+            assert!(fee(price).is_some_and(|fee| fee < price), "price {price}");
+        }
+    }
+
+    #[test]
+    fn keeps_the_currency_and_refuses_to_overflow() {
+        let eur = "EUR".parse().expect("EUR is a currency code");
+        assert_eq!(market_fee(Money::new(100, eur)), Some(Money::new(5, eur)));
+        assert_eq!(fee(i64::MAX), None);
+    }
+}
@@ -0,0 +1,63 @@
+use sqlx::PgConnection;
+use time::PrimitiveDateTime;
+use zenki_util::usize_to_i32;
+
+use crate::State;
+
+pub struct Notification {
+    pub nid: i32,
+    pub message: String,
+    pub tid: Option<i32>,
+    pub created_at: Option<PrimitiveDateTime>,
+    pub read_at: Option<PrimitiveDateTime>,
+}
+
+impl State {
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_notifications(&self, uid: usize) -> sqlx::Result<Vec<Notification>> {
+        sqlx::query_as!(
+            Notification,
+            r"SELECT nid, message, tid, created_at, read_at
+            FROM notifications
+            WHERE uid = $1
+            ORDER BY created_at DESC, nid DESC",
+            usize_to_i32(uid)
+        )
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn mark_notifications_read(&self, uid: usize) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"UPDATE notifications SET read_at = NOW() WHERE uid = $1 AND read_at IS NULL",
+            usize_to_i32(uid)
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Leaves a message for `uid`, optionally about transaction `tid`.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn notify(
+    tx: &mut PgConnection,
+    uid: i32,
+    message: &str,
+    tid: Option<i32>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r"INSERT INTO notifications (uid, message, tid) VALUES ($1, $2, $3)",
+        uid,
+        message,
+        tid,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
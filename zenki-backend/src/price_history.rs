@@ -0,0 +1,146 @@
+use sqlx::PgExecutor;
+use time::PrimitiveDateTime;
+use zenki_util::{Money, usize_to_i32};
+
+use crate::{
+    Purchase, State,
+    discount::{DiscountKind, discount_value},
+    purchase::decode_money,
+};
+
+/// A price a purchase was set to.
+pub struct PricePoint {
+    pub price: Money,
+    pub changed_at: PrimitiveDateTime,
+}
+
+impl State {
+    /// Every price `pid` has had, oldest first.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_price_history(&self, pid: usize) -> sqlx::Result<Vec<PricePoint>> {
+        sqlx::query!(
+            r"SELECT price, currency, changed_at
+            FROM price_history
+            WHERE pid = $1
+            ORDER BY changed_at, phid",
+            usize_to_i32(pid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| {
+            Ok(PricePoint {
+                price: decode_money(x.price, &x.currency)?,
+                changed_at: x.changed_at,
+            })
+        })
+        .collect()
+    }
+
+    /// Changes the price of `pid`, keeping the old one in its price history.
+    ///
+    /// # Errors
+    /// when the purchase does not exist or querying the database failed
+    pub async fn update_price(&self, pid: usize, price: Money) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"UPDATE purchases SET price = $2, currency = $3 WHERE pid = $1 RETURNING pid",
+            usize_to_i32(pid),
+            price.minor(),
+            price.currency().to_string(),
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Fills in the lowest price of the last 30 days of each purchase on sale, from the prices it
+/// had and the sales that ran on it then, so a sale can be told apart from a price that was
+/// raised just before.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn attach_lowest_prices<'e>(
+    executor: impl PgExecutor<'e> + Copy,
+    purchases: &mut [Purchase],
+) -> sqlx::Result<()> {
+    let pids = purchases
+        .iter()
+        .filter(|p| p.sale.is_some())
+        .map(|p| p.pid)
+        .collect::<Vec<_>>();
+    if pids.is_empty() {
+        return Ok(());
+    }
+
+    // the prices in effect at some point of the window, each with when it was in effect
+    let prices = sqlx::query!(
+        r#"SELECT pid AS "pid!", price AS "price!", currency AS "currency!",
+            GREATEST(changed_at, NOW()::timestamp - INTERVAL '30 days') AS "starts_at!",
+            ends_at
+        FROM (
+            SELECT pid, price, currency, changed_at,
+                LEAD(changed_at) OVER (PARTITION BY pid ORDER BY changed_at, phid) AS ends_at
+            FROM price_history
+            WHERE pid = ANY($1)
+        ) h
+        WHERE ends_at IS NULL OR ends_at > NOW() - INTERVAL '30 days'"#,
+        &pids
+    )
+    .fetch_all(executor)
+    .await?;
+    // the sales that ran in the window and are over by now
+    let discounts = sqlx::query!(
+        r#"SELECT
+            p.pid,
+            d.kind AS "kind: DiscountKind",
+            d.percent,
+            d.amount,
+            d.currency,
+            d.starts_at,
+            d.ends_at
+        FROM purchases p
+        JOIN discounts d ON d.pid = p.pid
+            OR d.tname IN (SELECT gt.tname FROM game_tag gt WHERE gt.gid = p.gid)
+            OR d.did IN (SELECT dg.did FROM developer_game dg WHERE dg.gid = p.gid)
+        WHERE p.pid = ANY($1)
+            AND d.ends_at > NOW() - INTERVAL '30 days'
+            AND d.ends_at <= NOW()"#,
+        &pids
+    )
+    .fetch_all(executor)
+    .await?;
+
+    for purchase in purchases {
+        let Some(sale) = &mut purchase.sale else {
+            continue;
+        };
+        let mut lowest = None::<Money>;
+        for x in prices.iter().filter(|x| x.pid == purchase.pid) {
+            let price = decode_money(x.price, &x.currency)?;
+            let mut candidates = vec![price];
+            // the sales that ran while the price was in effect
+            for d in discounts
+                .iter()
+                .filter(|d| d.pid == purchase.pid)
+                .filter(|d| x.starts_at < d.ends_at)
+                .filter(|d| x.ends_at.is_none_or(|ends_at| d.starts_at < ends_at))
+            {
+                if let Some(value) = discount_value(d.kind, d.percent, d.amount, &d.currency)? {
+                    candidates.extend(value.apply(price));
+                }
+            }
+            for candidate in candidates {
+                if candidate.currency() == sale.price.currency()
+                    && lowest.is_none_or(|x| candidate.minor() < x.minor())
+                {
+                    lowest = Some(candidate);
+                }
+            }
+        }
+        sale.lowest_price = lowest;
+    }
+    Ok(())
+}
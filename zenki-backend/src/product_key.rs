@@ -0,0 +1,359 @@
+use std::fmt::Write;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use thiserror::Error;
+use time::PrimitiveDateTime;
+use zenki_util::{i32_to_usize, usize_to_i32};
+
+use crate::{
+    AgeError, CheckoutError, Permission, PurchaseType, State,
+    age::purchase_age_check,
+    bundle::{bundle_items, grant_bundle},
+    entitlement::grant_entitlement,
+    game::claim_game,
+};
+
+/// The most keys a single batch may hold.
+pub const MAX_KEYS_PER_BATCH: usize = 10_000;
+
+/// Letters and digits that cannot be mistaken for one another when typed from print.
+const KEY_ALPHABET: &[u8; 32] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+const KEY_GROUPS: usize = 3;
+const KEY_GROUP_LEN: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum KeyError {
+    #[error("a batch must hold between 1 and {MAX_KEYS_PER_BATCH} keys")]
+    InvalidCount,
+    #[error("purchase not found")]
+    PurchaseNotFound,
+    #[error("keys can only unlock games, DLC, in-game items and bundles")]
+    NotRedeemable,
+    #[error("key not found")]
+    KeyNotFound,
+    #[error("key has already been redeemed")]
+    AlreadyRedeemed,
+    #[error("game is already in the library")]
+    AlreadyOwned,
+    #[error(transparent)]
+    Age(#[from] AgeError),
+    #[error(transparent)]
+    Checkout(#[from] CheckoutError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct KeyBatch {
+    pub kbid: i32,
+    pub pid: i32,
+    pub p_descr: Option<String>,
+    pub gid: i32,
+    pub gname: String,
+    pub descr: Option<String>,
+    pub created_at: Option<PrimitiveDateTime>,
+    pub total: i64,
+    pub redeemed: i64,
+}
+
+pub struct ProductKey {
+    pub code: String,
+    pub redeemed_by: Option<i32>,
+    pub r_uname: Option<String>,
+    pub redeemed_at: Option<PrimitiveDateTime>,
+}
+
+/// What a redeemed key was bound to.
+pub struct RedeemedKey {
+    pub pid: i32,
+    pub p_descr: Option<String>,
+    pub gid: i32,
+    pub gname: String,
+    pub purchase_type: PurchaseType,
+}
+
+/// Keys are case-insensitive and may be typed with or without dashes.
+#[must_use]
+pub fn normalize_key_code(code: &str) -> String {
+    let chars = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<Vec<_>>();
+    chars
+        .chunks(KEY_GROUP_LEN)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn generate_key_code() -> String {
+    let mut bytes = [0; KEY_GROUPS * KEY_GROUP_LEN];
+    OsRng.fill_bytes(&mut bytes);
+    let chars = bytes
+        .iter()
+        .map(|b| char::from(KEY_ALPHABET[usize::from(b % 32)]))
+        .collect::<String>();
+    normalize_key_code(&chars)
+}
+
+impl State {
+    /// Generates a batch of `count` unique keys for a purchase, returning the batch id.
+    ///
+    /// # Errors
+    /// when the count is out of range, the purchase does not exist or is of a type a key cannot
+    /// unlock, or querying the database failed
+    pub async fn generate_keys(
+        &self,
+        uid: usize,
+        pid: usize,
+        count: usize,
+        descr: Option<&str>,
+    ) -> Result<i32, KeyError> {
+        if !(1..=MAX_KEYS_PER_BATCH).contains(&count) {
+            return Err(KeyError::InvalidCount);
+        }
+        let purchase_type = sqlx::query!(
+            r#"SELECT purchase_type AS "purchase_type: PurchaseType" FROM purchases WHERE pid = $1"#,
+            usize_to_i32(pid),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(KeyError::PurchaseNotFound)?
+        .purchase_type;
+        if matches!(
+            purchase_type,
+            PurchaseType::Subscriptions | PurchaseType::Etc
+        ) {
+            return Err(KeyError::NotRedeemable);
+        }
+        let mut tx = self.db.begin().await?;
+
+        let kbid = sqlx::query!(
+            r"INSERT INTO key_batches (pid, descr, created_by)
+            VALUES ($1, $2, $3)
+            RETURNING kbid",
+            usize_to_i32(pid),
+            descr.map(str::trim).filter(|x| !x.is_empty()),
+            usize_to_i32(uid),
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .kbid;
+
+        let mut generated = 0;
+        while generated < count {
+            let codes = (generated..count)
+                .map(|_| generate_key_code())
+                .collect::<Vec<_>>();
+            // a collision just skips that code, and the next round makes up for it
+            generated += usize::try_from(
+                sqlx::query!(
+                    r"INSERT INTO product_keys (code, kbid)
+                    SELECT code, $2 FROM UNNEST($1::text[]) AS code
+                    ON CONFLICT DO NOTHING",
+                    &codes,
+                    kbid,
+                )
+                .execute(&mut *tx)
+                .await?
+                .rows_affected(),
+            )
+            .unwrap_or_default();
+        }
+
+        tx.commit().await?;
+        Ok(kbid)
+    }
+
+    /// The key batches of the games `uid` manages, which are all of them for store managers.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_key_batches(&self, uid: usize) -> sqlx::Result<Vec<KeyBatch>> {
+        let all = self.has_permission(uid, Permission::ManageStore).await?;
+        sqlx::query_as!(
+            KeyBatch,
+            r#"SELECT
+                kb.kbid,
+                p.pid,
+                p.descr AS p_descr,
+                g.gid,
+                g.gname,
+                kb.descr,
+                kb.created_at,
+                COUNT(k.code) AS "total!",
+                COUNT(k.redeemed_at) AS "redeemed!"
+            FROM key_batches kb
+            JOIN purchases p ON kb.pid = p.pid
+            JOIN games g ON p.gid = g.gid
+            LEFT JOIN product_keys k ON kb.kbid = k.kbid
+            WHERE $2 OR EXISTS (
+                SELECT 1 FROM developer_game dg
+                JOIN developer_members dm ON dg.did = dm.did
+                WHERE dg.gid = g.gid AND dm.uid = $1
+            )
+            GROUP BY kb.kbid, p.pid, g.gid
+            ORDER BY kb.created_at DESC, kb.kbid DESC"#,
+            usize_to_i32(uid),
+            all,
+        )
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// Whether `uid` manages the purchase the keys of batch `kbid` unlock.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn manages_key_batch(&self, uid: usize, kbid: usize) -> sqlx::Result<bool> {
+        let pid = sqlx::query!(
+            r"SELECT pid FROM key_batches WHERE kbid = $1",
+            usize_to_i32(kbid)
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        match pid {
+            Some(x) => self.manages_purchase(uid, i32_to_usize(x.pid)).await,
+            None => Ok(false),
+        }
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_product_keys(&self, kbid: usize) -> sqlx::Result<Vec<ProductKey>> {
+        sqlx::query_as!(
+            ProductKey,
+            r#"SELECT k.code, k.redeemed_by, u.uname AS "r_uname?", k.redeemed_at
+            FROM product_keys k
+            LEFT JOIN users u ON k.redeemed_by = u.uid
+            WHERE k.kbid = $1
+            ORDER BY k.redeemed_at DESC NULLS LAST, k.code"#,
+            usize_to_i32(kbid)
+        )
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// Renders the keys of a batch that are still unused as CSV.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn export_unused_keys(&self, kbid: usize) -> sqlx::Result<String> {
+        let keys = sqlx::query!(
+            r"SELECT k.code, kb.pid, kb.created_at
+            FROM product_keys k
+            JOIN key_batches kb ON k.kbid = kb.kbid
+            WHERE k.kbid = $1 AND k.redeemed_at IS NULL
+            ORDER BY k.code",
+            usize_to_i32(kbid)
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut csv = String::from("code,pid,batch,created_at\n");
+        for key in keys {
+            // codes, ids and timestamps never contain commas or quotes, so nothing needs escaping
+            let _ = writeln!(
+                csv,
+                "{},{},{},{}",
+                key.code,
+                key.pid,
+                kbid,
+                key.created_at.map(|x| x.to_string()).unwrap_or_default()
+            );
+        }
+        Ok(csv)
+    }
+
+    /// Redeems a key for `uid`, adding the games and add-ons it unlocks to their library and
+    /// entitlements.
+    ///
+    /// # Errors
+    /// when the key does not exist or has been used, it unlocks a type of purchase keys cannot
+    /// grant, `uid` is not old enough for the game or has yet to confirm their age, the game or
+    /// DLC is already owned, the base game of an add-on is not, or querying the database failed
+    pub async fn redeem_key(&self, uid: usize, code: &str) -> Result<RedeemedKey, KeyError> {
+        let mut tx = self.db.begin().await?;
+
+        let key = sqlx::query!(
+            r#"SELECT
+                k.redeemed_at,
+                p.pid,
+                p.descr AS p_descr,
+                g.gid,
+                g.gname,
+                p.purchase_type AS "purchase_type: PurchaseType",
+                EXISTS (
+                    SELECT 1 FROM game_user gu
+                    WHERE gu.uid = $2 AND gu.gid = g.gid AND gu.wishlist = FALSE
+                ) AS "owned!"
+            FROM product_keys k
+            JOIN key_batches kb ON k.kbid = kb.kbid
+            JOIN purchases p ON kb.pid = p.pid
+            JOIN games g ON p.gid = g.gid
+            WHERE k.code = $1
+            FOR UPDATE OF k"#,
+            normalize_key_code(code),
+            usize_to_i32(uid),
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(KeyError::KeyNotFound)?;
+
+        if key.redeemed_at.is_some() {
+            return Err(KeyError::AlreadyRedeemed);
+        }
+        if matches!(
+            key.purchase_type,
+            PurchaseType::Subscriptions | PurchaseType::Etc
+        ) {
+            return Err(KeyError::NotRedeemable);
+        }
+        let owned = match key.purchase_type {
+            PurchaseType::GamePurchase => key.owned,
+            PurchaseType::Bundle => {
+                let items = bundle_items(&mut *tx, key.pid, uid).await?;
+                !items.is_empty() && items.iter().all(|item| item.owned)
+            }
+            _ => false,
+        };
+        if owned {
+            return Err(KeyError::AlreadyOwned);
+        }
+        purchase_age_check(&mut tx, Some(uid), key.pid, key.gid)
+            .await?
+            .ensure()?;
+
+        sqlx::query!(
+            r"UPDATE product_keys SET redeemed_by = $2, redeemed_at = NOW() WHERE code = $1",
+            normalize_key_code(code),
+            usize_to_i32(uid),
+        )
+        .execute(&mut *tx)
+        .await?;
+        match key.purchase_type {
+            PurchaseType::GamePurchase => {
+                if !claim_game(&mut *tx, uid, i32_to_usize(key.gid)).await? {
+                    return Err(KeyError::AlreadyOwned);
+                }
+            }
+            PurchaseType::Bundle => grant_bundle(&mut tx, uid, None, key.pid).await?,
+            PurchaseType::Dlc | PurchaseType::InGamePurchase => {
+                grant_entitlement(&mut tx, uid, None, (key.pid, key.gid, key.purchase_type), 1)
+                    .await?;
+            }
+            PurchaseType::Subscriptions | PurchaseType::Etc => {
+                return Err(KeyError::NotRedeemable);
+            }
+        }
+
+        tx.commit().await?;
+        Ok(RedeemedKey {
+            pid: key.pid,
+            p_descr: key.p_descr,
+            gid: key.gid,
+            gname: key.gname,
+            purchase_type: key.purchase_type,
+        })
+    }
+}
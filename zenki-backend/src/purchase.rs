@@ -1,11 +1,20 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
+use thiserror::Error;
 use time::PrimitiveDateTime;
-use zenki_util::usize_to_i32;
+use zenki_util::{Currency, Money, usize_to_i32};
 
-use crate::State;
+use crate::{
+    State,
+    discount::{Sale, attach_sales},
+    price_history::attach_lowest_prices,
+};
 
-#[derive(sqlx::Type)]
+#[derive(Error, Debug)]
+#[error("error while parsing purchase type via string")]
+pub struct ParsePurchaseTypeError;
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
 #[sqlx(type_name = "purchase_n", rename_all = "snake_case")]
 pub enum PurchaseType {
     GamePurchase,
@@ -13,6 +22,7 @@ pub enum PurchaseType {
     Subscriptions,
     Dlc,
     Etc,
+    Bundle,
 }
 
 impl Display for PurchaseType {
@@ -23,57 +33,154 @@ impl Display for PurchaseType {
             Self::Subscriptions => "Subscription",
             Self::Dlc => "DLC",
             Self::Etc => "etc.",
+            Self::Bundle => "Bundle",
         })
     }
 }
 
+impl PurchaseType {
+    /// The name of the type in the database, as parsed by [`FromStr`].
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::GamePurchase => "game_purchase",
+            Self::InGamePurchase => "in_game_purchase",
+            Self::Subscriptions => "subscriptions",
+            Self::Dlc => "dlc",
+            Self::Etc => "etc",
+            Self::Bundle => "bundle",
+        }
+    }
+}
+
+impl FromStr for PurchaseType {
+    type Err = ParsePurchaseTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "game_purchase" => Ok(Self::GamePurchase),
+            "in_game_purchase" => Ok(Self::InGamePurchase),
+            "subscriptions" => Ok(Self::Subscriptions),
+            "dlc" => Ok(Self::Dlc),
+            "etc" => Ok(Self::Etc),
+            "bundle" => Ok(Self::Bundle),
+            _ => Err(ParsePurchaseTypeError),
+        }
+    }
+}
+
 pub struct Purchase {
     pub pid: i32,
     pub gid: i32,
     #[allow(clippy::struct_field_names)]
     pub purchase_type: PurchaseType,
-    pub price: f64,
+    pub price: Money,
+    pub sale: Option<Sale>,
+    pub descr: Option<String>,
+    pub created_at: Option<PrimitiveDateTime>,
+}
+
+impl Purchase {
+    /// The price charged right now, including any running sale.
+    #[must_use]
+    pub fn effective_price(&self) -> Money {
+        self.sale.as_ref().map_or(self.price, |sale| sale.price)
+    }
+}
+
+pub struct PurchaseRow {
+    pub pid: i32,
+    pub gid: i32,
+    #[allow(clippy::struct_field_names)]
+    pub purchase_type: PurchaseType,
+    pub price: i64,
+    pub currency: String,
     pub descr: Option<String>,
     pub created_at: Option<PrimitiveDateTime>,
 }
 
+impl TryFrom<PurchaseRow> for Purchase {
+    type Error = sqlx::Error;
+
+    fn try_from(value: PurchaseRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            pid: value.pid,
+            gid: value.gid,
+            purchase_type: value.purchase_type,
+            price: decode_money(value.price, &value.currency)?,
+            sale: None,
+            descr: value.descr,
+            created_at: value.created_at,
+        })
+    }
+}
+
+/// Builds a [`Money`] from a minor-unit column and its currency column.
+///
+/// # Errors
+/// when the currency column is not a valid ISO 4217 code
+pub fn decode_money(minor: i64, currency: &str) -> sqlx::Result<Money> {
+    Ok(Money::new(
+        minor,
+        currency
+            .parse::<Currency>()
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+    ))
+}
+
 impl State {
     /// # Errors
     /// when querying the database failed
     pub async fn query_purchases(&self, gid: usize) -> sqlx::Result<Vec<Purchase>> {
-        sqlx::query_as!(
-            Purchase,
-            r#"SELECT pid, gid, purchase_type AS "purchase_type: _", price, descr, created_at
+        let mut purchases = sqlx::query_as!(
+            PurchaseRow,
+            r#"SELECT pid, gid, purchase_type AS "purchase_type: _", price, currency, descr, created_at
             FROM purchases WHERE gid = $1"#,
             usize_to_i32(gid)
         )
         .fetch_all(&self.db)
-        .await
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<sqlx::Result<Vec<Purchase>>>()?;
+        attach_sales(&self.db, &mut purchases).await?;
+        attach_lowest_prices(&self.db, &mut purchases).await?;
+        Ok(purchases)
     }
 
     /// # Errors
     /// when querying the database failed
     pub async fn query_purchase(&self, pid: usize) -> sqlx::Result<Option<Purchase>> {
-        sqlx::query_as!(
-            Purchase,
-            r#"SELECT pid, gid, purchase_type AS "purchase_type: _", price, descr, created_at
+        let mut purchase = sqlx::query_as!(
+            PurchaseRow,
+            r#"SELECT pid, gid, purchase_type AS "purchase_type: _", price, currency, descr, created_at
             FROM purchases WHERE pid = $1"#,
             usize_to_i32(pid)
         )
         .fetch_optional(&self.db)
-        .await
+        .await?
+        .map(TryInto::try_into)
+        .transpose()?;
+        if let Some(purchase) = &mut purchase {
+            attach_sales(&self.db, std::slice::from_mut(purchase)).await?;
+            attach_lowest_prices(&self.db, std::slice::from_mut(purchase)).await?;
+        }
+        Ok(purchase)
     }
 
     /// # Errors
     /// when querying the database failed
     pub async fn expect_purchase(&self, pid: usize) -> sqlx::Result<Purchase> {
-        sqlx::query_as!(
-            Purchase,
-            r#"SELECT pid, gid, purchase_type AS "purchase_type: _", price, descr, created_at
+        let mut purchase: Purchase = sqlx::query_as!(
+            PurchaseRow,
+            r#"SELECT pid, gid, purchase_type AS "purchase_type: _", price, currency, descr, created_at
             FROM purchases WHERE pid = $1"#,
             usize_to_i32(pid)
         )
         .fetch_one(&self.db)
-        .await
+        .await?
+        .try_into()?;
+        attach_sales(&self.db, std::slice::from_mut(&mut purchase)).await?;
+        Ok(purchase)
     }
 }
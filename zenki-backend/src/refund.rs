@@ -0,0 +1,512 @@
+use sqlx::PgConnection;
+use thiserror::Error;
+use time::{Duration, PrimitiveDateTime};
+use zenki_util::{Money, i32_to_usize, usize_to_i32};
+
+use crate::{
+    PurchaseType, State, TransactionStatus,
+    inventory::take_from_inventory,
+    purchase::decode_money,
+    subscription::end_subscription,
+    transaction::PaymentMethod,
+    wallet::{WalletError, refund_to_wallet},
+};
+
+/// How long after buying a refund can still be requested.
+pub const REFUND_WINDOW: Duration = Duration::days(14);
+
+/// How long a game, or the games of a bundle together, may have been played since buying it and
+/// still be refunded.
+pub const REFUND_MAX_PLAYTIME: Duration = Duration::hours(2);
+
+/// How long an attempt to pay a refund back through the payment gateway is given before it is
+/// tried again.
+pub const REFUND_RETRY_AFTER: Duration = Duration::minutes(10);
+
+#[derive(Error, Debug)]
+pub enum RefundError {
+    #[error("transaction not found")]
+    TransactionNotFound,
+    #[error("transaction is {0}")]
+    InvalidStatus(TransactionStatus),
+    #[error("refund window has passed")]
+    WindowPassed,
+    #[error("game has been played for too long")]
+    PlaytimeExceeded,
+    #[error("bought items have been used up")]
+    ItemsConsumed,
+    #[error("transaction is not a pre-order of a game yet to be released")]
+    NotPreorder,
+    #[error("trades between players cannot be refunded")]
+    MarketTrade,
+    #[error(transparent)]
+    Wallet(#[from] WalletError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct RefundRequest {
+    pub tid: i32,
+    pub uid: i32,
+    pub s_uname: String,
+    pub pid: i32,
+    pub p_descr: Option<String>,
+    pub amount: Money,
+    pub bought_at: Option<PrimitiveDateTime>,
+    pub refund_reason: Option<String>,
+    pub refund_requested_at: Option<PrimitiveDateTime>,
+}
+
+impl State {
+    /// Asks for a refund of a completed transaction bought by `uid`.
+    ///
+    /// # Errors
+    /// when the transaction is not a completed purchase of `uid`, it is a trade between players,
+    /// it is past the refund window, the game or the games of a bundle have been played for too
+    /// long by its owner or the family members it was lent to, bought items have been used up, or
+    /// querying the database failed
+    pub async fn request_refund(
+        &self,
+        uid: usize,
+        tid: usize,
+        reason: Option<String>,
+    ) -> Result<(), RefundError> {
+        let mut tx = self.db.begin().await?;
+
+        let x = sqlx::query!(
+            r#"SELECT
+                t.status AS "status: TransactionStatus",
+                p.purchase_type AS "purchase_type: PurchaseType",
+                t.seller_uid IS NOT NULL AS "market_trade!",
+                COALESCE(t.bought_at + make_interval(secs => $3) > NOW(), FALSE) AS "in_window!",
+                COALESCE((
+                    SELECT SUM(gi.duration)
+                    FROM game_interaction gi
+                    WHERE COALESCE(t.receiver_uid, t.uid) IN (gi.uid, gi.lender_uid)
+                        AND CASE p.purchase_type
+                            WHEN 'bundle' THEN gi.gid IN (
+                                SELECT bg.gid FROM bundle_grants bg WHERE bg.tid = t.tid
+                            )
+                            ELSE gi.gid = p.gid
+                        END
+                        AND gi.startplay_at >= t.bought_at
+                ), make_interval()) > make_interval(secs => $4) AS "played_too_long!",
+                EXISTS (
+                    SELECT 1 FROM entitlements e
+                    JOIN purchases ep ON e.pid = ep.pid
+                    LEFT JOIN inventory i ON e.uid = i.uid AND e.pid = i.pid
+                    WHERE e.tid = t.tid AND ep.purchase_type = 'in_game_purchase'
+                        AND COALESCE(i.quantity, 0) < e.quantity
+                ) AS "consumed!"
+            FROM transactions t
+            JOIN purchases p ON t.pid = p.pid
+            WHERE t.tid = $1 AND t.uid = $2
+            FOR UPDATE OF t"#,
+            usize_to_i32(tid),
+            usize_to_i32(uid),
+            REFUND_WINDOW.as_seconds_f64(),
+            REFUND_MAX_PLAYTIME.as_seconds_f64(),
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(RefundError::TransactionNotFound)?;
+
+        if x.status != TransactionStatus::Completed {
+            return Err(RefundError::InvalidStatus(x.status));
+        }
+        if x.market_trade {
+            return Err(RefundError::MarketTrade);
+        }
+        if !x.in_window {
+            return Err(RefundError::WindowPassed);
+        }
+        if matches!(
+            x.purchase_type,
+            PurchaseType::GamePurchase | PurchaseType::Bundle
+        ) && x.played_too_long
+        {
+            return Err(RefundError::PlaytimeExceeded);
+        }
+        if x.consumed {
+            return Err(RefundError::ItemsConsumed);
+        }
+
+        sqlx::query!(
+            r"UPDATE transactions
+            SET status = 'refund_requested', refund_reason = $2, refund_requested_at = NOW()
+            WHERE tid = $1",
+            usize_to_i32(tid),
+            reason,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Refunds a requested transaction, taking back the games, entitlements and in-game items it
+    /// granted.
+    ///
+    /// # Errors
+    /// when no refund was requested for the transaction, bought items have been used up since,
+    /// or querying the database failed
+    pub async fn approve_refund(&self, tid: usize) -> Result<(), RefundError> {
+        let mut tx = self.db.begin().await?;
+
+        let Some(x) = refund_transaction(
+            &mut tx,
+            usize_to_i32(tid),
+            TransactionStatus::RefundRequested,
+        )
+        .await?
+        else {
+            return Err(self.refund_status_error(tid).await);
+        };
+        take_back(&mut tx, usize_to_i32(tid), &x).await?;
+        tx.commit().await?;
+
+        self.refund_payment(&x).await?;
+        Ok(())
+    }
+
+    /// Cancels a pre-order of `uid` before the game is released, refunding it in full right
+    /// away.
+    ///
+    /// # Errors
+    /// when the transaction is not a completed pre-order of `uid` of a game yet to be released,
+    /// or querying the database failed
+    pub async fn cancel_preorder(&self, uid: usize, tid: usize) -> Result<(), RefundError> {
+        let mut tx = self.db.begin().await?;
+
+        let x = sqlx::query!(
+            r#"SELECT t.preorder AND COALESCE(g.release_at > NOW(), FALSE) AS "cancellable!"
+            FROM transactions t
+            JOIN purchases p ON t.pid = p.pid
+            JOIN games g ON p.gid = g.gid
+            WHERE t.tid = $1 AND t.uid = $2
+            FOR UPDATE OF t"#,
+            usize_to_i32(tid),
+            usize_to_i32(uid),
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(RefundError::TransactionNotFound)?;
+        if !x.cancellable {
+            return Err(RefundError::NotPreorder);
+        }
+
+        let Some(x) =
+            refund_transaction(&mut tx, usize_to_i32(tid), TransactionStatus::Completed).await?
+        else {
+            return Err(self.refund_status_error(tid).await);
+        };
+        take_back(&mut tx, usize_to_i32(tid), &x).await?;
+        tx.commit().await?;
+
+        self.refund_payment(&x).await?;
+        Ok(())
+    }
+
+    /// # Errors
+    /// when no refund was requested for the transaction or querying the database failed
+    pub async fn decline_refund(&self, tid: usize) -> Result<(), RefundError> {
+        let declined = sqlx::query!(
+            r"UPDATE transactions SET status = 'completed'
+            WHERE tid = $1 AND status = 'refund_requested'",
+            usize_to_i32(tid),
+        )
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        if declined == 0 {
+            return Err(self.refund_status_error(tid).await);
+        }
+        Ok(())
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_refund_requests(&self) -> sqlx::Result<Vec<RefundRequest>> {
+        sqlx::query!(
+            r#"SELECT
+                t.tid,
+                t.uid,
+                u.uname,
+                t.pid,
+                p.descr,
+                t.amount,
+                t.currency,
+                t.bought_at,
+                t.refund_reason,
+                t.refund_requested_at
+            FROM transactions t
+            JOIN users u ON t.uid = u.uid
+            JOIN purchases p ON t.pid = p.pid
+            WHERE t.status = 'refund_requested'
+            ORDER BY t.refund_requested_at"#
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| {
+            Ok(RefundRequest {
+                tid: x.tid,
+                uid: x.uid,
+                s_uname: x.uname,
+                pid: x.pid,
+                p_descr: x.descr,
+                amount: decode_money(x.amount, &x.currency)?,
+                bought_at: x.bought_at,
+                refund_reason: x.refund_reason,
+                refund_requested_at: x.refund_requested_at,
+            })
+        })
+        .collect()
+    }
+
+    /// Pays a refunded transaction back through the payment gateway, if it was paid through it.
+    ///
+    /// A refund the gateway does not take stays pending for [`retry_pending_refunds`].
+    ///
+    /// [`retry_pending_refunds`]: Self::retry_pending_refunds
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn refund_payment(&self, refunded: &Refunded) -> sqlx::Result<()> {
+        if let Some(payment_ref) = &refunded.payment_ref
+            && !refunded.amount.is_zero()
+        {
+            self.pay_back(refunded.tid, payment_ref, refunded.amount)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Tries again to pay back the refunds the payment gateway did not take yet, returning how
+    /// many it took now.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn retry_pending_refunds(&self) -> sqlx::Result<usize> {
+        let pending = sqlx::query!(
+            r#"SELECT t.tid, t.amount, t.currency, o.payment_ref AS "payment_ref!"
+            FROM transactions t
+            JOIN orders o ON t.oid = o.oid
+            WHERE t.refund_pending AND o.payment_ref IS NOT NULL
+            ORDER BY t.refunded_at"#
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut paid = 0;
+        for x in pending {
+            let amount = decode_money(x.amount, &x.currency)?;
+            if self.pay_back(x.tid, &x.payment_ref, amount).await? {
+                paid += 1;
+            }
+        }
+        Ok(paid)
+    }
+
+    /// Pays back the transactions of order `oid` that failed after its payment was captured.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub(crate) async fn pay_back_order(&self, oid: i32) -> sqlx::Result<()> {
+        let pending = sqlx::query!(
+            r#"SELECT t.tid, t.amount, t.currency, o.payment_ref AS "payment_ref!"
+            FROM transactions t
+            JOIN orders o ON t.oid = o.oid
+            WHERE t.oid = $1 AND t.refund_pending AND o.payment_ref IS NOT NULL"#,
+            oid,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        for x in pending {
+            let amount = decode_money(x.amount, &x.currency)?;
+            self.pay_back(x.tid, &x.payment_ref, amount).await?;
+        }
+        Ok(())
+    }
+
+    /// Refunds `amount` of `payment_ref` for pending refund `tid`, returning whether the gateway
+    /// took it.
+    ///
+    /// The attempt is claimed first, so the refund is not paid twice while a recent attempt
+    /// may still be under way.
+    async fn pay_back(&self, tid: i32, payment_ref: &str, amount: Money) -> sqlx::Result<bool> {
+        let claimed = sqlx::query!(
+            r"UPDATE transactions SET refund_attempted_at = NOW()
+            WHERE tid = $1 AND refund_pending
+                AND (refund_attempted_at IS NULL
+                    OR refund_attempted_at <= NOW() - make_interval(secs => $2))",
+            tid,
+            REFUND_RETRY_AFTER.as_seconds_f64(),
+        )
+        .execute(&self.db)
+        .await?
+        .rows_affected()
+            > 0;
+        if !claimed {
+            return Ok(false);
+        }
+
+        match self.gateway.refund(payment_ref, amount).await {
+            Ok(()) => {
+                sqlx::query!(
+                    r"UPDATE transactions SET refund_pending = FALSE WHERE tid = $1",
+                    tid
+                )
+                .execute(&self.db)
+                .await?;
+                Ok(true)
+            }
+            Err(e) => {
+                log::warn!("paying back refund of transaction {tid} failed, will retry: {e}");
+                Ok(false)
+            }
+        }
+    }
+
+    async fn refund_status_error(&self, tid: usize) -> RefundError {
+        match sqlx::query!(
+            r#"SELECT status AS "status: TransactionStatus" FROM transactions WHERE tid = $1"#,
+            usize_to_i32(tid),
+        )
+        .fetch_optional(&self.db)
+        .await
+        {
+            Ok(Some(x)) => RefundError::InvalidStatus(x.status),
+            Ok(None) => RefundError::TransactionNotFound,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// Takes back the games, entitlements and in-game items refunded transaction `tid` granted,
+/// declining it instead while it waits in a gift inbox.
+///
+/// # Errors
+/// when bought items have been used up or querying the database failed
+async fn take_back(
+    tx: &mut PgConnection,
+    tid: i32,
+    refunded: &Refunded,
+) -> Result<(), RefundError> {
+    // a gift still waiting in the inbox never reached the receiver's library
+    let gift_pending = sqlx::query!(
+        r"UPDATE gifts SET status = 'declined', responded_at = NOW()
+        WHERE tid = $1 AND status = 'pending'",
+        tid,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if refunded.purchase_type == PurchaseType::GamePurchase && !gift_pending {
+        sqlx::query!(
+            r"DELETE FROM game_user WHERE uid = $1 AND gid = $2 AND wishlist = FALSE",
+            refunded.owner,
+            refunded.gid,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    if refunded.purchase_type == PurchaseType::Bundle {
+        sqlx::query!(
+            r"DELETE FROM game_user
+            WHERE uid = $1 AND wishlist = FALSE
+                AND gid IN (SELECT gid FROM bundle_grants WHERE tid = $2)",
+            refunded.owner,
+            tid,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    let stocked = sqlx::query!(
+        r"SELECT e.uid, e.pid, e.quantity
+        FROM entitlements e
+        JOIN purchases p ON e.pid = p.pid
+        WHERE e.tid = $1 AND p.purchase_type = 'in_game_purchase'",
+        tid
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for x in stocked {
+        take_from_inventory(tx, i32_to_usize(x.uid), x.pid, x.quantity)
+            .await?
+            .ok_or(RefundError::ItemsConsumed)?;
+    }
+    sqlx::query!(r"DELETE FROM entitlements WHERE tid = $1", tid)
+        .execute(&mut *tx)
+        .await?;
+    if refunded.purchase_type == PurchaseType::Subscriptions {
+        end_subscription(tx, tid).await?;
+    }
+    Ok(())
+}
+
+pub struct Refunded {
+    pub tid: i32,
+    /// Whoever received what was bought.
+    pub owner: i32,
+    pub gid: i32,
+    pub purchase_type: PurchaseType,
+    pub amount: Money,
+    /// How the payment gateway knows the payment, unless it was not made through it.
+    pub payment_ref: Option<String>,
+}
+
+/// Marks a transaction that is `from` as refunded, crediting wallet payments back to the buyer.
+/// Payments made through the payment gateway are left pending for [`State::refund_payment`].
+///
+/// Returns `None` when the transaction is not `from`.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn refund_transaction(
+    tx: &mut PgConnection,
+    tid: i32,
+    from: TransactionStatus,
+) -> Result<Option<Refunded>, RefundError> {
+    let Some(x) = sqlx::query!(
+        r#"UPDATE transactions t
+        SET status = 'refunded', refunded_at = NOW(),
+            refund_pending = t.amount <> 0
+                AND (SELECT payment_ref FROM orders WHERE oid = t.oid) IS NOT NULL
+        FROM purchases p
+        WHERE t.tid = $1 AND t.pid = p.pid AND t.status = $2
+        RETURNING
+            t.uid,
+            t.payment_method AS "payment_method: PaymentMethod",
+            t.amount,
+            t.currency,
+            COALESCE(t.receiver_uid, t.uid) AS "owner!",
+            p.gid,
+            p.purchase_type AS "purchase_type: PurchaseType",
+            (SELECT payment_ref FROM orders WHERE oid = t.oid) AS "payment_ref?""#,
+        tid,
+        from as TransactionStatus,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let amount = decode_money(x.amount, &x.currency)?;
+    if matches!(x.payment_method, PaymentMethod::Wallet) && !amount.is_zero() {
+        refund_to_wallet(tx, x.uid, amount, tid).await?;
+    }
+
+    Ok(Some(Refunded {
+        tid,
+        owner: x.owner,
+        gid: x.gid,
+        purchase_type: x.purchase_type,
+        amount,
+        payment_ref: x.payment_ref,
+    }))
+}
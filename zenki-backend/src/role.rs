@@ -0,0 +1,106 @@
+use std::fmt::Display;
+
+use zenki_util::usize_to_i32;
+
+use crate::State;
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "role_n", rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Developer,
+    Player,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Admin => "Admin",
+            Self::Developer => "Developer",
+            Self::Player => "Player",
+        })
+    }
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "permission_n", rename_all = "snake_case")]
+pub enum Permission {
+    ManageUsers,
+    ManageRoles,
+    ManageGames,
+    ManagePurchases,
+    ViewAllTransactions,
+    /// Store-wide settings and every studio's games, which only admins manage.
+    ManageStore,
+}
+
+impl State {
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_roles(&self, uid: usize) -> sqlx::Result<Vec<Role>> {
+        Ok(sqlx::query!(
+            r#"SELECT role AS "role: Role" FROM user_roles WHERE uid = $1 ORDER BY role"#,
+            usize_to_i32(uid)
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| x.role)
+        .collect())
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn has_role(&self, uid: usize, role: Role) -> sqlx::Result<bool> {
+        Ok(sqlx::query!(
+            r"SELECT 1 AS exists FROM user_roles WHERE uid = $1 AND role = $2",
+            usize_to_i32(uid),
+            role as Role,
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .is_some())
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn has_permission(&self, uid: usize, permission: Permission) -> sqlx::Result<bool> {
+        Ok(sqlx::query!(
+            r"SELECT 1 AS exists
+            FROM user_roles ur
+            JOIN role_permissions rp ON ur.role = rp.role
+            WHERE ur.uid = $1 AND rp.permission = $2",
+            usize_to_i32(uid),
+            permission as Permission,
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .is_some())
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn grant_role(&self, uid: usize, role: Role) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"INSERT INTO user_roles (uid, role) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            usize_to_i32(uid),
+            role as Role,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn revoke_role(&self, uid: usize, role: Role) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"DELETE FROM user_roles WHERE uid = $1 AND role = $2",
+            usize_to_i32(uid),
+            role as Role,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+}
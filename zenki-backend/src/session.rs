@@ -0,0 +1,104 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::PgExecutor;
+use time::Duration;
+use zenki_util::{i32_to_usize, usize_to_i32};
+
+use crate::State;
+
+pub type SessionMac = Hmac<Sha256>;
+
+/// How long a session stays valid after its last use.
+pub const SESSION_TTL: Duration = Duration::days(7);
+
+const SESSION_TOKEN_BYTES: usize = 32;
+
+impl State {
+    /// Creates a new session for the user and returns the signed cookie value for it.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn create_session(&self, uid: usize) -> sqlx::Result<String> {
+        let mut bytes = [0; SESSION_TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+
+        sqlx::query!(r"DELETE FROM sessions WHERE expires_at <= NOW()")
+            .execute(&self.db)
+            .await?;
+        sqlx::query!(
+            r"INSERT INTO sessions (token, uid, expires_at)
+            VALUES ($1, $2, NOW() + make_interval(secs => $3))",
+            token,
+            usize_to_i32(uid),
+            SESSION_TTL.as_seconds_f64(),
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(self.sign_session_token(&token))
+    }
+
+    /// Resolves a signed cookie value to the user owning the session, renewing its expiry.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn lookup_session(&self, cookie: &str) -> sqlx::Result<Option<usize>> {
+        let Some(token) = self.verify_session_cookie(cookie) else {
+            return Ok(None);
+        };
+
+        Ok(sqlx::query!(
+            r"UPDATE sessions SET expires_at = NOW() + make_interval(secs => $2)
+            WHERE token = $1 AND expires_at > NOW()
+            RETURNING uid",
+            token,
+            SESSION_TTL.as_seconds_f64(),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .map(|x| i32_to_usize(x.uid)))
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn revoke_session(&self, cookie: &str) -> sqlx::Result<()> {
+        let Some(token) = self.verify_session_cookie(cookie) else {
+            return Ok(());
+        };
+
+        sqlx::query!(r"DELETE FROM sessions WHERE token = $1", token)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    fn sign_session_token(&self, token: &str) -> String {
+        let mut mac = self.session_mac.clone();
+        mac.update(token.as_bytes());
+        format!("{token}.{}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn verify_session_cookie<'a>(&self, cookie: &'a str) -> Option<&'a str> {
+        let (token, signature) = cookie.split_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        let mut mac = self.session_mac.clone();
+        mac.update(token.as_bytes());
+        mac.verify_slice(&signature).ok().map(|()| token)
+    }
+}
+
+/// Ends every session of `uid`, e.g. once their password changed.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn revoke_user_sessions<'e>(
+    executor: impl PgExecutor<'e>,
+    uid: usize,
+) -> sqlx::Result<()> {
+    sqlx::query!(r"DELETE FROM sessions WHERE uid = $1", usize_to_i32(uid))
+        .execute(executor)
+        .await?;
+    Ok(())
+}
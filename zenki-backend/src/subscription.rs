@@ -0,0 +1,547 @@
+use std::fmt::Display;
+
+use sqlx::{PgConnection, PgExecutor};
+use thiserror::Error;
+use time::PrimitiveDateTime;
+use zenki_util::{Money, i32_to_usize, usize_to_i32};
+
+use crate::{
+    CheckoutError, GameRef, State, TransactionStatus, notification::notify, purchase::decode_money,
+    transaction::PaymentMethod,
+};
+
+/// How often the background job renews the subscriptions that are due and retries pending
+/// payments and refunds.
+pub const RENEWAL_INTERVAL: std::time::Duration = std::time::Duration::from_mins(10);
+
+#[derive(Error, Debug)]
+pub enum SubscriptionError {
+    #[error("subscription not found")]
+    SubscriptionNotFound,
+    #[error("subscription is {0}")]
+    InvalidStatus(SubscriptionStatus),
+    #[error("a subscription must include at least one game")]
+    EmptyCatalog,
+    #[error("price must be positive")]
+    InvalidPrice,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "billing_period_n", rename_all = "snake_case")]
+pub enum BillingPeriod {
+    Monthly,
+    Yearly,
+}
+
+impl Display for BillingPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Monthly => "Monthly",
+            Self::Yearly => "Yearly",
+        })
+    }
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "subscription_status_n", rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    /// Renews at expiry, when there is a payment method to charge.
+    Active,
+    /// Runs until expiry, then lapses.
+    Cancelled,
+    Expired,
+}
+
+impl Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Active => "active",
+            Self::Cancelled => "cancelled",
+            Self::Expired => "expired",
+        })
+    }
+}
+
+pub struct Subscription {
+    pub sid: i32,
+    pub pid: i32,
+    pub p_descr: Option<String>,
+    pub gid: i32,
+    pub gname: String,
+    pub billing_period: BillingPeriod,
+    pub price: Money,
+    pub payment_method: Option<PaymentMethod>,
+    pub status: SubscriptionStatus,
+    pub started_at: PrimitiveDateTime,
+    pub expires_at: PrimitiveDateTime,
+    pub cancelled_at: Option<PrimitiveDateTime>,
+}
+
+pub struct SubscriptionPlan {
+    pub pid: i32,
+    pub billing_period: BillingPeriod,
+    /// The games holders may play.
+    pub catalog: Vec<GameRef>,
+}
+
+/// What a run of the renewal job did.
+#[derive(Debug, Default)]
+pub struct RenewalReport {
+    pub renewed: usize,
+    pub expired: usize,
+}
+
+impl State {
+    /// Lists every subscription `uid` has held, running ones first.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_subscriptions(&self, uid: usize) -> sqlx::Result<Vec<Subscription>> {
+        sqlx::query!(
+            r#"SELECT
+                s.sid,
+                p.pid,
+                p.descr AS p_descr,
+                g.gid,
+                g.gname,
+                sp.billing_period AS "billing_period: BillingPeriod",
+                p.price,
+                p.currency,
+                s.payment_method AS "payment_method: PaymentMethod",
+                s.status AS "status: SubscriptionStatus",
+                s.started_at,
+                s.expires_at,
+                s.cancelled_at
+            FROM subscriptions s
+            JOIN subscription_plans sp ON s.pid = sp.pid
+            JOIN purchases p ON s.pid = p.pid
+            JOIN games g ON p.gid = g.gid
+            WHERE s.uid = $1
+            ORDER BY s.status = 'expired', s.expires_at DESC"#,
+            usize_to_i32(uid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| {
+            Ok(Subscription {
+                sid: x.sid,
+                pid: x.pid,
+                p_descr: x.p_descr,
+                gid: x.gid,
+                gname: x.gname,
+                billing_period: x.billing_period,
+                price: decode_money(x.price, &x.currency)?,
+                payment_method: x.payment_method,
+                status: x.status,
+                started_at: x.started_at,
+                expires_at: x.expires_at,
+                cancelled_at: x.cancelled_at,
+            })
+        })
+        .collect()
+    }
+
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_subscription_plan(
+        &self,
+        pid: usize,
+    ) -> sqlx::Result<Option<SubscriptionPlan>> {
+        let Some(plan) = sqlx::query!(
+            r#"SELECT pid, billing_period AS "billing_period: BillingPeriod"
+            FROM subscription_plans WHERE pid = $1"#,
+            usize_to_i32(pid),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let catalog = sqlx::query_as!(
+            GameRef,
+            r"SELECT g.gid, g.gname
+            FROM subscription_catalog sc
+            JOIN games g ON sc.gid = g.gid
+            WHERE sc.pid = $1
+            ORDER BY g.gname",
+            plan.pid,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(Some(SubscriptionPlan {
+            pid: plan.pid,
+            billing_period: plan.billing_period,
+            catalog,
+        }))
+    }
+
+    /// Creates a subscription plan for a game, returning its purchase id.
+    ///
+    /// # Errors
+    /// when the price is not positive, the catalog is empty, or querying the database failed
+    pub async fn create_subscription_plan(
+        &self,
+        gid: usize,
+        price: Money,
+        billing_period: BillingPeriod,
+        descr: Option<&str>,
+        catalog: &[usize],
+    ) -> Result<i32, SubscriptionError> {
+        if price.minor() <= 0 {
+            return Err(SubscriptionError::InvalidPrice);
+        }
+        let catalog = catalog
+            .iter()
+            .copied()
+            .map(usize_to_i32)
+            .collect::<Vec<_>>();
+        if catalog.is_empty() {
+            return Err(SubscriptionError::EmptyCatalog);
+        }
+
+        let mut tx = self.db.begin().await?;
+        let pid = sqlx::query!(
+            r"INSERT INTO purchases (gid, purchase_type, price, currency, descr)
+            VALUES ($1, 'subscriptions', $2, $3, $4)
+            RETURNING pid",
+            usize_to_i32(gid),
+            price.minor(),
+            price.currency().to_string(),
+            descr.map(str::trim).filter(|x| !x.is_empty()),
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .pid;
+        sqlx::query!(
+            r"INSERT INTO subscription_plans (pid, billing_period) VALUES ($1, $2)",
+            pid,
+            billing_period as BillingPeriod,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r"INSERT INTO subscription_catalog (pid, gid)
+            SELECT $1, UNNEST($2::int[]) ON CONFLICT DO NOTHING",
+            pid,
+            &catalog,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(pid)
+    }
+
+    /// Stops an active subscription of `uid` from renewing; it stays usable until it expires.
+    ///
+    /// # Errors
+    /// when the subscription is not an active one of `uid` or querying the database failed
+    pub async fn cancel_subscription(
+        &self,
+        uid: usize,
+        sid: usize,
+    ) -> Result<(), SubscriptionError> {
+        self.set_subscription_status(
+            uid,
+            sid,
+            SubscriptionStatus::Active,
+            SubscriptionStatus::Cancelled,
+        )
+        .await
+    }
+
+    /// Lets a cancelled subscription of `uid` renew again, as long as it has not expired.
+    ///
+    /// # Errors
+    /// when the subscription is not a cancelled one of `uid` or querying the database failed
+    pub async fn resume_subscription(
+        &self,
+        uid: usize,
+        sid: usize,
+    ) -> Result<(), SubscriptionError> {
+        self.set_subscription_status(
+            uid,
+            sid,
+            SubscriptionStatus::Cancelled,
+            SubscriptionStatus::Active,
+        )
+        .await
+    }
+
+    async fn set_subscription_status(
+        &self,
+        uid: usize,
+        sid: usize,
+        from: SubscriptionStatus,
+        to: SubscriptionStatus,
+    ) -> Result<(), SubscriptionError> {
+        let updated = sqlx::query!(
+            r"UPDATE subscriptions
+            SET status = $4,
+                cancelled_at = CASE WHEN $4::subscription_status_n = 'cancelled' THEN NOW() END
+            WHERE sid = $1 AND uid = $2 AND status = $3 AND expires_at > NOW()",
+            usize_to_i32(sid),
+            usize_to_i32(uid),
+            from as SubscriptionStatus,
+            to as SubscriptionStatus,
+        )
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        if updated > 0 {
+            return Ok(());
+        }
+
+        let status = sqlx::query!(
+            r#"SELECT
+                CASE WHEN expires_at > NOW() THEN status ELSE 'expired' END
+                    AS "status!: SubscriptionStatus"
+            FROM subscriptions WHERE sid = $1 AND uid = $2"#,
+            usize_to_i32(sid),
+            usize_to_i32(uid),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(SubscriptionError::SubscriptionNotFound)?
+        .status;
+        Err(SubscriptionError::InvalidStatus(status))
+    }
+
+    /// Whether `uid` may play a game, by owning it or holding a subscription that includes it.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn is_entitled(&self, uid: usize, gid: usize) -> sqlx::Result<bool> {
+        entitled(&self.db, uid, gid).await
+    }
+
+    /// Charges every subscription that is due for another period, and expires the ones that
+    /// are cancelled or cannot be charged.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn renew_subscriptions(&self) -> sqlx::Result<RenewalReport> {
+        let due = sqlx::query!(
+            r"SELECT sid FROM subscriptions
+            WHERE status <> 'expired' AND expires_at <= NOW()
+            ORDER BY expires_at"
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut report = RenewalReport::default();
+        for x in due {
+            match self.renew_subscription(x.sid).await? {
+                Some(true) => report.renewed += 1,
+                Some(false) => report.expired += 1,
+                None => {}
+            }
+        }
+        Ok(report)
+    }
+
+    /// Renews one due subscription, returning whether it renewed, or `None` when it was not due
+    /// after all or its renewal still waits for the payment gateway.
+    ///
+    /// The renewal is placed as a pending order like any checkout, so the gateway is never called
+    /// while the subscription is locked.
+    async fn renew_subscription(&self, sid: i32) -> sqlx::Result<Option<bool>> {
+        let mut tx = self.db.begin().await?;
+        let Some(sub) = sqlx::query!(
+            r#"SELECT
+                s.uid,
+                s.pid,
+                s.payment_method AS "payment_method: PaymentMethod",
+                s.status AS "status: SubscriptionStatus",
+                g.gname,
+                EXISTS (
+                    SELECT 1 FROM transactions t
+                    WHERE t.uid = s.uid AND t.pid = s.pid AND t.status = 'pending'
+                ) AS "renewing!"
+            FROM subscriptions s
+            JOIN purchases p ON s.pid = p.pid
+            JOIN games g ON p.gid = g.gid
+            WHERE s.sid = $1 AND s.status <> 'expired' AND s.expires_at <= NOW()
+            FOR UPDATE OF s"#,
+            sid,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+        if sub.renewing {
+            return Ok(None);
+        }
+
+        let uid = i32_to_usize(sub.uid);
+        if sub.status == SubscriptionStatus::Active
+            && let Some(payment_method) = sub.payment_method
+        {
+            let renewed = self
+                .checkout(
+                    tx,
+                    uid,
+                    uid,
+                    payment_method,
+                    &[(i32_to_usize(sub.pid), 1)],
+                    None,
+                    None,
+                )
+                .await;
+            match renewed {
+                Ok(receipt) if receipt.status == TransactionStatus::Completed => {
+                    let mut conn = self.db.acquire().await?;
+                    notify(
+                        &mut conn,
+                        sub.uid,
+                        &format!("Your subscription to {} has been renewed.", sub.gname),
+                        receipt.lines.first().map(|line| line.tid),
+                    )
+                    .await?;
+                    return Ok(Some(true));
+                }
+                // a webhook settles the renewal; when it declines, the next run tries again
+                Ok(_) => return Ok(None),
+                Err(CheckoutError::Database(e)) => return Err(e),
+                Err(e) => log::info!("renewing subscription {sid} failed: {e}"),
+            }
+            tx = self.db.begin().await?;
+        }
+
+        let expired = sqlx::query!(
+            r"UPDATE subscriptions SET status = 'expired'
+            WHERE sid = $1 AND status <> 'expired' AND expires_at <= NOW()",
+            sid,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if expired == 0 {
+            return Ok(None);
+        }
+        notify(
+            &mut tx,
+            sub.uid,
+            &format!("Your subscription to {} has ended.", sub.gname),
+            None,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(Some(false))
+    }
+}
+
+/// Starts or extends by one billing period the subscription of `uid` that transaction `tid`
+/// paid for.
+///
+/// Only a subscription `uid` paid for themselves renews with the same payment method; a gifted
+/// one lapses at expiry unless they had one running already.
+///
+/// # Errors
+/// when the purchase is not a subscription plan or querying the database failed
+pub async fn subscribe(
+    tx: &mut PgConnection,
+    uid: usize,
+    tid: i32,
+    pid: i32,
+) -> Result<(), CheckoutError> {
+    let payment_method = sqlx::query!(
+        r#"SELECT
+            CASE WHEN uid = $2 THEN payment_method END AS "payment_method: PaymentMethod"
+        FROM transactions WHERE tid = $1"#,
+        tid,
+        usize_to_i32(uid),
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .payment_method;
+
+    let extended = sqlx::query!(
+        r"UPDATE subscriptions s
+        SET expires_at = GREATEST(s.expires_at, NOW()) + billing_interval(sp.billing_period),
+            payment_method = COALESCE($3, s.payment_method),
+            status = CASE WHEN $3 IS NULL THEN s.status ELSE 'active' END,
+            cancelled_at = CASE WHEN $3 IS NULL THEN s.cancelled_at END
+        FROM subscription_plans sp
+        WHERE sp.pid = s.pid AND s.uid = $1 AND s.pid = $2 AND s.status <> 'expired'
+        RETURNING s.sid",
+        usize_to_i32(uid),
+        pid,
+        payment_method as Option<PaymentMethod>,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let sid = match extended {
+        Some(x) => x.sid,
+        None => {
+            sqlx::query!(
+                r"INSERT INTO subscriptions (uid, pid, payment_method, expires_at)
+                SELECT $1, pid, $3, NOW() + billing_interval(billing_period)
+                FROM subscription_plans WHERE pid = $2
+                RETURNING sid",
+                usize_to_i32(uid),
+                pid,
+                payment_method as Option<PaymentMethod>,
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(CheckoutError::PurchaseNotFound)?
+            .sid
+        }
+    };
+
+    sqlx::query!(
+        r"INSERT INTO subscription_payments (tid, sid) VALUES ($1, $2)",
+        tid,
+        sid,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Ends the subscription that refunded transaction `tid` paid for, right away.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn end_subscription(tx: &mut PgConnection, tid: i32) -> sqlx::Result<()> {
+    sqlx::query!(
+        r"UPDATE subscriptions
+        SET status = 'expired', expires_at = LEAST(expires_at, NOW())
+        WHERE sid = (SELECT sid FROM subscription_payments WHERE tid = $1)",
+        tid,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// # Errors
+/// when querying the database failed
+pub async fn entitled<'e>(
+    executor: impl PgExecutor<'e>,
+    uid: usize,
+    gid: usize,
+) -> sqlx::Result<bool> {
+    Ok(sqlx::query!(
+        r#"SELECT
+            EXISTS (
+                SELECT 1 FROM game_user
+                WHERE uid = $1 AND gid = $2 AND wishlist IS NOT TRUE
+            ) OR EXISTS (
+                SELECT 1 FROM subscriptions s
+                JOIN subscription_catalog sc ON s.pid = sc.pid
+                WHERE s.uid = $1 AND sc.gid = $2
+                AND s.status <> 'expired' AND s.expires_at > NOW()
+            ) AS "entitled!""#,
+        usize_to_i32(uid),
+        usize_to_i32(gid),
+    )
+    .fetch_one(executor)
+    .await?
+    .entitled)
+}
@@ -0,0 +1,277 @@
+use std::fmt::Display;
+
+use sqlx::PgConnection;
+use thiserror::Error;
+use zenki_util::{Money, usize_to_i32};
+
+use crate::{PurchaseType, State};
+
+#[derive(Error, Debug)]
+pub enum TaxError {
+    #[error("country must be a two letter ISO 3166-1 code")]
+    InvalidCountry,
+    #[error("tax rate must be between 0% and 100%")]
+    InvalidRate,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// The tax levied on one purchase type in one country.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaxRate {
+    pub country: String,
+    pub purchase_type: PurchaseType,
+    /// In basis points, hundredths of a percent.
+    pub rate_bp: i32,
+    /// Whether prices already include the tax, as with VAT, rather than it being added at
+    /// checkout, as with sales tax.
+    pub inclusive: bool,
+}
+
+impl TaxRate {
+    /// The tax on `amount` charged: the part of it that is tax when inclusive, or what is added
+    /// on top otherwise, rounded half up to the minor unit.
+    #[must_use]
+    pub fn tax_on(&self, amount: Money) -> Option<Money> {
+        let rate = i64::from(self.rate_bp);
+        let base = if self.inclusive {
+            10_000 + rate
+        } else {
+            10_000
+        };
+        let tax = amount.minor().checked_mul(rate)?.checked_add(base / 2)? / base;
+        Some(Money::new(tax, amount.currency()))
+    }
+}
+
+impl Display for TaxRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}, {})",
+            format_tax_rate(self.rate_bp),
+            self.country,
+            if self.inclusive { "included" } else { "added" }
+        )
+    }
+}
+
+/// Formats basis points as a percentage, e.g. `19%` or `7.25%`.
+#[must_use]
+pub fn format_tax_rate(rate_bp: i32) -> String {
+    let (whole, fraction) = (rate_bp / 100, rate_bp % 100);
+    if fraction == 0 {
+        format!("{whole}%")
+    } else if fraction % 10 == 0 {
+        format!("{whole}.{}%", fraction / 10)
+    } else {
+        format!("{whole}.{fraction:02}%")
+    }
+}
+
+/// Parses a percentage with at most two decimals, e.g. `19` or `7.25`, into basis points.
+#[must_use]
+pub fn parse_tax_rate(s: &str) -> Option<i32> {
+    let s = s.trim().trim_end_matches('%');
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if whole.is_empty()
+        || fraction.len() > 2
+        || !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let whole = whole.parse::<i32>().ok()?;
+    let fraction = format!("{fraction:0<2}").parse::<i32>().ok()?;
+    whole
+        .checked_mul(100)?
+        .checked_add(fraction)
+        .filter(|x| *x <= 10_000)
+}
+
+/// Normalizes a country code to upper case, checking it is two ASCII letters.
+fn normalize_country(country: &str) -> Result<String, TaxError> {
+    let country = country.trim().to_ascii_uppercase();
+    if country.len() == 2 && country.bytes().all(|b| b.is_ascii_uppercase()) {
+        Ok(country)
+    } else {
+        Err(TaxError::InvalidCountry)
+    }
+}
+
+impl State {
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_tax_rates(&self) -> sqlx::Result<Vec<TaxRate>> {
+        sqlx::query_as!(
+            TaxRate,
+            r#"SELECT country, purchase_type AS "purchase_type: _", rate_bp, inclusive
+            FROM tax_rates
+            ORDER BY country, purchase_type"#
+        )
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// Sets the tax levied on `purchase_type` in `country`, replacing any earlier rate.
+    ///
+    /// # Errors
+    /// when the country code or rate is invalid, or querying the database failed
+    pub async fn set_tax_rate(
+        &self,
+        country: &str,
+        purchase_type: PurchaseType,
+        rate_bp: i32,
+        inclusive: bool,
+    ) -> Result<(), TaxError> {
+        let country = normalize_country(country)?;
+        if !(0..=10_000).contains(&rate_bp) {
+            return Err(TaxError::InvalidRate);
+        }
+        sqlx::query!(
+            r"INSERT INTO tax_rates (country, purchase_type, rate_bp, inclusive)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (country, purchase_type) DO UPDATE
+            SET rate_bp = EXCLUDED.rate_bp, inclusive = EXCLUDED.inclusive",
+            country,
+            purchase_type as PurchaseType,
+            rate_bp,
+            inclusive,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// # Errors
+    /// when the country code is invalid or querying the database failed
+    pub async fn delete_tax_rate(
+        &self,
+        country: &str,
+        purchase_type: PurchaseType,
+    ) -> Result<(), TaxError> {
+        let country = normalize_country(country)?;
+        sqlx::query!(
+            r"DELETE FROM tax_rates WHERE country = $1 AND purchase_type = $2",
+            country,
+            purchase_type as PurchaseType,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// # Errors
+    /// when the country code is invalid or querying the database failed
+    pub async fn update_billing_country(
+        &self,
+        id: usize,
+        country: Option<&str>,
+    ) -> Result<(), TaxError> {
+        let country = country.map(normalize_country).transpose()?;
+        sqlx::query!(
+            r"UPDATE users SET billing_country = $1 WHERE uid = $2",
+            country,
+            usize_to_i32(id),
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+/// The tax rates of the billing country of `uid`, none when it is not known.
+///
+/// # Errors
+/// when querying the database failed
+pub async fn billing_tax_rates(tx: &mut PgConnection, uid: usize) -> sqlx::Result<Vec<TaxRate>> {
+    sqlx::query_as!(
+        TaxRate,
+        r#"SELECT r.country, r.purchase_type AS "purchase_type: _", r.rate_bp, r.inclusive
+        FROM tax_rates r
+        JOIN users u ON r.country = u.billing_country
+        WHERE u.uid = $1"#,
+        usize_to_i32(uid),
+    )
+    .fetch_all(&mut *tx)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use zenki_util::Currency;
+
+    use super::*;
+
+    fn rate(rate_bp: i32, inclusive: bool) -> TaxRate {
+        TaxRate {
+            country: "DE".to_string(),
+            purchase_type: PurchaseType::GamePurchase,
+            rate_bp,
+            inclusive,
+        }
+    }
+
+    fn tax_on(rate_bp: i32, inclusive: bool, minor: i64) -> Option<i64> {
+        rate(rate_bp, inclusive)
+            .tax_on(Money::new(minor, Currency::USD))
+            .map(Money::minor)
+    }
+
+    #[test]
+    fn takes_inclusive_tax_out_of_the_price() {
+        assert_eq!(tax_on(1_900, true, 1_190), Some(190));
+        // 19% of 9.99 included is 1.595...
+        assert_eq!(tax_on(1_900, true, 999), Some(160));
+        assert_eq!(tax_on(1_900, true, 1), Some(0));
+    }
+
+    #[test]
+    fn adds_exclusive_tax_on_top_rounding_half_up() {
+        assert_eq!(tax_on(725, false, 1_000), Some(73));
+        assert_eq!(tax_on(500, false, 10), Some(1));
+        assert_eq!(tax_on(500, false, 9), Some(0));
+        assert_eq!(tax_on(1_900, false, 1), Some(0));
+        assert_eq!(tax_on(10_000, false, 1), Some(1));
+    }
+
+    #[test]
+    fn levies_nothing_on_nothing_or_at_zero_percent() {
+        assert_eq!(tax_on(0, false, 1_000), Some(0));
+        assert_eq!(tax_on(0, true, 1_000), Some(0));
+        assert_eq!(tax_on(1_900, false, 0), Some(0));
+        assert_eq!(tax_on(1_900, true, 0), Some(0));
+    }
+
+    #[test]
+    fn refuses_to_overflow() {
+        assert_eq!(tax_on(1_900, false, i64::MAX), None);
+        assert_eq!(tax_on(1_900, true, i64::MAX), None);
+    }
+
+    #[test]
+    fn formats_and_parses_basis_points() {
+        assert_eq!(format_tax_rate(1_900), "19%");
+        assert_eq!(format_tax_rate(750), "7.5%");
+        assert_eq!(format_tax_rate(725), "7.25%");
+        assert_eq!(format_tax_rate(5), "0.05%");
+        assert_eq!(parse_tax_rate("19"), Some(1_900));
+        assert_eq!(parse_tax_rate("7.5%"), Some(750));
+        assert_eq!(parse_tax_rate(" 7.25 "), Some(725));
+        assert_eq!(parse_tax_rate("100"), Some(10_000));
+        assert_eq!(parse_tax_rate("100.01"), None);
+        assert_eq!(parse_tax_rate("7.255"), None);
+        assert_eq!(parse_tax_rate("-1"), None);
+        assert_eq!(parse_tax_rate(""), None);
+    }
+
+    #[test]
+    fn normalizes_country_codes() {
+        assert_eq!(normalize_country(" de").ok().as_deref(), Some("DE"));
+        assert!(normalize_country("DEU").is_err());
+        assert!(normalize_country("D1").is_err());
+        assert!(normalize_country("").is_err());
+    }
+}
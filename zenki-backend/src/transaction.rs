@@ -1,22 +1,92 @@
 use std::{fmt::Display, str::FromStr};
 
+use sqlx::PgConnection;
 use thiserror::Error;
-use time::PrimitiveDateTime;
-use zenki_util::{i32_to_usize, usize_to_i32};
+use time::{Duration, PrimitiveDateTime};
+use zenki_util::{Currency, Money, i32_to_usize, usize_to_i32};
 
-use crate::{PurchaseType, State};
+use crate::{
+    GiftStatus, Purchase, PurchaseType, State,
+    age::{AgeCheck, AgeError, purchase_age_check},
+    bundle::{bundle_items, complete_the_bundle, grant_bundle},
+    coupon::{coupon_eligibility, redeemable_coupon},
+    discount::attach_sales,
+    entitlement::{ensure_entitleable, grant_entitlement, is_add_on},
+    game::claim_game,
+    gateway::{GatewayError, PaymentRequest, WebhookEvent},
+    gift::send_gift,
+    invoice::issue_invoice,
+    purchase::{PurchaseRow, decode_money},
+    subscription::subscribe,
+    tax::{TaxRate, billing_tax_rates},
+    wallet::{TopUpError, WalletError, parse_top_up_reference, pay_from_wallet},
+};
+
+/// How long an attempt to settle a pending order or top-up is given before it is tried again.
+pub const SETTLE_RETRY_AFTER: Duration = Duration::minutes(10);
 
 #[derive(Error, Debug)]
 #[error("error while parsing payment method via string")]
 pub struct ParsePaymentMethodError;
 
-#[derive(sqlx::Type)]
+#[derive(Error, Debug)]
+pub enum CheckoutError {
+    #[error("purchase not found")]
+    PurchaseNotFound,
+    #[error(
+        "quantity must be at least one, and exactly one for games, DLC, bundles and subscriptions"
+    )]
+    InvalidQuantity,
+    #[error("game is already owned by the receiver")]
+    AlreadyOwned,
+    #[error("the receiver must own the base game first")]
+    RequiresBaseGame,
+    #[error("amount does not fit into the price range")]
+    AmountOverflow,
+    #[error("all items of an order must be priced in the same currency")]
+    CurrencyMismatch,
+    #[error("order has no items")]
+    EmptyOrder,
+    #[error("coupon not found")]
+    CouponNotFound,
+    #[error("coupon has expired")]
+    CouponExpired,
+    #[error("coupon has reached its usage limit")]
+    CouponUsedUp,
+    #[error("coupon does not apply to these items")]
+    CouponNotApplicable,
+    #[error("insufficient funds in wallet")]
+    InsufficientFunds,
+    #[error("pending order not found")]
+    OrderNotFound,
+    #[error(transparent)]
+    Age(#[from] AgeError),
+    #[error(transparent)]
+    Payment(#[from] GatewayError),
+    #[error(transparent)]
+    TopUp(#[from] TopUpError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<WalletError> for CheckoutError {
+    fn from(value: WalletError) -> Self {
+        match value {
+            WalletError::InvalidAmount => Self::AmountOverflow,
+            WalletError::InsufficientFunds => Self::InsufficientFunds,
+            WalletError::Database(e) => Self::Database(e),
+        }
+    }
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy)]
 #[sqlx(type_name = "payment_n", rename_all = "snake_case")]
 pub enum PaymentMethod {
     CreditCard,
     DebitCard,
     Paypal,
     Etc,
+    Wallet,
 }
 
 impl Display for PaymentMethod {
@@ -26,6 +96,7 @@ impl Display for PaymentMethod {
             Self::DebitCard => "Debit Card",
             Self::Paypal => "PayPal",
             Self::Etc => "etc.",
+            Self::Wallet => "Wallet",
         })
     }
 }
@@ -39,18 +110,50 @@ impl FromStr for PaymentMethod {
             "debit_card" => Ok(Self::DebitCard),
             "paypal" => Ok(Self::Paypal),
             "etc" => Ok(Self::Etc),
+            "wallet" => Ok(Self::Wallet),
             _ => Err(ParsePaymentMethodError),
         }
     }
 }
 
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "transaction_status_n", rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Pending,
+    Completed,
+    RefundRequested,
+    Refunded,
+    Failed,
+}
+
+impl Display for TransactionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Pending => "Pending",
+            Self::Completed => "Completed",
+            Self::RefundRequested => "Refund requested",
+            Self::Refunded => "Refunded",
+            Self::Failed => "Failed",
+        })
+    }
+}
+
 pub struct TransactionHistory {
     pub tid: i32,
+    pub uid: i32,
+    pub s_uname: String,
+    pub receiver_uid: Option<i32>,
+    pub r_uname: Option<String>,
+    pub gift_status: Option<GiftStatus>,
     pub gid: i32,
     pub gname: String,
     pub pid: i32,
     pub p_descr: Option<String>,
     pub bought_at: Option<PrimitiveDateTime>,
+    pub status: TransactionStatus,
+    /// Who sold the items, for trades between players.
+    pub seller_uid: Option<i32>,
+    pub seller_uname: Option<String>,
 }
 
 pub struct RichTransaction {
@@ -59,12 +162,56 @@ pub struct RichTransaction {
     pub receiver_uid: Option<i32>,
     pub pid: i32,
     pub payment_method: PaymentMethod,
-    pub amount: i32,
+    pub amount: Money,
+    /// The part of `amount` that is tax.
+    pub tax: Money,
+    pub tax_rate: Option<TaxRate>,
+    pub quantity: i32,
     pub bought_at: Option<PrimitiveDateTime>,
-    pub status: Option<String>,
+    pub status: TransactionStatus,
+    pub refund_reason: Option<String>,
     pub s_uname: String,
     pub r_uname: String,
     pub p_descr: Option<String>,
+    /// Whether it was bought before the game was released.
+    pub preorder: bool,
+    /// When the game comes out, while it is pre-ordered and not released yet.
+    pub available_at: Option<PrimitiveDateTime>,
+    /// Who sold the items, for trades between players.
+    pub seller_uid: Option<i32>,
+    pub seller_uname: Option<String>,
+    /// The part of `amount` the store kept of a trade between players.
+    pub market_fee: Money,
+}
+
+pub struct ReceiptLine {
+    pub tid: i32,
+    pub pid: i32,
+    pub descr: Option<String>,
+    pub purchase_type: PurchaseType,
+    pub unit_price: Money,
+    pub quantity: i32,
+    pub line_total: Money,
+    pub discount: Money,
+    pub tax: Money,
+    pub tax_rate: Option<TaxRate>,
+}
+
+/// What a checkout charged, priced from the purchases table at the time of sale.
+pub struct Receipt {
+    pub oid: i32,
+    pub payment_method: PaymentMethod,
+    pub lines: Vec<ReceiptLine>,
+    pub subtotal: Money,
+    pub discount: Money,
+    /// All tax levied, whether included in prices or added to the total.
+    pub tax: Money,
+    /// The part of `tax` added on top of prices rather than included in them.
+    pub added_tax: Money,
+    pub total: Money,
+    pub coupon_code: Option<String>,
+    /// `Pending` while the payment gateway has not settled the payment yet.
+    pub status: TransactionStatus,
 }
 
 impl State {
@@ -74,60 +221,412 @@ impl State {
         &self,
         tid: usize,
     ) -> sqlx::Result<Option<RichTransaction>> {
-        sqlx::query_as!(
-            RichTransaction,
+        sqlx::query!(
             r#"SELECT
                 t.tid,
                 t.uid,
                 t.receiver_uid,
                 t.pid,
-                t.payment_method AS "payment_method: _",
+                t.payment_method AS "payment_method: PaymentMethod",
                 t.amount,
+                t.currency,
+                t.tax,
+                t.tax_country,
+                t.tax_rate_bp,
+                t.tax_inclusive,
+                t.quantity,
                 t.bought_at,
-                t.status,
+                t.status AS "status: TransactionStatus",
+                t.refund_reason,
                 p.descr AS "p_descr",
+                p.purchase_type AS "purchase_type!: PurchaseType",
                 sender.uname AS "s_uname",
-                receiver.uname AS "r_uname"
+                receiver.uname AS "r_uname",
+                t.preorder,
+                CASE WHEN t.preorder AND g.release_at > NOW() THEN g.release_at END AS available_at,
+                t.seller_uid,
+                seller.uname AS "seller_uname?",
+                t.market_fee
             FROM transactions t
             LEFT JOIN purchases p ON t.pid = p.pid
+            LEFT JOIN games g ON p.gid = g.gid
             LEFT JOIN users sender ON t.uid = sender.uid
             LEFT JOIN users receiver ON t.receiver_uid = receiver.uid
+            LEFT JOIN users seller ON t.seller_uid = seller.uid
             WHERE t.tid = $1"#,
             usize_to_i32(tid)
         )
         .fetch_optional(&self.db)
-        .await
+        .await?
+        .map(|x| {
+            Ok(RichTransaction {
+                tid: x.tid,
+                uid: x.uid,
+                receiver_uid: x.receiver_uid,
+                pid: x.pid,
+                payment_method: x.payment_method,
+                amount: decode_money(x.amount, &x.currency)?,
+                tax: decode_money(x.tax, &x.currency)?,
+                tax_rate: x.tax_country.map(|country| TaxRate {
+                    country,
+                    purchase_type: x.purchase_type,
+                    rate_bp: x.tax_rate_bp,
+                    inclusive: x.tax_inclusive,
+                }),
+                quantity: x.quantity,
+                bought_at: x.bought_at,
+                status: x.status,
+                refund_reason: x.refund_reason,
+                s_uname: x.s_uname,
+                r_uname: x.r_uname,
+                p_descr: x.p_descr,
+                preorder: x.preorder,
+                available_at: x.available_at,
+                seller_uid: x.seller_uid,
+                seller_uname: x.seller_uname,
+                market_fee: decode_money(x.market_fee, &x.currency)?,
+            })
+        })
+        .transpose()
     }
 
+    /// Sells `quantity` of a purchase to `ruid`, paid by `uid`, as an order of one line.
+    ///
     /// # Errors
-    /// when querying the database failed
+    /// when the purchase does not exist, the quantity is invalid, the coupon cannot be redeemed,
+    /// the receiver already owns the game, or querying the database failed
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_transaction(
         &self,
         uid: usize,
         pid: usize,
         ruid: usize,
         payment_method: PaymentMethod,
-        amount: usize,
-    ) -> sqlx::Result<()> {
-        let purchase = self.expect_purchase(pid).await?;
-        if matches!(purchase.purchase_type, PurchaseType::GamePurchase) {
-            let gid = i32_to_usize(purchase.gid);
-            self.remove_game_from_wishlist(ruid, gid).await?;
-            self.add_game_to_library(ruid, gid).await?;
+        quantity: usize,
+        coupon: Option<&str>,
+        gift_message: Option<&str>,
+    ) -> Result<Receipt, CheckoutError> {
+        let tx = self.db.begin().await?;
+        self.checkout(
+            tx,
+            uid,
+            ruid,
+            payment_method,
+            &[(pid, quantity)],
+            coupon,
+            gift_message,
+        )
+        .await
+    }
+
+    /// Places an order on `tx` and pays for it. Wallet payments and free orders settle at once;
+    /// anything else waits as pending while the payment gateway authorizes and captures it, and
+    /// stays pending when the gateway times out, until a webhook or a retry settles it.
+    ///
+    /// # Errors
+    /// when the order cannot be priced, the payment was declined, the wallet cannot cover a
+    /// wallet payment, or querying the database failed
+    #[allow(clippy::too_many_arguments)]
+    pub async fn checkout(
+        &self,
+        mut tx: sqlx::Transaction<'_, sqlx::Postgres>,
+        uid: usize,
+        ruid: usize,
+        payment_method: PaymentMethod,
+        lines: &[(usize, usize)],
+        coupon: Option<&str>,
+        gift_message: Option<&str>,
+    ) -> Result<Receipt, CheckoutError> {
+        let order = price_order(&mut tx, uid, ruid, lines, coupon).await?;
+        if matches!(payment_method, PaymentMethod::Wallet) || order.total.is_zero() {
+            let receipt = record_order(
+                &mut tx,
+                (uid, ruid),
+                payment_method,
+                order,
+                gift_message,
+                TransactionStatus::Completed,
+            )
+            .await?;
+            fulfill_order(&mut tx, receipt.oid).await?;
+            tx.commit().await?;
+            return Ok(receipt);
         }
 
-        sqlx::query!(
-            r#"INSERT INTO transactions (uid, pid, receiver_uid, payment_method, amount)
-            VALUES ($1, $2, $3, $4, $5)"#,
-            usize_to_i32(uid),
-            usize_to_i32(pid),
-            usize_to_i32(ruid),
-            payment_method as PaymentMethod,
-            usize_to_i32(amount),
+        let mut receipt = record_order(
+            &mut tx,
+            (uid, ruid),
+            payment_method,
+            order,
+            gift_message,
+            TransactionStatus::Pending,
         )
-        .execute(&self.db)
         .await?;
-        Ok(())
+        tx.commit().await?;
+
+        let request = PaymentRequest {
+            reference: order_reference(receipt.oid),
+            uid,
+            payment_method,
+            amount: receipt.total,
+        };
+        match self.gateway.authorize(&request).await {
+            Ok(payment_ref) => {
+                if self.settle_order(receipt.oid, &payment_ref).await? {
+                    receipt.status = TransactionStatus::Completed;
+                }
+            }
+            Err(GatewayError::Timeout) => {
+                log::info!(
+                    "payment of order {} timed out, awaiting webhook",
+                    receipt.oid
+                );
+            }
+            Err(e) => {
+                self.fail_order(receipt.oid).await?;
+                return Err(e.into());
+            }
+        }
+        Ok(receipt)
+    }
+
+    /// Settles or fails a pending order or wallet top-up as a webhook call of the payment gateway
+    /// says.
+    ///
+    /// # Errors
+    /// when the webhook cannot be verified, names no pending order or top-up, the order cannot be
+    /// fulfilled anymore, the capture was declined, or querying the database failed
+    pub async fn handle_payment_webhook(
+        &self,
+        payload: &str,
+        signature: &str,
+    ) -> Result<(), CheckoutError> {
+        match self.gateway.parse_webhook(payload, signature)? {
+            WebhookEvent::Authorized {
+                reference,
+                payment_ref,
+            } => {
+                if let Some(wtid) = parse_top_up_reference(&reference) {
+                    if self.settle_top_up(wtid, &payment_ref).await?.is_none()
+                        && self.top_up_failed(wtid).await?
+                    {
+                        // a top-up that failed meanwhile never captures its payment
+                        self.void_payment(&payment_ref).await;
+                    }
+                    return Ok(());
+                }
+                let oid = parse_order_reference(&reference).ok_or(CheckoutError::OrderNotFound)?;
+                self.settle_order(oid, &payment_ref).await.map(|_| ())
+            }
+            WebhookEvent::Declined { reference } => {
+                if let Some(wtid) = parse_top_up_reference(&reference) {
+                    return if self.fail_top_up(wtid).await? {
+                        Ok(())
+                    } else {
+                        Err(CheckoutError::OrderNotFound)
+                    };
+                }
+                let oid = parse_order_reference(&reference).ok_or(CheckoutError::OrderNotFound)?;
+                if self.fail_order(oid).await? {
+                    Ok(())
+                } else {
+                    Err(CheckoutError::OrderNotFound)
+                }
+            }
+        }
+    }
+
+    /// Settles a pending order whose payment the gateway authorized as `payment_ref`, returning
+    /// whether it completed.
+    ///
+    /// The payment is captured before anything is handed out, and outside of any database
+    /// transaction. A capture that times out leaves the order pending for
+    /// [`retry_pending_payments`], and a declined one fails the order and releases the
+    /// authorization. Returns `false` as well while another attempt is settling the order.
+    ///
+    /// [`retry_pending_payments`]: Self::retry_pending_payments
+    async fn settle_order(&self, oid: i32, payment_ref: &str) -> Result<bool, CheckoutError> {
+        // remembering the authorization first lets a retry pick the order up, whatever happens
+        let Some(x) = sqlx::query!(
+            r#"UPDATE orders SET payment_ref = $2, settle_attempted_at = NOW()
+            WHERE oid = $1
+                AND EXISTS (SELECT 1 FROM transactions WHERE oid = $1 AND status = 'pending')
+                AND (settle_attempted_at IS NULL
+                    OR settle_attempted_at <= NOW() - make_interval(secs => $3))
+            RETURNING total, currency, captured_at IS NOT NULL AS "captured!""#,
+            oid,
+            payment_ref,
+            SETTLE_RETRY_AFTER.as_seconds_f64(),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        else {
+            return self.pending_order(oid).await.map(|()| false);
+        };
+        let total = decode_money(x.total, &x.currency)?;
+
+        if !x.captured {
+            match self.gateway.capture(payment_ref, total).await {
+                Ok(()) => {}
+                Err(GatewayError::Timeout) => {
+                    log::info!("capturing the payment of order {oid} timed out, will retry");
+                    return Ok(false);
+                }
+                Err(e) => {
+                    self.fail_order(oid).await?;
+                    self.void_payment(payment_ref).await;
+                    return Err(e.into());
+                }
+            }
+            sqlx::query!(r"UPDATE orders SET captured_at = NOW() WHERE oid = $1", oid)
+                .execute(&self.db)
+                .await?;
+        }
+        self.fulfill_captured_order(oid).await
+    }
+
+    /// Completes a pending order whose payment was captured and hands out what it bought. An
+    /// order that cannot be fulfilled anymore, e.g. because the receiver got the game elsewhere
+    /// meanwhile, fails instead and its payment is paid back.
+    async fn fulfill_captured_order(&self, oid: i32) -> Result<bool, CheckoutError> {
+        let mut tx = self.db.begin().await?;
+        let pending = sqlx::query!(
+            r"UPDATE transactions SET status = 'completed' WHERE oid = $1 AND status = 'pending'",
+            oid,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if pending == 0 {
+            return Ok(false);
+        }
+        match fulfill_order(&mut tx, oid).await {
+            Ok(()) => {
+                tx.commit().await?;
+                Ok(true)
+            }
+            // the order stays pending with its capture for a retry
+            Err(CheckoutError::Database(e)) => Err(e.into()),
+            Err(e) => {
+                tx.rollback().await?;
+                self.fail_captured_order(oid).await?;
+                self.pay_back_order(oid).await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Settles again the pending orders whose settlement was interrupted, e.g. because capturing
+    /// the payment timed out, returning how many completed now.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn retry_pending_payments(&self) -> sqlx::Result<usize> {
+        let pending = sqlx::query!(
+            r#"SELECT o.oid, o.payment_ref AS "payment_ref!"
+            FROM orders o
+            WHERE o.payment_ref IS NOT NULL
+                AND o.settle_attempted_at <= NOW() - make_interval(secs => $1)
+                AND EXISTS (SELECT 1 FROM transactions t WHERE t.oid = o.oid AND t.status = 'pending')
+            ORDER BY o.oid"#,
+            SETTLE_RETRY_AFTER.as_seconds_f64(),
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut completed = 0;
+        for x in pending {
+            match self.settle_order(x.oid, &x.payment_ref).await {
+                Ok(true) => completed += 1,
+                Ok(false) => {}
+                Err(CheckoutError::Database(e)) => return Err(e),
+                Err(e) => log::info!("settling order {} failed: {e}", x.oid),
+            }
+        }
+        Ok(completed)
+    }
+
+    /// Succeeds when order `oid` is still pending.
+    async fn pending_order(&self, oid: i32) -> Result<(), CheckoutError> {
+        let pending = sqlx::query!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM transactions WHERE oid = $1 AND status = 'pending'
+            ) AS "pending!""#,
+            oid,
+        )
+        .fetch_one(&self.db)
+        .await?
+        .pending;
+        if pending {
+            Ok(())
+        } else {
+            Err(CheckoutError::OrderNotFound)
+        }
+    }
+
+    /// Releases an authorization that will never be captured. A provider that cannot release it
+    /// lets it expire on its own, so that is only logged.
+    pub(crate) async fn void_payment(&self, payment_ref: &str) {
+        if let Err(e) = self.gateway.void(payment_ref).await {
+            log::warn!("releasing payment {payment_ref} failed: {e}");
+        }
+    }
+
+    /// Marks the transactions of a pending order failed, giving back the coupon use it took.
+    /// Returns whether the order was pending.
+    async fn fail_order(&self, oid: i32) -> sqlx::Result<bool> {
+        let mut tx = self.db.begin().await?;
+        let failed = sqlx::query!(
+            r"UPDATE transactions SET status = 'failed' WHERE oid = $1 AND status = 'pending'",
+            oid,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if failed > 0 {
+            sqlx::query!(r"DELETE FROM coupon_redemptions WHERE oid = $1", oid)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(failed > 0)
+    }
+
+    /// Marks the transactions of a pending order whose payment was captured failed, leaving
+    /// what they charged pending to be paid back.
+    async fn fail_captured_order(&self, oid: i32) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        let failed = sqlx::query!(
+            r"UPDATE transactions SET status = 'failed', refund_pending = amount <> 0
+            WHERE oid = $1 AND status = 'pending'",
+            oid,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if failed > 0 {
+            sqlx::query!(r"DELETE FROM coupon_redemptions WHERE oid = $1", oid)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Prices an order by `uid` for `ruid` without placing it, e.g. to preview a coupon.
+    ///
+    /// # Errors
+    /// when the order cannot be priced or querying the database failed
+    pub async fn preview_order(
+        &self,
+        uid: usize,
+        ruid: usize,
+        lines: &[(usize, usize)],
+        coupon: Option<&str>,
+    ) -> Result<PricedOrder, CheckoutError> {
+        let mut tx = self.db.begin().await?;
+        let order = price_order(&mut tx, uid, ruid, lines, coupon).await?;
+        tx.rollback().await?;
+        Ok(order)
     }
 
     /// # Errors
@@ -140,15 +639,27 @@ impl State {
             TransactionHistory,
             r#"SELECT
             t.tid,
+            t.uid,
+            sender.uname AS s_uname,
+            t.receiver_uid,
+            receiver.uname AS "r_uname?",
+            gf.status AS "gift_status?: _",
             g.gid,
             g.gname,
             p.pid,
             p.descr AS p_descr,
-            t.bought_at
+            t.bought_at,
+            t.status AS "status: _",
+            t.seller_uid,
+            seller.uname AS "seller_uname?"
             FROM transactions t
             JOIN purchases p ON t.pid = p.pid
             JOIN games g ON p.gid = g.gid
-            WHERE t.uid = $1
+            JOIN users sender ON t.uid = sender.uid
+            LEFT JOIN users receiver ON t.receiver_uid = receiver.uid
+            LEFT JOIN users seller ON t.seller_uid = seller.uid
+            LEFT JOIN gifts gf ON t.tid = gf.tid
+            WHERE t.uid = $1 OR gf.receiver_uid = $1 OR t.seller_uid = $1
             ORDER BY t.bought_at DESC;"#,
             usize_to_i32(uid)
         )
@@ -156,3 +667,480 @@ impl State {
         .await
     }
 }
+
+pub struct PricedLine {
+    pub purchase: Purchase,
+    /// The effective price, or what is left of a bundle the receiver partly owns.
+    pub unit_price: Money,
+    pub quantity: i32,
+    pub line_total: Money,
+    pub discount: Money,
+    /// Included in what is charged for the line, or added to it, as the tax rate says.
+    pub tax: Money,
+    pub tax_rate: Option<TaxRate>,
+}
+
+impl PricedLine {
+    /// What the line costs after the coupon, including any tax added on top.
+    ///
+    /// # Errors
+    /// when the amount does not fit into the price range
+    pub fn charged(&self) -> Result<Money, CheckoutError> {
+        let charged = self
+            .line_total
+            .checked_sub(self.discount)
+            .ok_or(CheckoutError::AmountOverflow)?;
+        match &self.tax_rate {
+            Some(rate) if !rate.inclusive => charged
+                .checked_add(self.tax)
+                .ok_or(CheckoutError::AmountOverflow),
+            _ => Ok(charged),
+        }
+    }
+}
+
+/// An order priced from the purchases table, before anything is written.
+pub struct PricedOrder {
+    pub lines: Vec<PricedLine>,
+    pub subtotal: Money,
+    pub discount: Money,
+    /// All tax levied, whether included in prices or added to the total.
+    pub tax: Money,
+    /// The part of `tax` added on top of prices rather than included in them.
+    pub added_tax: Money,
+    pub total: Money,
+    pub coupon_code: Option<String>,
+}
+
+/// The part of the tax added on top of prices, which is what `total` exceeds `subtotal` less
+/// `discount` by.
+fn added_tax(subtotal: Money, discount: Money, total: Money) -> Option<Money> {
+    total.checked_add(discount)?.checked_sub(subtotal)
+}
+
+/// Checks that `uid` is old enough for the games purchase `pid` of game `gid` grants and, when
+/// buying it for someone else, that `ruid` is not known to be too young for them.
+///
+/// # Errors
+/// when the buyer is too young or has yet to confirm their age, the receiver is too young, or
+/// querying the database failed
+async fn ensure_old_enough(
+    tx: &mut PgConnection,
+    uid: usize,
+    ruid: usize,
+    (pid, gid): (i32, i32),
+) -> Result<(), AgeError> {
+    purchase_age_check(&mut *tx, Some(uid), pid, gid)
+        .await?
+        .ensure()?;
+    if ruid != uid {
+        // nobody vouches for the age of the receiver, so only a known one is checked; they confirm
+        // it themselves when accepting the gift
+        if let AgeCheck::TooYoung { min_age } =
+            purchase_age_check(&mut *tx, Some(ruid), pid, gid).await?
+        {
+            return Err(AgeError::TooYoung(min_age));
+        }
+    }
+    Ok(())
+}
+
+/// Prices every `(pid, quantity)` line bought by `uid` for `ruid`, applying running sales,
+/// bundle completion, the coupon and the taxes of the billing country of `uid`.
+///
+/// # Errors
+/// when a purchase does not exist, a quantity is invalid, the buyer is not old enough for a game
+/// or has yet to confirm their age, the receiver of a gift is known to be too young for it, the
+/// receiver already owns a game or DLC or lacks the base game of one, a bundle is owned entirely,
+/// the lines are priced in different currencies, the coupon cannot be redeemed, or querying the
+/// database failed
+pub async fn price_order(
+    tx: &mut PgConnection,
+    uid: usize,
+    ruid: usize,
+    lines: &[(usize, usize)],
+    coupon: Option<&str>,
+) -> Result<PricedOrder, CheckoutError> {
+    let mut priced = Vec::with_capacity(lines.len());
+    let mut subtotal = None::<Money>;
+    for &(pid, quantity) in lines {
+        let mut purchase: Purchase = sqlx::query_as!(
+            PurchaseRow,
+            r#"SELECT pid, gid, purchase_type AS "purchase_type: _", price, currency, descr, created_at
+            FROM purchases WHERE pid = $1"#,
+            usize_to_i32(pid)
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(CheckoutError::PurchaseNotFound)?
+        .try_into()?;
+        attach_sales(&mut *tx, std::slice::from_mut(&mut purchase)).await?;
+        ensure_old_enough(&mut *tx, uid, ruid, (purchase.pid, purchase.gid)).await?;
+
+        let is_single = matches!(
+            purchase.purchase_type,
+            PurchaseType::GamePurchase
+                | PurchaseType::Dlc
+                | PurchaseType::Bundle
+                | PurchaseType::Subscriptions
+        );
+        let quantity = i32::try_from(quantity).map_err(|_| CheckoutError::InvalidQuantity)?;
+        if quantity == 0 || (is_single && quantity != 1) {
+            return Err(CheckoutError::InvalidQuantity);
+        }
+        let unit_price = if purchase.purchase_type == PurchaseType::Bundle {
+            let items = bundle_items(&mut *tx, purchase.pid, ruid).await?;
+            complete_the_bundle(purchase.effective_price(), &items)?
+        } else {
+            purchase.effective_price()
+        };
+        let line_total = unit_price
+            .checked_mul(i64::from(quantity))
+            .ok_or(CheckoutError::AmountOverflow)?;
+        subtotal = Some(match subtotal {
+            None => line_total,
+            Some(subtotal) => subtotal.checked_add(line_total).ok_or(
+                if subtotal.currency() == line_total.currency() {
+                    CheckoutError::AmountOverflow
+                } else {
+                    CheckoutError::CurrencyMismatch
+                },
+            )?,
+        });
+        priced.push(PricedLine {
+            purchase,
+            unit_price,
+            quantity,
+            line_total,
+            discount: Money::zero(line_total.currency()),
+            tax: Money::zero(line_total.currency()),
+            tax_rate: None,
+        });
+    }
+    let subtotal = subtotal.ok_or(CheckoutError::EmptyOrder)?;
+
+    // games go first, so DLC bought alongside its game is granted after it
+    priced.sort_by_key(|x| x.purchase.purchase_type != PurchaseType::GamePurchase);
+    ensure_receivable(tx, ruid, &priced).await?;
+
+    let coupon_code = match coupon.filter(|code| !code.trim().is_empty()) {
+        None => None,
+        Some(code) => {
+            let coupon = redeemable_coupon(tx, uid, code).await?;
+            let pids = priced.iter().map(|x| x.purchase.pid).collect::<Vec<_>>();
+            let eligible = coupon_eligibility(tx, &coupon.code, &pids).await?;
+            let discounts = coupon.allocate(&priced, &eligible)?;
+            for (line, discount) in priced.iter_mut().zip(discounts) {
+                line.discount = discount;
+            }
+            Some(coupon.code)
+        }
+    };
+
+    let discount = priced
+        .iter()
+        .try_fold(Money::zero(subtotal.currency()), |total, line| {
+            total.checked_add(line.discount)
+        })
+        .ok_or(CheckoutError::AmountOverflow)?;
+    let tax = apply_taxes(tx, uid, &mut priced, subtotal.currency()).await?;
+    let total = priced
+        .iter()
+        .try_fold(Money::zero(subtotal.currency()), |total, line| {
+            total
+                .checked_add(line.charged()?)
+                .ok_or(CheckoutError::AmountOverflow)
+        })?;
+    Ok(PricedOrder {
+        lines: priced,
+        subtotal,
+        discount,
+        tax,
+        added_tax: added_tax(subtotal, discount, total).ok_or(CheckoutError::AmountOverflow)?,
+        total,
+        coupon_code,
+    })
+}
+
+/// Levies the tax of the billing country of `uid` on what each line is charged after the
+/// coupon, returning all tax levied.
+///
+/// # Errors
+/// when an amount does not fit into the price range or querying the database failed
+async fn apply_taxes(
+    tx: &mut PgConnection,
+    uid: usize,
+    lines: &mut [PricedLine],
+    currency: Currency,
+) -> Result<Money, CheckoutError> {
+    let rates = billing_tax_rates(tx, uid).await?;
+    let mut tax = Money::zero(currency);
+    for line in lines {
+        let Some(rate) = rates
+            .iter()
+            .find(|x| x.purchase_type == line.purchase.purchase_type)
+        else {
+            continue;
+        };
+        line.tax = line
+            .line_total
+            .checked_sub(line.discount)
+            .and_then(|x| rate.tax_on(x))
+            .ok_or(CheckoutError::AmountOverflow)?;
+        line.tax_rate = Some(rate.clone());
+        tax = tax
+            .checked_add(line.tax)
+            .ok_or(CheckoutError::AmountOverflow)?;
+    }
+    Ok(tax)
+}
+
+/// Checks that `ruid` may be given every line: games they do not own yet, and add-ons of games
+/// they own or buy in the same order.
+///
+/// # Errors
+/// when the receiver already owns a game or DLC, lacks the base game of an add-on, or querying
+/// the database failed
+async fn ensure_receivable(
+    tx: &mut PgConnection,
+    ruid: usize,
+    lines: &[PricedLine],
+) -> Result<(), CheckoutError> {
+    for line in lines {
+        let purchase = &line.purchase;
+        if purchase.purchase_type == PurchaseType::GamePurchase {
+            ensure_not_owned(tx, ruid, purchase.gid).await?;
+        } else if is_add_on(purchase.purchase_type)
+            && !lines.iter().any(|x| {
+                x.purchase.purchase_type == PurchaseType::GamePurchase
+                    && x.purchase.gid == purchase.gid
+            })
+        {
+            ensure_entitleable(
+                tx,
+                ruid,
+                (purchase.pid, purchase.gid, purchase.purchase_type),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a priced order by `uid` for `ruid` and its transactions in `status`, paying wallet
+/// payments and redeeming the coupon, without handing out anything yet.
+///
+/// # Errors
+/// when the wallet cannot cover a wallet payment or querying the database failed
+async fn record_order(
+    tx: &mut PgConnection,
+    (uid, ruid): (usize, usize),
+    payment_method: PaymentMethod,
+    order: PricedOrder,
+    gift_message: Option<&str>,
+    status: TransactionStatus,
+) -> Result<Receipt, CheckoutError> {
+    let oid = sqlx::query!(
+        r"INSERT INTO orders
+        (uid, receiver_uid, payment_method, subtotal, discount, tax, total, currency, coupon_code,
+            gift_message)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING oid",
+        usize_to_i32(uid),
+        usize_to_i32(ruid),
+        payment_method as PaymentMethod,
+        order.subtotal.minor(),
+        order.discount.minor(),
+        order.tax.minor(),
+        order.total.minor(),
+        order.total.currency().to_string(),
+        order.coupon_code,
+        gift_message,
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .oid;
+
+    if matches!(payment_method, PaymentMethod::Wallet) && !order.total.is_zero() {
+        pay_from_wallet(tx, uid, order.total, oid).await?;
+    }
+
+    if let Some(code) = &order.coupon_code {
+        sqlx::query!(
+            r"INSERT INTO coupon_redemptions (code, uid, oid) VALUES ($1, $2, $3)",
+            code,
+            usize_to_i32(uid),
+            oid,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let mut receipt_lines = Vec::with_capacity(order.lines.len());
+    for line in order.lines {
+        let charged = line.charged()?;
+        let purchase = line.purchase;
+        let tid = sqlx::query!(
+            r"INSERT INTO transactions
+            (uid, pid, receiver_uid, payment_method, amount, discount, currency, quantity, oid,
+                status, tax, tax_country, tax_rate_bp, tax_inclusive, preorder)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, COALESCE((
+                SELECT g.release_at > NOW()
+                FROM purchases p JOIN games g ON p.gid = g.gid
+                WHERE p.pid = $2
+            ), FALSE))
+            RETURNING tid",
+            usize_to_i32(uid),
+            purchase.pid,
+            usize_to_i32(ruid),
+            payment_method as PaymentMethod,
+            charged.minor(),
+            line.discount.minor(),
+            charged.currency().to_string(),
+            line.quantity,
+            oid,
+            status as TransactionStatus,
+            line.tax.minor(),
+            line.tax_rate.as_ref().map(|x| x.country.as_str()),
+            line.tax_rate.as_ref().map_or(0, |x| x.rate_bp),
+            line.tax_rate.as_ref().is_none_or(|x| x.inclusive),
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .tid;
+
+        receipt_lines.push(ReceiptLine {
+            tid,
+            pid: purchase.pid,
+            unit_price: line.unit_price,
+            descr: purchase.descr,
+            purchase_type: purchase.purchase_type,
+            quantity: line.quantity,
+            line_total: line.line_total,
+            discount: line.discount,
+            tax: line.tax,
+            tax_rate: line.tax_rate,
+        });
+    }
+
+    Ok(Receipt {
+        oid,
+        payment_method,
+        lines: receipt_lines,
+        subtotal: order.subtotal,
+        discount: order.discount,
+        tax: order.tax,
+        added_tax: order.added_tax,
+        total: order.total,
+        coupon_code: order.coupon_code,
+        status,
+    })
+}
+
+/// Issues the invoices of a paid order and hands out what it bought, into the gift inbox of
+/// the receiver for gifts and into their library or entitlements otherwise.
+///
+/// # Errors
+/// when the receiver already owns a bought game or DLC, lacks the base game of one, or querying
+/// the database failed
+async fn fulfill_order(tx: &mut PgConnection, oid: i32) -> Result<(), CheckoutError> {
+    let lines = sqlx::query!(
+        r#"SELECT
+            t.tid,
+            t.uid,
+            t.receiver_uid,
+            p.pid,
+            p.gid,
+            p.purchase_type AS "purchase_type: PurchaseType",
+            o.gift_message
+        FROM transactions t
+        JOIN purchases p ON t.pid = p.pid
+        JOIN orders o ON t.oid = o.oid
+        WHERE t.oid = $1
+        ORDER BY t.tid"#,
+        oid,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for x in lines {
+        issue_invoice(tx, x.tid).await?;
+        let uid = i32_to_usize(x.uid);
+        let ruid = x.receiver_uid.map_or(uid, i32_to_usize);
+        if uid == ruid {
+            grant_purchase(tx, ruid, x.tid, (x.pid, x.gid, x.purchase_type)).await?;
+        } else {
+            send_gift(tx, x.tid, uid, ruid, x.gift_message.as_deref()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The reference the payment gateway knows order `oid` by.
+fn order_reference(oid: i32) -> String {
+    format!("order-{oid}")
+}
+
+fn parse_order_reference(reference: &str) -> Option<i32> {
+    reference.strip_prefix("order-")?.parse().ok()
+}
+
+/// Adds what transaction `tid` bought to the library or entitlements of `uid`.
+///
+/// # Errors
+/// when `uid` already owns a bought game or DLC, lacks the base game of one, or querying the
+/// database failed
+pub async fn grant_purchase(
+    tx: &mut PgConnection,
+    uid: usize,
+    tid: i32,
+    (pid, gid, purchase_type): (i32, i32, PurchaseType),
+) -> Result<(), CheckoutError> {
+    match purchase_type {
+        PurchaseType::GamePurchase => grant_game(tx, uid, gid).await,
+        PurchaseType::Bundle => grant_bundle(tx, uid, Some(tid), pid).await,
+        PurchaseType::Subscriptions => subscribe(tx, uid, tid, pid).await,
+        PurchaseType::Dlc | PurchaseType::InGamePurchase => {
+            let quantity = sqlx::query!(r"SELECT quantity FROM transactions WHERE tid = $1", tid)
+                .fetch_one(&mut *tx)
+                .await?
+                .quantity;
+            grant_entitlement(tx, uid, Some(tid), (pid, gid, purchase_type), quantity).await
+        }
+        PurchaseType::Etc => Ok(()),
+    }
+}
+
+/// Adds a bought game to the library of `uid`, who must not own it yet.
+///
+/// # Errors
+/// when the user already owns the game or querying the database failed
+pub async fn grant_game(tx: &mut PgConnection, uid: usize, gid: i32) -> Result<(), CheckoutError> {
+    if !claim_game(&mut *tx, uid, i32_to_usize(gid)).await? {
+        return Err(CheckoutError::AlreadyOwned);
+    }
+    Ok(())
+}
+
+/// Rejects orders for games the user owns already; granting the game claims it for good.
+///
+/// # Errors
+/// when the user already owns the game or querying the database failed
+async fn ensure_not_owned(
+    tx: &mut PgConnection,
+    uid: usize,
+    gid: i32,
+) -> Result<(), CheckoutError> {
+    let owned = sqlx::query!(
+        r"SELECT wishlist FROM game_user WHERE gid = $1 AND uid = $2",
+        gid,
+        usize_to_i32(uid),
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .is_some_and(|x| x.wishlist != Some(true));
+    if owned {
+        return Err(CheckoutError::AlreadyOwned);
+    }
+    Ok(())
+}
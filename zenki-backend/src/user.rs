@@ -1,27 +1,39 @@
-use serde::{Deserialize, Serialize};
 use time::{Date, PrimitiveDateTime, error::Parse, macros::format_description};
 use zenki_util::usize_to_i32;
 
-use crate::State;
+use crate::{State, session::revoke_user_sessions};
 
-#[derive(Debug, Clone, Deserialize, Serialize, Default)]
-pub struct User {
+/// What anyone may see about a user.
+pub struct PublicProfile {
     pub uid: i32,
     pub uname: String,
-    pub passwd: String,
-    pub email: Option<String>,
-    pub created_at: Option<PrimitiveDateTime>,
     pub bio: Option<String>,
+    pub created_at: Option<PrimitiveDateTime>,
+    pub avatar: Option<String>,
+}
+
+/// What only the owner of the account may see.
+pub struct PrivateAccount {
+    pub uid: i32,
+    pub email: Option<String>,
     pub birth_date: Option<Date>,
+    /// Two letter ISO 3166-1 code of the country taxes are levied for.
+    pub billing_country: Option<String>,
+}
+
+/// Login credentials, never to leave the backend.
+pub struct Credential {
+    pub uid: i32,
+    pub passwd: String,
 }
 
 impl State {
     /// # Errors
     /// when querying the database failed
-    pub async fn query_user(&self, id: usize) -> sqlx::Result<Option<User>> {
+    pub async fn query_user(&self, id: usize) -> sqlx::Result<Option<PublicProfile>> {
         sqlx::query_as!(
-            User,
-            r"SELECT * FROM users WHERE uid = $1",
+            PublicProfile,
+            r"SELECT uid, uname, bio, created_at, avatar FROM users WHERE uid = $1",
             usize_to_i32(id)
         )
         .fetch_optional(&self.db)
@@ -30,19 +42,58 @@ impl State {
 
     /// # Errors
     /// when querying the database failed
-    pub async fn query_users(&self) -> sqlx::Result<Vec<User>> {
-        sqlx::query_as!(User, r"SELECT * FROM users",)
-            .fetch_all(&self.db)
-            .await
+    pub async fn query_users(&self) -> sqlx::Result<Vec<PublicProfile>> {
+        sqlx::query_as!(
+            PublicProfile,
+            r"SELECT uid, uname, bio, created_at, avatar FROM users",
+        )
+        .fetch_all(&self.db)
+        .await
     }
 
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_private_account(&self, id: usize) -> sqlx::Result<Option<PrivateAccount>> {
+        sqlx::query_as!(
+            PrivateAccount,
+            r"SELECT uid, email, birth_date, billing_country FROM users WHERE uid = $1",
+            usize_to_i32(id)
+        )
+        .fetch_optional(&self.db)
+        .await
+    }
+
+    pub(crate) async fn query_credential(&self, uname: &str) -> sqlx::Result<Credential> {
+        sqlx::query_as!(
+            Credential,
+            r"SELECT uid, passwd FROM users WHERE uname = $1",
+            uname
+        )
+        .fetch_one(&self.db)
+        .await
+    }
+
+    pub(crate) async fn query_credential_by_id(&self, id: usize) -> sqlx::Result<Credential> {
+        sqlx::query_as!(
+            Credential,
+            r"SELECT uid, passwd FROM users WHERE uid = $1",
+            usize_to_i32(id)
+        )
+        .fetch_one(&self.db)
+        .await
+    }
+
+    /// Deletes the user along with their sessions.
+    ///
     /// # Errors
     /// when querying the database failed
     pub async fn delete_user(&self, id: usize) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        revoke_user_sessions(&mut *tx, id).await?;
         sqlx::query!(r"DELETE FROM users WHERE uid = $1", usize_to_i32(id))
-            .execute(&self.db)
+            .execute(&mut *tx)
             .await?;
-        Ok(())
+        tx.commit().await
     }
 
     /// # Errors
@@ -71,6 +122,19 @@ impl State {
         Ok(())
     }
 
+    /// # Errors
+    /// when querying the database failed
+    pub async fn update_avatar(&self, id: usize, avatar: Option<&str>) -> sqlx::Result<()> {
+        sqlx::query!(
+            r"UPDATE users SET avatar = $1 WHERE uid = $2",
+            avatar,
+            usize_to_i32(id),
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
     /// # Errors
     /// when querying the database failed
     pub async fn update_email(&self, id: usize, email: Option<&str>) -> sqlx::Result<()> {
@@ -103,3 +167,12 @@ impl State {
 pub fn parse_html_date(date: &str) -> Result<Date, Parse> {
     Date::parse(date, format_description!("[year]-[month]-[day]"))
 }
+
+/// # Errors
+/// when parsing the datetime failed
+pub fn parse_html_datetime(datetime: &str) -> Result<PrimitiveDateTime, Parse> {
+    PrimitiveDateTime::parse(
+        datetime,
+        format_description!("[year]-[month]-[day]T[hour]:[minute]"),
+    )
+}
@@ -0,0 +1,587 @@
+use std::fmt::Display;
+
+use sqlx::PgConnection;
+use thiserror::Error;
+use time::PrimitiveDateTime;
+use zenki_util::{Currency, Money, usize_to_i32};
+
+use crate::{
+    GatewayError, PaymentRequest, State,
+    purchase::decode_money,
+    transaction::{PaymentMethod, SETTLE_RETRY_AFTER},
+};
+
+#[derive(Error, Debug)]
+pub enum WalletError {
+    #[error("amount must be positive")]
+    InvalidAmount,
+    #[error("insufficient funds")]
+    InsufficientFunds,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum TopUpError {
+    #[error("a top-up cannot be paid from the wallet")]
+    InvalidPaymentMethod,
+    #[error(transparent)]
+    Wallet(#[from] WalletError),
+    #[error(transparent)]
+    Payment(#[from] GatewayError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "ledger_account_n", rename_all = "snake_case")]
+pub enum LedgerAccount {
+    /// Money a user holds with the store.
+    Wallet,
+    /// Where top-ups come from, i.e. money paid in from outside the store.
+    Funding,
+    /// Where wallet purchases go to, and refunds are paid back from.
+    Sales,
+    /// What is held back for open buy orders of the marketplace.
+    Market,
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "ledger_journal_n", rename_all = "snake_case")]
+pub enum LedgerJournalKind {
+    TopUp,
+    Purchase,
+    Refund,
+    MarketHold,
+    MarketRelease,
+    MarketSale,
+    MarketFee,
+}
+
+impl Display for LedgerJournalKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::TopUp => "Top-up",
+            Self::Purchase => "Purchase",
+            Self::Refund => "Refund",
+            Self::MarketHold => "Held for buy order",
+            Self::MarketRelease => "Released from buy order",
+            Self::MarketSale => "Market sale",
+            Self::MarketFee => "Market fee",
+        })
+    }
+}
+
+/// One movement on a wallet, positive when money came in.
+pub struct WalletEntry {
+    pub jid: i32,
+    pub kind: LedgerJournalKind,
+    pub amount: Money,
+    pub oid: Option<i32>,
+    pub tid: Option<i32>,
+    pub created_at: Option<PrimitiveDateTime>,
+}
+
+pub struct Wallet {
+    /// One balance per currency the wallet has ever held.
+    pub balances: Vec<Money>,
+    pub entries: Vec<WalletEntry>,
+}
+
+impl State {
+    /// # Errors
+    /// when querying the database failed
+    pub async fn query_wallet(&self, uid: usize) -> sqlx::Result<Wallet> {
+        let balances = sqlx::query!(
+            r"SELECT balance, currency FROM ledger_accounts
+            WHERE kind = 'wallet' AND uid = $1
+            ORDER BY currency",
+            usize_to_i32(uid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| decode_money(x.balance, &x.currency))
+        .collect::<sqlx::Result<_>>()?;
+
+        let entries = sqlx::query!(
+            r#"SELECT
+                j.jid,
+                j.kind AS "kind: LedgerJournalKind",
+                e.amount,
+                a.currency,
+                j.oid,
+                j.tid,
+                j.created_at
+            FROM ledger_entries e
+            JOIN ledger_accounts a ON e.aid = a.aid
+            JOIN ledger_journals j ON e.jid = j.jid
+            WHERE a.kind = 'wallet' AND a.uid = $1
+            ORDER BY j.jid DESC"#,
+            usize_to_i32(uid),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|x| {
+            Ok(WalletEntry {
+                jid: x.jid,
+                kind: x.kind,
+                amount: decode_money(x.amount, &x.currency)?,
+                oid: x.oid,
+                tid: x.tid,
+                created_at: x.created_at,
+            })
+        })
+        .collect::<sqlx::Result<_>>()?;
+
+        Ok(Wallet { balances, entries })
+    }
+
+    /// Charges `amount` through the payment gateway and adds it to the wallet of `uid` once the
+    /// payment was captured, returning the new balance.
+    ///
+    /// A top-up whose authorization or capture times out stays pending, until a webhook or
+    /// [`retry_pending_top_ups`] settles it.
+    ///
+    /// # Errors
+    /// when the amount is not positive, the payment method is the wallet, the payment was
+    /// declined or timed out, or querying the database failed
+    ///
+    /// [`retry_pending_top_ups`]: Self::retry_pending_top_ups
+    pub async fn top_up_wallet(
+        &self,
+        uid: usize,
+        amount: Money,
+        payment_method: PaymentMethod,
+    ) -> Result<Money, TopUpError> {
+        if amount.minor() <= 0 {
+            return Err(WalletError::InvalidAmount.into());
+        }
+        if matches!(payment_method, PaymentMethod::Wallet) {
+            return Err(TopUpError::InvalidPaymentMethod);
+        }
+
+        let wtid = sqlx::query!(
+            r"INSERT INTO wallet_top_ups (uid, payment_method, amount, currency)
+            VALUES ($1, $2, $3, $4)
+            RETURNING wtid",
+            usize_to_i32(uid),
+            payment_method as PaymentMethod,
+            amount.minor(),
+            amount.currency().to_string(),
+        )
+        .fetch_one(&self.db)
+        .await?
+        .wtid;
+
+        let request = PaymentRequest {
+            reference: top_up_reference(wtid),
+            uid,
+            payment_method,
+            amount,
+        };
+        let payment_ref = match self.gateway.authorize(&request).await {
+            Ok(payment_ref) => payment_ref,
+            // the payment may have been authorized after all, which a webhook tells about
+            Err(GatewayError::Timeout) => {
+                log::info!("payment of top-up {wtid} timed out, awaiting webhook");
+                return Err(GatewayError::Timeout.into());
+            }
+            Err(e) => {
+                self.fail_top_up(wtid).await?;
+                return Err(e.into());
+            }
+        };
+        self.settle_top_up(wtid, &payment_ref)
+            .await?
+            .ok_or_else(|| GatewayError::Timeout.into())
+    }
+
+    /// Credits a pending top-up whose payment the gateway authorized as `payment_ref`, returning
+    /// the new balance.
+    ///
+    /// The payment is captured outside of any database transaction, and the capture is
+    /// remembered so a retry never captures twice. A capture that times out leaves the top-up
+    /// pending for [`retry_pending_top_ups`], and a declined one fails the top-up and releases
+    /// the authorization. Returns `None` as well while another attempt is settling the top-up.
+    ///
+    /// # Errors
+    /// when the capture was declined or querying the database failed
+    ///
+    /// [`retry_pending_top_ups`]: Self::retry_pending_top_ups
+    pub(crate) async fn settle_top_up(
+        &self,
+        wtid: i32,
+        payment_ref: &str,
+    ) -> Result<Option<Money>, TopUpError> {
+        // remembering the authorization first lets a retry pick the top-up up, whatever happens
+        let Some(x) = sqlx::query!(
+            r#"UPDATE wallet_top_ups SET payment_ref = $2, settle_attempted_at = NOW()
+            WHERE wtid = $1
+                AND status = 'pending'
+                AND (settle_attempted_at IS NULL
+                    OR settle_attempted_at <= NOW() - make_interval(secs => $3))
+            RETURNING uid, amount, currency, captured_at IS NOT NULL AS "captured!""#,
+            wtid,
+            payment_ref,
+            SETTLE_RETRY_AFTER.as_seconds_f64(),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let amount = decode_money(x.amount, &x.currency)?;
+
+        if !x.captured {
+            match self.gateway.capture(payment_ref, amount).await {
+                Ok(()) => {}
+                Err(GatewayError::Timeout) => {
+                    log::info!("capturing the payment of top-up {wtid} timed out, will retry");
+                    return Ok(None);
+                }
+                Err(e) => {
+                    self.fail_top_up(wtid).await?;
+                    self.void_payment(payment_ref).await;
+                    return Err(e.into());
+                }
+            }
+            sqlx::query!(
+                r"UPDATE wallet_top_ups SET captured_at = NOW() WHERE wtid = $1",
+                wtid
+            )
+            .execute(&self.db)
+            .await?;
+        }
+
+        let mut tx = self.db.begin().await?;
+        let pending = sqlx::query!(
+            r"UPDATE wallet_top_ups SET status = 'completed' WHERE wtid = $1 AND status = 'pending'",
+            wtid,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if pending == 0 {
+            return Ok(None);
+        }
+        let funding =
+            ledger_account(&mut tx, LedgerAccount::Funding, None, amount.currency()).await?;
+        let wallet = ledger_account(
+            &mut tx,
+            LedgerAccount::Wallet,
+            Some(x.uid),
+            amount.currency(),
+        )
+        .await?;
+        post_transfer(
+            &mut tx,
+            LedgerJournalKind::TopUp,
+            funding,
+            wallet,
+            amount,
+            (None, None),
+        )
+        .await?;
+        let balance = sqlx::query!(
+            r"SELECT balance FROM ledger_accounts WHERE aid = $1",
+            wallet
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .balance;
+        tx.commit().await?;
+        Ok(Some(Money::new(balance, amount.currency())))
+    }
+
+    /// Settles again the pending top-ups whose settlement was interrupted, e.g. because
+    /// capturing the payment timed out, returning how many were credited now.
+    ///
+    /// # Errors
+    /// when querying the database failed
+    pub async fn retry_pending_top_ups(&self) -> sqlx::Result<usize> {
+        let pending = sqlx::query!(
+            r#"SELECT wtid, payment_ref AS "payment_ref!"
+            FROM wallet_top_ups
+            WHERE status = 'pending'
+                AND payment_ref IS NOT NULL
+                AND settle_attempted_at <= NOW() - make_interval(secs => $1)
+            ORDER BY wtid"#,
+            SETTLE_RETRY_AFTER.as_seconds_f64(),
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut credited = 0;
+        for x in pending {
+            match self.settle_top_up(x.wtid, &x.payment_ref).await {
+                Ok(Some(_)) => credited += 1,
+                Ok(None) => {}
+                Err(TopUpError::Database(e) | TopUpError::Wallet(WalletError::Database(e))) => {
+                    return Err(e);
+                }
+                Err(e) => log::info!("settling top-up {} failed: {e}", x.wtid),
+            }
+        }
+        Ok(credited)
+    }
+
+    /// Marks a pending top-up failed, returning whether it was pending.
+    pub(crate) async fn fail_top_up(&self, wtid: i32) -> sqlx::Result<bool> {
+        let failed = sqlx::query!(
+            r"UPDATE wallet_top_ups SET status = 'failed' WHERE wtid = $1 AND status = 'pending'",
+            wtid
+        )
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        Ok(failed > 0)
+    }
+
+    /// Whether top-up `wtid` failed, so an authorization of it will never be captured.
+    pub(crate) async fn top_up_failed(&self, wtid: i32) -> sqlx::Result<bool> {
+        Ok(sqlx::query!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM wallet_top_ups WHERE wtid = $1 AND status = 'failed'
+            ) AS "failed!""#,
+            wtid,
+        )
+        .fetch_one(&self.db)
+        .await?
+        .failed)
+    }
+}
+
+/// The reference the payment gateway knows top-up `wtid` by.
+fn top_up_reference(wtid: i32) -> String {
+    format!("top-up-{wtid}")
+}
+
+pub fn parse_top_up_reference(reference: &str) -> Option<i32> {
+    reference.strip_prefix("top-up-")?.parse().ok()
+}
+
+/// Pays `amount` for order `oid` out of the wallet of `uid`.
+///
+/// # Errors
+/// when the wallet holds less than `amount`, the amount is not positive, or querying the
+/// database failed
+pub async fn pay_from_wallet(
+    tx: &mut PgConnection,
+    uid: usize,
+    amount: Money,
+    oid: i32,
+) -> Result<(), WalletError> {
+    let wallet = ledger_account(
+        tx,
+        LedgerAccount::Wallet,
+        Some(usize_to_i32(uid)),
+        amount.currency(),
+    )
+    .await?;
+    let sales = ledger_account(tx, LedgerAccount::Sales, None, amount.currency()).await?;
+    post_transfer(
+        tx,
+        LedgerJournalKind::Purchase,
+        wallet,
+        sales,
+        amount,
+        (Some(oid), None),
+    )
+    .await
+}
+
+/// Credits `amount` for refunded transaction `tid` back to the wallet of `uid`.
+///
+/// # Errors
+/// when the amount is not positive or querying the database failed
+pub async fn refund_to_wallet(
+    tx: &mut PgConnection,
+    uid: i32,
+    amount: Money,
+    tid: i32,
+) -> Result<(), WalletError> {
+    let sales = ledger_account(tx, LedgerAccount::Sales, None, amount.currency()).await?;
+    let wallet = ledger_account(tx, LedgerAccount::Wallet, Some(uid), amount.currency()).await?;
+    post_transfer(
+        tx,
+        LedgerJournalKind::Refund,
+        sales,
+        wallet,
+        amount,
+        (None, Some(tid)),
+    )
+    .await
+}
+
+/// Holds `amount` of the wallet of `uid` back for an open buy order.
+///
+/// # Errors
+/// when the wallet holds less than `amount`, the amount is not positive, or querying the
+/// database failed
+pub async fn hold_for_market(
+    tx: &mut PgConnection,
+    uid: i32,
+    amount: Money,
+) -> Result<(), WalletError> {
+    let wallet = ledger_account(tx, LedgerAccount::Wallet, Some(uid), amount.currency()).await?;
+    let market = ledger_account(tx, LedgerAccount::Market, None, amount.currency()).await?;
+    post_transfer(
+        tx,
+        LedgerJournalKind::MarketHold,
+        wallet,
+        market,
+        amount,
+        (None, None),
+    )
+    .await
+}
+
+/// Gives `amount` held back for a buy order of `uid` back to their wallet.
+///
+/// # Errors
+/// when the amount is not positive or querying the database failed
+pub async fn release_from_market(
+    tx: &mut PgConnection,
+    uid: i32,
+    amount: Money,
+) -> Result<(), WalletError> {
+    let market = ledger_account(tx, LedgerAccount::Market, None, amount.currency()).await?;
+    let wallet = ledger_account(tx, LedgerAccount::Wallet, Some(uid), amount.currency()).await?;
+    post_transfer(
+        tx,
+        LedgerJournalKind::MarketRelease,
+        market,
+        wallet,
+        amount,
+        (None, None),
+    )
+    .await
+}
+
+/// Pays `price` for trade `tid` to the wallet of `seller_uid`, less the `fee` kept by the store,
+/// out of the wallet of `buyer_uid`, or out of what was held back when filling a buy order.
+///
+/// # Errors
+/// when the buyer's wallet holds less than `price`, the fee is not below the price, or querying
+/// the database failed
+pub async fn pay_for_trade(
+    tx: &mut PgConnection,
+    buyer_uid: Option<i32>,
+    seller_uid: i32,
+    (price, fee): (Money, Money),
+    tid: i32,
+) -> Result<(), WalletError> {
+    let proceeds = price.checked_sub(fee).ok_or(WalletError::InvalidAmount)?;
+    let from = match buyer_uid {
+        Some(uid) => ledger_account(tx, LedgerAccount::Wallet, Some(uid), price.currency()).await?,
+        None => ledger_account(tx, LedgerAccount::Market, None, price.currency()).await?,
+    };
+    let seller = ledger_account(
+        tx,
+        LedgerAccount::Wallet,
+        Some(seller_uid),
+        price.currency(),
+    )
+    .await?;
+    post_transfer(
+        tx,
+        LedgerJournalKind::MarketSale,
+        from,
+        seller,
+        proceeds,
+        (None, Some(tid)),
+    )
+    .await?;
+    if !fee.is_zero() {
+        let sales = ledger_account(tx, LedgerAccount::Sales, None, price.currency()).await?;
+        post_transfer(
+            tx,
+            LedgerJournalKind::MarketFee,
+            from,
+            sales,
+            fee,
+            (None, Some(tid)),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Finds or opens the account of `kind` in `currency`, owned by `uid` for wallets.
+async fn ledger_account(
+    tx: &mut PgConnection,
+    kind: LedgerAccount,
+    uid: Option<i32>,
+    currency: Currency,
+) -> sqlx::Result<i32> {
+    Ok(sqlx::query!(
+        r"INSERT INTO ledger_accounts (kind, uid, currency) VALUES ($1, $2, $3)
+        ON CONFLICT (kind, uid, currency) DO UPDATE SET kind = EXCLUDED.kind
+        RETURNING aid",
+        kind as LedgerAccount,
+        uid,
+        currency.to_string(),
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .aid)
+}
+
+/// Moves `amount` between two accounts as a journal of two entries that sum to zero.
+///
+/// A wallet is never overdrawn; the other accounts may go negative.
+async fn post_transfer(
+    tx: &mut PgConnection,
+    kind: LedgerJournalKind,
+    from: i32,
+    to: i32,
+    amount: Money,
+    (oid, tid): (Option<i32>, Option<i32>),
+) -> Result<(), WalletError> {
+    if amount.minor() <= 0 {
+        return Err(WalletError::InvalidAmount);
+    }
+
+    let debited = sqlx::query!(
+        r"UPDATE ledger_accounts SET balance = balance - $2
+        WHERE aid = $1 AND (kind <> 'wallet' OR balance >= $2)",
+        from,
+        amount.minor(),
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if debited == 0 {
+        return Err(WalletError::InsufficientFunds);
+    }
+    sqlx::query!(
+        r"UPDATE ledger_accounts SET balance = balance + $2 WHERE aid = $1",
+        to,
+        amount.minor(),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let jid = sqlx::query!(
+        r"INSERT INTO ledger_journals (kind, oid, tid) VALUES ($1, $2, $3) RETURNING jid",
+        kind as LedgerJournalKind,
+        oid,
+        tid,
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .jid;
+    sqlx::query!(
+        r"INSERT INTO ledger_entries (jid, aid, amount) VALUES ($1, $2, $3), ($1, $4, $5)",
+        jid,
+        from,
+        -amount.minor(),
+        to,
+        amount.minor(),
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
@@ -29,19 +29,22 @@ impl From<zenki_backend::GameActivity> for GameActivity {
 }
 
 #[server]
-pub async fn start_playing(uid: usize, gid: usize) -> Result<(), ServerFnError> {
+pub async fn start_playing(gid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.start_playing(uid, gid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.start_playing(auth.uid, gid).await?)
 }
 #[server]
-pub async fn stop_playing(uid: usize, gid: usize) -> Result<(), ServerFnError> {
+pub async fn stop_playing(gid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.stop_playing(uid, gid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.stop_playing(auth.uid, gid).await?)
 }
 #[server]
-pub async fn is_playing(uid: usize, gid: usize) -> Result<bool, ServerFnError> {
+pub async fn is_playing(gid: usize) -> Result<bool, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.is_playing(uid, gid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.is_playing(auth.uid, gid).await?)
 }
 
 #[server]
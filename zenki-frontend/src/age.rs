@@ -0,0 +1,81 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AgeCheck {
+    Allowed,
+    ConfirmationRequired { min_age: i32 },
+    TooYoung { min_age: i32 },
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::AgeCheck> for AgeCheck {
+    fn from(value: zenki_backend::AgeCheck) -> Self {
+        match value {
+            zenki_backend::AgeCheck::Allowed => Self::Allowed,
+            zenki_backend::AgeCheck::ConfirmationRequired { min_age } => {
+                Self::ConfirmationRequired { min_age }
+            }
+            zenki_backend::AgeCheck::TooYoung { min_age } => Self::TooYoung { min_age },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RatingAge {
+    /// The rating as submitted by forms, e.g. `mature`.
+    pub rating: String,
+    pub name: String,
+    pub min_age: i32,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::RatingAge> for RatingAge {
+    fn from(value: zenki_backend::RatingAge) -> Self {
+        Self {
+            rating: value.rating.as_str().to_string(),
+            name: value.rating.to_string(),
+            min_age: value.min_age,
+        }
+    }
+}
+
+/// Whether the current visitor may see game `gid`, logged in or not.
+#[server]
+pub async fn get_age_check(gid: usize) -> Result<AgeCheck, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let uid = crate::auth::get_session_uid().await?;
+    Ok(state.query_age_check(uid, gid).await?.into())
+}
+
+#[server]
+pub async fn confirm_age(gid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.confirm_age(auth.uid, gid).await?)
+}
+
+#[server]
+pub async fn get_rating_ages() -> Result<Vec<RatingAge>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageGames)
+        .await?;
+    Ok(state
+        .query_rating_ages()
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn set_rating_age(rating: String, min_age: i32) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageStore)
+        .await?;
+    Ok(state.set_rating_age(rating.parse()?, min_age).await?)
+}
@@ -2,14 +2,20 @@
 use leptos::prelude::*;
 use leptos_meta::{Meta, MetaTags, Stylesheet, Title, provide_meta_context};
 use leptos_router::{
-    ParamSegment, SsrMode, StaticSegment,
+    MatchNestedRoutes, ParamSegment, SsrMode, StaticSegment,
     components::{FlatRoutes, ProtectedRoute, Route, Router},
 };
 
 use crate::{
-    auth::{SetLoggedIn, is_logged_in},
-    page::{Account, Developer, Game, Home, Item, Login, Main, Register, Tag, Transaction, User},
-    route::{ACCOUNT, DEVELOPER, GAME, HOME, ITEM, LOGIN, MAIN, REGISTER, TAG, TRANSACTION, USER},
+    page::{
+        Account, Admin, Cart, Developer, Game, Gifts, Home, Invoice, Item, Keys, Login, Main,
+        Market, Redeem, Register, Subscriptions, Tag, Transaction, User, Wallet,
+    },
+    role::{Role, RouteGuard},
+    route::{
+        ACCOUNT, ADMIN, CART, DEVELOPER, GAME, GIFTS, HOME, ITEM, KEYS, LOGIN, MAIN, REDEEM,
+        REGISTER, TAG, TRANSACTION, USER,
+    },
 };
 
 #[must_use]
@@ -36,11 +42,7 @@ pub fn App() -> impl IntoView {
     // Provides context that manages stylesheets, titles, meta tags, etc.
     provide_meta_context();
     let fallback = || view! { "Page not found." }.into_view();
-    let set_logged_in_act = ServerAction::<SetLoggedIn>::new();
-    let is_admin = Resource::new(
-        move || set_logged_in_act.version().get(),
-        |_| is_logged_in(),
-    );
+    let guard = RouteGuard::new();
 
     view! {
         <Stylesheet id="leptos" href="/pkg/ssr_modes.css"/>
@@ -54,83 +56,164 @@ pub fn App() -> impl IntoView {
             </nav>
             <main>
                 <FlatRoutes fallback>
-                    // We’ll load the home page with out-of-order streaming and <Suspense/>
-                    <Route path=StaticSegment(HOME) view=Home/>
-                    <ProtectedRoute
-                        path=StaticSegment(LOGIN)
-                        view=Login
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| !(n.unwrap_or(false)))
-                        redirect_path=|| MAIN
-                    />
-                    <ProtectedRoute
-                        path=StaticSegment(REGISTER)
-                        view=Register
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| !(n.unwrap_or(false)))
-                        redirect_path=|| MAIN
-                    />
-
-                    // We'll load the users with async rendering, so they can set
-                    // the title and metadata *after* loading the data
-                    <ProtectedRoute
-                        path=StaticSegment(MAIN)
-                        view=Main
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| n.unwrap_or(false))
-                        redirect_path=|| LOGIN
-                    />
-                    <ProtectedRoute
-                        path=StaticSegment(ACCOUNT)
-                        view=Account
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| n.unwrap_or(false))
-                        redirect_path=|| LOGIN
-                    />
-                    <ProtectedRoute
-                        path=(StaticSegment(USER), ParamSegment("id"))
-                        view=User
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| n.unwrap_or(false))
-                        redirect_path=|| LOGIN
-                    />
-                    <ProtectedRoute
-                        path=(StaticSegment(GAME), ParamSegment("id"))
-                        view=Game
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| n.unwrap_or(false))
-                        redirect_path=|| LOGIN
-                    />
-                    <ProtectedRoute
-                        path=(StaticSegment(TAG), ParamSegment("tname"))
-                        view=Tag
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| n.unwrap_or(false))
-                        redirect_path=|| LOGIN
-                    />
-                    <ProtectedRoute
-                        path=(StaticSegment(ITEM), ParamSegment("id"))
-                        view=Item
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| n.unwrap_or(false))
-                        redirect_path=|| LOGIN
-                    />
-                    <ProtectedRoute
-                        path=(StaticSegment(DEVELOPER), ParamSegment("id"))
-                        view=Developer
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| n.unwrap_or(false))
-                        redirect_path=|| LOGIN
-                    />
-                    <ProtectedRoute
-                        path=(StaticSegment(TRANSACTION), ParamSegment("id"))
-                        view=Transaction
-                        ssr=SsrMode::Async
-                        condition=move || is_admin.get().map(|n| n.unwrap_or(false))
-                        redirect_path=|| LOGIN
-                    />
+                    <AccountRoutes guard/>
+                    <StoreRoutes guard/>
                 </FlatRoutes>
             </main>
         </Router>
     }
 }
+
+/// The home page and the pages about the logged in user's own account.
+#[component(transparent)]
+fn AccountRoutes(guard: RouteGuard) -> impl MatchNestedRoutes + Clone + Send + 'static {
+    view! {
+        // We’ll load the home page with out-of-order streaming and <Suspense/>
+        <Route path=StaticSegment(HOME) view=Home/>
+        <ProtectedRoute
+            path=StaticSegment(LOGIN)
+            view=Login
+            ssr=SsrMode::Async
+            condition=guard.logged_out()
+            redirect_path=|| MAIN
+        />
+        <ProtectedRoute
+            path=StaticSegment(REGISTER)
+            view=Register
+            ssr=SsrMode::Async
+            condition=guard.logged_out()
+            redirect_path=|| MAIN
+        />
+
+        // We'll load the users with async rendering, so they can set
+        // the title and metadata *after* loading the data
+        <ProtectedRoute
+            path=StaticSegment(MAIN)
+            view=Main
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=StaticSegment(ACCOUNT)
+            view=Account
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(ACCOUNT), StaticSegment("wallet"))
+            view=Wallet
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(ACCOUNT), StaticSegment("subscriptions"))
+            view=Subscriptions
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(USER), ParamSegment("id"))
+            view=User
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=StaticSegment(CART)
+            view=Cart
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=StaticSegment(GIFTS)
+            view=Gifts
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=StaticSegment(REDEEM)
+            view=Redeem
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+    }
+    .into_inner()
+}
+
+/// The catalog, transactions and the pages for staff.
+#[component(transparent)]
+fn StoreRoutes(guard: RouteGuard) -> impl MatchNestedRoutes + Clone + Send + 'static {
+    view! {
+        <ProtectedRoute
+            path=(StaticSegment(GAME), ParamSegment("id"))
+            view=Game
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(GAME), ParamSegment("id"), StaticSegment("market"))
+            view=Market
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(TAG), ParamSegment("tname"))
+            view=Tag
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(ITEM), ParamSegment("id"))
+            view=Item
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(DEVELOPER), ParamSegment("id"))
+            view=Developer
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(TRANSACTION), ParamSegment("id"))
+            view=Transaction
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=(StaticSegment(TRANSACTION), ParamSegment("id"), StaticSegment("invoice"))
+            view=Invoice
+            ssr=SsrMode::Async
+            condition=guard.logged_in()
+            redirect_path=|| LOGIN
+        />
+        <ProtectedRoute
+            path=StaticSegment(KEYS)
+            view=Keys
+            ssr=SsrMode::Async
+            condition=guard.has_any_role(&[Role::Admin, Role::Developer])
+            redirect_path=|| MAIN
+        />
+        <ProtectedRoute
+            path=StaticSegment(ADMIN)
+            view=Admin
+            ssr=SsrMode::Async
+            condition=guard.has_role(Role::Admin)
+            redirect_path=|| MAIN
+        />
+    }
+    .into_inner()
+}
@@ -1,31 +1,124 @@
-use codee::string::FromToStringCodec;
 use leptos::{prelude::*, server};
 
+#[cfg(feature = "ssr")]
 const LOGIN_SESSION_NAME: &str = "zenki-login-session";
 
+/// Whether the session cookie is only sent over HTTPS, which everything but a development server
+/// serves.
+#[cfg(feature = "ssr")]
+fn secure_session_cookie() -> bool {
+    !std::env::var("LEPTOS_ENV").is_ok_and(|env| env.eq_ignore_ascii_case("dev"))
+}
+
+#[cfg(feature = "ssr")]
 #[inline]
-pub fn get_login_session() -> (Signal<Option<usize>>, WriteSignal<Option<usize>>) {
-    leptos_use::use_cookie::<_, FromToStringCodec>(LOGIN_SESSION_NAME)
+pub fn get_login_session() -> (Signal<Option<String>>, WriteSignal<Option<String>>) {
+    use codee::string::FromToStringCodec;
+    use leptos_use::{SameSite, UseCookieOptions, use_cookie_with_options};
+
+    use_cookie_with_options::<_, FromToStringCodec>(
+        LOGIN_SESSION_NAME,
+        UseCookieOptions::default()
+            .path("/")
+            .http_only(true)
+            .secure(secure_session_cookie())
+            .same_site(SameSite::Lax)
+            .max_age(zenki_backend::SESSION_TTL.whole_seconds() * 1000),
+    )
+}
+
+/// Resolves the session cookie of the current request to a user id, renewing the session.
+///
+/// # Errors
+/// when querying the database failed
+#[cfg(feature = "ssr")]
+pub async fn get_session_uid() -> Result<Option<usize>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let (cookie, set_cookie) = get_login_session();
+    let Some(token) = cookie.get_untracked() else {
+        return Ok(None);
+    };
+    let uid = state.lookup_session(&token).await?;
+    if uid.is_some() {
+        set_cookie.set(Some(token));
+    } else {
+        set_cookie.set(None);
+    }
+    Ok(uid)
+}
+
+/// # Errors
+/// when querying the database failed
+#[cfg(feature = "ssr")]
+pub async fn set_logged_in(uid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let token = state.create_session(uid).await?;
+    get_login_session().1.set(Some(token));
+    Ok(())
+}
+
+/// The logged in user of the current request.
+#[cfg(feature = "ssr")]
+#[derive(Clone, Copy, Debug)]
+pub struct AuthUser {
+    pub uid: usize,
+}
+
+#[cfg(feature = "ssr")]
+impl AuthUser {
+    /// # Errors
+    /// when the request has no valid session or querying the database failed
+    pub async fn extract() -> Result<Self, ServerFnError> {
+        get_session_uid().await?.map_or_else(
+            || Err(ServerFnError::ServerError("Not logged in.".to_string())),
+            |uid| Ok(Self { uid }),
+        )
+    }
+
+    /// # Errors
+    /// when the user is not allowed to touch the target row
+    pub fn ensure(self, allowed: bool) -> Result<Self, ServerFnError> {
+        if allowed {
+            Ok(self)
+        } else {
+            Err(ServerFnError::ServerError("Not allowed.".to_string()))
+        }
+    }
+
+    /// # Errors
+    /// when the user lacks the permission or querying the database failed
+    pub async fn require(
+        self,
+        permission: zenki_backend::Permission,
+    ) -> Result<Self, ServerFnError> {
+        let state = expect_context::<zenki_backend::State>();
+        self.ensure(state.has_permission(self.uid, permission).await?)
+    }
 }
 
 #[server]
-#[allow(clippy::unused_async)]
 pub async fn is_logged_in() -> Result<bool, ServerFnError> {
-    Ok(get_login_session().0.get().is_some())
+    Ok(get_session_uid().await?.is_some())
 }
 
 #[server]
-#[allow(clippy::unused_async)]
-pub async fn set_logged_in(uid: usize) -> Result<(), ServerFnError> {
-    get_login_session().1.set(Some(uid));
-    Ok(())
+pub async fn get_current_uid() -> Result<Option<usize>, ServerFnError> {
+    get_session_uid().await
+}
+
+#[inline]
+pub fn use_current_uid() -> Resource<Option<usize>> {
+    Resource::new(
+        || (),
+        |()| async move { get_current_uid().await.ok().flatten() },
+    )
 }
 
 #[server]
-pub async fn login(username: String, password: String) -> Result<usize, ServerFnError> {
+pub async fn login(username: String, password: String) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
     if let Ok(Some(id)) = state.verify_password(&username, &password).await {
-        Ok(id)
+        set_logged_in(id).await
     } else {
         Err(ServerFnError::ServerError(
             "Password does not match.".to_string(),
@@ -34,15 +127,14 @@ pub async fn login(username: String, password: String) -> Result<usize, ServerFn
 }
 
 #[server]
-pub async fn login_by_id(uid: usize, password: String) -> Result<(), ServerFnError> {
+pub async fn logout() -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    if matches!(state.verify_password_by_id(uid, &password).await, Ok(true)) {
-        Ok(())
-    } else {
-        Err(ServerFnError::ServerError(
-            "Password does not match.".to_string(),
-        ))
+    let (cookie, set_cookie) = get_login_session();
+    if let Some(token) = cookie.get_untracked() {
+        state.revoke_session(&token).await?;
     }
+    set_cookie.set(None);
+    Ok(())
 }
 
 #[server]
@@ -63,21 +155,22 @@ pub async fn register(
 
 #[server]
 pub async fn change_password(
-    id: usize,
     old_passwd: String,
     passwd: String,
     passwd_conf: String,
 ) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
+    let auth = AuthUser::extract().await?;
     if passwd != passwd_conf
-        || state
-            .change_password(id, &old_passwd, &passwd)
-            .await
-            .is_err()
+        || !matches!(
+            state.change_password(auth.uid, &old_passwd, &passwd).await,
+            Ok(true)
+        )
     {
         return Err(ServerFnError::ServerError(
             "Password does not match.".to_string(),
         ));
     }
-    Ok(())
+    // every session was revoked with the old password, so this one starts over
+    set_logged_in(auth.uid).await
 }
@@ -0,0 +1,119 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zenki_util::Money;
+#[cfg(feature = "ssr")]
+use zenki_util::i32_to_usize;
+
+use crate::transaction::{CheckoutPreview, Receipt};
+
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CartError {
+    #[error("Server error.")]
+    ServerError,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CartItem {
+    pub pid: usize,
+    pub gid: usize,
+    pub gname: String,
+    pub kind: String,
+    /// Current unit price, including any running sale.
+    pub price: Money,
+    pub descr: Option<String>,
+    pub quantity: usize,
+    pub added_at: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::CartItem> for CartItem {
+    fn from(value: zenki_backend::CartItem) -> Self {
+        Self {
+            pid: i32_to_usize(value.purchase.pid),
+            gid: i32_to_usize(value.purchase.gid),
+            gname: value.gname,
+            kind: value.purchase.purchase_type.to_string(),
+            price: value.purchase.effective_price(),
+            descr: value.purchase.descr,
+            quantity: i32_to_usize(value.quantity),
+            added_at: value.added_at.map(|x| x.to_string()),
+        }
+    }
+}
+
+#[server]
+pub async fn get_cart() -> Result<Vec<CartItem>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_cart(auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn add_to_cart(pid: usize, quantity: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .ensure(quantity > 0)?;
+    Ok(state.add_to_cart(auth.uid, pid, quantity).await?)
+}
+
+#[server]
+pub async fn update_cart_quantity(pid: usize, quantity: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.update_cart_quantity(auth.uid, pid, quantity).await?)
+}
+
+#[server]
+pub async fn remove_from_cart(pid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.remove_from_cart(auth.uid, pid).await?)
+}
+
+#[server]
+pub async fn checkout_cart(
+    ruid: Option<usize>,
+    payment_method: String,
+    coupon: Option<String>,
+    gift_message: Option<String>,
+) -> Result<Receipt, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    let ruid = ruid.unwrap_or(auth.uid);
+    Ok(state
+        .checkout_cart(
+            auth.uid,
+            ruid,
+            payment_method.parse()?,
+            coupon.as_deref(),
+            gift_message.as_deref(),
+        )
+        .await?
+        .into())
+}
+
+#[server]
+pub async fn preview_cart_checkout(
+    ruid: Option<usize>,
+    coupon: Option<String>,
+) -> Result<CheckoutPreview, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    let lines = state.query_cart_lines(auth.uid).await?;
+    Ok(state
+        .preview_order(
+            auth.uid,
+            ruid.unwrap_or(auth.uid),
+            &lines,
+            coupon.as_deref(),
+        )
+        .await?
+        .into())
+}
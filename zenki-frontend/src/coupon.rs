@@ -0,0 +1,69 @@
+use leptos::prelude::*;
+
+#[server]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_coupon(
+    code: String,
+    kind: String,
+    value: String,
+    min_spend: String,
+    max_uses: String,
+    max_uses_per_user: String,
+    gid: String,
+    did: String,
+    purchase_type: String,
+    expires_at: String,
+) -> Result<(), ServerFnError> {
+    use zenki_backend::{CouponLimits, CouponScope, DiscountValue, PurchaseType};
+    use zenki_util::{Currency, Money};
+
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+
+    let invalid = || ServerFnError::ServerError("Invalid coupon.".to_string());
+    let optional = |x: &str| Some(x.trim().to_string()).filter(|x| !x.is_empty());
+    let value = match kind.as_str() {
+        "percentage" => DiscountValue::Percentage(
+            value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|x| (1..=100).contains(x))
+                .ok_or_else(invalid)?,
+        ),
+        "fixed" => DiscountValue::Fixed(
+            Money::parse_major(&value, Currency::USD)
+                .filter(|x| x.minor() > 0)
+                .ok_or_else(invalid)?,
+        ),
+        _ => return Err(invalid()),
+    };
+    let scope = CouponScope {
+        gid: optional(&gid).map(|x| x.parse()).transpose()?,
+        did: optional(&did).map(|x| x.parse()).transpose()?,
+        purchase_type: optional(&purchase_type)
+            .as_deref()
+            .map(str::parse::<PurchaseType>)
+            .transpose()?,
+    };
+    let limits = CouponLimits {
+        max_uses: optional(&max_uses).map(|x| x.parse()).transpose()?,
+        max_uses_per_user: optional(&max_uses_per_user)
+            .map(|x| x.parse())
+            .transpose()?,
+        min_spend: optional(&min_spend)
+            .map(|x| Money::parse_major(&x, Currency::USD).ok_or_else(invalid))
+            .transpose()?,
+        expires_at: optional(&expires_at)
+            .map(|x| zenki_backend::parse_html_datetime(&x))
+            .transpose()?,
+    };
+    if optional(&code).is_none() {
+        return Err(invalid());
+    }
+    auth.ensure(state.manages_coupon_scope(auth.uid, &scope).await?)?;
+    Ok(state.create_coupon(&code, value, scope, limits).await?)
+}
@@ -3,7 +3,7 @@ use leptos_router::params::Params;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::game::Game;
+use crate::{game::Game, user::PublicProfile};
 
 #[derive(Params, Clone, Debug, PartialEq, Eq)]
 pub struct DeveloperParams {
@@ -56,3 +56,34 @@ pub async fn get_games_from_developer(did: usize) -> Result<Vec<Game>, ServerFnE
         .map(Into::into)
         .collect())
 }
+
+#[server]
+pub async fn get_developer_members(did: usize) -> Result<Vec<PublicProfile>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    Ok(state
+        .query_developer_members(did)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn add_developer_member(did: usize, uid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageUsers)
+        .await?;
+    Ok(state.add_developer_member(did, uid).await?)
+}
+
+#[server]
+pub async fn remove_developer_member(did: usize, uid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageUsers)
+        .await?;
+    Ok(state.remove_developer_member(did, uid).await?)
+}
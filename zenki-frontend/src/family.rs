@@ -0,0 +1,112 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::Game;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FamilyMember {
+    pub uid: usize,
+    pub uname: String,
+    pub added_at: String,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::FamilyMember> for FamilyMember {
+    fn from(value: zenki_backend::FamilyMember) -> Self {
+        Self {
+            uid: zenki_util::i32_to_usize(value.uid),
+            uname: value.uname,
+            added_at: value.added_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Family {
+    pub owner: Option<FamilyMember>,
+    pub members: Vec<FamilyMember>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Family> for Family {
+    fn from(value: zenki_backend::Family) -> Self {
+        Self {
+            owner: value.owner.map(Into::into),
+            members: value.members.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BorrowedGame {
+    pub game: Game,
+    pub owner_uid: usize,
+    pub owner_uname: String,
+    pub in_use: bool,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::BorrowedGame> for BorrowedGame {
+    fn from(value: zenki_backend::BorrowedGame) -> Self {
+        Self {
+            game: value.game.into(),
+            owner_uid: zenki_util::i32_to_usize(value.owner_uid),
+            owner_uname: value.owner_uname,
+            in_use: value.in_use,
+        }
+    }
+}
+
+/// The family of `uid` as the logged in user may see it: all of it for their own, and otherwise
+/// only whether `uid` borrows games from them.
+#[server]
+pub async fn get_family(uid: usize) -> Result<Family, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    let mut family: Family = state.query_family(uid).await?.into();
+    if auth.uid != uid {
+        family.owner = family.owner.filter(|owner| owner.uid == auth.uid);
+        family.members.clear();
+    }
+    Ok(family)
+}
+
+#[server]
+pub async fn add_family_member(uid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.add_family_member(auth.uid, uid).await?)
+}
+
+#[server]
+pub async fn remove_family_member(uid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.remove_family_member(auth.uid, uid).await?)
+}
+
+#[server]
+pub async fn leave_family() -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.leave_family(auth.uid).await?)
+}
+
+#[server]
+pub async fn set_game_shared(gid: usize, shared: bool) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.set_game_shared(auth.uid, gid, shared).await?)
+}
+
+#[server]
+pub async fn get_borrowed_games() -> Result<Vec<BorrowedGame>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_borrowed_games(auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
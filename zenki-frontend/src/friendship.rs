@@ -1,7 +1,7 @@
 use leptos::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::user::User;
+use crate::user::PublicProfile;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub enum FriendshipStatus {
@@ -12,13 +12,11 @@ pub enum FriendshipStatus {
 }
 
 #[server]
-pub async fn get_friendship_status(
-    uid: usize,
-    fid: usize,
-) -> Result<FriendshipStatus, ServerFnError> {
+pub async fn get_friendship_status(fid: usize) -> Result<FriendshipStatus, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
     Ok(state
-        .get_friendship_status(uid, fid)
+        .get_friendship_status(auth.uid, fid)
         .await
         .map(|x| match x {
             zenki_backend::FriendshipStatus::NotFriends => FriendshipStatus::NotFriends,
@@ -29,37 +27,42 @@ pub async fn get_friendship_status(
 }
 
 #[server]
-pub async fn accept_friend_request(uid: usize, fid: usize) -> Result<(), ServerFnError> {
+pub async fn accept_friend_request(fid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.accept_friend_request(uid, fid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.accept_friend_request(auth.uid, fid).await?)
 }
 
 #[server]
-pub async fn decline_friend_request(uid: usize, fid: usize) -> Result<(), ServerFnError> {
+pub async fn decline_friend_request(fid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.decline_friend_request(uid, fid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.decline_friend_request(auth.uid, fid).await?)
 }
 
 #[server]
-pub async fn send_friend_request(uid: usize, fid: usize) -> Result<(), ServerFnError> {
+pub async fn send_friend_request(fid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.send_friend_request(uid, fid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.send_friend_request(auth.uid, fid).await?)
 }
 
 #[server]
-pub async fn cancel_friend_request(uid: usize, fid: usize) -> Result<(), ServerFnError> {
+pub async fn cancel_friend_request(fid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.cancel_friend_request(uid, fid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.cancel_friend_request(auth.uid, fid).await?)
 }
 
 #[server]
-pub async fn remove_friend(uid: usize, fid: usize) -> Result<(), ServerFnError> {
+pub async fn remove_friend(fid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.remove_friend(uid, fid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.remove_friend(auth.uid, fid).await?)
 }
 
 #[server]
-pub async fn query_friends(uid: usize) -> Result<Vec<User>, ServerFnError> {
+pub async fn query_friends(uid: usize) -> Result<Vec<PublicProfile>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
     Ok(state
         .query_friends(uid)
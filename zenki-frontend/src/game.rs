@@ -30,6 +30,24 @@ pub struct Game {
     pub created_at: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LibraryGame {
+    pub game: Game,
+    pub available_at: Option<String>,
+    pub shared: bool,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::LibraryGame> for LibraryGame {
+    fn from(value: zenki_backend::LibraryGame) -> Self {
+        Self {
+            game: value.game.into(),
+            available_at: value.available_at.map(|x| x.to_string()),
+            shared: value.shared,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GameRef {
     pub gid: usize,
@@ -87,9 +105,15 @@ pub async fn list_games() -> Result<Vec<Game>, ServerFnError> {
         .map(|x| x.into_iter().map(Into::into).collect())?)
 }
 
+/// The game `id`, unless the visitor is known to be too young for it.
 #[server]
 pub async fn get_game(id: usize) -> Result<Option<Game>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
+    let uid = crate::auth::get_session_uid().await?;
+    // visitors without a birth date confirm their age on the page itself
+    if let zenki_backend::AgeCheck::TooYoung { min_age } = state.query_age_check(uid, id).await? {
+        return Err(zenki_backend::AgeError::TooYoung(min_age).into());
+    }
     Ok(state.query_game(id).await.map(|x| x.map(Into::into))?)
 }
 
@@ -103,7 +127,7 @@ pub async fn get_wishlist(id: usize) -> Result<Vec<Game>, ServerFnError> {
 }
 
 #[server]
-pub async fn get_library(id: usize) -> Result<Vec<Game>, ServerFnError> {
+pub async fn get_library(id: usize) -> Result<Vec<LibraryGame>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
     Ok(state
         .query_library(id)
@@ -112,21 +136,27 @@ pub async fn get_library(id: usize) -> Result<Vec<Game>, ServerFnError> {
 }
 
 #[server]
-pub async fn get_wishlist_status(uid: usize, gid: usize) -> Result<WishlistStatus, ServerFnError> {
+pub async fn get_wishlist_status(gid: usize) -> Result<WishlistStatus, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.get_wishlist_status(uid, gid).await.map(Into::into)?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .get_wishlist_status(auth.uid, gid)
+        .await
+        .map(Into::into)?)
 }
 
 #[server]
-pub async fn add_game_to_wishlist(uid: usize, gid: usize) -> Result<(), ServerFnError> {
+pub async fn add_game_to_wishlist(gid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.add_game_to_wishlist(uid, gid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.add_game_to_wishlist(auth.uid, gid).await?)
 }
 
 #[server]
-pub async fn remove_game_from_wishlist(uid: usize, gid: usize) -> Result<(), ServerFnError> {
+pub async fn remove_game_from_wishlist(gid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.remove_game_from_wishlist(uid, gid).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.remove_game_from_wishlist(auth.uid, gid).await?)
 }
 
 #[server]
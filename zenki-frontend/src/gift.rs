@@ -0,0 +1,144 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(feature = "ssr")]
+use zenki_util::i32_to_usize;
+
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GiftError {
+    #[error("Server error.")]
+    ServerError,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GiftStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
+
+impl std::fmt::Display for GiftStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Pending => "Pending",
+            Self::Accepted => "Accepted",
+            Self::Declined => "Declined",
+        })
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::GiftStatus> for GiftStatus {
+    fn from(value: zenki_backend::GiftStatus) -> Self {
+        match value {
+            zenki_backend::GiftStatus::Pending => Self::Pending,
+            zenki_backend::GiftStatus::Accepted => Self::Accepted,
+            zenki_backend::GiftStatus::Declined => Self::Declined,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Gift {
+    pub tid: usize,
+    pub sender_uid: usize,
+    pub s_uname: String,
+    pub receiver_uid: usize,
+    pub r_uname: String,
+    pub pid: usize,
+    pub p_descr: Option<String>,
+    pub gid: usize,
+    pub gname: String,
+    pub message: Option<String>,
+    pub status: GiftStatus,
+    pub created_at: Option<String>,
+    pub responded_at: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Gift> for Gift {
+    fn from(value: zenki_backend::Gift) -> Self {
+        Self {
+            tid: i32_to_usize(value.tid),
+            sender_uid: i32_to_usize(value.sender_uid),
+            s_uname: value.s_uname,
+            receiver_uid: i32_to_usize(value.receiver_uid),
+            r_uname: value.r_uname,
+            pid: i32_to_usize(value.pid),
+            p_descr: value.p_descr,
+            gid: i32_to_usize(value.gid),
+            gname: value.gname,
+            message: value.message,
+            status: value.status.into(),
+            created_at: value.created_at.map(|x| x.to_string()),
+            responded_at: value.responded_at.map(|x| x.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Notification {
+    pub nid: usize,
+    pub message: String,
+    pub tid: Option<usize>,
+    pub created_at: Option<String>,
+    pub read: bool,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Notification> for Notification {
+    fn from(value: zenki_backend::Notification) -> Self {
+        Self {
+            nid: i32_to_usize(value.nid),
+            message: value.message,
+            tid: value.tid.map(i32_to_usize),
+            created_at: value.created_at.map(|x| x.to_string()),
+            read: value.read_at.is_some(),
+        }
+    }
+}
+
+#[server]
+pub async fn get_gifts() -> Result<Vec<Gift>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_gifts(auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn accept_gift(tid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.accept_gift(auth.uid, tid).await?)
+}
+
+#[server]
+pub async fn decline_gift(tid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.decline_gift(auth.uid, tid).await?)
+}
+
+#[server]
+pub async fn get_notifications() -> Result<Vec<Notification>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_notifications(auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn mark_notifications_read() -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.mark_notifications_read(auth.uid).await?)
+}
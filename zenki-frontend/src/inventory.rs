@@ -0,0 +1,50 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "ssr")]
+use zenki_util::i32_to_usize;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct InventoryItem {
+    pub pid: usize,
+    pub descr: Option<String>,
+    pub gid: usize,
+    pub gname: String,
+    pub quantity: i32,
+    pub updated_at: Option<String>,
+    pub tradeable: bool,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::InventoryItem> for InventoryItem {
+    fn from(value: zenki_backend::InventoryItem) -> Self {
+        Self {
+            pid: i32_to_usize(value.pid),
+            descr: value.descr,
+            gid: i32_to_usize(value.gid),
+            gname: value.gname,
+            quantity: value.quantity,
+            updated_at: value.updated_at.map(|x| x.to_string()),
+            tradeable: value.tradeable,
+        }
+    }
+}
+
+/// The in-game items of the logged in user, of one game or of all of them.
+#[server]
+pub async fn get_inventory(gid: Option<usize>) -> Result<Vec<InventoryItem>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_inventory(auth.uid, gid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn consume_item(pid: usize, quantity: usize) -> Result<i32, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.consume_item(auth.uid, pid, quantity).await?)
+}
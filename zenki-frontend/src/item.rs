@@ -2,6 +2,7 @@ use leptos::prelude::*;
 use leptos_router::params::Params;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use zenki_util::Money;
 
 #[derive(Params, Clone, Debug, PartialEq, Eq)]
 pub struct ItemParams {
@@ -18,12 +19,52 @@ pub enum ItemError {
     ServerError,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Sale {
+    pub price: Money,
+    pub descr: Option<String>,
+    pub ends_at: String,
+    pub lowest_price: Option<Money>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Sale> for Sale {
+    fn from(value: zenki_backend::Sale) -> Self {
+        Self {
+            price: value.price,
+            descr: value.descr,
+            ends_at: value.ends_at.to_string(),
+            lowest_price: value.lowest_price,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub price: Money,
+    pub changed_at: String,
+    /// Seconds since the Unix epoch, for plotting.
+    pub timestamp: i64,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::PricePoint> for PricePoint {
+    fn from(value: zenki_backend::PricePoint) -> Self {
+        Self {
+            price: value.price,
+            changed_at: value.changed_at.to_string(),
+            timestamp: value.changed_at.assume_utc().unix_timestamp(),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Item {
     pub pid: i32,
     pub gid: i32,
     pub kind: String,
-    pub price: f64,
+    pub price: Money,
+    pub sale: Option<Sale>,
     pub descr: Option<String>,
     pub created_at: Option<String>,
 }
@@ -36,12 +77,56 @@ impl From<zenki_backend::Purchase> for Item {
             gid: value.gid,
             kind: value.purchase_type.to_string(),
             price: value.price,
+            sale: value.sale.map(Into::into),
             descr: value.descr,
             created_at: value.created_at.map(|x| x.to_string()),
         }
     }
 }
 
+/// A DLC or in-game purchase, with how many of it the user holds.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AddOn {
+    pub item: Item,
+    pub quantity: i64,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::AddOn> for AddOn {
+    fn from(value: zenki_backend::AddOn) -> Self {
+        Self {
+            item: value.purchase.into(),
+            quantity: value.quantity,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BundleItem {
+    pub pid: i32,
+    pub gid: i32,
+    pub gname: String,
+    pub descr: Option<String>,
+    pub kind: String,
+    pub price: Money,
+    pub owned: bool,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::BundleItem> for BundleItem {
+    fn from(value: zenki_backend::BundleItem) -> Self {
+        Self {
+            pid: value.pid,
+            gid: value.gid,
+            gname: value.gname,
+            descr: value.descr,
+            kind: value.purchase_type.to_string(),
+            price: value.price,
+            owned: value.owned,
+        }
+    }
+}
+
 #[server]
 pub async fn get_items(gid: usize) -> Result<Vec<Item>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
@@ -58,3 +143,150 @@ pub async fn get_item(pid: usize) -> Result<Option<Item>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
     Ok(state.query_purchase(pid).await?.map(Into::into))
 }
+
+#[server]
+pub async fn get_price_history(pid: usize) -> Result<Vec<PricePoint>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    Ok(state
+        .query_price_history(pid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn update_price(pid: usize, price: String) -> Result<(), ServerFnError> {
+    use zenki_util::{Currency, Money};
+
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+    auth.ensure(state.manages_purchase(auth.uid, pid).await?)?;
+
+    let Some(price) = Money::parse_major(&price, Currency::USD).filter(|x| x.minor() >= 0) else {
+        return Err(ServerFnError::ServerError("Invalid price.".to_string()));
+    };
+    Ok(state.update_price(pid, price).await?)
+}
+
+#[server]
+pub async fn get_add_ons(gid: usize) -> Result<Vec<AddOn>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_add_ons(gid, auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn get_bundle_items(pid: usize) -> Result<Vec<BundleItem>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_bundle_items(pid, auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn create_bundle(
+    gid: usize,
+    price: String,
+    descr: String,
+    pids: String,
+) -> Result<i32, ServerFnError> {
+    use zenki_util::{Currency, Money};
+
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+
+    let Some(price) = Money::parse_major(&price, Currency::USD).filter(|x| x.minor() >= 0) else {
+        return Err(ServerFnError::ServerError("Invalid price.".to_string()));
+    };
+    let pids = pids
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut allowed = state.manages_game(auth.uid, gid).await?;
+    for &pid in &pids {
+        allowed = allowed && state.manages_purchase(auth.uid, pid).await?;
+    }
+    auth.ensure(allowed)?;
+    Ok(state
+        .create_bundle(gid, price, Some(descr.as_str()), &pids)
+        .await?)
+}
+
+#[server]
+pub async fn create_discount(
+    kind: String,
+    value: String,
+    scope: String,
+    target: String,
+    descr: String,
+    starts_at: String,
+    ends_at: String,
+) -> Result<(), ServerFnError> {
+    use zenki_backend::{DiscountScope, DiscountValue, parse_html_datetime};
+    use zenki_util::{Currency, Money};
+
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+
+    let invalid = || ServerFnError::ServerError("Invalid discount.".to_string());
+    let value = match kind.as_str() {
+        "percentage" => DiscountValue::Percentage(
+            value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|x| (1..=100).contains(x))
+                .ok_or_else(invalid)?,
+        ),
+        "fixed" => DiscountValue::Fixed(
+            Money::parse_major(&value, Currency::USD)
+                .filter(|x| x.minor() > 0)
+                .ok_or_else(invalid)?,
+        ),
+        _ => return Err(invalid()),
+    };
+    let scope = match scope.as_str() {
+        "purchase" => DiscountScope::Purchase(target.trim().parse()?),
+        "tag" => DiscountScope::Tag(target),
+        "developer" => DiscountScope::Developer(target.trim().parse()?),
+        _ => return Err(invalid()),
+    };
+    auth.ensure(state.manages_discount_scope(auth.uid, &scope).await?)?;
+    let (starts_at, ends_at) = (
+        parse_html_datetime(&starts_at)?,
+        parse_html_datetime(&ends_at)?,
+    );
+    if ends_at <= starts_at {
+        return Err(invalid());
+    }
+    Ok(state
+        .create_discount(
+            value,
+            scope,
+            Some(descr).filter(|x| !x.is_empty()),
+            starts_at,
+            ends_at,
+        )
+        .await?)
+}
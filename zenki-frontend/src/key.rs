@@ -0,0 +1,151 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(feature = "ssr")]
+use zenki_util::i32_to_usize;
+
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyError {
+    #[error("Server error.")]
+    ServerError,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct KeyBatch {
+    pub kbid: usize,
+    pub pid: usize,
+    pub p_descr: Option<String>,
+    pub gid: usize,
+    pub gname: String,
+    pub descr: Option<String>,
+    pub created_at: Option<String>,
+    pub total: usize,
+    pub redeemed: usize,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::KeyBatch> for KeyBatch {
+    fn from(value: zenki_backend::KeyBatch) -> Self {
+        Self {
+            kbid: i32_to_usize(value.kbid),
+            pid: i32_to_usize(value.pid),
+            p_descr: value.p_descr,
+            gid: i32_to_usize(value.gid),
+            gname: value.gname,
+            descr: value.descr,
+            created_at: value.created_at.map(|x| x.to_string()),
+            total: usize::try_from(value.total).unwrap_or_default(),
+            redeemed: usize::try_from(value.redeemed).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ProductKey {
+    pub code: String,
+    pub redeemed_by: Option<usize>,
+    pub r_uname: Option<String>,
+    pub redeemed_at: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::ProductKey> for ProductKey {
+    fn from(value: zenki_backend::ProductKey) -> Self {
+        Self {
+            code: value.code,
+            redeemed_by: value.redeemed_by.map(i32_to_usize),
+            r_uname: value.r_uname,
+            redeemed_at: value.redeemed_at.map(|x| x.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct RedeemedKey {
+    pub pid: usize,
+    pub p_descr: Option<String>,
+    pub gid: usize,
+    pub gname: String,
+    pub kind: String,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::RedeemedKey> for RedeemedKey {
+    fn from(value: zenki_backend::RedeemedKey) -> Self {
+        Self {
+            pid: i32_to_usize(value.pid),
+            p_descr: value.p_descr,
+            gid: i32_to_usize(value.gid),
+            gname: value.gname,
+            kind: value.purchase_type.to_string(),
+        }
+    }
+}
+
+#[server]
+pub async fn generate_keys(
+    pid: usize,
+    count: usize,
+    descr: Option<String>,
+) -> Result<usize, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+    auth.ensure(state.manages_purchase(auth.uid, pid).await?)?;
+    Ok(i32_to_usize(
+        state
+            .generate_keys(auth.uid, pid, count, descr.as_deref())
+            .await?,
+    ))
+}
+
+#[server]
+pub async fn get_key_batches() -> Result<Vec<KeyBatch>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+    Ok(state
+        .query_key_batches(auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn get_product_keys(kbid: usize) -> Result<Vec<ProductKey>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+    auth.ensure(state.manages_key_batch(auth.uid, kbid).await?)?;
+    Ok(state
+        .query_product_keys(kbid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn export_unused_keys(kbid: usize) -> Result<String, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+    auth.ensure(state.manages_key_batch(auth.uid, kbid).await?)?;
+    Ok(state.export_unused_keys(kbid).await?)
+}
+
+#[server]
+pub async fn redeem_key(code: String) -> Result<RedeemedKey, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.redeem_key(auth.uid, &code).await?.into())
+}
@@ -1,16 +1,28 @@
 mod activity;
+mod age;
 pub mod app;
 mod auth;
+mod cart;
+mod coupon;
 mod developer;
+mod family;
 mod friendship;
 mod game;
+mod gift;
+mod inventory;
 mod item;
+mod key;
+mod market;
 mod page;
 mod review;
+mod role;
 mod route;
+mod subscription;
 mod tag;
+mod tax;
 mod transaction;
 mod user;
+mod wallet;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
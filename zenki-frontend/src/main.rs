@@ -16,6 +16,39 @@ async fn main() {
     let app_state = zenki_backend::State::new()
         .await
         .expect("creating app state failed");
+    tokio::spawn({
+        let app_state = app_state.clone();
+        async move {
+            let mut interval = tokio::time::interval(zenki_backend::RENEWAL_INTERVAL);
+            loop {
+                interval.tick().await;
+                match app_state.renew_subscriptions().await {
+                    Ok(report) if report.renewed + report.expired > 0 => log!(
+                        "renewed {} and expired {} subscriptions",
+                        report.renewed,
+                        report.expired
+                    ),
+                    Ok(_) => {}
+                    Err(e) => log!("renewing subscriptions failed: {e}"),
+                }
+                match app_state.retry_pending_payments().await {
+                    Ok(0) => {}
+                    Ok(completed) => log!("settled {completed} pending orders"),
+                    Err(e) => log!("retrying pending payments failed: {e}"),
+                }
+                match app_state.retry_pending_refunds().await {
+                    Ok(0) => {}
+                    Ok(paid) => log!("paid back {paid} pending refunds"),
+                    Err(e) => log!("retrying pending refunds failed: {e}"),
+                }
+                match app_state.retry_pending_top_ups().await {
+                    Ok(0) => {}
+                    Ok(credited) => log!("credited {credited} pending top-ups"),
+                    Err(e) => log!("retrying pending top-ups failed: {e}"),
+                }
+            }
+        }
+    });
     let app = Router::new()
         .leptos_routes_with_context(
             &leptos_options,
@@ -44,7 +77,7 @@ async fn main() {
 }
 
 #[cfg(not(feature = "ssr"))]
-pub fn main() {
+pub const fn main() {
     // no client-side main function
     // unless we want this to work with e.g., Trunk for pure client-side testing
     // see lib.rs for hydration function instead
@@ -0,0 +1,210 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use zenki_util::Money;
+#[cfg(feature = "ssr")]
+use zenki_util::i32_to_usize;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct MarketListing {
+    pub lid: usize,
+    pub seller_uid: usize,
+    pub s_uname: String,
+    pub pid: usize,
+    pub descr: Option<String>,
+    pub quantity: i32,
+    pub sold: i32,
+    pub price: Money,
+    pub status: String,
+    pub created_at: String,
+    pub closed_at: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::MarketListing> for MarketListing {
+    fn from(value: zenki_backend::MarketListing) -> Self {
+        Self {
+            lid: i32_to_usize(value.lid),
+            seller_uid: i32_to_usize(value.seller_uid),
+            s_uname: value.s_uname,
+            pid: i32_to_usize(value.pid),
+            descr: value.descr,
+            quantity: value.quantity,
+            sold: value.sold,
+            price: value.price,
+            status: value.status.to_string(),
+            created_at: value.created_at.to_string(),
+            closed_at: value.closed_at.map(|x| x.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BuyOrder {
+    pub boid: usize,
+    pub buyer_uid: usize,
+    pub b_uname: String,
+    pub pid: usize,
+    pub descr: Option<String>,
+    pub quantity: i32,
+    pub filled: i32,
+    pub max_price: Money,
+    pub created_at: String,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::BuyOrder> for BuyOrder {
+    fn from(value: zenki_backend::BuyOrder) -> Self {
+        Self {
+            boid: i32_to_usize(value.boid),
+            buyer_uid: i32_to_usize(value.buyer_uid),
+            b_uname: value.b_uname,
+            pid: i32_to_usize(value.pid),
+            descr: value.descr,
+            quantity: value.quantity,
+            filled: value.filled,
+            max_price: value.max_price,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Market {
+    pub listings: Vec<MarketListing>,
+    pub buy_orders: Vec<BuyOrder>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Market> for Market {
+    fn from(value: zenki_backend::Market) -> Self {
+        Self {
+            listings: value.listings.into_iter().map(Into::into).collect(),
+            buy_orders: value.buy_orders.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct MarketTrade {
+    pub tid: usize,
+    pub seller_uid: Option<usize>,
+    pub s_uname: Option<String>,
+    pub buyer_uid: usize,
+    pub b_uname: String,
+    pub quantity: i32,
+    pub unit_price: Money,
+    pub bought_at: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::MarketTrade> for MarketTrade {
+    fn from(value: zenki_backend::MarketTrade) -> Self {
+        Self {
+            tid: i32_to_usize(value.tid),
+            seller_uid: value.seller_uid.map(i32_to_usize),
+            s_uname: value.s_uname,
+            buyer_uid: i32_to_usize(value.buyer_uid),
+            b_uname: value.b_uname,
+            quantity: value.quantity,
+            unit_price: value.unit_price,
+            bought_at: value.bought_at.map(|x| x.to_string()),
+        }
+    }
+}
+
+/// Parses a price per unit in US dollars, which must be positive.
+#[cfg(feature = "ssr")]
+fn parse_unit_price(price: &str) -> Result<Money, zenki_backend::MarketError> {
+    Money::parse_major(price, zenki_util::Currency::USD)
+        .filter(|x| x.minor() > 0)
+        .ok_or(zenki_backend::MarketError::InvalidPrice)
+}
+
+#[server]
+pub async fn get_market(gid: usize) -> Result<Market, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    Ok(state.query_market(gid).await?.into())
+}
+
+#[server]
+pub async fn get_listing_history(pid: usize) -> Result<Vec<MarketListing>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    Ok(state
+        .query_listing_history(pid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn get_market_trades(pid: usize) -> Result<Vec<MarketTrade>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    Ok(state
+        .query_market_trades(pid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn create_listing(
+    pid: usize,
+    quantity: usize,
+    price: String,
+) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    state
+        .create_listing(auth.uid, pid, quantity, parse_unit_price(&price)?)
+        .await?;
+    Ok(())
+}
+
+#[server]
+pub async fn cancel_listing(lid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.cancel_listing(auth.uid, lid).await?)
+}
+
+#[server]
+pub async fn buy_listing(lid: usize, quantity: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    state.buy_listing(auth.uid, lid, quantity).await?;
+    Ok(())
+}
+
+#[server]
+pub async fn create_buy_order(
+    pid: usize,
+    quantity: usize,
+    max_price: String,
+) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    state
+        .create_buy_order(auth.uid, pid, quantity, parse_unit_price(&max_price)?)
+        .await?;
+    Ok(())
+}
+
+#[server]
+pub async fn cancel_buy_order(boid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.cancel_buy_order(auth.uid, boid).await?)
+}
+
+#[server]
+pub async fn set_tradeable(pid: usize, tradeable: Option<String>) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+    auth.ensure(state.manages_purchase(auth.uid, pid).await?)?;
+    Ok(state.set_tradeable(pid, tradeable.is_some()).await?)
+}
@@ -3,28 +3,31 @@ use leptos::{prelude::*, task::spawn_local};
 use leptos_meta::Title;
 
 use crate::{
-    auth::{change_password, login_by_id},
-    route::redirect_to_login,
-    user::{delete_user, update_bio, update_birth_date, update_email, update_username},
+    auth::change_password,
+    route::{SUBSCRIPTIONS, WALLET, redirect_to_login},
+    user::{
+        delete_user, update_avatar, update_billing_country, update_bio, update_birth_date,
+        update_email, update_username,
+    },
 };
 
 #[component]
 pub fn Account() -> impl IntoView {
-    let (cookie, set_cookie) = crate::auth::get_login_session();
     let username = RwSignal::new(String::new());
     let bio = RwSignal::new(String::new());
+    let avatar = RwSignal::new(String::new());
     let email = RwSignal::new(String::new());
     let birth_date = RwSignal::new(String::new());
+    let billing_country = RwSignal::new(String::new());
     let old_passwd = RwSignal::new(String::new());
     let passwd = RwSignal::new(String::new());
     let passwd_conf = RwSignal::new(String::new());
     let passwd_del = RwSignal::new(String::new());
 
-    let id = move || cookie.get().unwrap_or_default();
     let on_submit_uname = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if update_username(id(), username.get()).await.is_ok() {
+            if update_username(username.get()).await.is_ok() {
                 username.set(String::new());
             }
         });
@@ -32,7 +35,7 @@ pub fn Account() -> impl IntoView {
     let on_submit_email = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if update_email(id(), email.get()).await.is_ok() {
+            if update_email(email.get()).await.is_ok() {
                 email.set(String::new());
             }
         });
@@ -40,23 +43,39 @@ pub fn Account() -> impl IntoView {
     let on_submit_bio = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if update_bio(id(), bio.get()).await.is_ok() {
+            if update_bio(bio.get()).await.is_ok() {
                 bio.set(String::new());
             }
         });
     };
+    let on_submit_avatar = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        spawn_local(async move {
+            if update_avatar(avatar.get()).await.is_ok() {
+                avatar.set(String::new());
+            }
+        });
+    };
     let on_submit_birth_date = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if update_birth_date(id(), birth_date.get()).await.is_ok() {
+            if update_birth_date(birth_date.get()).await.is_ok() {
                 birth_date.set(String::new());
             }
         });
     };
+    let on_submit_billing_country = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        spawn_local(async move {
+            if update_billing_country(billing_country.get()).await.is_ok() {
+                billing_country.set(String::new());
+            }
+        });
+    };
     let on_submit_change_passwd = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if change_password(id(), old_passwd.get(), passwd.get(), passwd_conf.get())
+            if change_password(old_passwd.get(), passwd.get(), passwd_conf.get())
                 .await
                 .is_ok()
             {
@@ -69,9 +88,7 @@ pub fn Account() -> impl IntoView {
     let on_submit_del_account = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if login_by_id(id(), passwd_del.get()).await.is_ok() {
-                let _ = delete_user(id()).await;
-                set_cookie.set(None);
+            if delete_user(passwd_del.get()).await.is_ok() {
                 let _ = redirect_to_login().await;
             } else {
                 passwd_del.set(String::new());
@@ -82,6 +99,8 @@ pub fn Account() -> impl IntoView {
     view! {
         <Title text="Edit Account"/>
         <h1>"Edit Account"</h1>
+        <p><a href=WALLET>"Wallet"</a></p>
+        <p><a href=SUBSCRIPTIONS>"Subscriptions"</a></p>
         <form on:submit=on_submit_uname>
             <div>
                 <label for="username">"Username:"</label>
@@ -121,6 +140,19 @@ pub fn Account() -> impl IntoView {
                 <button type="submit">"Save"</button>
             </div>
         </form>
+        <form on:submit=on_submit_avatar>
+            <div>
+                <label for="avatar">"Avatar URL:"</label>
+                <input
+                    type="url"
+                    id="avatar"
+                    bind:value=avatar
+                />
+            </div>
+            <div>
+                <button type="submit">"Save"</button>
+            </div>
+        </form>
         <form on:submit=on_submit_birth_date>
             <div>
                 <label for="birth_date">"Birth Date:"</label>
@@ -134,6 +166,21 @@ pub fn Account() -> impl IntoView {
                 <button type="submit">"Save"</button>
             </div>
         </form>
+        <form on:submit=on_submit_billing_country>
+            <div>
+                <label for="billing_country">"Billing Country:"</label>
+                <input
+                    type="text"
+                    id="billing_country"
+                    placeholder="e.g. DE"
+                    maxlength="2"
+                    bind:value=billing_country
+                />
+            </div>
+            <div>
+                <button type="submit">"Save"</button>
+            </div>
+        </form>
 
         <h2>"Change Password"</h2>
         <form on:submit=on_submit_change_passwd>
@@ -0,0 +1,506 @@
+use leptos::prelude::*;
+use leptos_meta::Title;
+
+use crate::{
+    age::{SetRatingAge, get_rating_ages},
+    coupon::CreateCoupon,
+    developer::{AddDeveloperMember, RemoveDeveloperMember},
+    item::{CreateBundle, CreateDiscount, UpdatePrice},
+    market::SetTradeable,
+    role::{GrantRole, RevokeRole, Role},
+    route::{ITEM, TRANSACTION, USER},
+    subscription::CreateSubscriptionPlan,
+    tax::{DeleteTaxRate, SetTaxRate, get_tax_rates},
+    transaction::{ApproveRefund, DeclineRefund, TransactionError, get_refund_requests},
+    user::{PublicProfile, UserError, get_users},
+};
+
+#[component]
+pub fn Admin() -> impl IntoView {
+    view! {
+        <Title text="Administration"/>
+        <h1>"Administration"</h1>
+        <RoleManagement/>
+        <RefundRequests/>
+        <TaxRates/>
+        <RatingAges/>
+        <PriceSettings/>
+        <Promotions/>
+        <BundlesAndPlans/>
+    }
+}
+
+/// Granting and revoking roles, and the developers users are members of.
+#[component]
+fn RoleManagement() -> impl IntoView {
+    let grant_role_act = ServerAction::<GrantRole>::new();
+    let revoke_role_act = ServerAction::<RevokeRole>::new();
+    let add_developer_member_act = ServerAction::<AddDeveloperMember>::new();
+    let remove_developer_member_act = ServerAction::<RemoveDeveloperMember>::new();
+
+    let users_resource = Resource::new(
+        || (),
+        |()| async move { get_users().await.map_err(|_| UserError::ServerError) },
+    );
+    let roles_view = Suspend::new(async move {
+        (users_resource.await).map_or(Err(UserError::ServerError), |users| {
+            let user_options = |users: Vec<PublicProfile>| {
+                users
+                    .into_iter()
+                    .map(|user| view! { <option value=user.uid>{user.uname}</option> })
+                    .collect_view()
+            };
+            let role_options = || {
+                [Role::Admin, Role::Developer, Role::Player]
+                    .into_iter()
+                    .map(|role| view! { <option value=format!("{role:?}")>{role.to_string()}</option> })
+                    .collect_view()
+            };
+            let grant_users = user_options(users.clone());
+            let revoke_users = user_options(users.clone());
+            let add_member_users = user_options(users.clone());
+            let remove_member_users = user_options(users);
+            Ok(view! {
+                <h3>"Grant Role"</h3>
+                <ActionForm action=grant_role_act>
+                    <select name="uid">{grant_users}</select>
+                    <select name="role">{role_options()}</select>
+                    <button type="submit">"Grant"</button>
+                </ActionForm>
+                <h3>"Revoke Role"</h3>
+                <ActionForm action=revoke_role_act>
+                    <select name="uid">{revoke_users}</select>
+                    <select name="role">{role_options()}</select>
+                    <button type="submit">"Revoke"</button>
+                </ActionForm>
+                <h3>"Developer Members"</h3>
+                <p>"Developers only manage the games of the studios they are members of."</p>
+                <ActionForm action=add_developer_member_act>
+                    <select name="uid">{add_member_users}</select>
+                    <input type="number" name="did" min="1" placeholder="Developer ID" required/>
+                    <button type="submit">"Add"</button>
+                </ActionForm>
+                <ActionForm action=remove_developer_member_act>
+                    <select name="uid">{remove_member_users}</select>
+                    <input type="number" name="did" min="1" placeholder="Developer ID" required/>
+                    <button type="submit">"Remove"</button>
+                </ActionForm>
+                {move || {
+                    add_developer_member_act
+                        .value()
+                        .get()
+                        .or_else(|| remove_developer_member_act.value().get())
+                        .and_then(Result::err)
+                        .map(|e| view! { <p class="error">{e.to_string()}</p> })
+                }}
+            })
+        })
+    });
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading users..."</p> }>{roles_view}</Suspense>
+    }
+}
+
+/// Pending refund requests, which are approved or declined here.
+#[component]
+fn RefundRequests() -> impl IntoView {
+    let approve_refund_act = ServerAction::<ApproveRefund>::new();
+    let decline_refund_act = ServerAction::<DeclineRefund>::new();
+
+    let refunds_resource = Resource::new(
+        move || {
+            (
+                approve_refund_act.version().get(),
+                decline_refund_act.version().get(),
+            )
+        },
+        |_| async move {
+            get_refund_requests()
+                .await
+                .map_err(|_| TransactionError::ServerError)
+        },
+    );
+    let refunds_view = Suspend::new(async move {
+        (refunds_resource.await).map(|requests| {
+            view! {
+                <h3>"Refund Requests"</h3>
+                <ul>{
+                    if requests.is_empty() {
+                        view! {<p>"<empty>"</p>}.into_any()
+                    } else {
+                        requests
+                            .into_iter()
+                            .map(|request| view! {
+                                <li>
+                                    <a href=format!("{}/{}", TRANSACTION, request.tid)>{request.refund_requested_at}</a>
+                                    {" | "}
+                                    <a href=format!("{}/{}", USER, request.uid)>{request.s_uname}</a>
+                                    {" | "}
+                                    <a href=format!("{}/{}", ITEM, request.pid)>{request.p_descr}</a>
+                                    {" | "}
+                                    {request.amount.to_string()}
+                                    {" | "}
+                                    {request.refund_reason.unwrap_or_else(|| String::from("<no reason provided>"))}
+                                    <ActionForm action=approve_refund_act>
+                                        <input type="hidden" name="tid" value=request.tid/>
+                                        <button type="submit">"Approve"</button>
+                                    </ActionForm>
+                                    <ActionForm action=decline_refund_act>
+                                        <input type="hidden" name="tid" value=request.tid/>
+                                        <button type="submit">"Decline"</button>
+                                    </ActionForm>
+                                </li>
+                            })
+                            .collect_view().into_any()
+                    }
+                }</ul>
+            }
+        })
+    });
+    view! {
+        <Transition fallback=move || view! { <p>"Loading refund requests..."</p> }>{refunds_view}</Transition>
+    }
+}
+
+/// The tax rate of each country and purchase type, and a form to set one.
+#[component]
+fn TaxRates() -> impl IntoView {
+    let set_tax_rate_act = ServerAction::<SetTaxRate>::new();
+    let delete_tax_rate_act = ServerAction::<DeleteTaxRate>::new();
+
+    let tax_rates_resource = Resource::new(
+        move || {
+            (
+                set_tax_rate_act.version().get(),
+                delete_tax_rate_act.version().get(),
+            )
+        },
+        |_| async move {
+            get_tax_rates()
+                .await
+                .map_err(|_| TransactionError::ServerError)
+        },
+    );
+    let tax_rates_view = Suspend::new(async move {
+        tax_rates_resource.await.map(|rates| {
+            view! {
+                <h3>"Tax Rates"</h3>
+                {if rates.is_empty() {
+                    view! { <p>"<empty>"</p> }.into_any()
+                } else {
+                    view! {
+                        <table>
+                            <tr>
+                                <th>"Country"</th>
+                                <th>"Type"</th>
+                                <th>"Rate"</th>
+                                <th>"Prices"</th>
+                                <th></th>
+                            </tr>
+                            {rates
+                                .into_iter()
+                                .map(|rate| view! {
+                                    <tr>
+                                        <td>{rate.country.clone()}</td>
+                                        <td>{rate.kind}</td>
+                                        <td>{rate.rate}</td>
+                                        <td>{if rate.inclusive { "Include tax" } else { "Exclude tax" }}</td>
+                                        <td>
+                                            <ActionForm action=delete_tax_rate_act>
+                                                <input type="hidden" name="country" value=rate.country/>
+                                                <input type="hidden" name="purchase_type" value=rate.purchase_type/>
+                                                <button type="submit">"Delete"</button>
+                                            </ActionForm>
+                                        </td>
+                                    </tr>
+                                })
+                                .collect_view()
+                            }
+                        </table>
+                    }
+                    .into_any()
+                }}
+            }
+        })
+    });
+    view! {
+        <Transition fallback=move || view! { <p>"Loading tax rates..."</p> }>{tax_rates_view}</Transition>
+        <h3>"Set Tax Rate"</h3>
+        <ActionForm action=set_tax_rate_act>
+            <div>
+                <input type="text" name="country" placeholder="Country, e.g. DE" maxlength="2" required/>
+                <select name="purchase_type">
+                    <option value="game_purchase">"Game purchase"</option>
+                    <option value="in_game_purchase">"In-game purchase"</option>
+                    <option value="subscriptions">"Subscription"</option>
+                    <option value="dlc">"DLC"</option>
+                    <option value="bundle">"Bundle"</option>
+                    <option value="etc">"etc."</option>
+                </select>
+                <input type="text" name="rate" placeholder="Rate in %, e.g. 19 or 7.25" required/>
+            </div>
+            <div>
+                <label>
+                    <input type="checkbox" name="inclusive" value="true" checked/>
+                    "Prices include the tax"
+                </label>
+            </div>
+            <button type="submit">"Set"</button>
+        </ActionForm>
+        {move || {
+            set_tax_rate_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(()) => String::from("Tax rate set."),
+                    Err(e) => e.to_string(),
+                })
+        }}
+    }
+}
+
+/// The minimum age of each age rating.
+#[component]
+fn RatingAges() -> impl IntoView {
+    let set_rating_age_act = ServerAction::<SetRatingAge>::new();
+
+    let rating_ages_resource = Resource::new(
+        move || set_rating_age_act.version().get(),
+        |_| async move {
+            get_rating_ages()
+                .await
+                .map_err(|_| TransactionError::ServerError)
+        },
+    );
+    let rating_ages_view = Suspend::new(async move {
+        rating_ages_resource.await.map(|ages| {
+            view! {
+                <h3>"Minimum Ages"</h3>
+                <table>
+                    <tr>
+                        <th>"Rating"</th>
+                        <th>"Minimum Age"</th>
+                    </tr>
+                    {ages
+                        .into_iter()
+                        .map(|age| view! {
+                            <tr>
+                                <td>{age.name}</td>
+                                <td>
+                                    <ActionForm action=set_rating_age_act>
+                                        <input type="hidden" name="rating" value=age.rating/>
+                                        <input type="number" name="min_age" min="0" max="99" value=age.min_age required/>
+                                        <button type="submit">"Save"</button>
+                                    </ActionForm>
+                                </td>
+                            </tr>
+                        })
+                        .collect_view()
+                    }
+                </table>
+            }
+        })
+    });
+    view! {
+        <Transition fallback=move || view! { <p>"Loading minimum ages..."</p> }>{rating_ages_view}</Transition>
+        {move || {
+            set_rating_age_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(()) => String::from("Minimum age set."),
+                    Err(e) => e.to_string(),
+                })
+        }}
+    }
+}
+
+/// Forms to change an item's price and whether it can be traded.
+#[component]
+fn PriceSettings() -> impl IntoView {
+    let update_price_act = ServerAction::<UpdatePrice>::new();
+    let set_tradeable_act = ServerAction::<SetTradeable>::new();
+
+    view! {
+        <h3>"Change Price"</h3>
+        <ActionForm action=update_price_act>
+            <input type="number" name="pid" min="1" placeholder="Item ID" required/>
+            <input type="text" name="price" placeholder="Price (USD)" required/>
+            <button type="submit">"Change"</button>
+        </ActionForm>
+        {move || {
+            update_price_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(()) => String::from("Price changed."),
+                    Err(e) => e.to_string(),
+                })
+        }}
+        <h3>"Tradeable Items"</h3>
+        <ActionForm action=set_tradeable_act>
+            <input type="number" name="pid" min="1" placeholder="Item ID" required/>
+            <label>
+                <input type="checkbox" name="tradeable" value="on"/>
+                "Tradeable on the market"
+            </label>
+            <button type="submit">"Save"</button>
+        </ActionForm>
+        {move || {
+            set_tradeable_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(()) => String::from("Item updated."),
+                    Err(e) => e.to_string(),
+                })
+        }}
+    }
+}
+
+/// Forms to create sales and coupons.
+#[component]
+fn Promotions() -> impl IntoView {
+    let create_discount_act = ServerAction::<CreateDiscount>::new();
+    let create_coupon_act = ServerAction::<CreateCoupon>::new();
+
+    view! {
+        <h3>"Create Sale"</h3>
+        <ActionForm action=create_discount_act>
+            <div>
+                <select name="kind">
+                    <option value="percentage">"Percent off"</option>
+                    <option value="fixed">"Fixed amount off (USD)"</option>
+                </select>
+                <input type="text" name="value" placeholder="e.g. 25 or 4.99" required/>
+            </div>
+            <div>
+                <select name="scope">
+                    <option value="purchase">"Item ID"</option>
+                    <option value="tag">"Tag name"</option>
+                    <option value="developer">"Developer ID"</option>
+                </select>
+                <input type="text" name="target" required/>
+            </div>
+            <div>
+                <input type="text" name="descr" placeholder="Sale name"/>
+            </div>
+            <div>
+                <label>"Starts At: "<input type="datetime-local" name="starts_at" required/></label>
+                <label>"Ends At: "<input type="datetime-local" name="ends_at" required/></label>
+            </div>
+            <button type="submit">"Create"</button>
+        </ActionForm>
+        {move || {
+            create_discount_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(()) => String::from("Sale created."),
+                    Err(e) => e.to_string(),
+                })
+        }}
+        <h3>"Create Coupon"</h3>
+        <ActionForm action=create_coupon_act>
+            <div>
+                <input type="text" name="code" placeholder="Code" required/>
+                <select name="kind">
+                    <option value="percentage">"Percent off"</option>
+                    <option value="fixed">"Fixed amount off (USD)"</option>
+                </select>
+                <input type="text" name="value" placeholder="e.g. 25 or 4.99" required/>
+            </div>
+            <div>
+                <input type="text" name="min_spend" placeholder="Minimum spend (USD)"/>
+                <input type="number" name="max_uses" min="1" placeholder="Max uses"/>
+                <input type="number" name="max_uses_per_user" min="1" placeholder="Max uses per user"/>
+            </div>
+            <div>
+                <input type="number" name="gid" min="1" placeholder="Game ID"/>
+                <input type="number" name="did" min="1" placeholder="Developer ID"/>
+                <select name="purchase_type">
+                    <option value="">"Any type"</option>
+                    <option value="game_purchase">"Game purchase"</option>
+                    <option value="in_game_purchase">"In-game purchase"</option>
+                    <option value="subscriptions">"Subscription"</option>
+                    <option value="dlc">"DLC"</option>
+                    <option value="bundle">"Bundle"</option>
+                    <option value="etc">"etc."</option>
+                </select>
+            </div>
+            <div>
+                <label>"Expires At: "<input type="datetime-local" name="expires_at"/></label>
+            </div>
+            <button type="submit">"Create"</button>
+        </ActionForm>
+        {move || {
+            create_coupon_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(()) => String::from("Coupon created."),
+                    Err(e) => e.to_string(),
+                })
+        }}
+    }
+}
+
+/// Forms to create bundles and subscription plans.
+#[component]
+fn BundlesAndPlans() -> impl IntoView {
+    let create_bundle_act = ServerAction::<CreateBundle>::new();
+    let create_plan_act = ServerAction::<CreateSubscriptionPlan>::new();
+
+    view! {
+        <h3>"Create Bundle"</h3>
+        <ActionForm action=create_bundle_act>
+            <div>
+                <input type="number" name="gid" min="1" placeholder="Game ID" required/>
+                <input type="text" name="price" placeholder="Price (USD)" required/>
+            </div>
+            <div>
+                <input type="text" name="pids" placeholder="Item IDs, e.g. 1, 2, 3" required/>
+            </div>
+            <div>
+                <input type="text" name="descr" placeholder="Bundle name"/>
+            </div>
+            <button type="submit">"Create"</button>
+        </ActionForm>
+        {move || {
+            create_bundle_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(pid) => view! { "Bundle created: " <a href=format!("{ITEM}/{pid}")>{format!("#{pid}")}</a> }.into_any(),
+                    Err(e) => e.to_string().into_any(),
+                })
+        }}
+        <h3>"Create Subscription Plan"</h3>
+        <ActionForm action=create_plan_act>
+            <div>
+                <input type="number" name="gid" min="1" placeholder="Game ID" required/>
+                <input type="text" name="price" placeholder="Price per period (USD)" required/>
+                <select name="billing_period">
+                    <option value="monthly">"Monthly"</option>
+                    <option value="yearly">"Yearly"</option>
+                </select>
+            </div>
+            <div>
+                <input type="text" name="catalog" placeholder="Included game IDs, e.g. 1, 2, 3" required/>
+            </div>
+            <div>
+                <input type="text" name="descr" placeholder="Plan name"/>
+            </div>
+            <button type="submit">"Create"</button>
+        </ActionForm>
+        {move || {
+            create_plan_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(pid) => view! { "Plan created: " <a href=format!("{ITEM}/{pid}")>{format!("#{pid}")}</a> }.into_any(),
+                    Err(e) => e.to_string().into_any(),
+                })
+        }}
+    }
+}
@@ -0,0 +1,242 @@
+use leptos::{prelude::*, task::spawn_local};
+use leptos_meta::Title;
+
+use crate::{
+    cart::{
+        CartError, RemoveFromCart, UpdateCartQuantity, checkout_cart, get_cart,
+        preview_cart_checkout,
+    },
+    page::{CheckoutTotals, ReceiptSummary},
+    route::{GAME, ITEM},
+    transaction::Receipt,
+    user::{UserError, get_users},
+};
+
+#[component]
+pub fn Cart() -> impl IntoView {
+    let remove_act = ServerAction::<RemoveFromCart>::new();
+    let update_act = ServerAction::<UpdateCartQuantity>::new();
+    let receipt = RwSignal::new(None::<Receipt>);
+
+    let cart_resource = Resource::new(
+        move || {
+            (
+                remove_act.version().get(),
+                update_act.version().get(),
+                receipt.with(Option::is_some),
+            )
+        },
+        |_| async move { get_cart().await.map_err(|_| CartError::ServerError) },
+    );
+    let cart_view = Suspend::new(async move {
+        (cart_resource.await).map(|items| {
+            view! {
+                <table>
+                    <tr>
+                        <th>"Game"</th>
+                        <th>"Item"</th>
+                        <th>"Type"</th>
+                        <th>"Unit Price"</th>
+                        <th>"Quantity"</th>
+                        <th></th>
+                    </tr>
+                    {items
+                        .into_iter()
+                        .map(|item| view! {
+                            <tr>
+                                <td><a href=format!("{}/{}", GAME, item.gid)>{item.gname}</a></td>
+                                <td><a href=format!("{}/{}", ITEM, item.pid)>{item.descr}</a></td>
+                                <td>{item.kind}</td>
+                                <td>{item.price.to_string()}</td>
+                                <td>
+                                    <ActionForm action=update_act>
+                                        <input type="hidden" name="pid" value=item.pid/>
+                                        <input type="number" name="quantity" min="0" value=item.quantity/>
+                                        <button type="submit">"Update"</button>
+                                    </ActionForm>
+                                </td>
+                                <td>
+                                    <ActionForm action=remove_act>
+                                        <input type="hidden" name="pid" value=item.pid/>
+                                        <button type="submit">"Remove"</button>
+                                    </ActionForm>
+                                </td>
+                            </tr>
+                        })
+                        .collect_view()
+                    }
+                </table>
+            }
+        })
+    });
+
+    view! {
+        <Title text="Cart"/>
+        <h1>"Cart"</h1>
+        <Transition fallback=move || view! { <p>"Loading cart..."</p> }>
+            <ErrorBoundary fallback=|errors| {
+                view! {
+                    <div class="error">
+                        <h1>"Something went wrong."</h1>
+                        <ul>
+                            {move || {
+                                errors
+                                    .get()
+                                    .into_iter()
+                                    .map(|(_, error)| view! { <li>{error.to_string()}</li> })
+                                    .collect::<Vec<_>>()
+                            }}
+                        </ul>
+                    </div>
+                }
+            }>{cart_view}</ErrorBoundary>
+        </Transition>
+        <CartCheckout remove_act update_act receipt/>
+        {move || receipt.get().map(|receipt| view! { <ReceiptSummary receipt/> })}
+    }
+}
+
+/// The checkout form of the cart, which fills `receipt` once the order is paid.
+#[component]
+fn CartCheckout(
+    remove_act: ServerAction<RemoveFromCart>,
+    update_act: ServerAction<UpdateCartQuantity>,
+    receipt: RwSignal<Option<Receipt>>,
+) -> impl IntoView {
+    let receiver_uid = RwSignal::new(String::new());
+    let payment_method = RwSignal::new(String::from("credit_card"));
+    let coupon = RwSignal::new(String::new());
+    let gift_message = RwSignal::new(String::new());
+    let checkout_error = RwSignal::new(None::<String>);
+
+    let on_submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        spawn_local(async move {
+            match checkout_cart(
+                receiver_uid.get().parse().ok(),
+                payment_method.get(),
+                Some(coupon.get()).filter(|x| !x.is_empty()),
+                Some(gift_message.get()).filter(|x| !x.is_empty()),
+            )
+            .await
+            {
+                Ok(paid) => {
+                    receiver_uid.set(String::new());
+                    coupon.set(String::new());
+                    gift_message.set(String::new());
+                    checkout_error.set(None);
+                    receipt.set(Some(paid));
+                }
+                Err(e) => checkout_error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let users_resource = Resource::new(
+        || (),
+        |()| async move { get_users().await.map_err(|_| UserError::ServerError) },
+    );
+    let checkout_view = Suspend::new(async move {
+        (users_resource.await).map_or(Err(UserError::ServerError), |users| {
+            Ok(view! {
+                <h3>"Checkout"</h3>
+                <form on:submit=on_submit>
+                    <div>
+                        <label for="receiver_id">"Receiver UID:"</label>
+                        <select id="receiver_id" bind:value=receiver_uid>
+                            <option value="">"Myself"</option>
+                            {users
+                                .into_iter()
+                                .map(|user| view! {
+                                    <option value={user.uid}>{user.uname}</option>
+                                })
+                                .collect_view()
+                            }
+                        </select>
+                    </div>
+                    {move || (!receiver_uid.with(String::is_empty)).then(|| view! {
+                        <div>
+                            <label for="gift_message">"Gift Message:"</label>
+                            <input id="gift_message" type="text" bind:value=gift_message/>
+                        </div>
+                    })}
+                    <div>
+                        <label for="payment_method">"Payment Method:"</label>
+                        <select id="payment_method" bind:value=payment_method>
+                            <option value="credit_card">Credit Card</option>
+                            <option value="debit_card">Debit Card</option>
+                            <option value="paypal">PayPal</option>
+                            <option value="wallet">Wallet</option>
+                            <option value="etc">etc.</option>
+                        </select>
+                    </div>
+                    <div>
+                        <label for="coupon">"Coupon Code:"</label>
+                        <input id="coupon" type="text" bind:value=coupon/>
+                    </div>
+                    <CheckoutPreview remove_act update_act receipt receiver_uid coupon/>
+                    <div>
+                        <button type="submit">"Pay for all items"</button>
+                    </div>
+                </form>
+                {move || checkout_error.get().map(|e| view! { <p class="error">{e}</p> })}
+            })
+        })
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading checkout..."</p> }>{checkout_view}</Suspense>
+    }
+}
+
+/// The totals the cart would be charged for the chosen receiver and coupon.
+#[component]
+fn CheckoutPreview(
+    remove_act: ServerAction<RemoveFromCart>,
+    update_act: ServerAction<UpdateCartQuantity>,
+    receipt: RwSignal<Option<Receipt>>,
+    receiver_uid: RwSignal<String>,
+    coupon: RwSignal<String>,
+) -> impl IntoView {
+    let preview_resource = Resource::new(
+        move || {
+            (
+                remove_act.version().get(),
+                update_act.version().get(),
+                receipt.with(Option::is_some),
+                receiver_uid.get(),
+                coupon.get(),
+            )
+        },
+        |(_, _, _, receiver_uid, coupon)| async move {
+            preview_cart_checkout(
+                receiver_uid.parse().ok(),
+                Some(coupon).filter(|x| !x.is_empty()),
+            )
+            .await
+            .map_err(|e| e.to_string())
+        },
+    );
+    let preview_view = move || {
+        Suspend::new(async move {
+            match preview_resource.await {
+                Ok(preview) => view! {
+                    <CheckoutTotals
+                        subtotal=preview.subtotal
+                        discount=preview.discount
+                        tax=preview.tax
+                        tax_added=preview.tax_added
+                        total=preview.total
+                        coupon_code=preview.coupon_code
+                    />
+                }
+                .into_any(),
+                Err(e) => view! { <p class="error">{e}</p> }.into_any(),
+            }
+        })
+    };
+
+    view! {
+        <Transition fallback=move || view! { <p>"Pricing..."</p> }>{preview_view}</Transition>
+    }
+}
@@ -4,8 +4,11 @@ use leptos_meta::{Meta, Title};
 use leptos_router::hooks::use_params;
 
 use crate::{
-    developer::{DeveloperError, DeveloperParams, get_developer, get_games_from_developer},
-    route::GAME,
+    developer::{
+        DeveloperError, DeveloperParams, get_developer, get_developer_members,
+        get_games_from_developer,
+    },
+    route::{GAME, USER},
 };
 
 #[component]
@@ -76,6 +79,36 @@ pub fn Developer() -> impl IntoView {
         })
     });
 
+    let members_resource = Resource::new(id, |id| async move {
+        match id {
+            Err(e) => Err(e),
+            Ok(id) => get_developer_members(id)
+                .await
+                .map_err(|_| DeveloperError::ServerError),
+        }
+    });
+    let members_view = Suspend::new(async move {
+        (members_resource.await).map_or(Err(DeveloperError::ServerError), |members| {
+            Ok(view! {
+                <h3>"Team"</h3>
+                <ul>{
+                    if members.is_empty() {
+                        view! {<p>"<empty>"</p>}.into_any()
+                    } else {
+                        members
+                            .into_iter()
+                            .map(|member| view! {
+                                <li>
+                                    <a href=format!("{}/{}", USER, member.uid)>{member.uname}</a>
+                                </li>
+                            })
+                            .collect_view().into_any()
+                    }
+                }</ul>
+            })
+        })
+    });
+
     view! {
         <h1>"Developer Info"</h1>
         <Suspense fallback=move || view! { <p>"Loading developer..."</p> }>
@@ -97,5 +130,6 @@ pub fn Developer() -> impl IntoView {
             }>{developer_view}</ErrorBoundary>
         </Suspense>
         <Suspense fallback=move || view! { <p>"Loading games..."</p> }>{games_view}</Suspense>
+        <Suspense fallback=move || view! { <p>"Loading team..."</p> }>{members_view}</Suspense>
     }
 }
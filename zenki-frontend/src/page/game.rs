@@ -5,20 +5,79 @@ use leptos_router::hooks::use_params;
 
 use crate::{
     activity::{is_playing, start_playing, stop_playing},
-    auth::get_login_session,
+    age::{AgeCheck, confirm_age, get_age_check},
     game::{
         GameError, GameParams, WishlistStatus, add_game_to_wishlist, get_developers_by_game,
         get_game, get_other_games_from_same_developers, get_wishlist_status,
         remove_game_from_wishlist,
     },
-    item::get_items,
+    item::{AddOn, get_add_ons, get_items},
     review::{get_reviews, post_review},
     route::{DEVELOPER, GAME, ITEM, TAG, USER},
     tag::get_tags,
 };
 
+/// The game page, behind a gate for games rated for older players than the visitor is known to
+/// be.
 #[component]
 pub fn Game() -> impl IntoView {
+    let query = use_params::<GameParams>();
+    let id = move || {
+        query.with(|q| {
+            q.as_ref()
+                .map(|q| q.id.unwrap_or_default())
+                .map_err(|_| GameError::InvalidId)
+        })
+    };
+    let age_check_resource = Resource::new_blocking(id, |id| async move {
+        match id {
+            Err(e) => Err(e),
+            Ok(id) => get_age_check(id).await.map_err(|_| GameError::ServerError),
+        }
+    });
+    // visitors who are not logged in confirm their age for this visit only
+    let confirmed = RwSignal::new(false);
+    let on_submit_confirm_age = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        spawn_local(async move {
+            let _ = confirm_age(id().unwrap_or_default()).await;
+            confirmed.set(true);
+        });
+    };
+    let gate_view = Suspend::new(async move {
+        match age_check_resource.await {
+            Ok(AgeCheck::TooYoung { min_age }) => view! {
+                <h1>"Game Info"</h1>
+                <p class="error">{format!("This game is only available to players aged {min_age} or older.")}</p>
+            }
+            .into_any(),
+            Ok(AgeCheck::ConfirmationRequired { min_age }) => (move || {
+                if confirmed.get() {
+                    view! { <GameDetails/> }.into_any()
+                } else {
+                    view! {
+                        <h1>"Game Info"</h1>
+                        <p>{format!("This game is only available to players aged {min_age} or older.")}</p>
+                        <form on:submit=on_submit_confirm_age>
+                            <button type="submit">{format!("I am {min_age} or older")}</button>
+                        </form>
+                    }
+                    .into_any()
+                }
+            })
+            .into_any(),
+            // the page itself reports invalid ids and missing games
+            _ => view! { <GameDetails/> }.into_any(),
+        }
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading game..."</p> }>{gate_view}</Suspense>
+    }
+}
+
+#[component]
+fn GameDetails() -> impl IntoView {
     let query = use_params::<GameParams>();
     let id = move || {
         query.with(|q| {
@@ -45,6 +104,7 @@ pub fn Game() -> impl IntoView {
                     <p><b>Rating: </b>{game.rating.clone()}</p>
                     <p><b>Release Date: </b>{game.release_at.clone().unwrap_or_else(|| String::from("<no release date provided>"))}</p>
                     <p><b>Date Added: </b>{game.created_at.clone().unwrap_or_else(|| String::from("<no added date provided>"))}</p>
+                    <p><a href=format!("{}/{}/market", GAME, game.gid)>"Market"</a></p>
 
                     // since we're using async rendering for this page,
                     // this metadata should be included in the actual HTML <head>
@@ -116,37 +176,26 @@ pub fn Game() -> impl IntoView {
         })
     });
 
-    let cookie = get_login_session().0;
-    let uid = cookie.get().unwrap_or_default();
-    let wishlist_status_resource = Resource::new(
-        move || (uid, id()),
-        |(uid, gid)| async move {
-            match gid {
-                Err(e) => Err(e),
-                Ok(gid) => get_wishlist_status(uid, gid)
-                    .await
-                    .map_err(|_| GameError::ServerError),
-            }
-        },
-    );
+    let wishlist_status_resource = Resource::new(id, |gid| async move {
+        match gid {
+            Err(e) => Err(e),
+            Ok(gid) => get_wishlist_status(gid)
+                .await
+                .map_err(|_| GameError::ServerError),
+        }
+    });
     let rated = RwSignal::new(String::new());
     let reviewed_text = RwSignal::new(String::new());
     let on_submit_review = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if let Ok(rated_f64) = rated.get().parse() {
-                if post_review(
-                    uid,
-                    id().unwrap_or_default(),
-                    rated_f64,
-                    reviewed_text.get(),
-                )
-                .await
-                .is_ok()
-                {
-                    rated.set(String::new());
-                    reviewed_text.set(String::new());
-                }
+            if let Ok(rated_f64) = rated.get().parse()
+                && post_review(id().unwrap_or_default(), rated_f64, reviewed_text.get())
+                    .await
+                    .is_ok()
+            {
+                rated.set(String::new());
+                reviewed_text.set(String::new());
             }
         });
     };
@@ -191,13 +240,13 @@ pub fn Game() -> impl IntoView {
     let on_submit_add_to_wishlist = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            let _ = add_game_to_wishlist(uid, id().unwrap_or_default()).await;
+            let _ = add_game_to_wishlist(id().unwrap_or_default()).await;
         });
     };
     let on_submit_remove_from_wishlist = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            let _ = remove_game_from_wishlist(uid, id().unwrap_or_default()).await;
+            let _ = remove_game_from_wishlist(id().unwrap_or_default()).await;
         });
     };
     let add_to_wishlist_view = Suspend::new(async move {
@@ -250,7 +299,16 @@ pub fn Game() -> impl IntoView {
                             .map(|item| view! {
                                 <li>
                                    <b><a href=format!("{}/{}", ITEM, item.pid)>{" "}{item.descr}{" "}</a></b> " - "
-                                   <b>{" ["}{item.price} " USD]"</b>
+                                   {match item.sale {
+                                       Some(sale) => view! {
+                                           <b>" ["<s>{item.price.to_string()}</s>" "{sale.price.to_string()}"]"</b>
+                                           " - sale ends at "{sale.ends_at}
+                                           {sale.lowest_price.map(|price| format!(" - lowest price in the last 30 days: {price}"))}
+                                       }.into_any(),
+                                       None => view! {
+                                           <b>{" ["}{item.price.to_string()}"]"</b>
+                                       }.into_any(),
+                                   }}
                                 </li>
                             })
                             .collect_view().into_any()
@@ -290,6 +348,44 @@ pub fn Game() -> impl IntoView {
         })
     });
 
+    let add_ons_resource = Resource::new(id, |id| async move {
+        match id {
+            Err(e) => Err(e),
+            Ok(id) => get_add_ons(id).await.map_err(|_| GameError::ServerError),
+        }
+    });
+    let add_ons_view = Suspend::new(async move {
+        (add_ons_resource.await).map_or(Err(GameError::ServerError), |add_ons| {
+            let (owned, unowned): (Vec<_>, Vec<_>) =
+                add_ons.into_iter().partition(|x| x.quantity > 0);
+            let add_on_list = |add_ons: Vec<AddOn>| {
+                if add_ons.is_empty() {
+                    return view! { <p>"<empty>"</p> }.into_any();
+                }
+                add_ons
+                    .into_iter()
+                    .map(|add_on| view! {
+                        <li>
+                            <a href=format!("{}/{}", ITEM, add_on.item.pid)>
+                                {add_on.item.descr.unwrap_or_else(|| format!("#{}", add_on.item.pid))}
+                            </a>
+                            " (" {add_on.item.kind} ")"
+                            {(add_on.quantity > 1).then(|| format!(" x{}", add_on.quantity))}
+                        </li>
+                    })
+                    .collect_view()
+                    .into_any()
+            };
+            Ok(view! {
+                <h3>"Downloadable Content"</h3>
+                <h4>"Owned"</h4>
+                <ul>{add_on_list(owned)}</ul>
+                <h4>"Not Owned"</h4>
+                <ul>{add_on_list(unowned)}</ul>
+            })
+        })
+    });
+
     let other_games_resource = Resource::new(id, |id| async move {
         match id {
             Err(e) => Err(e),
@@ -320,29 +416,30 @@ pub fn Game() -> impl IntoView {
         })
     });
 
+    let play_error = RwSignal::new(None::<String>);
     let on_submit_start_playing = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            let _ = start_playing(uid, id().unwrap_or_default()).await;
+            play_error.set(
+                start_playing(id().unwrap_or_default())
+                    .await
+                    .err()
+                    .map(|e| e.to_string()),
+            );
         });
     };
     let on_submit_stop_playing = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            let _ = stop_playing(uid, id().unwrap_or_default()).await;
+            let _ = stop_playing(id().unwrap_or_default()).await;
         });
     };
-    let is_playing_resource = Resource::new(
-        move || (uid, id()),
-        |(uid, gid)| async move {
-            match gid {
-                Err(e) => Err(e),
-                Ok(gid) => is_playing(uid, gid)
-                    .await
-                    .map_err(|_| GameError::ServerError),
-            }
-        },
-    );
+    let is_playing_resource = Resource::new(id, |gid| async move {
+        match gid {
+            Err(e) => Err(e),
+            Ok(gid) => is_playing(gid).await.map_err(|_| GameError::ServerError),
+        }
+    });
     let play_button_view = Suspend::new(async move {
         (is_playing_resource.await).map_or(Err(GameError::ServerError), |is_playing| {
             Ok(view! {
@@ -358,6 +455,7 @@ pub fn Game() -> impl IntoView {
                          <div>
                              <button type="submit">"Play"</button>
                          </div>
+                         {move || play_error.get().map(|e| view! { <p class="error">{e}</p> })}
                     </form> }.into_any()
                 }
             }})
@@ -388,6 +486,7 @@ pub fn Game() -> impl IntoView {
         <Suspense fallback=move || view! { <p>"Loading developers..."</p> }>{developers_view}</Suspense>
         <Suspense fallback=move || view! { <p>"Loading tags..."</p> }>{tags_view}</Suspense>
         <Suspense fallback=move || view! { <p>"Loading items..."</p> }>{items_view}</Suspense>
+        <Suspense fallback=move || view! { <p>"Loading DLC..."</p> }>{add_ons_view}</Suspense>
         <Suspense fallback=move || view! { <p>"Loading other games..."</p> }>{other_games_view}</Suspense>
         <Suspense fallback=move || view! { <p>"Loading wishlist..."</p> }>{add_to_wishlist_view}</Suspense>
         <Suspense fallback=move || view! { <p>"Loading review writer..."</p> }>{write_review_view}</Suspense>
@@ -0,0 +1,188 @@
+use leptos::prelude::*;
+use leptos_meta::Title;
+
+use crate::{
+    gift::{
+        AcceptGift, DeclineGift, Gift, GiftError, GiftStatus, MarkNotificationsRead, get_gifts,
+        get_notifications,
+    },
+    route::{GAME, ITEM, TRANSACTION, USER},
+};
+
+#[component]
+pub fn Gifts() -> impl IntoView {
+    let current_uid = crate::auth::use_current_uid();
+    let accept_act = ServerAction::<AcceptGift>::new();
+    let decline_act = ServerAction::<DeclineGift>::new();
+
+    let gifts_resource = Resource::new(
+        move || (accept_act.version().get(), decline_act.version().get()),
+        |_| async move { get_gifts().await.map_err(|_| GiftError::ServerError) },
+    );
+    let gift_list = move |gifts: Vec<Gift>, received: bool| {
+        if gifts.is_empty() {
+            view! {<p>"<empty>"</p>}.into_any()
+        } else {
+            gifts
+                .into_iter()
+                .map(|gift| view! { <GiftEntry gift received accept_act decline_act/> })
+                .collect_view()
+                .into_any()
+        }
+    };
+    let gifts_view = Suspend::new(async move {
+        let uid = current_uid.await;
+        (gifts_resource.await).map(|gifts| {
+            let (received, sent) = gifts
+                .into_iter()
+                .partition::<Vec<_>, _>(|gift| Some(gift.receiver_uid) == uid);
+            let (inbox, received) = received
+                .into_iter()
+                .partition::<Vec<_>, _>(|gift| gift.status == GiftStatus::Pending);
+            view! {
+                <h2>"Inbox"</h2>
+                <ul>{gift_list(inbox, true)}</ul>
+                <h2>"Received"</h2>
+                <ul>{gift_list(received, true)}</ul>
+                <h2>"Sent"</h2>
+                <ul>{gift_list(sent, false)}</ul>
+            }
+        })
+    });
+
+    view! {
+        <Title text="Gifts"/>
+        <h1>"Gifts"</h1>
+        {move || {
+            accept_act
+                .value()
+                .get()
+                .and_then(Result::err)
+                .or_else(|| decline_act.value().get().and_then(Result::err))
+                .map(|e| view! { <p class="error">{e.to_string()}</p> })
+        }}
+        <Notifications accept_act decline_act/>
+        <Transition fallback=move || view! { <p>"Loading gifts..."</p> }>
+            <ErrorBoundary fallback=|errors| {
+                view! {
+                    <div class="error">
+                        <h1>"Something went wrong."</h1>
+                        <ul>
+                            {move || {
+                                errors
+                                    .get()
+                                    .into_iter()
+                                    .map(|(_, error)| view! { <li>{error.to_string()}</li> })
+                                    .collect::<Vec<_>>()
+                            }}
+                        </ul>
+                    </div>
+                }
+            }>{gifts_view}</ErrorBoundary>
+        </Transition>
+    }
+}
+
+/// The viewer's notifications, which accepting or declining a gift may add to.
+#[component]
+fn Notifications(
+    accept_act: ServerAction<AcceptGift>,
+    decline_act: ServerAction<DeclineGift>,
+) -> impl IntoView {
+    let mark_read_act = ServerAction::<MarkNotificationsRead>::new();
+
+    let notifications_resource = Resource::new(
+        move || {
+            (
+                accept_act.version().get(),
+                decline_act.version().get(),
+                mark_read_act.version().get(),
+            )
+        },
+        |_| async move {
+            get_notifications()
+                .await
+                .map_err(|_| GiftError::ServerError)
+        },
+    );
+    let notifications_view = Suspend::new(async move {
+        (notifications_resource.await).map(|notifications| {
+            let any_unread = notifications.iter().any(|x| !x.read);
+            view! {
+                <h2>"Notifications"</h2>
+                <ul>{
+                    if notifications.is_empty() {
+                        view! {<p>"<empty>"</p>}.into_any()
+                    } else {
+                        notifications
+                            .into_iter()
+                            .map(|notification| {
+                                let message = notification.tid.map_or_else(
+                                    || view! { {notification.message.clone()} }.into_any(),
+                                    |tid| view! {
+                                        <a href=format!("{}/{}", TRANSACTION, tid)>{notification.message.clone()}</a>
+                                    }.into_any(),
+                                );
+                                view! {
+                                    <li>
+                                        {notification.created_at}
+                                        {" | "}
+                                        {if notification.read { message } else { view! { <b>{message}</b> }.into_any() }}
+                                    </li>
+                                }
+                            })
+                            .collect_view().into_any()
+                    }
+                }</ul>
+                {any_unread.then(|| view! {
+                    <ActionForm action=mark_read_act>
+                        <button type="submit">"Mark all as read"</button>
+                    </ActionForm>
+                })}
+            }
+        })
+    });
+
+    view! {
+        <Transition fallback=move || view! { <p>"Loading notifications..."</p> }>{notifications_view}</Transition>
+    }
+}
+
+/// A sent or received gift, with buttons to accept or decline it while it is pending.
+#[component]
+fn GiftEntry(
+    gift: Gift,
+    received: bool,
+    accept_act: ServerAction<AcceptGift>,
+    decline_act: ServerAction<DeclineGift>,
+) -> impl IntoView {
+    let pending = received && gift.status == GiftStatus::Pending;
+    view! {
+        <li>
+            <a href=format!("{}/{}", TRANSACTION, gift.tid)>{gift.created_at}</a>
+            {" | "}
+            {if received {
+                view! { "From " <a href=format!("{}/{}", USER, gift.sender_uid)>{gift.s_uname}</a> }.into_any()
+            } else {
+                view! { "To " <a href=format!("{}/{}", USER, gift.receiver_uid)>{gift.r_uname}</a> }.into_any()
+            }}
+            {" | "}
+            <a href=format!("{}/{}", GAME, gift.gid)><b>{gift.gname}</b></a>
+            {" | "}
+            <a href=format!("{}/{}", ITEM, gift.pid)>{gift.p_descr}</a>
+            {" | "}
+            {gift.status.to_string()}
+            {gift.message.map(|message| view! { <blockquote>{message}</blockquote> })}
+            {pending.then(|| view! {
+                <ActionForm action=accept_act>
+                    <input type="hidden" name="tid" value=gift.tid/>
+                    <button type="submit">"Accept"</button>
+                </ActionForm>
+                <ActionForm action=decline_act>
+                    <input type="hidden" name="tid" value=gift.tid/>
+                    <button type="submit">"Decline"</button>
+                </ActionForm>
+            })}
+        </li>
+    }
+}
@@ -2,10 +2,16 @@
 use leptos::{prelude::*, task::spawn_local};
 use leptos_meta::{Meta, Title};
 use leptos_router::hooks::use_params;
+use zenki_util::Money;
 
 use crate::{
-    item::{ItemError, ItemParams, get_item},
-    transaction::create_transaction,
+    cart::add_to_cart,
+    item::{ItemError, ItemParams, PricePoint, get_bundle_items, get_item, get_price_history},
+    market::{get_listing_history, get_market_trades},
+    page::{CheckoutTotals, ReceiptSummary},
+    route::{CART, GAME, ITEM, USER},
+    subscription::get_subscription_plan,
+    transaction::{Receipt, create_transaction, preview_checkout},
     user::{UserError, get_users},
 };
 
@@ -33,8 +39,24 @@ pub fn Item() -> impl IntoView {
             Ok(Ok(item)) => Ok(view! {
                 <h2>{item.descr.clone()}</h2>
                 <p><b>Description: </b>{item.descr.clone().unwrap_or_else(|| String::from("<no description provided>"))}</p>
-                <p><b>Price: </b>{item.price}{" USD"}</p>
-                <p><b>Type: </b>{item.kind}</p>
+                {match item.sale {
+                    Some(sale) => view! {
+                        <p>
+                            <b>Price: </b><s>{item.price.to_string()}</s>" "{sale.price.to_string()}
+                            {sale.descr.map(|descr| format!(" ({descr})"))}
+                        </p>
+                        {sale.lowest_price.map(|price| view! {
+                            <p><b>Lowest Price in the Last 30 Days: </b>{price.to_string()}</p>
+                        })}
+                        <p><b>Sale Ends At: </b>{sale.ends_at}</p>
+                    }.into_any(),
+                    None => view! { <p><b>Price: </b>{item.price.to_string()}</p> }.into_any(),
+                }}
+                <PriceHistory pid=item.pid/>
+                <MarketHistory pid=item.pid/>
+                <p><b>Type: </b>{item.kind.clone()}</p>
+                {(item.kind == "Bundle").then(|| view! { <BundleContents pid=item.pid/> })}
+                {(item.kind == "Subscription").then(|| view! { <SubscriptionDetails pid=item.pid/> })}
                 <p><b>Created At: </b>{item.created_at.clone().unwrap_or_else(|| String::from("<no creation time provided>"))}</p>
 
                 <Title text=item.pid.to_string()/>
@@ -44,33 +66,96 @@ pub fn Item() -> impl IntoView {
         }
     });
 
-    let cookie = crate::auth::get_login_session().0;
-    let uid = move || cookie.get();
-    let receiver_uid = RwSignal::new(uid().unwrap_or_default().to_string());
-    let amount = RwSignal::new(String::from("1"));
-    let payment_method = RwSignal::new(String::new());
+    let receiver_uid = RwSignal::new(String::new());
+    let quantity = RwSignal::new(String::from("1"));
+    let payment_method = RwSignal::new(String::from("credit_card"));
+    let coupon = RwSignal::new(String::new());
+    let gift_message = RwSignal::new(String::new());
+    let receipt = RwSignal::new(None::<Receipt>);
+    let checkout_error = RwSignal::new(None::<String>);
 
     let on_submit = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if let (Some(uid), Ok(pid), Ok(ruid), Ok(amount_usize)) = (
-                uid(),
-                id(),
-                receiver_uid.get().parse(),
-                amount.get().parse(),
-            ) {
-                if create_transaction(uid, pid, ruid, payment_method.get(), amount_usize)
-                    .await
-                    .is_ok()
+            if let (Ok(pid), Ok(quantity_usize)) = (id(), quantity.get().parse()) {
+                match create_transaction(
+                    pid,
+                    receiver_uid.get().parse().ok(),
+                    payment_method.get(),
+                    quantity_usize,
+                    Some(coupon.get()).filter(|x| !x.is_empty()),
+                    Some(gift_message.get()).filter(|x| !x.is_empty()),
+                )
+                .await
                 {
-                    receiver_uid.set(String::new());
-                    amount.set(String::new());
-                    payment_method.set(String::new());
+                    Ok(paid) => {
+                        receiver_uid.set(String::new());
+                        quantity.set(String::from("1"));
+                        coupon.set(String::new());
+                        gift_message.set(String::new());
+                        checkout_error.set(None);
+                        receipt.set(Some(paid));
+                    }
+                    Err(e) => checkout_error.set(Some(e.to_string())),
                 }
             }
         });
     };
 
+    let added_to_cart = RwSignal::new(false);
+    let on_add_to_cart = move |_| {
+        spawn_local(async move {
+            if let (Ok(pid), Ok(quantity_usize)) = (id(), quantity.get().parse())
+                && add_to_cart(pid, quantity_usize).await.is_ok()
+            {
+                added_to_cart.set(true);
+            }
+        });
+    };
+
+    let receipt_view = move || {
+        receipt
+            .get()
+            .map(|receipt| view! { <ReceiptSummary receipt/> })
+    };
+
+    let preview_resource = Resource::new(
+        move || (id(), quantity.get(), receiver_uid.get(), coupon.get()),
+        |(id, quantity, receiver_uid, coupon)| async move {
+            let (Ok(pid), Ok(quantity)) = (id, quantity.parse()) else {
+                return None;
+            };
+            Some(
+                preview_checkout(
+                    pid,
+                    quantity,
+                    receiver_uid.parse().ok(),
+                    Some(coupon).filter(|x| !x.is_empty()),
+                )
+                .await
+                .map_err(|e| e.to_string()),
+            )
+        },
+    );
+    let preview_view = move || {
+        Suspend::new(async move {
+            preview_resource.await.map(|preview| match preview {
+                Ok(preview) => view! {
+                    <CheckoutTotals
+                        subtotal=preview.subtotal
+                        discount=preview.discount
+                        tax=preview.tax
+                        tax_added=preview.tax_added
+                        total=preview.total
+                        coupon_code=preview.coupon_code
+                    />
+                }
+                .into_any(),
+                Err(e) => view! { <p class="error">{e}</p> }.into_any(),
+            })
+        })
+    };
+
     let users_resource = Resource::new(
         || (),
         |()| async move { get_users().await.map_err(|_| UserError::ServerError) },
@@ -82,37 +167,58 @@ pub fn Item() -> impl IntoView {
                 <form on:submit=on_submit>
                     <div>
                         <label for="receiver_id">"Receiver UID:"</label>
-                        <select id="receiver_id" bind:value=receiver_uid>{
-                            users
+                        <select id="receiver_id" bind:value=receiver_uid>
+                            <option value="">"Myself"</option>
+                            {users
                                 .into_iter()
                                 .map(|user| view! {
                                     <option value={user.uid}>{user.uname}</option>
                                 })
                                 .collect_view().into_any()
-                        }</select>
+                            }
+                        </select>
                     </div>
+                    {move || (!receiver_uid.with(String::is_empty)).then(|| view! {
+                        <div>
+                            <label for="gift_message">"Gift Message:"</label>
+                            <input id="gift_message" type="text" bind:value=gift_message/>
+                        </div>
+                    })}
                     <div>
-                        <label for="amount">"Amount:"</label>
+                        <label for="quantity">"Quantity:"</label>
                         <input
-                            id="amount"
+                            id="quantity"
                             type="number"
                             min="1"
-                            bind:value=amount
+                            bind:value=quantity
                         />
                     </div>
                     <div>
-                        <label for="amount">"Payment Method:"</label>
+                        <label for="payment_method">"Payment Method:"</label>
                         <select id="payment_method" bind:value=payment_method>
                             <option value="credit_card">Credit Card</option>
                             <option value="debit_card">Debit Card</option>
                             <option value="paypal">PayPal</option>
+                            <option value="wallet">Wallet</option>
                             <option value="etc">etc.</option>
                         </select>
                     </div>
+                    <div>
+                        <label for="coupon">"Coupon Code:"</label>
+                        <input id="coupon" type="text" bind:value=coupon/>
+                    </div>
+                    <Transition fallback=move || view! { <p>"Pricing..."</p> }>{preview_view}</Transition>
                     <div>
                         <button type="submit">"Pay"</button>
                     </div>
                 </form>
+                {move || checkout_error.get().map(|e| view! { <p class="error">{e}</p> })}
+                <div>
+                    <button on:click=on_add_to_cart>"Add to cart"</button>
+                    {move || added_to_cart.get().then(|| view! {
+                        " Added. " <a href=CART>"View cart"</a>
+                    })}
+                </div>
             })
         })
     });
@@ -138,5 +244,250 @@ pub fn Item() -> impl IntoView {
             }>{item_view}</ErrorBoundary>
         </Suspense>
         <Suspense fallback=move || view! { <p>"Loading checkout..."</p> }>{checkout_view}</Suspense>
+        {receipt_view}
+    }
+}
+
+#[component]
+fn PriceHistory(pid: i32) -> impl IntoView {
+    let history_resource = Resource::new(
+        move || pid,
+        |pid| async move {
+            let pid = usize::try_from(pid).map_err(|_| ItemError::InvalidId)?;
+            get_price_history(pid)
+                .await
+                .map_err(|_| ItemError::ServerError)
+        },
+    );
+    let history_view = Suspend::new(async move {
+        history_resource.await.map(|points| {
+            (points.len() > 1).then(|| {
+                view! {
+                    <p><b>"Price History:"</b></p>
+                    <PriceChart points/>
+                }
+            })
+        })
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading price history..."</p> }>
+            <ErrorBoundary fallback=|_| view! { <p class="error">"Could not load the price history."</p> }>
+                {history_view}
+            </ErrorBoundary>
+        </Suspense>
+    }
+}
+
+/// Past listings of an item on the player marketplace and the trades they settled in.
+#[component]
+fn MarketHistory(pid: i32) -> impl IntoView {
+    let market_resource = Resource::new(
+        move || pid,
+        |pid| async move {
+            let pid = usize::try_from(pid).map_err(|_| ItemError::InvalidId)?;
+            let listings = get_listing_history(pid)
+                .await
+                .map_err(|_| ItemError::ServerError)?;
+            let trades = get_market_trades(pid)
+                .await
+                .map_err(|_| ItemError::ServerError)?;
+            Ok::<_, ItemError>((listings, trades))
+        },
+    );
+    let market_view = Suspend::new(async move {
+        market_resource.await.map(|(listings, trades)| {
+            (!listings.is_empty()).then(|| {
+                view! {
+                    <p><b>"Listing History:"</b></p>
+                    <ul>
+                        {listings
+                            .into_iter()
+                            .map(|listing| view! {
+                                <li>
+                                    <a href=format!("{}/{}", USER, listing.seller_uid)>{listing.s_uname}</a>
+                                    {format!(
+                                        " | {} of {} sold at {} each | {} | listed at {}",
+                                        listing.sold,
+                                        listing.quantity,
+                                        listing.price,
+                                        listing.status,
+                                        listing.created_at,
+                                    )}
+                                    {listing.closed_at.map(|x| format!(" | closed at {x}"))}
+                                </li>
+                            })
+                            .collect_view()}
+                    </ul>
+                    <p><b>"Market Trades:"</b></p>
+                    <ul>
+                        {trades
+                            .into_iter()
+                            .map(|trade| view! {
+                                <li>
+                                    {format!("{} x {} | ", trade.quantity, trade.unit_price)}
+                                    {trade.seller_uid.zip(trade.s_uname).map(|(uid, uname)| view! {
+                                        <a href=format!("{}/{}", USER, uid)>{uname}</a>
+                                        " to "
+                                    })}
+                                    <a href=format!("{}/{}", USER, trade.buyer_uid)>{trade.b_uname}</a>
+                                    {trade.bought_at.map(|x| format!(" | at {x}"))}
+                                </li>
+                            })
+                            .collect_view()}
+                    </ul>
+                }
+            })
+        })
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading market history..."</p> }>
+            <ErrorBoundary fallback=|_| view! { <p class="error">"Could not load the market history."</p> }>
+                {market_view}
+            </ErrorBoundary>
+        </Suspense>
+    }
+}
+
+/// A step chart of prices over time, each price holding until the next one.
+#[component]
+fn PriceChart(points: Vec<PricePoint>) -> impl IntoView {
+    const WIDTH: i64 = 400;
+    const HEIGHT: i64 = 120;
+    // room for the price labels on the left and the last price on the right
+    const LEFT: i64 = 60;
+    const RIGHT: i64 = WIDTH - 20;
+
+    let first = points.first().map_or(0, |x| x.timestamp);
+    let span = points.last().map_or(0, |x| x.timestamp) - first;
+    let highest = points
+        .iter()
+        .map(|x| x.price)
+        .max_by_key(|x| x.minor())
+        .filter(|x| x.minor() > 0);
+    let x = move |timestamp: i64| LEFT + (timestamp - first) * (RIGHT - LEFT) / span.max(1);
+    let y = move |minor: i64| HEIGHT - 10 - minor * (HEIGHT - 20) / highest.map_or(1, Money::minor);
+
+    let mut line = Vec::new();
+    for (i, point) in points.iter().enumerate() {
+        if let Some(previous) = i.checked_sub(1).and_then(|i| points.get(i)) {
+            line.push((x(point.timestamp), y(previous.price.minor())));
+        }
+        line.push((x(point.timestamp), y(point.price.minor())));
+    }
+    if let Some(last) = points.last() {
+        line.push((WIDTH, y(last.price.minor())));
+    }
+    let line = line
+        .into_iter()
+        .map(|(x, y)| format!("{x},{y}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    view! {
+        <svg
+            class="price-chart"
+            width=WIDTH
+            height=HEIGHT
+            viewBox=format!("0 0 {WIDTH} {HEIGHT}")
+        >
+            <line x1=LEFT y1=y(0) x2=WIDTH y2=y(0) stroke="gray"/>
+            <text x="0" y=y(0)>{highest.map(|x| Money::zero(x.currency()).to_string())}</text>
+            <text x="0" y=y(highest.map_or(0, Money::minor)) dominant-baseline="hanging">
+                {highest.map(|x| x.to_string())}
+            </text>
+            <polyline points=line fill="none" stroke="currentColor" stroke-width="2"/>
+            {points
+                .into_iter()
+                .map(|point| view! {
+                    <circle cx=x(point.timestamp) cy=y(point.price.minor()) r="3">
+                        <title>{format!("{} since {}", point.price, point.changed_at)}</title>
+                    </circle>
+                })
+                .collect_view()}
+        </svg>
+    }
+}
+
+#[component]
+fn BundleContents(pid: i32) -> impl IntoView {
+    let bundle_resource = Resource::new(
+        move || pid,
+        |pid| async move {
+            let pid = usize::try_from(pid).map_err(|_| ItemError::InvalidId)?;
+            get_bundle_items(pid)
+                .await
+                .map_err(|_| ItemError::ServerError)
+        },
+    );
+    let bundle_view = Suspend::new(async move {
+        bundle_resource.await.map(|items| {
+            view! {
+                <p><b>"Includes:"</b></p>
+                <ul>
+                    {items
+                        .into_iter()
+                        .map(|item| view! {
+                            <li>
+                                <a href=format!("{}/{}", ITEM, item.pid)>
+                                    {item.descr.unwrap_or(item.gname)}
+                                </a>
+                                " (" {item.kind} ", " {item.price.to_string()} ")"
+                                {item.owned.then_some(" - already owned")}
+                            </li>
+                        })
+                        .collect_view()}
+                </ul>
+            }
+        })
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading bundle..."</p> }>
+            <ErrorBoundary fallback=|_| view! { <p class="error">"Could not load the bundle."</p> }>
+                {bundle_view}
+            </ErrorBoundary>
+        </Suspense>
+    }
+}
+
+#[component]
+fn SubscriptionDetails(pid: i32) -> impl IntoView {
+    let plan_resource = Resource::new(
+        move || pid,
+        |pid| async move {
+            let pid = usize::try_from(pid).map_err(|_| ItemError::InvalidId)?;
+            get_subscription_plan(pid)
+                .await
+                .map_err(|_| ItemError::ServerError)
+        },
+    );
+    let plan_view = Suspend::new(async move {
+        plan_resource.await.map(|plan| {
+            plan.map(|plan| {
+                view! {
+                    <p><b>"Billing Period: "</b>{plan.billing_period}</p>
+                    <p><b>"Play while subscribed:"</b></p>
+                    <ul>
+                        {plan
+                            .catalog
+                            .into_iter()
+                            .map(|game| view! {
+                                <li><a href=format!("{}/{}", GAME, game.gid)>{game.gname}</a></li>
+                            })
+                            .collect_view()}
+                    </ul>
+                }
+            })
+        })
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading subscription..."</p> }>
+            <ErrorBoundary fallback=|_| view! { <p class="error">"Could not load the subscription."</p> }>
+                {plan_view}
+            </ErrorBoundary>
+        </Suspense>
     }
 }
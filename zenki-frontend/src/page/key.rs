@@ -0,0 +1,197 @@
+use leptos::{prelude::*, task::spawn_local};
+use leptos_meta::Title;
+
+use crate::{
+    key::{
+        GenerateKeys, KeyError, RedeemKey, export_unused_keys, get_key_batches, get_product_keys,
+    },
+    page::data_url,
+    route::{GAME, ITEM, USER},
+};
+
+#[component]
+pub fn Keys() -> impl IntoView {
+    let generate_act = ServerAction::<GenerateKeys>::new();
+    let selected_batch = RwSignal::new(None::<usize>);
+    let export = RwSignal::new(None::<(usize, String)>);
+
+    let batches_resource = Resource::new(
+        move || generate_act.version().get(),
+        |_| async move { get_key_batches().await.map_err(|_| KeyError::ServerError) },
+    );
+    let batches_view = Suspend::new(async move {
+        (batches_resource.await).map(|batches| {
+            if batches.is_empty() {
+                return view! {<p>"<empty>"</p>}.into_any();
+            }
+            view! {
+                <table>
+                    <tr>
+                        <th>"Batch"</th>
+                        <th>"Game"</th>
+                        <th>"Item"</th>
+                        <th>"Description"</th>
+                        <th>"Created At"</th>
+                        <th>"Redeemed"</th>
+                        <th></th>
+                    </tr>
+                    {batches
+                        .into_iter()
+                        .map(|batch| {
+                            let kbid = batch.kbid;
+                            let on_export = move |_| {
+                                spawn_local(async move {
+                                    if let Ok(csv) = export_unused_keys(kbid).await {
+                                        export.set(Some((kbid, csv)));
+                                    }
+                                });
+                            };
+                            view! {
+                                <tr>
+                                    <td>{kbid}</td>
+                                    <td><a href=format!("{}/{}", GAME, batch.gid)>{batch.gname}</a></td>
+                                    <td><a href=format!("{}/{}", ITEM, batch.pid)>{batch.p_descr}</a></td>
+                                    <td>{batch.descr}</td>
+                                    <td>{batch.created_at}</td>
+                                    <td>{format!("{} / {}", batch.redeemed, batch.total)}</td>
+                                    <td>
+                                        <button on:click=move |_| selected_batch.set(Some(kbid))>"Show keys"</button>
+                                        <button on:click=on_export>"Export unused (CSV)"</button>
+                                    </td>
+                                </tr>
+                            }
+                        })
+                        .collect_view()
+                    }
+                </table>
+            }
+            .into_any()
+        })
+    });
+
+    let export_view = move || {
+        export.get().map(|(kbid, csv)| {
+            view! {
+                <p>
+                    <a download=format!("keys-{kbid}.csv") href=data_url("text/csv", csv.as_bytes())>
+                        {format!("Download unused keys of batch {kbid}")}
+                    </a>
+                </p>
+            }
+        })
+    };
+
+    view! {
+        <Title text="Product Keys"/>
+        <h1>"Product Keys"</h1>
+        <h2>"Generate Keys"</h2>
+        <ActionForm action=generate_act>
+            <div>
+                <input type="number" name="pid" min="1" placeholder="Item ID" required/>
+                <input type="number" name="count" min="1" placeholder="Number of keys" required/>
+                <input type="text" name="descr" placeholder="Batch description"/>
+            </div>
+            <button type="submit">"Generate"</button>
+        </ActionForm>
+        {move || {
+            generate_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(kbid) => format!("Batch {kbid} generated."),
+                    Err(e) => e.to_string(),
+                })
+        }}
+        <h2>"Batches"</h2>
+        <Transition fallback=move || view! { <p>"Loading batches..."</p> }>{batches_view}</Transition>
+        {export_view}
+        <BatchKeys selected_batch/>
+    }
+}
+
+/// The keys of the batch picked on the product keys page, with who redeemed them.
+#[component]
+fn BatchKeys(selected_batch: RwSignal<Option<usize>>) -> impl IntoView {
+    let keys_resource = Resource::new(
+        move || selected_batch.get(),
+        |kbid| async move {
+            match kbid {
+                None => Ok(None),
+                Some(kbid) => get_product_keys(kbid)
+                    .await
+                    .map(|keys| Some((kbid, keys)))
+                    .map_err(|_| KeyError::ServerError),
+            }
+        },
+    );
+    let keys_view = Suspend::new(async move {
+        (keys_resource.await).map(|keys| {
+            keys.map(|(kbid, keys)| {
+                view! {
+                    <h2>{format!("Keys of Batch {kbid}")}</h2>
+                    <table>
+                        <tr>
+                            <th>"Key"</th>
+                            <th>"Redeemed By"</th>
+                            <th>"Redeemed At"</th>
+                        </tr>
+                        {keys
+                            .into_iter()
+                            .map(|key| view! {
+                                <tr>
+                                    <td><code>{key.code}</code></td>
+                                    <td>{key.redeemed_by.map(|uid| view! {
+                                        <a href=format!("{}/{}", USER, uid)>{key.r_uname}</a>
+                                    })}</td>
+                                    <td>{key.redeemed_at}</td>
+                                </tr>
+                            })
+                            .collect_view()
+                        }
+                    </table>
+                }
+            })
+        })
+    });
+
+    view! {
+        <Transition fallback=move || view! { <p>"Loading keys..."</p> }>{keys_view}</Transition>
+    }
+}
+
+#[component]
+pub fn Redeem() -> impl IntoView {
+    let redeem_act = ServerAction::<RedeemKey>::new();
+
+    view! {
+        <Title text="Redeem a Key"/>
+        <h1>"Redeem a Key"</h1>
+        <ActionForm action=redeem_act>
+            <div>
+                <label for="code">"Key:"</label>
+                <input id="code" type="text" name="code" placeholder="XXXXX-XXXXX-XXXXX" required/>
+            </div>
+            <div>
+                <button type="submit">"Redeem"</button>
+            </div>
+        </ActionForm>
+        {move || {
+            redeem_act
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(redeemed) => view! {
+                        <p>
+                            "Redeemed "
+                            <a href=format!("{}/{}", ITEM, redeemed.pid)>{redeemed.p_descr}</a>
+                            {format!(" ({}) for ", redeemed.kind)}
+                            <a href=format!("{}/{}", GAME, redeemed.gid)>{redeemed.gname}</a>
+                            "."
+                        </p>
+                    }
+                    .into_any(),
+                    Err(e) => view! { <p class="error">{e.to_string()}</p> }.into_any(),
+                })
+        }}
+    }
+}
@@ -5,15 +5,16 @@ use crate::{auth::login, route::REGISTER};
 
 #[component]
 pub fn Login() -> impl IntoView {
-    let set_cookie = crate::auth::get_login_session().1;
     let username = RwSignal::new(String::new());
     let password = RwSignal::new(String::new());
 
     let on_submit = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            if let Ok(id) = login(username.get().clone(), password.get().clone()).await {
-                set_cookie.set(Some(id));
+            if login(username.get().clone(), password.get().clone())
+                .await
+                .is_ok()
+            {
                 let _ = crate::route::redirect_to_main().await;
             } else {
                 username.set(String::new());
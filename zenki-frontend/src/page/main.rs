@@ -3,13 +3,13 @@ use leptos_meta::{Meta, Title};
 
 use crate::{
     game::list_games,
-    route::{GAME, USER},
-    user::{User, UserError, get_user},
+    route::{CART, GAME, GIFTS, REDEEM, USER},
+    user::{PublicProfile, UserError, get_user},
 };
 
 #[component]
 pub fn Main() -> impl IntoView {
-    let (get_cookie, set_cookie) = crate::auth::get_login_session();
+    let current_uid = crate::auth::use_current_uid();
     // load the games
     let games = Resource::new(|| (), |()| list_games());
     let games = move || {
@@ -22,10 +22,10 @@ pub fn Main() -> impl IntoView {
     let games_count = Resource::new(|| (), |()| list_games());
     let games_count = Resource::new(
         || (),
-        move |()| async move { games_count.await.as_ref().map(Vec::len).unwrap_or(0) },
+        move |()| async move { games_count.await.as_ref().map_or(0, Vec::len) },
     );
 
-    let id = move || get_cookie.get().ok_or(UserError::ServerError);
+    let id = move || current_uid.get().flatten().ok_or(UserError::ServerError);
     let user_resource = Resource::new_blocking(id, |id| async move {
         match id {
             Err(e) => Err(e),
@@ -37,16 +37,19 @@ pub fn Main() -> impl IntoView {
     });
 
     let on_signout_click = move |_| {
-        set_cookie.set(None);
         spawn_local(async {
+            let _ = crate::auth::logout().await;
             let _ = crate::route::redirect_to_login().await;
         });
     };
     let user_view = Suspend::new(async move {
         match user_resource.await {
-            Ok(Ok(User { uid, uname, .. })) => Ok(view! {
+            Ok(Ok(PublicProfile { uid, uname, .. })) => Ok(view! {
                 <div>
                     <p>Logged in as: <a href={format!("{USER}/{uid}")}><b>{uname.clone()}</b></a></p>
+                    <p><a href=CART>"Cart"</a></p>
+                    <p><a href=GIFTS>"Gifts"</a></p>
+                    <p><a href=REDEEM>"Redeem a key"</a></p>
                 </div>
                 <div>
                     <button on:click=on_signout_click>"Sign Out"</button>
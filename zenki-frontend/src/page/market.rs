@@ -0,0 +1,333 @@
+use leptos::prelude::*;
+use leptos_meta::Title;
+use leptos_router::hooks::use_params;
+
+use crate::{
+    game::{GameError, GameParams, get_game},
+    inventory::get_inventory,
+    item::get_items,
+    market::{
+        BuyListing, CancelBuyOrder, CancelListing, CreateBuyOrder, CreateListing, MarketListing,
+        get_market,
+    },
+    route::{GAME, ITEM, USER},
+};
+
+/// The player marketplace of a game, where its tradeable items are listed and bought.
+#[component]
+pub fn Market() -> impl IntoView {
+    let query = use_params::<GameParams>();
+    let id = Signal::derive(move || {
+        query.with(|q| {
+            q.as_ref()
+                .map(|q| q.id.unwrap_or_default())
+                .map_err(|_| GameError::InvalidId)
+        })
+    });
+
+    let create_listing_act = ServerAction::<CreateListing>::new();
+    let cancel_listing_act = ServerAction::<CancelListing>::new();
+    let buy_listing_act = ServerAction::<BuyListing>::new();
+    let create_buy_order_act = ServerAction::<CreateBuyOrder>::new();
+    let cancel_buy_order_act = ServerAction::<CancelBuyOrder>::new();
+    let version = Signal::derive(move || {
+        create_listing_act.version().get()
+            + cancel_listing_act.version().get()
+            + buy_listing_act.version().get()
+            + create_buy_order_act.version().get()
+            + cancel_buy_order_act.version().get()
+    });
+
+    let game_resource = Resource::new_blocking(id, |id| async move {
+        match id {
+            Err(e) => Err(e),
+            Ok(id) => get_game(id)
+                .await
+                .map_err(|_| GameError::ServerError)?
+                .ok_or(GameError::GameNotFound),
+        }
+    });
+    let game_view = Suspend::new(async move {
+        game_resource.await.map(|game| {
+            view! {
+                <Title text=format!("{} Market", game.gname)/>
+                <h1>"Market"</h1>
+                <p><a href=format!("{}/{}", GAME, game.gid)>{game.gname}</a></p>
+            }
+        })
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading game..."</p> }>
+            <ErrorBoundary fallback=|_| view! { <p class="error">"Could not load the game."</p> }>
+                {game_view}
+            </ErrorBoundary>
+        </Suspense>
+        {move || {
+            create_listing_act
+                .value()
+                .get()
+                .and_then(Result::err)
+                .or_else(|| cancel_listing_act.value().get().and_then(Result::err))
+                .or_else(|| buy_listing_act.value().get().and_then(Result::err))
+                .or_else(|| create_buy_order_act.value().get().and_then(Result::err))
+                .or_else(|| cancel_buy_order_act.value().get().and_then(Result::err))
+                .map(|e| view! { <p class="error">{e.to_string()}</p> })
+        }}
+        <MarketOrders id version cancel_listing_act buy_listing_act cancel_buy_order_act/>
+        <SellItems id version create_listing_act/>
+        <PlaceBuyOrder id create_buy_order_act/>
+    }
+}
+
+/// The open listings and buy orders of a game, which their owners may cancel.
+#[component]
+fn MarketOrders(
+    id: Signal<Result<usize, GameError>>,
+    version: Signal<usize>,
+    cancel_listing_act: ServerAction<CancelListing>,
+    buy_listing_act: ServerAction<BuyListing>,
+    cancel_buy_order_act: ServerAction<CancelBuyOrder>,
+) -> impl IntoView {
+    let current_uid = crate::auth::use_current_uid();
+    let market_resource = Resource::new(
+        move || (id.get(), version.get()),
+        |(id, _)| async move {
+            match id {
+                Err(e) => Err(e),
+                Ok(id) => get_market(id).await.map_err(|_| GameError::ServerError),
+            }
+        },
+    );
+    let market_view = Suspend::new(async move {
+        let uid = current_uid.await;
+        market_resource.await.map(|market| {
+            view! {
+                <h3>"Listings"</h3>
+                <ul>{
+                    if market.listings.is_empty() {
+                        view! {<p>"<empty>"</p>}.into_any()
+                    } else {
+                        market
+                            .listings
+                            .into_iter()
+                            .map(|listing| view! {
+                                <ListingEntry listing uid cancel_listing_act buy_listing_act/>
+                            })
+                            .collect_view()
+                            .into_any()
+                    }
+                }</ul>
+                <h3>"Buy Orders"</h3>
+                <ul>{
+                    if market.buy_orders.is_empty() {
+                        view! {<p>"<empty>"</p>}.into_any()
+                    } else {
+                        market
+                            .buy_orders
+                            .into_iter()
+                            .map(|order| view! {
+                                <li>
+                                    <a href=format!("{}/{}", ITEM, order.pid)>
+                                        {order.descr.unwrap_or_else(|| order.pid.to_string())}
+                                    </a>
+                                    {format!(
+                                        " | up to {} each | {} wanted | by ",
+                                        order.max_price,
+                                        order.quantity - order.filled,
+                                    )}
+                                    <a href=format!("{}/{}", USER, order.buyer_uid)>{order.b_uname}</a>
+                                    {(Some(order.buyer_uid) == uid).then(|| view! {
+                                        <ActionForm action=cancel_buy_order_act>
+                                            <input type="hidden" name="boid" value=order.boid/>
+                                            <button type="submit">"Cancel"</button>
+                                        </ActionForm>
+                                    })}
+                                </li>
+                            })
+                            .collect_view()
+                            .into_any()
+                    }
+                }</ul>
+            }
+        })
+    });
+
+    view! {
+        <Transition fallback=move || view! { <p>"Loading market..."</p> }>
+            <ErrorBoundary fallback=|_| view! { <p class="error">"Could not load the market."</p> }>
+                {market_view}
+            </ErrorBoundary>
+        </Transition>
+    }
+}
+
+/// A listing with a form to buy from it, or to cancel it when it is the viewer's own.
+#[component]
+fn ListingEntry(
+    listing: MarketListing,
+    uid: Option<usize>,
+    cancel_listing_act: ServerAction<CancelListing>,
+    buy_listing_act: ServerAction<BuyListing>,
+) -> impl IntoView {
+    let left = listing.quantity - listing.sold;
+    view! {
+        <li>
+            <a href=format!("{}/{}", ITEM, listing.pid)>
+                {listing.descr.unwrap_or_else(|| listing.pid.to_string())}
+            </a>
+            {format!(" | {} each | {left} left | from ", listing.price)}
+            <a href=format!("{}/{}", USER, listing.seller_uid)>{listing.s_uname}</a>
+            {if Some(listing.seller_uid) == uid {
+                view! {
+                    <ActionForm action=cancel_listing_act>
+                        <input type="hidden" name="lid" value=listing.lid/>
+                        <button type="submit">"Cancel"</button>
+                    </ActionForm>
+                }
+                .into_any()
+            } else {
+                view! {
+                    <ActionForm action=buy_listing_act>
+                        <input type="hidden" name="lid" value=listing.lid/>
+                        <input type="number" name="quantity" min="1" max=left value="1"/>
+                        <button type="submit">"Buy"</button>
+                    </ActionForm>
+                }
+                .into_any()
+            }}
+        </li>
+    }
+}
+
+/// A form to list the viewer's tradeable items of a game for sale.
+#[component]
+fn SellItems(
+    id: Signal<Result<usize, GameError>>,
+    version: Signal<usize>,
+    create_listing_act: ServerAction<CreateListing>,
+) -> impl IntoView {
+    let current_uid = crate::auth::use_current_uid();
+    let inventory_resource = Resource::new(
+        move || (id.get(), version.get()),
+        move |(id, _)| async move {
+            let id = id?;
+            if current_uid.await.is_none() {
+                return Ok(Vec::new());
+            }
+            get_inventory(Some(id))
+                .await
+                .map(|items| {
+                    items
+                        .into_iter()
+                        .filter(|x| x.tradeable)
+                        .collect::<Vec<_>>()
+                })
+                .map_err(|_| GameError::ServerError)
+        },
+    );
+    let sell_view = Suspend::new(async move {
+        inventory_resource.await.map(|items| {
+            view! {
+                <h3>"Sell Items"</h3>
+                {if items.is_empty() {
+                    view! {<p>"You hold no tradeable items of this game."</p>}.into_any()
+                } else {
+                    view! {
+                        <ActionForm action=create_listing_act>
+                            <div>
+                                <label for="listing_pid">"Item:"</label>
+                                <select id="listing_pid" name="pid">
+                                    {items
+                                        .into_iter()
+                                        .map(|item| view! {
+                                            <option value=item.pid>
+                                                {format!(
+                                                    "{} ({} held)",
+                                                    item.descr.unwrap_or_else(|| item.pid.to_string()),
+                                                    item.quantity,
+                                                )}
+                                            </option>
+                                        })
+                                        .collect_view()}
+                                </select>
+                            </div>
+                            <div>
+                                <label for="listing_quantity">"Quantity:"</label>
+                                <input id="listing_quantity" type="number" name="quantity" min="1" value="1"/>
+                            </div>
+                            <div>
+                                <label for="listing_price">"Price Each (USD):"</label>
+                                <input id="listing_price" type="text" name="price" placeholder="0.99"/>
+                            </div>
+                            <button type="submit">"List for sale"</button>
+                        </ActionForm>
+                    }
+                    .into_any()
+                }}
+            }
+        })
+    });
+
+    view! {
+        <Transition fallback=move || view! { <p>"Loading inventory..."</p> }>
+            <ErrorBoundary fallback=|_| view! { <p class="error">"Could not load your inventory."</p> }>
+                {sell_view}
+            </ErrorBoundary>
+        </Transition>
+    }
+}
+
+/// A form to place a buy order for any item of a game.
+#[component]
+fn PlaceBuyOrder(
+    id: Signal<Result<usize, GameError>>,
+    create_buy_order_act: ServerAction<CreateBuyOrder>,
+) -> impl IntoView {
+    let items_resource = Resource::new(id, |id| async move {
+        match id {
+            Err(e) => Err(e),
+            Ok(id) => get_items(id).await.map_err(|_| GameError::ServerError),
+        }
+    });
+    let buy_order_view = Suspend::new(async move {
+        items_resource.await.map(|items| {
+            view! {
+                <h3>"Place Buy Order"</h3>
+                <ActionForm action=create_buy_order_act>
+                    <div>
+                        <label for="order_pid">"Item:"</label>
+                        <select id="order_pid" name="pid">
+                            {items
+                                .into_iter()
+                                .map(|item| view! {
+                                    <option value=item.pid>
+                                        {item.descr.unwrap_or_else(|| item.pid.to_string())}
+                                    </option>
+                                })
+                                .collect_view()}
+                        </select>
+                    </div>
+                    <div>
+                        <label for="order_quantity">"Quantity:"</label>
+                        <input id="order_quantity" type="number" name="quantity" min="1" value="1"/>
+                    </div>
+                    <div>
+                        <label for="order_max_price">"Max Price Each (USD):"</label>
+                        <input id="order_max_price" type="text" name="max_price" placeholder="0.99"/>
+                    </div>
+                    <button type="submit">"Place order"</button>
+                </ActionForm>
+                <p>"Funds for the unfilled part are held from your wallet until the order fills or is cancelled."</p>
+            }
+        })
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p>"Loading items..."</p> }>
+            <ErrorBoundary fallback=|_| view! { <p class="error">"Could not load the items."</p> }>
+                {buy_order_view}
+            </ErrorBoundary>
+        </Suspense>
+    }
+}
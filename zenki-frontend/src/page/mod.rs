@@ -1,16 +1,54 @@
+use std::fmt::Write as _;
+
 mod account;
+mod admin;
+mod cart;
 mod developer;
 mod game;
+mod gift;
 mod home;
 mod item;
+mod key;
 mod login;
 mod main;
+mod market;
 mod register;
+mod subscription;
 mod tag;
 mod transaction;
 mod user;
+mod wallet;
 
 pub use {
-    account::Account, developer::Developer, game::Game, home::Home, item::Item, login::Login,
-    main::Main, register::Register, tag::Tag, transaction::Transaction, user::User,
+    account::Account,
+    admin::Admin,
+    cart::Cart,
+    developer::Developer,
+    game::Game,
+    gift::Gifts,
+    home::Home,
+    item::Item,
+    key::{Keys, Redeem},
+    login::Login,
+    main::Main,
+    market::Market,
+    register::Register,
+    subscription::Subscriptions,
+    tag::Tag,
+    transaction::{CheckoutTotals, Invoice, ReceiptSummary, Transaction},
+    user::User,
+    wallet::Wallet,
 };
+
+/// Percent-encodes `data` for use in a `data:` URL.
+fn data_url(mime: &str, data: &[u8]) -> String {
+    let mut url = format!("data:{mime},");
+    for &b in data {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b',') {
+            url.push(char::from(b));
+        } else {
+            let _ = write!(url, "%{b:02X}");
+        }
+    }
+    url
+}
@@ -0,0 +1,127 @@
+use leptos::prelude::*;
+use leptos_meta::Title;
+
+use crate::{
+    route::{GAME, ITEM},
+    subscription::{
+        CancelSubscription, ResumeSubscription, Subscription, SubscriptionError,
+        SubscriptionStatus, get_subscriptions,
+    },
+};
+
+#[component]
+pub fn Subscriptions() -> impl IntoView {
+    let cancel_act = ServerAction::<CancelSubscription>::new();
+    let resume_act = ServerAction::<ResumeSubscription>::new();
+
+    let subscriptions_resource = Resource::new(
+        move || (cancel_act.version().get(), resume_act.version().get()),
+        |_| async move {
+            get_subscriptions()
+                .await
+                .map_err(|_| SubscriptionError::ServerError)
+        },
+    );
+    let subscriptions_view = Suspend::new(async move {
+        (subscriptions_resource.await).map(|subscriptions| {
+            if subscriptions.is_empty() {
+                return view! { <p>"<empty>"</p> }.into_any();
+            }
+            view! {
+                <table>
+                    <tr>
+                        <th>"Plan"</th>
+                        <th>"Game"</th>
+                        <th>"Price"</th>
+                        <th>"Status"</th>
+                        <th>"Started"</th>
+                        <th>"Expires"</th>
+                        <th></th>
+                    </tr>
+                    {subscriptions
+                        .into_iter()
+                        .map(|sub| view! { <SubscriptionRow sub cancel_act resume_act/> })
+                        .collect_view()
+                    }
+                </table>
+            }
+            .into_any()
+        })
+    });
+
+    view! {
+        <Title text="Subscriptions"/>
+        <h1>"Subscriptions"</h1>
+        <Transition fallback=move || view! { <p>"Loading subscriptions..."</p> }>
+            <ErrorBoundary fallback=|errors| {
+                view! {
+                    <div class="error">
+                        <h1>"Something went wrong."</h1>
+                        <ul>
+                            {move || {
+                                errors
+                                    .get()
+                                    .into_iter()
+                                    .map(|(_, error)| view! { <li>{error.to_string()}</li> })
+                                    .collect::<Vec<_>>()
+                            }}
+                        </ul>
+                    </div>
+                }
+            }>{subscriptions_view}</ErrorBoundary>
+        </Transition>
+        {move || {
+            cancel_act
+                .value()
+                .get()
+                .and_then(Result::err)
+                .or_else(|| resume_act.value().get().and_then(Result::err))
+                .map(|e| view! { <p class="error">{e.to_string()}</p> })
+        }}
+    }
+}
+
+/// A subscription with a button to cancel or resume its renewal.
+#[component]
+fn SubscriptionRow(
+    sub: Subscription,
+    cancel_act: ServerAction<CancelSubscription>,
+    resume_act: ServerAction<ResumeSubscription>,
+) -> impl IntoView {
+    view! {
+        <tr>
+            <td>
+                <a href=format!("{}/{}", ITEM, sub.pid)>
+                    {sub.p_descr.unwrap_or_else(|| format!("#{}", sub.pid))}
+                </a>
+            </td>
+            <td><a href=format!("{}/{}", GAME, sub.gid)>{sub.gname}</a></td>
+            <td>{format!("{} ({})", sub.price, sub.billing_period)}</td>
+            <td>{sub.status.to_string()}</td>
+            <td>{sub.started_at}</td>
+            <td>
+                {sub.expires_at}
+                {match (sub.status, sub.renews) {
+                    (SubscriptionStatus::Active, true) => " (renews)",
+                    (SubscriptionStatus::Expired, _) => "",
+                    _ => " (does not renew)",
+                }}
+            </td>
+            <td>{match sub.status {
+                SubscriptionStatus::Active => view! {
+                    <ActionForm action=cancel_act>
+                        <input type="hidden" name="sid" value=sub.sid/>
+                        <button type="submit">"Cancel"</button>
+                    </ActionForm>
+                }.into_any(),
+                SubscriptionStatus::Cancelled => view! {
+                    <ActionForm action=resume_act>
+                        <input type="hidden" name="sid" value=sub.sid/>
+                        <button type="submit">"Resume"</button>
+                    </ActionForm>
+                }.into_any(),
+                SubscriptionStatus::Expired => ().into_any(),
+            }}</td>
+        </tr>
+    }
+}
@@ -1,11 +1,16 @@
 #![allow(clippy::too_many_lines)]
-use leptos::prelude::*;
+use leptos::{prelude::*, task::spawn_local};
 use leptos_meta::{Meta, Title};
 use leptos_router::hooks::use_params;
+use zenki_util::Money;
 
 use crate::{
-    route::{ITEM, USER},
-    transaction::{TransactionError, TransactionParams, get_transaction},
+    page::data_url,
+    route::{ITEM, TRANSACTION, USER},
+    transaction::{
+        CancelPreorder, Receipt, RequestRefund, TransactionError, TransactionParams,
+        TransactionStatus, get_invoice, get_invoice_pdf, get_transaction,
+    },
 };
 
 #[component]
@@ -18,26 +23,79 @@ pub fn Transaction() -> impl IntoView {
                 .map_err(|_| TransactionError::InvalidId)
         })
     };
-    let transaction_resource = Resource::new_blocking(id, |id| async move {
-        match id {
-            Err(e) => Err(e),
-            Ok(tname) => get_transaction(tname)
-                .await
-                .map(|data| data.ok_or(TransactionError::TransactionNotFound))
-                .map_err(|_| TransactionError::ServerError),
-        }
-    });
+    let current_uid = crate::auth::use_current_uid();
+    let request_refund_act = ServerAction::<RequestRefund>::new();
+    let cancel_preorder_act = ServerAction::<CancelPreorder>::new();
+    let transaction_resource = Resource::new_blocking(
+        move || {
+            (
+                id(),
+                request_refund_act.version().get(),
+                cancel_preorder_act.version().get(),
+            )
+        },
+        |(id, _, _)| async move {
+            match id {
+                Err(e) => Err(e),
+                Ok(tname) => get_transaction(tname)
+                    .await
+                    .map(|data| data.ok_or(TransactionError::TransactionNotFound))
+                    .map_err(|_| TransactionError::ServerError),
+            }
+        },
+    );
     let transaction_view = Suspend::new(async move {
+        let uid = current_uid.await;
         match transaction_resource.await {
             Ok(Ok(tx)) => {
                 Ok(view! {
                     <h2>{tx.bought_at.clone().unwrap_or_else(|| String::from("<no bought timestamp provided>"))}</h2>
-                    <p><b>Amount: </b>{tx.amount}</p>
+                    <p><b>Quantity: </b>{tx.quantity}</p>
+                    <p><b>Amount: </b>{tx.amount.to_string()}</p>
+                    {(!tx.tax.is_zero()).then(|| view! {
+                        <p>
+                            <b>Tax: </b>{tx.tax.to_string()}
+                            {tx.tax_rate.map(|rate| format!(" ({rate})"))}
+                        </p>
+                    })}
                     <p><b>Sender: </b><a href={format!("{}/{}", USER, tx.uid)}>{tx.s_uname}</a></p>
                     <p><b>Receiver: </b><a href={format!("{}/{}", USER, tx.receiver_uid.unwrap_or_default())}>{tx.r_uname}</a></p>
+                    {tx.seller_uid.zip(tx.seller_uname).map(|(seller_uid, seller_uname)| view! {
+                        <p><b>Seller: </b><a href=format!("{}/{}", USER, seller_uid)>{seller_uname}</a></p>
+                        <p><b>Market Fee: </b>{tx.market_fee.to_string()}</p>
+                    })}
                     <p><b>Item: </b><a href={format!("{}/{}", ITEM, tx.pid)}>{tx.p_descr}</a></p>
                     <p><b>Payment Method: </b>{tx.payment_method}</p>
-                    <p><b>Status: </b>{tx.status.clone().unwrap_or_else(|| String::from("<no statys provided>"))}</p>
+                    <p><b>Status: </b>{tx.status.to_string()}</p>
+                    {tx.preorder.then(|| view! {
+                        <p>
+                            <b>"Pre-order"</b>
+                            {tx.available_at.clone().map(|at| format!(" - available on {at}"))}
+                        </p>
+                    })}
+                    {tx.seller_uid.is_none().then(|| view! {
+                        <p><a href=format!("{}/{}/invoice", TRANSACTION, tx.tid)>"Invoice"</a></p>
+                    })}
+                    {tx.refund_reason.map(|reason| view! { <p><b>Refund Reason: </b>{reason}</p> })}
+                    {(tx.status == TransactionStatus::Completed
+                        && uid == Some(tx.uid)
+                        && tx.seller_uid.is_none())
+                        .then(|| view! {
+                            <ActionForm action=request_refund_act>
+                                <input type="hidden" name="tid" value=tx.tid/>
+                                <input type="text" name="reason" placeholder="Reason (optional)"/>
+                                <button type="submit">"Request refund"</button>
+                            </ActionForm>
+                        })}
+                    {(tx.status == TransactionStatus::Completed
+                        && uid == Some(tx.uid)
+                        && tx.available_at.is_some())
+                        .then(|| view! {
+                            <ActionForm action=cancel_preorder_act>
+                                <input type="hidden" name="tid" value=tx.tid/>
+                                <button type="submit">"Cancel pre-order"</button>
+                            </ActionForm>
+                        })}
 
                     /*
                     pub uid: usize,
@@ -58,6 +116,20 @@ pub fn Transaction() -> impl IntoView {
 
     view! {
         <h1>"Transaction Info"</h1>
+        {move || {
+            request_refund_act
+                .value()
+                .get()
+                .and_then(Result::err)
+                .map(|e| view! { <p class="error">{e.to_string()}</p> })
+        }}
+        {move || {
+            cancel_preorder_act
+                .value()
+                .get()
+                .and_then(Result::err)
+                .map(|e| view! { <p class="error">{e.to_string()}</p> })
+        }}
         <Suspense fallback=move || view! { <p>"Loading transaction..."</p> }>
             <ErrorBoundary fallback=|errors| {
                 view! {
@@ -78,3 +150,213 @@ pub fn Transaction() -> impl IntoView {
         </Suspense>
     }
 }
+
+#[component]
+pub fn Invoice() -> impl IntoView {
+    let query = use_params::<TransactionParams>();
+    let id = move || {
+        query.with(|q| {
+            q.as_ref()
+                .map(|q| q.id.unwrap_or_default())
+                .map_err(|_| TransactionError::InvalidId)
+        })
+    };
+    let pdf = RwSignal::new(None::<(String, Vec<u8>)>);
+    let invoice_resource = Resource::new_blocking(id, |id| async move {
+        match id {
+            Err(e) => Err(e),
+            Ok(tid) => get_invoice(tid)
+                .await
+                .map_err(|_| TransactionError::ServerError)
+                .and_then(|data| data.ok_or(TransactionError::InvoiceNotFound)),
+        }
+    });
+    let invoice_view = Suspend::new(async move {
+        invoice_resource.await.map(|invoice| {
+            let tid = invoice.tid;
+            let number = invoice.number.clone();
+            let on_download = move |_| {
+                let number = number.clone();
+                spawn_local(async move {
+                    if let Ok(bytes) = get_invoice_pdf(tid).await {
+                        pdf.set(Some((number, bytes)));
+                    }
+                });
+            };
+            view! {
+                <Title text=invoice.number.clone()/>
+                <h2>{invoice.number}</h2>
+                <p><b>Issued: </b>{invoice.issued_at}</p>
+                <p>
+                    <b>Transaction: </b>
+                    <a href=format!("{}/{}", TRANSACTION, tid)>{format!("#{tid}")}</a>
+                    {invoice.oid.map(|oid| format!(" (order #{oid})"))}
+                </p>
+                <p><b>Buyer: </b><a href=format!("{}/{}", USER, invoice.uid)>{invoice.s_uname}</a></p>
+                {invoice.receiver_uid.zip(invoice.r_uname).map(|(ruid, r_uname)| view! {
+                    <p><b>Receiver: </b><a href=format!("{}/{}", USER, ruid)>{r_uname}</a></p>
+                })}
+                <p><b>Payment Method: </b>{invoice.payment_method}</p>
+                <p><b>Status: </b>{invoice.status.to_string()}</p>
+                <table>
+                    <tr>
+                        <th>"Item"</th>
+                        <th>"Type"</th>
+                        <th>"Unit Price"</th>
+                        <th>"Quantity"</th>
+                        <th>"Line Total"</th>
+                    </tr>
+                    {invoice
+                        .lines
+                        .into_iter()
+                        .map(|line| view! {
+                            <tr>
+                                <td>
+                                    <a href=format!("{}/{}", ITEM, line.pid)>
+                                        {line.descr.unwrap_or_else(|| format!("#{}", line.pid))}
+                                    </a>
+                                </td>
+                                <td>{line.kind}</td>
+                                <td>{line.unit_price.to_string()}</td>
+                                <td>{line.quantity}</td>
+                                <td>{line.line_total.to_string()}</td>
+                            </tr>
+                        })
+                        .collect_view()
+                    }
+                </table>
+                <p><b>Subtotal: </b>{invoice.subtotal.to_string()}</p>
+                {(!invoice.discount.is_zero()).then(|| view! {
+                    <p>
+                        <b>Discount: </b>{format!("-{}", invoice.discount)}
+                        {invoice.coupon_code.map(|code| format!(" ({code})"))}
+                    </p>
+                })}
+                <p>
+                    <b>Tax: </b>{invoice.tax.to_string()}
+                    {invoice.tax_rate.map(|rate| format!(" ({rate})"))}
+                </p>
+                <p><b>Total: </b>{invoice.total.to_string()}</p>
+                <p class="no-print">
+                    <button on:click=move |_| {
+                        let _ = window().print();
+                    }>"Print"</button>
+                    <button on:click=on_download>"Download PDF"</button>
+                </p>
+            }
+        })
+    });
+
+    let pdf_view = move || {
+        pdf.get().map(|(number, bytes)| {
+            view! {
+                <p class="no-print">
+                    <a download=format!("{number}.pdf") href=data_url("application/pdf", &bytes)>
+                        {format!("Download {number}.pdf")}
+                    </a>
+                </p>
+            }
+        })
+    };
+
+    view! {
+        <h1>"Invoice"</h1>
+        <Suspense fallback=move || view! { <p>"Loading invoice..."</p> }>
+            <ErrorBoundary fallback=|errors| {
+                view! {
+                    <div class="error">
+                        <h1>"Something went wrong."</h1>
+                        <ul>
+                            {move || {
+                                errors
+                                    .get()
+                                    .into_iter()
+                                    .map(|(_, error)| view! { <li>{error.to_string()}</li> })
+                                    .collect::<Vec<_>>()
+                            }}
+                        </ul>
+                    </div>
+                }
+            }>{invoice_view}</ErrorBoundary>
+        </Suspense>
+        {pdf_view}
+    }
+}
+
+#[component]
+pub fn ReceiptSummary(receipt: Receipt) -> impl IntoView {
+    view! {
+        <h3>"Receipt"</h3>
+        <table>
+            <tr>
+                <th>"Item"</th>
+                <th>"Type"</th>
+                <th>"Unit Price"</th>
+                <th>"Quantity"</th>
+                <th>"Line Total"</th>
+                <th>"Discount"</th>
+                <th>"Tax"</th>
+            </tr>
+            {receipt
+                .lines
+                .into_iter()
+                .map(|line| view! {
+                    <tr>
+                        <td><a href={format!("{}/{}", TRANSACTION, line.tid)}>{line.descr}</a></td>
+                        <td>{line.kind}</td>
+                        <td>{line.unit_price.to_string()}</td>
+                        <td>{line.quantity}</td>
+                        <td>{line.line_total.to_string()}</td>
+                        <td>{(!line.discount.is_zero()).then(|| format!("-{}", line.discount))}</td>
+                        <td>
+                            {(!line.tax.is_zero()).then(|| line.tax.to_string())}
+                            {line.tax_rate.map(|rate| format!(" ({rate})"))}
+                        </td>
+                    </tr>
+                })
+                .collect_view()
+            }
+        </table>
+        <CheckoutTotals
+            subtotal=receipt.subtotal
+            discount=receipt.discount
+            tax=receipt.tax
+            tax_added=receipt.tax_added
+            total=receipt.total
+            coupon_code=receipt.coupon_code
+        />
+        <p><b>Payment Method: </b>{receipt.payment_method}</p>
+        {(receipt.status == TransactionStatus::Pending).then(|| view! {
+            <p>"Your payment is still being processed. Your items will arrive once it goes through."</p>
+        })}
+    }
+}
+
+#[component]
+pub fn CheckoutTotals(
+    subtotal: Money,
+    discount: Money,
+    tax: Money,
+    /// The part of `tax` added on top of prices, the rest being included in them.
+    tax_added: Money,
+    total: Money,
+    coupon_code: Option<String>,
+) -> impl IntoView {
+    let tax_included = Money::new(tax.minor() - tax_added.minor(), tax.currency());
+    view! {
+        {(!discount.is_zero()).then(|| view! {
+            <p><b>Subtotal: </b>{subtotal.to_string()}</p>
+            <p>
+                <b>Coupon: </b>{format!("-{discount}")}
+                {coupon_code.map(|code| format!(" ({code})"))}
+            </p>
+        })}
+        {(!tax_added.is_zero()).then(|| view! {
+            <p><b>Tax: </b>{format!("+{tax_added}")}</p>
+        })}
+        <p><b>Total: </b>{total.to_string()}</p>
+        {(!tax_included.is_zero()).then(|| view! {
+            <p>{format!("Includes {tax_included} tax")}</p>
+        })}
+    }
+}
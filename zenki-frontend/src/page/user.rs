@@ -5,14 +5,20 @@ use leptos_router::hooks::use_params;
 
 use crate::{
     activity::get_game_activity,
+    family::{
+        AddFamilyMember, LeaveFamily, RemoveFamilyMember, SetGameShared, get_borrowed_games,
+        get_family,
+    },
     friendship::{
         AcceptFriendRequest, CancelFriendRequest, DeclineFriendRequest, FriendshipStatus,
         RemoveFriend, SendFriendRequest, get_friendship_status, query_friends,
     },
     game::{get_library, get_wishlist},
+    inventory::{ConsumeItem, get_inventory},
+    role::get_user_roles,
     route::{ACCOUNT, GAME, ITEM, TRANSACTION, USER},
     transaction::get_transaction_history,
-    user::{UserError, UserParams, get_user},
+    user::{UserError, UserParams, get_private_account, get_user},
 };
 
 #[component]
@@ -39,10 +45,9 @@ pub fn User() -> impl IntoView {
             Ok(Ok(user)) => {
                 Ok(view! {
                     <h2>{user.uname.clone()}</h2>
+                    {user.avatar.map(|src| view! { <img src=src alt="avatar" width="128" height="128"/> })}
                     <p><b>Bio: </b>{user.bio.clone().unwrap_or_else(|| String::from("<no bio provided>"))}</p>
                     <p><b>Created At: </b>{user.created_at.unwrap_or_else(|| String::from("<no creation time provided>"))}</p>
-                    <p><b>Email: </b>{user.email.unwrap_or_else(|| String::from("<no email provided>"))}</p>
-                    <p><b>Birth Date: </b>{user.birth_date.unwrap_or_else(|| String::from("<no birth date provided>"))}</p>
 
                     // since we're using async rendering for this page,
                     // this metadata should be included in the actual HTML <head>
@@ -55,7 +60,45 @@ pub fn User() -> impl IntoView {
         }
     });
 
-    let curr_id = move || crate::auth::get_login_session().0.get();
+    let roles_resource = Resource::new(id, |id| async move {
+        match id {
+            Err(e) => Err(e),
+            Ok(id) => get_user_roles(id).await.map_err(|_| UserError::ServerError),
+        }
+    });
+    let roles_view = Suspend::new(async move {
+        (roles_resource.await).map_or(Err(UserError::ServerError), |roles| {
+            Ok(view! {
+                <p><b>Roles: </b>{
+                    roles.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+                }</p>
+            })
+        })
+    });
+
+    let current_uid = crate::auth::use_current_uid();
+    let curr_id = move || current_uid.get().flatten();
+    let private_account_resource = Resource::new(
+        move || (curr_id(), id()),
+        |(uid, id)| async move {
+            match (uid, id) {
+                (_, Err(e)) => Err(e),
+                (Some(uid), Ok(id)) if uid == id => get_private_account()
+                    .await
+                    .map_err(|_| UserError::ServerError),
+                _ => Ok(None),
+            }
+        },
+    );
+    let private_account_view = Suspend::new(async move {
+        (private_account_resource.await).map_or(Err(UserError::ServerError), |account| {
+            Ok(account.map(|account| view! {
+                <p><b>Email: </b>{account.email.unwrap_or_else(|| String::from("<no email provided>"))}</p>
+                <p><b>Birth Date: </b>{account.birth_date.unwrap_or_else(|| String::from("<no birth date provided>"))}</p>
+                <p><b>Billing Country: </b>{account.billing_country.unwrap_or_else(|| String::from("<no billing country provided>"))}</p>
+            }))
+        })
+    });
     let friendship_resource = Resource::new_blocking(
         move || (curr_id(), id()),
         |(uid, fid)| async move {
@@ -63,13 +106,12 @@ pub fn User() -> impl IntoView {
                 (_, Err(e)) => Err(e),
                 (None, _) => Err(UserError::ServerError),
                 (Some(uid), Ok(fid)) => Ok((
-                    uid,
                     fid,
                     if uid == fid {
                         None
                     } else {
                         Some(
-                            get_friendship_status(uid, fid)
+                            get_friendship_status(fid)
                                 .await
                                 .map_err(|_| UserError::ServerError)?,
                         )
@@ -87,12 +129,11 @@ pub fn User() -> impl IntoView {
 
     let friendship_view = Suspend::new(async move {
         match friendship_resource.await {
-            Ok((uid, fid, status)) => Ok(view! {
+            Ok((fid, status)) => Ok(view! {
                 <div>
                     {match status {
                         Some(FriendshipStatus::NotFriends) => view! {
                             <ActionForm action=send_friend_request_act>
-                                <input type="hidden" name="uid" value=uid/>
                                 <input type="hidden" name="fid" value=fid/>
                                 <button class="btn btn-blue">
                                     "Send Friend Request"
@@ -101,7 +142,6 @@ pub fn User() -> impl IntoView {
                         }.into_any(),
                         Some(FriendshipStatus::RequestSent) => view! {
                             <ActionForm action=cancel_friend_request_act>
-                                <input type="hidden" name="uid" value=uid/>
                                 <input type="hidden" name="fid" value=fid/>
                                 <button class="btn btn-red">
                                     "Cancel Friend Request"
@@ -110,14 +150,12 @@ pub fn User() -> impl IntoView {
                         }.into_any(),
                         Some(FriendshipStatus::RequestReceived) => view! {
                             <ActionForm action=accept_friend_request_act>
-                                <input type="hidden" name="uid" value=uid/>
                                 <input type="hidden" name="fid" value=fid/>
                                 <button class="btn btn-green">
                                     "Accept Friend Request"
                                 </button>
                             </ActionForm>
                             <ActionForm action=decline_friend_request_act>
-                                <input type="hidden" name="uid" value=uid/>
                                 <input type="hidden" name="fid" value=fid/>
                                 <button class="btn btn-red">
                                     "Decline Friend Request"
@@ -126,7 +164,6 @@ pub fn User() -> impl IntoView {
                         }.into_any(),
                         Some(FriendshipStatus::Friends) => view! {
                             <ActionForm action=remove_friend_act>
-                                <input type="hidden" name="uid" value=uid/>
                                 <input type="hidden" name="fid" value=fid/>
                                 <button class="btn btn-red">
                                     "Remove Friend"
@@ -195,14 +232,21 @@ pub fn User() -> impl IntoView {
         })
     });
 
-    let library_resource = Resource::new(id, |id| async move {
-        match id {
-            Err(e) => Err(e),
-            Ok(id) => get_library(id).await.map_err(|_| UserError::ServerError),
-        }
-    });
+    let set_game_shared_act = ServerAction::<SetGameShared>::new();
+    let library_resource = Resource::new(
+        move || (curr_id(), id(), set_game_shared_act.version().get()),
+        |(uid, id, _)| async move {
+            match id {
+                Err(e) => Err(e),
+                Ok(id) => get_library(id)
+                    .await
+                    .map(|games| (uid == Some(id), games))
+                    .map_err(|_| UserError::ServerError),
+            }
+        },
+    );
     let library_view = Suspend::new(async move {
-        (library_resource.await).map_or(Err(UserError::ServerError), |games| {
+        (library_resource.await).map_or(Err(UserError::ServerError), |(is_self, games)| {
             Ok(view! {
                 <h3>"Library"</h3>
                 <ul>{
@@ -211,7 +255,21 @@ pub fn User() -> impl IntoView {
                     } else {
                         games
                             .into_iter()
-                            .map(|user| view! { <li><a href=format!("{}/{}", GAME, user.gid)>{user.gname}</a></li> })
+                            .map(|entry| view! {
+                                <li>
+                                    <a href=format!("{}/{}", GAME, entry.game.gid)>{entry.game.gname}</a>
+                                    {entry.available_at.map(|at| format!(" - available on {at}"))}
+                                    {is_self.then(|| view! {
+                                        <ActionForm action=set_game_shared_act>
+                                            <input type="hidden" name="gid" value=entry.game.gid/>
+                                            <input type="hidden" name="shared" value=(!entry.shared).to_string()/>
+                                            <button type="submit">{
+                                                if entry.shared { "Stop sharing with family" } else { "Share with family" }
+                                            }</button>
+                                        </ActionForm>
+                                    })}
+                                </li>
+                            })
                             .collect_view().into_any()
                     }
                 }</ul>
@@ -219,17 +277,198 @@ pub fn User() -> impl IntoView {
         })
     });
 
-    let transactions_resource = Resource::new(id, |id| async move {
-        match id {
-            Err(e) => Err(e),
-            Ok(id) => get_transaction_history(id)
-                .await
-                .map_err(|_| UserError::ServerError),
-        }
+    let borrowed_resource = Resource::new(
+        move || (curr_id(), id()),
+        |(uid, id)| async move {
+            match (uid, id) {
+                (_, Err(e)) => Err(e),
+                (Some(uid), Ok(id)) if uid == id => get_borrowed_games()
+                    .await
+                    .map(Some)
+                    .map_err(|_| UserError::ServerError),
+                _ => Ok(None),
+            }
+        },
+    );
+    let borrowed_view = Suspend::new(async move {
+        (borrowed_resource.await).map_or(Err(UserError::ServerError), |games| {
+            Ok(games.filter(|games| !games.is_empty()).map(|games| view! {
+                <h3>"Borrowed Games"</h3>
+                <ul>{
+                    games
+                        .into_iter()
+                        .map(|entry| view! {
+                            <li>
+                                <a href=format!("{}/{}", GAME, entry.game.gid)>{entry.game.gname}</a>
+                                {" | from "}
+                                <a href=format!("{}/{}", USER, entry.owner_uid)>{entry.owner_uname}</a>
+                                {entry.in_use.then_some(" | in use")}
+                            </li>
+                        })
+                        .collect_view()
+                }</ul>
+            }))
+        })
     });
+
+    let add_family_member_act = ServerAction::<AddFamilyMember>::new();
+    let remove_family_member_act = ServerAction::<RemoveFamilyMember>::new();
+    let leave_family_act = ServerAction::<LeaveFamily>::new();
+    let family_resource = Resource::new(
+        move || {
+            (
+                curr_id(),
+                id(),
+                add_family_member_act.version().get(),
+                remove_family_member_act.version().get(),
+                leave_family_act.version().get(),
+            )
+        },
+        |(uid, id, ..)| async move {
+            match (uid, id) {
+                (_, Err(e)) => Err(e),
+                (Some(uid), Ok(id)) => get_family(id)
+                    .await
+                    .map(|family| Some((uid, id, family)))
+                    .map_err(|_| UserError::ServerError),
+                (None, Ok(_)) => Ok(None),
+            }
+        },
+    );
+    let family_view = Suspend::new(async move {
+        (family_resource.await).map_or(Err(UserError::ServerError), |family| {
+            Ok(family.map(|(viewer, id, family)| {
+                let is_self = viewer == id;
+                let is_member_of_viewer = family
+                    .owner
+                    .as_ref()
+                    .is_some_and(|owner| owner.uid == viewer);
+                view! {
+                    <h3>"Family"</h3>
+                    {family.owner.map(|owner| view! {
+                        <p>
+                            "Borrows games from "
+                            <a href=format!("{}/{}", USER, owner.uid)>{owner.uname}</a>
+                            {format!(" since {}", owner.added_at)}
+                        </p>
+                        {is_self.then(|| view! {
+                            <ActionForm action=leave_family_act>
+                                <button type="submit">"Leave family"</button>
+                            </ActionForm>
+                        })}
+                    })}
+                    <ul>{
+                        family
+                            .members
+                            .into_iter()
+                            .map(|member| view! {
+                                <li>
+                                    <a href=format!("{}/{}", USER, member.uid)>{member.uname}</a>
+                                    {format!(" | since {}", member.added_at)}
+                                    {is_self.then(|| view! {
+                                        <ActionForm action=remove_family_member_act>
+                                            <input type="hidden" name="uid" value=member.uid/>
+                                            <button type="submit">"Remove"</button>
+                                        </ActionForm>
+                                    })}
+                                </li>
+                            })
+                            .collect_view()
+                    }</ul>
+                    {match (is_self, is_member_of_viewer) {
+                        (true, _) => None,
+                        (false, true) => Some(view! {
+                            <ActionForm action=remove_family_member_act>
+                                <input type="hidden" name="uid" value=id/>
+                                <button type="submit">"Remove from family"</button>
+                            </ActionForm>
+                        }.into_any()),
+                        (false, false) => Some(view! {
+                            <ActionForm action=add_family_member_act>
+                                <input type="hidden" name="uid" value=id/>
+                                <button type="submit">"Add to family"</button>
+                            </ActionForm>
+                        }.into_any()),
+                    }}
+                    {move || {
+                        add_family_member_act
+                            .value()
+                            .get()
+                            .and_then(Result::err)
+                            .map(|e| view! { <p class="error">{e.to_string()}</p> })
+                    }}
+                }
+            }))
+        })
+    });
+
+    let consume_act = ServerAction::<ConsumeItem>::new();
+    let inventory_resource = Resource::new(
+        move || (curr_id(), id(), consume_act.version().get()),
+        |(uid, id, _)| async move {
+            match (uid, id) {
+                (_, Err(e)) => Err(e),
+                (Some(uid), Ok(id)) if uid == id => get_inventory(None)
+                    .await
+                    .map(Some)
+                    .map_err(|_| UserError::ServerError),
+                _ => Ok(None),
+            }
+        },
+    );
+    let inventory_view = Suspend::new(async move {
+        (inventory_resource.await).map_or(Err(UserError::ServerError), |items| {
+            Ok(items.map(|items| view! {
+                <h3>"Inventory"</h3>
+                <ul>{
+                    if items.is_empty() {
+                        view! {<p>"<empty>"</p>}.into_any()
+                    } else {
+                        items
+                            .into_iter()
+                            .map(|item| view! {
+                                <li>
+                                    <a href=format!("{}/{}", GAME, item.gid)><b>{item.gname}</b></a>
+                                    {" | "}
+                                    <a href=format!("{}/{}", ITEM, item.pid)>{item.descr}</a>
+                                    {format!(" x{}", item.quantity)}
+                                    <ActionForm action=consume_act>
+                                        <input type="hidden" name="pid" value=item.pid/>
+                                        <input type="number" name="quantity" min="1" max=item.quantity value="1"/>
+                                        <button type="submit">"Use"</button>
+                                    </ActionForm>
+                                </li>
+                            })
+                            .collect_view().into_any()
+                    }
+                }</ul>
+                {move || {
+                    consume_act
+                        .value()
+                        .get()
+                        .and_then(Result::err)
+                        .map(|e| view! { <p class="error">{e.to_string()}</p> })
+                }}
+            }))
+        })
+    });
+
+    let transactions_resource = Resource::new(
+        move || (curr_id(), id()),
+        |(uid, id)| async move {
+            match (uid, id) {
+                (_, Err(e)) => Err(e),
+                (Some(uid), Ok(id)) if uid == id => get_transaction_history()
+                    .await
+                    .map(|txs| Some((uid, txs)))
+                    .map_err(|_| UserError::ServerError),
+                _ => Ok(None),
+            }
+        },
+    );
     let transactions_view = Suspend::new(async move {
         (transactions_resource.await).map_or(Err(UserError::ServerError), |txs| {
-            Ok(view! {
+            Ok(txs.map(|(viewer, txs)| view! {
                 <h3>"Transaction History"</h3>
                 <ul>{
                     if txs.is_empty() {
@@ -246,12 +485,29 @@ pub fn User() -> impl IntoView {
                                     <a href=format!("{}/{}", GAME, tx.gid)><b>{tx.gname}</b></a>
                                     {" | "}
                                     <a href=format!("{}/{}", ITEM, tx.pid)>{tx.p_descr}</a>
+                                    {" | "}
+                                    {tx.status.to_string()}
+                                    {tx.gift_status.map(|gift_status| {
+                                        let gift = if tx.receiver_uid == Some(viewer) {
+                                            view! { " | Gift from " <a href=format!("{}/{}", USER, tx.uid)>{tx.s_uname.clone()}</a> }.into_any()
+                                        } else {
+                                            view! { " | Gift to " <a href=format!("{}/{}", USER, tx.receiver_uid.unwrap_or_default())>{tx.r_uname.clone()}</a> }.into_any()
+                                        };
+                                        view! { {gift} {format!(" ({gift_status})")} }
+                                    })}
+                                    {tx.seller_uid.map(|seller_uid| {
+                                        if seller_uid == viewer {
+                                            view! { " | Sold to " <a href=format!("{}/{}", USER, tx.uid)>{tx.s_uname.clone()}</a> }.into_any()
+                                        } else {
+                                            view! { " | Bought from " <a href=format!("{}/{}", USER, seller_uid)>{tx.seller_uname.clone()}</a> }.into_any()
+                                        }
+                                    })}
                                 </li>
                             })
                             .collect_view().into_any()
                     }
                 }</ul>
-            })
+            }))
         })
     });
 
@@ -315,9 +571,14 @@ pub fn User() -> impl IntoView {
                 }
             }>{user_view}</ErrorBoundary>
         </Suspense>
+        <Suspense fallback=move || view! { <p>"Loading roles..."</p> }>{roles_view}</Suspense>
+        <Suspense fallback=move || view! { <p>"Loading account..."</p> }>{private_account_view}</Suspense>
         <Transition fallback=move || view! { <p>"Loading friendship..."</p> }>{friendship_view}</Transition>
         <Suspense fallback=move || view! { <p>"Loading friends..."</p> }>{friends_view}</Suspense>
-        <Suspense fallback=move || view! { <p>"Loading library..."</p> }>{library_view}</Suspense>
+        <Transition fallback=move || view! { <p>"Loading family..."</p> }>{family_view}</Transition>
+        <Transition fallback=move || view! { <p>"Loading library..."</p> }>{library_view}</Transition>
+        <Suspense fallback=move || view! { <p>"Loading borrowed games..."</p> }>{borrowed_view}</Suspense>
+        <Transition fallback=move || view! { <p>"Loading inventory..."</p> }>{inventory_view}</Transition>
         <Suspense fallback=move || view! { <p>"Loading wishlist..."</p> }>{wishlist_view}</Suspense>
         <Suspense fallback=move || view! { <p>"Loading activity..."</p> }>{activity_view}</Suspense>
         <Suspense fallback=move || view! { <p>"Loading transaction history..."</p> }>{transactions_view}</Suspense>
@@ -0,0 +1,123 @@
+use leptos::prelude::*;
+use leptos_meta::Title;
+
+use crate::{
+    route::TRANSACTION,
+    wallet::{TopUpWallet, WalletError, get_wallet},
+};
+
+#[component]
+pub fn Wallet() -> impl IntoView {
+    let top_up_act = ServerAction::<TopUpWallet>::new();
+
+    let wallet_resource = Resource::new(
+        move || top_up_act.version().get(),
+        |_| async move { get_wallet().await.map_err(|_| WalletError::ServerError) },
+    );
+    let wallet_view = Suspend::new(async move {
+        (wallet_resource.await).map(|wallet| {
+            let balances = if wallet.balances.is_empty() {
+                view! { <p><b>"Balance: "</b>"0.00 USD"</p> }.into_any()
+            } else {
+                wallet
+                    .balances
+                    .into_iter()
+                    .map(|balance| view! { <p><b>"Balance: "</b>{balance.to_string()}</p> })
+                    .collect_view()
+                    .into_any()
+            };
+            let entries = if wallet.entries.is_empty() {
+                view! { <p>"<empty>"</p> }.into_any()
+            } else {
+                view! {
+                    <table>
+                        <tr>
+                            <th>"Date"</th>
+                            <th>"Type"</th>
+                            <th>"Amount"</th>
+                            <th>"Transaction"</th>
+                        </tr>
+                        {wallet
+                            .entries
+                            .into_iter()
+                            .map(|entry| view! {
+                                <tr>
+                                    <td>{entry.created_at}</td>
+                                    <td>{entry.kind}</td>
+                                    <td>{entry.amount.to_string()}</td>
+                                    <td>{entry.tid.map(|tid| view! {
+                                        <a href=format!("{}/{}", TRANSACTION, tid)>{format!("#{tid}")}</a>
+                                    })}</td>
+                                </tr>
+                            })
+                            .collect_view()
+                        }
+                    </table>
+                }
+                .into_any()
+            };
+            view! {
+                {balances}
+                <h2>"History"</h2>
+                {entries}
+            }
+        })
+    });
+
+    view! {
+        <Title text="Wallet"/>
+        <h1>"Wallet"</h1>
+        <Transition fallback=move || view! { <p>"Loading wallet..."</p> }>
+            <ErrorBoundary fallback=|errors| {
+                view! {
+                    <div class="error">
+                        <h1>"Something went wrong."</h1>
+                        <ul>
+                            {move || {
+                                errors
+                                    .get()
+                                    .into_iter()
+                                    .map(|(_, error)| view! { <li>{error.to_string()}</li> })
+                                    .collect::<Vec<_>>()
+                            }}
+                        </ul>
+                    </div>
+                }
+            }>{wallet_view}</ErrorBoundary>
+        </Transition>
+        <TopUpForm top_up_act/>
+    }
+}
+
+/// A form to add funds to the wallet, charged through the chosen payment method.
+#[component]
+fn TopUpForm(top_up_act: ServerAction<TopUpWallet>) -> impl IntoView {
+    view! {
+        <h2>"Top Up"</h2>
+        <ActionForm action=top_up_act>
+            <div>
+                <label for="amount">"Amount (USD):"</label>
+                <input id="amount" type="number" name="amount" min="0.01" step="0.01" required/>
+            </div>
+            <div>
+                <label for="payment_method">"Payment Method:"</label>
+                <select id="payment_method" name="payment_method">
+                    <option value="credit_card">Credit Card</option>
+                    <option value="debit_card">Debit Card</option>
+                    <option value="paypal">PayPal</option>
+                    <option value="etc">etc.</option>
+                </select>
+            </div>
+            <div>
+                <button type="submit">"Add Funds"</button>
+            </div>
+        </ActionForm>
+        {move || {
+            top_up_act
+                .value()
+                .get()
+                .and_then(Result::err)
+                .map(|e| view! { <p class="error">{e.to_string()}</p> })
+        }}
+    }
+}
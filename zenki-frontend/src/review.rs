@@ -1,16 +1,5 @@
 use leptos::prelude::*;
 use serde::{Deserialize, Serialize};
-use thiserror::Error;
-
-#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum ReviewError {
-    #[error("Invalid review ID.")]
-    InvalidId,
-    #[error("Review not found.")]
-    ReviewNotFound,
-    #[error("Server error.")]
-    ServerError,
-}
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Review {
@@ -48,11 +37,17 @@ pub async fn get_reviews(id: usize) -> Result<Vec<Review>, ServerFnError> {
 
 #[server]
 pub async fn post_review(
-    uid: usize,
     gid: usize,
     rated: f64,
     reviewed_text: String,
 ) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.post_review(uid, gid, rated, reviewed_text).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    let auth = auth.ensure(matches!(
+        state.get_wishlist_status(auth.uid, gid).await?,
+        zenki_backend::WishlistStatus::Owned
+    ))?;
+    Ok(state
+        .post_review(auth.uid, gid, rated, reviewed_text)
+        .await?)
 }
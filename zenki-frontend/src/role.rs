@@ -0,0 +1,135 @@
+use std::fmt::Display;
+
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Developer,
+    Player,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Admin => "Admin",
+            Self::Developer => "Developer",
+            Self::Player => "Player",
+        })
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Role> for Role {
+    fn from(value: zenki_backend::Role) -> Self {
+        match value {
+            zenki_backend::Role::Admin => Self::Admin,
+            zenki_backend::Role::Developer => Self::Developer,
+            zenki_backend::Role::Player => Self::Player,
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<Role> for zenki_backend::Role {
+    fn from(value: Role) -> Self {
+        match value {
+            Role::Admin => Self::Admin,
+            Role::Developer => Self::Developer,
+            Role::Player => Self::Player,
+        }
+    }
+}
+
+/// Roles of the logged in user, or `None` when nobody is logged in.
+#[server]
+pub async fn get_current_roles() -> Result<Option<Vec<Role>>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let Some(uid) = crate::auth::get_session_uid().await? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        state
+            .query_roles(uid)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    ))
+}
+
+#[server]
+pub async fn get_user_roles(uid: usize) -> Result<Vec<Role>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    Ok(state
+        .query_roles(uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn grant_role(uid: usize, role: Role) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageRoles)
+        .await?;
+    Ok(state.grant_role(uid, role.into()).await?)
+}
+
+#[server]
+pub async fn revoke_role(uid: usize, role: Role) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageRoles)
+        .await?;
+    Ok(state.revoke_role(uid, role.into()).await?)
+}
+
+/// Route conditions for `ProtectedRoute`, resolved from the roles of the logged in user.
+#[derive(Clone, Copy)]
+pub struct RouteGuard {
+    roles: Resource<Option<Vec<Role>>>,
+}
+
+impl RouteGuard {
+    pub fn new() -> Self {
+        Self {
+            roles: Resource::new(
+                || (),
+                |()| async move { get_current_roles().await.ok().flatten() },
+            ),
+        }
+    }
+
+    pub fn logged_in(self) -> impl Fn() -> Option<bool> + Clone + Send + Sync + 'static {
+        move || self.roles.get().map(|roles| roles.is_some())
+    }
+
+    pub fn logged_out(self) -> impl Fn() -> Option<bool> + Clone + Send + Sync + 'static {
+        move || self.roles.get().map(|roles| roles.is_none())
+    }
+
+    pub fn has_any_role(
+        self,
+        any: &'static [Role],
+    ) -> impl Fn() -> Option<bool> + Clone + Send + Sync + 'static {
+        move || {
+            self.roles
+                .get()
+                .map(|roles| roles.is_some_and(|roles| any.iter().any(|x| roles.contains(x))))
+        }
+    }
+
+    pub fn has_role(self, role: Role) -> impl Fn() -> Option<bool> + Clone + Send + Sync + 'static {
+        move || {
+            self.roles
+                .get()
+                .map(|roles| roles.is_some_and(|roles| roles.contains(&role)))
+        }
+    }
+}
@@ -8,10 +8,17 @@ pub const MAIN: &str = const_concat!(HOME, "app");
 pub const USER: &str = const_concat!(HOME, "user");
 pub const GAME: &str = const_concat!(HOME, "game");
 pub const ACCOUNT: &str = const_concat!(HOME, "account");
+pub const WALLET: &str = const_concat!(ACCOUNT, "/wallet");
+pub const SUBSCRIPTIONS: &str = const_concat!(ACCOUNT, "/subscriptions");
 pub const TAG: &str = const_concat!(HOME, "tag");
 pub const ITEM: &str = const_concat!(HOME, "item");
 pub const TRANSACTION: &str = const_concat!(HOME, "transaction");
 pub const DEVELOPER: &str = const_concat!(HOME, "developer");
+pub const CART: &str = const_concat!(HOME, "cart");
+pub const GIFTS: &str = const_concat!(HOME, "gifts");
+pub const KEYS: &str = const_concat!(HOME, "keys");
+pub const REDEEM: &str = const_concat!(HOME, "redeem");
+pub const ADMIN: &str = const_concat!(HOME, "admin");
 
 #[server]
 #[allow(clippy::unused_async)]
@@ -0,0 +1,173 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zenki_util::Money;
+#[cfg(feature = "ssr")]
+use zenki_util::i32_to_usize;
+
+use crate::game::GameRef;
+
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubscriptionError {
+    #[error("Server error.")]
+    ServerError,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    Active,
+    Cancelled,
+    Expired,
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Active => "Active",
+            Self::Cancelled => "Cancelled",
+            Self::Expired => "Expired",
+        })
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::SubscriptionStatus> for SubscriptionStatus {
+    fn from(value: zenki_backend::SubscriptionStatus) -> Self {
+        match value {
+            zenki_backend::SubscriptionStatus::Active => Self::Active,
+            zenki_backend::SubscriptionStatus::Cancelled => Self::Cancelled,
+            zenki_backend::SubscriptionStatus::Expired => Self::Expired,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Subscription {
+    pub sid: usize,
+    pub pid: usize,
+    pub p_descr: Option<String>,
+    pub gid: usize,
+    pub gname: String,
+    pub billing_period: String,
+    pub price: Money,
+    /// Whether the subscription is charged again at expiry.
+    pub renews: bool,
+    pub status: SubscriptionStatus,
+    pub started_at: String,
+    pub expires_at: String,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Subscription> for Subscription {
+    fn from(value: zenki_backend::Subscription) -> Self {
+        Self {
+            sid: i32_to_usize(value.sid),
+            pid: i32_to_usize(value.pid),
+            p_descr: value.p_descr,
+            gid: i32_to_usize(value.gid),
+            gname: value.gname,
+            billing_period: value.billing_period.to_string(),
+            price: value.price,
+            renews: value.status == zenki_backend::SubscriptionStatus::Active
+                && value.payment_method.is_some(),
+            status: value.status.into(),
+            started_at: value.started_at.to_string(),
+            expires_at: value.expires_at.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SubscriptionPlan {
+    pub pid: usize,
+    pub billing_period: String,
+    pub catalog: Vec<GameRef>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::SubscriptionPlan> for SubscriptionPlan {
+    fn from(value: zenki_backend::SubscriptionPlan) -> Self {
+        Self {
+            pid: i32_to_usize(value.pid),
+            billing_period: value.billing_period.to_string(),
+            catalog: value.catalog.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[server]
+pub async fn get_subscriptions() -> Result<Vec<Subscription>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_subscriptions(auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn get_subscription_plan(pid: usize) -> Result<Option<SubscriptionPlan>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    Ok(state.query_subscription_plan(pid).await?.map(Into::into))
+}
+
+#[server]
+pub async fn cancel_subscription(sid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.cancel_subscription(auth.uid, sid).await?)
+}
+
+#[server]
+pub async fn resume_subscription(sid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.resume_subscription(auth.uid, sid).await?)
+}
+
+#[server]
+pub async fn create_subscription_plan(
+    gid: usize,
+    price: String,
+    billing_period: String,
+    descr: String,
+    catalog: String,
+) -> Result<i32, ServerFnError> {
+    use zenki_backend::BillingPeriod;
+    use zenki_util::Currency;
+
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+
+    let Some(price) = Money::parse_major(&price, Currency::USD).filter(|x| x.minor() > 0) else {
+        return Err(ServerFnError::ServerError("Invalid price.".to_string()));
+    };
+    let billing_period = match billing_period.as_str() {
+        "monthly" => BillingPeriod::Monthly,
+        "yearly" => BillingPeriod::Yearly,
+        _ => {
+            return Err(ServerFnError::ServerError(
+                "Invalid billing period.".to_string(),
+            ));
+        }
+    };
+    let catalog = catalog
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut allowed = state.manages_game(auth.uid, gid).await?;
+    for &gid in &catalog {
+        allowed = allowed && state.manages_game(auth.uid, gid).await?;
+    }
+    auth.ensure(allowed)?;
+    Ok(state
+        .create_subscription_plan(gid, price, billing_period, Some(descr.as_str()), &catalog)
+        .await?)
+}
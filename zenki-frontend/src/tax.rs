@@ -0,0 +1,76 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaxRate {
+    pub country: String,
+    /// The purchase type as submitted by forms, e.g. `game_purchase`.
+    pub purchase_type: String,
+    pub kind: String,
+    pub rate: String,
+    pub inclusive: bool,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::TaxRate> for TaxRate {
+    fn from(value: zenki_backend::TaxRate) -> Self {
+        Self {
+            country: value.country,
+            purchase_type: value.purchase_type.as_str().to_string(),
+            kind: value.purchase_type.to_string(),
+            rate: zenki_backend::format_tax_rate(value.rate_bp),
+            inclusive: value.inclusive,
+        }
+    }
+}
+
+#[server]
+pub async fn get_tax_rates() -> Result<Vec<TaxRate>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManagePurchases)
+        .await?;
+    Ok(state
+        .query_tax_rates()
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[server]
+pub async fn set_tax_rate(
+    country: String,
+    purchase_type: String,
+    rate: String,
+    inclusive: Option<String>,
+) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageStore)
+        .await?;
+    let rate_bp =
+        zenki_backend::parse_tax_rate(&rate).ok_or(zenki_backend::TaxError::InvalidRate)?;
+    Ok(state
+        .set_tax_rate(
+            &country,
+            purchase_type.parse()?,
+            rate_bp,
+            inclusive.is_some(),
+        )
+        .await?)
+}
+
+#[server]
+pub async fn delete_tax_rate(country: String, purchase_type: String) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageStore)
+        .await?;
+    Ok(state
+        .delete_tax_rate(&country, purchase_type.parse()?)
+        .await?)
+}
@@ -2,6 +2,9 @@ use leptos::prelude::*;
 use leptos_router::params::Params;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use zenki_util::Money;
+
+use crate::gift::GiftStatus;
 #[cfg(feature = "ssr")]
 use zenki_util::i32_to_usize;
 
@@ -16,18 +19,62 @@ pub enum TransactionError {
     InvalidId,
     #[error("Transaction not found.")]
     TransactionNotFound,
+    #[error("Invoice not found.")]
+    InvoiceNotFound,
     #[error("Server error.")]
     ServerError,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionStatus {
+    Pending,
+    Completed,
+    RefundRequested,
+    Refunded,
+    Failed,
+}
+
+impl std::fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Pending => "Pending",
+            Self::Completed => "Completed",
+            Self::RefundRequested => "Refund requested",
+            Self::Refunded => "Refunded",
+            Self::Failed => "Failed",
+        })
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::TransactionStatus> for TransactionStatus {
+    fn from(value: zenki_backend::TransactionStatus) -> Self {
+        match value {
+            zenki_backend::TransactionStatus::Pending => Self::Pending,
+            zenki_backend::TransactionStatus::Completed => Self::Completed,
+            zenki_backend::TransactionStatus::RefundRequested => Self::RefundRequested,
+            zenki_backend::TransactionStatus::Refunded => Self::Refunded,
+            zenki_backend::TransactionStatus::Failed => Self::Failed,
+        }
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct TransactionHistory {
     pub tid: usize,
+    pub uid: usize,
+    pub s_uname: String,
+    pub receiver_uid: Option<usize>,
+    pub r_uname: Option<String>,
+    pub gift_status: Option<GiftStatus>,
     pub gid: usize,
     pub gname: String,
     pub pid: usize,
     pub p_descr: Option<String>,
     pub bought_at: Option<String>,
+    pub status: TransactionStatus,
+    pub seller_uid: Option<usize>,
+    pub seller_uname: Option<String>,
 }
 
 #[cfg(feature = "ssr")]
@@ -35,11 +82,19 @@ impl From<zenki_backend::TransactionHistory> for TransactionHistory {
     fn from(value: zenki_backend::TransactionHistory) -> Self {
         Self {
             tid: i32_to_usize(value.tid),
+            uid: i32_to_usize(value.uid),
+            s_uname: value.s_uname,
+            receiver_uid: value.receiver_uid.map(i32_to_usize),
+            r_uname: value.r_uname,
+            gift_status: value.gift_status.map(Into::into),
             gid: i32_to_usize(value.gid),
             gname: value.gname,
             pid: i32_to_usize(value.pid),
             p_descr: value.p_descr,
             bought_at: value.bought_at.map(|x| x.to_string()),
+            status: value.status.into(),
+            seller_uid: value.seller_uid.map(i32_to_usize),
+            seller_uname: value.seller_uname,
         }
     }
 }
@@ -51,12 +106,21 @@ pub struct RichTransaction {
     pub receiver_uid: Option<usize>,
     pub pid: usize,
     pub payment_method: String,
-    pub amount: usize,
+    pub amount: Money,
+    pub tax: Money,
+    pub tax_rate: Option<String>,
+    pub quantity: usize,
     pub bought_at: Option<String>,
-    pub status: Option<String>,
+    pub status: TransactionStatus,
+    pub refund_reason: Option<String>,
     pub s_uname: String,
     pub r_uname: String,
     pub p_descr: Option<String>,
+    pub preorder: bool,
+    pub available_at: Option<String>,
+    pub seller_uid: Option<usize>,
+    pub seller_uname: Option<String>,
+    pub market_fee: Money,
 }
 
 #[cfg(feature = "ssr")]
@@ -68,41 +132,370 @@ impl From<zenki_backend::RichTransaction> for RichTransaction {
             receiver_uid: value.receiver_uid.map(i32_to_usize),
             pid: i32_to_usize(value.pid),
             payment_method: value.payment_method.to_string(),
-            amount: i32_to_usize(value.amount),
+            amount: value.amount,
+            tax: value.tax,
+            tax_rate: value.tax_rate.map(|x| x.to_string()),
+            quantity: i32_to_usize(value.quantity),
             bought_at: value.bought_at.map(|x| x.to_string()),
-            status: value.status,
+            status: value.status.into(),
+            refund_reason: value.refund_reason,
             s_uname: value.s_uname,
             r_uname: value.r_uname,
             p_descr: value.p_descr,
+            preorder: value.preorder,
+            available_at: value.available_at.map(|x| x.to_string()),
+            seller_uid: value.seller_uid.map(i32_to_usize),
+            seller_uname: value.seller_uname,
+            market_fee: value.market_fee,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ReceiptLine {
+    pub tid: usize,
+    pub pid: usize,
+    pub descr: Option<String>,
+    pub kind: String,
+    pub unit_price: Money,
+    pub quantity: usize,
+    pub line_total: Money,
+    pub discount: Money,
+    pub tax: Money,
+    pub tax_rate: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::ReceiptLine> for ReceiptLine {
+    fn from(value: zenki_backend::ReceiptLine) -> Self {
+        Self {
+            tid: i32_to_usize(value.tid),
+            pid: i32_to_usize(value.pid),
+            descr: value.descr,
+            kind: value.purchase_type.to_string(),
+            unit_price: value.unit_price,
+            quantity: i32_to_usize(value.quantity),
+            line_total: value.line_total,
+            discount: value.discount,
+            tax: value.tax,
+            tax_rate: value.tax_rate.map(|x| x.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Receipt {
+    pub oid: usize,
+    pub payment_method: String,
+    pub lines: Vec<ReceiptLine>,
+    pub subtotal: Money,
+    pub discount: Money,
+    pub tax: Money,
+    pub tax_added: Money,
+    pub total: Money,
+    pub coupon_code: Option<String>,
+    pub status: TransactionStatus,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Receipt> for Receipt {
+    fn from(value: zenki_backend::Receipt) -> Self {
+        Self {
+            oid: i32_to_usize(value.oid),
+            payment_method: value.payment_method.to_string(),
+            tax_added: value.added_tax,
+            lines: value.lines.into_iter().map(Into::into).collect(),
+            subtotal: value.subtotal,
+            discount: value.discount,
+            tax: value.tax,
+            total: value.total,
+            coupon_code: value.coupon_code,
+            status: value.status.into(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct InvoiceLine {
+    pub pid: usize,
+    pub descr: Option<String>,
+    pub kind: String,
+    pub unit_price: Money,
+    pub quantity: usize,
+    pub line_total: Money,
+    pub discount: Money,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::InvoiceLine> for InvoiceLine {
+    fn from(value: zenki_backend::InvoiceLine) -> Self {
+        Self {
+            pid: i32_to_usize(value.pid),
+            descr: value.descr,
+            kind: value.purchase_type.to_string(),
+            unit_price: value.unit_price,
+            quantity: i32_to_usize(value.quantity),
+            line_total: value.line_total,
+            discount: value.discount,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Invoice {
+    pub number: String,
+    pub issued_at: String,
+    pub tid: usize,
+    pub oid: Option<usize>,
+    pub uid: usize,
+    pub s_uname: String,
+    pub receiver_uid: Option<usize>,
+    pub r_uname: Option<String>,
+    pub payment_method: String,
+    pub status: TransactionStatus,
+    pub lines: Vec<InvoiceLine>,
+    pub subtotal: Money,
+    pub discount: Money,
+    pub tax: Money,
+    pub tax_rate: Option<String>,
+    pub total: Money,
+    pub coupon_code: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Invoice> for Invoice {
+    fn from(value: zenki_backend::Invoice) -> Self {
+        Self {
+            number: value.display_number(),
+            issued_at: value.issued_at.to_string(),
+            tid: i32_to_usize(value.tid),
+            oid: value.oid.map(i32_to_usize),
+            uid: i32_to_usize(value.uid),
+            s_uname: value.s_uname,
+            receiver_uid: value.receiver_uid.map(i32_to_usize),
+            r_uname: value.r_uname,
+            payment_method: value.payment_method.to_string(),
+            status: value.status.into(),
+            lines: value.lines.into_iter().map(Into::into).collect(),
+            subtotal: value.subtotal,
+            discount: value.discount,
+            tax: value.tax,
+            tax_rate: value.tax_rate.map(|x| x.to_string()),
+            total: value.total,
+            coupon_code: value.coupon_code,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CheckoutPreview {
+    pub subtotal: Money,
+    pub discount: Money,
+    pub tax: Money,
+    pub tax_added: Money,
+    pub total: Money,
+    pub coupon_code: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::PricedOrder> for CheckoutPreview {
+    fn from(value: zenki_backend::PricedOrder) -> Self {
+        Self {
+            tax_added: value.added_tax,
+            subtotal: value.subtotal,
+            discount: value.discount,
+            tax: value.tax,
+            total: value.total,
+            coupon_code: value.coupon_code,
         }
     }
 }
 
 #[server]
 pub async fn create_transaction(
-    uid: usize,
     pid: usize,
-    ruid: usize,
+    ruid: Option<usize>,
     payment_method: String,
-    amount: usize,
-) -> Result<(), ServerFnError> {
+    quantity: usize,
+    coupon: Option<String>,
+    gift_message: Option<String>,
+) -> Result<Receipt, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    let ruid = ruid.unwrap_or(auth.uid);
     Ok(state
-        .create_transaction(uid, pid, ruid, payment_method.parse()?, amount)
-        .await?)
+        .create_transaction(
+            auth.uid,
+            pid,
+            ruid,
+            payment_method.parse()?,
+            quantity,
+            coupon.as_deref(),
+            gift_message.as_deref(),
+        )
+        .await?
+        .into())
+}
+
+#[server]
+pub async fn preview_checkout(
+    pid: usize,
+    quantity: usize,
+    ruid: Option<usize>,
+    coupon: Option<String>,
+) -> Result<CheckoutPreview, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .preview_order(
+            auth.uid,
+            ruid.unwrap_or(auth.uid),
+            &[(pid, quantity)],
+            coupon.as_deref(),
+        )
+        .await?
+        .into())
+}
+
+/// Called by the payment gateway when a payment that timed out at checkout settles.
+#[server(endpoint = "payment_webhook")]
+pub async fn payment_webhook(payload: String, signature: String) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    Ok(state.handle_payment_webhook(&payload, &signature).await?)
 }
 
 #[server]
 pub async fn get_transaction(tid: usize) -> Result<Option<RichTransaction>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.query_rich_transaction(tid).await?.map(Into::into))
+    let auth = crate::auth::AuthUser::extract().await?;
+    let Some(tx) = state.query_rich_transaction(tid).await? else {
+        return Ok(None);
+    };
+    let uid = zenki_util::usize_to_i32(auth.uid);
+    if tx.uid != uid && tx.receiver_uid != Some(uid) && tx.seller_uid != Some(uid) {
+        auth.require(zenki_backend::Permission::ViewAllTransactions)
+            .await?;
+    }
+    Ok(Some(tx.into()))
+}
+
+/// The invoice of transaction `tid`, if the current user bought or received it or may view all
+/// transactions.
+#[cfg(feature = "ssr")]
+async fn authorized_invoice(tid: usize) -> Result<Option<zenki_backend::Invoice>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    let Some(invoice) = state.query_invoice(tid).await? else {
+        return Ok(None);
+    };
+    let uid = zenki_util::usize_to_i32(auth.uid);
+    if invoice.uid != uid && invoice.receiver_uid != Some(uid) {
+        auth.require(zenki_backend::Permission::ViewAllTransactions)
+            .await?;
+    }
+    Ok(Some(invoice))
+}
+
+#[server]
+pub async fn get_invoice(tid: usize) -> Result<Option<Invoice>, ServerFnError> {
+    Ok(authorized_invoice(tid).await?.map(Into::into))
+}
+
+#[server]
+pub async fn get_invoice_pdf(tid: usize) -> Result<Vec<u8>, ServerFnError> {
+    let Some(invoice) = authorized_invoice(tid).await? else {
+        return Err(ServerFnError::ServerError("Invoice not found.".to_string()));
+    };
+    Ok(invoice.to_pdf())
+}
+
+#[server]
+pub async fn get_transaction_history() -> Result<Vec<TransactionHistory>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .query_transaction_history(auth.uid)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct RefundRequest {
+    pub tid: usize,
+    pub uid: usize,
+    pub s_uname: String,
+    pub pid: usize,
+    pub p_descr: Option<String>,
+    pub amount: Money,
+    pub bought_at: Option<String>,
+    pub refund_reason: Option<String>,
+    pub refund_requested_at: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::RefundRequest> for RefundRequest {
+    fn from(value: zenki_backend::RefundRequest) -> Self {
+        Self {
+            tid: i32_to_usize(value.tid),
+            uid: i32_to_usize(value.uid),
+            s_uname: value.s_uname,
+            pid: i32_to_usize(value.pid),
+            p_descr: value.p_descr,
+            amount: value.amount,
+            bought_at: value.bought_at.map(|x| x.to_string()),
+            refund_reason: value.refund_reason,
+            refund_requested_at: value.refund_requested_at.map(|x| x.to_string()),
+        }
+    }
+}
+
+#[server]
+pub async fn request_refund(tid: usize, reason: String) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .request_refund(auth.uid, tid, Some(reason).filter(|x| !x.is_empty()))
+        .await?)
+}
+
+#[server]
+pub async fn cancel_preorder(tid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.cancel_preorder(auth.uid, tid).await?)
 }
 
 #[server]
-pub async fn get_transaction_history(uid: usize) -> Result<Vec<TransactionHistory>, ServerFnError> {
+pub async fn approve_refund(tid: usize) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageStore)
+        .await?;
+    Ok(state.approve_refund(tid).await?)
+}
+
+#[server]
+pub async fn decline_refund(tid: usize) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageStore)
+        .await?;
+    Ok(state.decline_refund(tid).await?)
+}
+
+#[server]
+pub async fn get_refund_requests() -> Result<Vec<RefundRequest>, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    crate::auth::AuthUser::extract()
+        .await?
+        .require(zenki_backend::Permission::ManageStore)
+        .await?;
     Ok(state
-        .query_transaction_history(uid)
+        .query_refund_requests()
         .await?
         .into_iter()
         .map(Into::into)
@@ -19,65 +19,101 @@ pub enum UserError {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct User {
+pub struct PublicProfile {
     pub uid: usize,
     pub uname: String,
-    pub passwd: String,
-    pub email: Option<String>,
-    pub created_at: Option<String>,
     pub bio: Option<String>,
-    pub birth_date: Option<String>,
+    pub created_at: Option<String>,
+    pub avatar: Option<String>,
 }
 
 #[cfg(feature = "ssr")]
-impl From<zenki_backend::User> for User {
-    fn from(value: zenki_backend::User) -> Self {
+impl From<zenki_backend::PublicProfile> for PublicProfile {
+    fn from(value: zenki_backend::PublicProfile) -> Self {
         Self {
             uid: zenki_util::i32_to_usize(value.uid),
             uname: value.uname,
             bio: value.bio,
-            passwd: value.passwd,
-            email: value.email,
             created_at: value.created_at.map(|x| x.to_string()),
+            avatar: value.avatar,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivateAccount {
+    pub uid: usize,
+    pub email: Option<String>,
+    pub birth_date: Option<String>,
+    pub billing_country: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::PrivateAccount> for PrivateAccount {
+    fn from(value: zenki_backend::PrivateAccount) -> Self {
+        Self {
+            uid: zenki_util::i32_to_usize(value.uid),
+            email: value.email,
             birth_date: value.birth_date.map(|x| x.to_string()),
+            billing_country: value.billing_country,
         }
     }
 }
 
 #[server]
-pub async fn get_user(id: usize) -> Result<Option<User>, ServerFnError> {
+pub async fn get_user(id: usize) -> Result<Option<PublicProfile>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
     Ok(state.query_user(id).await?.map(Into::into))
 }
 
 #[server]
-pub async fn update_bio(id: usize, bio: String) -> Result<(), ServerFnError> {
+pub async fn get_private_account() -> Result<Option<PrivateAccount>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.query_private_account(auth.uid).await?.map(Into::into))
+}
+
+#[server]
+pub async fn update_bio(bio: String) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
     Ok(state
-        .update_bio(id, (!bio.is_empty()).then_some(bio).as_deref())
+        .update_bio(auth.uid, (!bio.is_empty()).then_some(bio).as_deref())
         .await?)
 }
 
 #[server]
-pub async fn update_username(id: usize, name: String) -> Result<(), ServerFnError> {
+pub async fn update_username(name: String) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.update_username(id, &name).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.update_username(auth.uid, &name).await?)
+}
+
+#[server]
+pub async fn update_avatar(avatar: String) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .update_avatar(auth.uid, (!avatar.is_empty()).then_some(avatar).as_deref())
+        .await?)
 }
 
 #[server]
-pub async fn update_email(id: usize, email: String) -> Result<(), ServerFnError> {
+pub async fn update_email(email: String) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
     Ok(state
-        .update_email(id, (!email.is_empty()).then_some(email).as_deref())
+        .update_email(auth.uid, (!email.is_empty()).then_some(email).as_deref())
         .await?)
 }
 
 #[server]
-pub async fn update_birth_date(id: usize, birth_date: String) -> Result<(), ServerFnError> {
+pub async fn update_birth_date(birth_date: String) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
     Ok(state
         .update_birth_date(
-            id,
+            auth.uid,
             if birth_date.is_empty() {
                 None
             } else {
@@ -88,13 +124,36 @@ pub async fn update_birth_date(id: usize, birth_date: String) -> Result<(), Serv
 }
 
 #[server]
-pub async fn delete_user(id: usize) -> Result<(), ServerFnError> {
+pub async fn update_billing_country(country: String) -> Result<(), ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
-    Ok(state.delete_user(id).await?)
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state
+        .update_billing_country(
+            auth.uid,
+            (!country.is_empty()).then_some(country).as_deref(),
+        )
+        .await?)
+}
+
+#[server]
+pub async fn delete_user(password: String) -> Result<(), ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    if !matches!(
+        state.verify_password_by_id(auth.uid, &password).await,
+        Ok(true)
+    ) {
+        return Err(ServerFnError::ServerError(
+            "Password does not match.".to_string(),
+        ));
+    }
+    state.delete_user(auth.uid).await?;
+    crate::auth::get_login_session().1.set(None);
+    Ok(())
 }
 
 #[server]
-pub async fn get_users() -> Result<Vec<User>, ServerFnError> {
+pub async fn get_users() -> Result<Vec<PublicProfile>, ServerFnError> {
     let state = expect_context::<zenki_backend::State>();
     Ok(state
         .query_users()
@@ -0,0 +1,73 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zenki_util::Money;
+#[cfg(feature = "ssr")]
+use zenki_util::i32_to_usize;
+
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WalletError {
+    #[error("Server error.")]
+    ServerError,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct WalletEntry {
+    pub jid: usize,
+    pub kind: String,
+    pub amount: Money,
+    pub oid: Option<usize>,
+    pub tid: Option<usize>,
+    pub created_at: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::WalletEntry> for WalletEntry {
+    fn from(value: zenki_backend::WalletEntry) -> Self {
+        Self {
+            jid: i32_to_usize(value.jid),
+            kind: value.kind.to_string(),
+            amount: value.amount,
+            oid: value.oid.map(i32_to_usize),
+            tid: value.tid.map(i32_to_usize),
+            created_at: value.created_at.map(|x| x.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Wallet {
+    pub balances: Vec<Money>,
+    pub entries: Vec<WalletEntry>,
+}
+
+#[cfg(feature = "ssr")]
+impl From<zenki_backend::Wallet> for Wallet {
+    fn from(value: zenki_backend::Wallet) -> Self {
+        Self {
+            balances: value.balances,
+            entries: value.entries.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[server]
+pub async fn get_wallet() -> Result<Wallet, ServerFnError> {
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    Ok(state.query_wallet(auth.uid).await?.into())
+}
+
+#[server]
+pub async fn top_up_wallet(amount: String, payment_method: String) -> Result<Money, ServerFnError> {
+    use zenki_util::Currency;
+
+    let state = expect_context::<zenki_backend::State>();
+    let auth = crate::auth::AuthUser::extract().await?;
+    let Some(amount) = Money::parse_major(&amount, Currency::USD) else {
+        return Err(ServerFnError::ServerError("Invalid amount.".to_string()));
+    };
+    Ok(state
+        .top_up_wallet(auth.uid, amount, payment_method.parse()?)
+        .await?)
+}
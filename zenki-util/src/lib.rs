@@ -1,3 +1,7 @@
+mod money;
+
+pub use money::{Currency, Money, ParseCurrencyError};
+
 #[inline]
 #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
 pub const fn usize_to_i32(n: usize) -> i32 {
@@ -0,0 +1,244 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error("error while parsing currency code via string")]
+pub struct ParseCurrencyError;
+
+/// An ISO 4217 currency code.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const USD: Self = Self(*b"USD");
+
+    #[must_use]
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap_or("???")
+    }
+
+    /// Number of decimal digits in one major unit, e.g. 2 for cents.
+    #[must_use]
+    pub const fn minor_digits(self) -> u32 {
+        match &self.0 {
+            b"JPY" | b"KRW" | b"VND" | b"CLP" | b"ISK" => 0,
+            b"BHD" | b"KWD" | b"OMR" | b"JOD" | b"TND" => 3,
+            _ => 2,
+        }
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = ParseCurrencyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().as_bytes() {
+            &[a, b, c] if [a, b, c].iter().all(u8::is_ascii_uppercase) => Ok(Self([a, b, c])),
+            _ => Err(ParseCurrencyError),
+        }
+    }
+}
+
+/// An exact amount of money in minor units (e.g. cents) of a currency.
+///
+/// Arithmetic is checked and refuses to mix currencies, so it never rounds or overflows silently.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Money {
+    minor: i64,
+    currency: Currency,
+}
+
+impl Money {
+    #[must_use]
+    pub const fn new(minor: i64, currency: Currency) -> Self {
+        Self { minor, currency }
+    }
+
+    /// Parses a decimal amount in major units such as `"4.99"` without going through floats.
+    #[must_use]
+    pub fn parse_major(s: &str, currency: Currency) -> Option<Self> {
+        let s = s.trim();
+        let (negative, s) = s.strip_prefix('-').map_or((false, s), |s| (true, s));
+        let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+        let digits = currency.minor_digits() as usize;
+        if whole.is_empty()
+            || frac.len() > digits
+            || !whole
+                .bytes()
+                .chain(frac.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let scale = 10_i64.checked_pow(currency.minor_digits())?;
+        let frac = format!("{frac:0<digits$}");
+        let minor =
+            whole
+                .parse::<i64>()
+                .ok()?
+                .checked_mul(scale)?
+                .checked_add(if frac.is_empty() {
+                    0
+                } else {
+                    frac.parse().ok()?
+                })?;
+        Some(Self::new(if negative { -minor } else { minor }, currency))
+    }
+
+    #[must_use]
+    pub const fn zero(currency: Currency) -> Self {
+        Self::new(0, currency)
+    }
+
+    #[must_use]
+    pub const fn minor(self) -> i64 {
+        self.minor
+    }
+
+    #[must_use]
+    pub const fn currency(self) -> Currency {
+        self.currency
+    }
+
+    #[must_use]
+    pub const fn is_zero(self) -> bool {
+        self.minor == 0
+    }
+
+    #[must_use]
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        (self.currency == rhs.currency)
+            .then(|| self.minor.checked_add(rhs.minor))
+            .flatten()
+            .map(|minor| Self::new(minor, self.currency))
+    }
+
+    #[must_use]
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        (self.currency == rhs.currency)
+            .then(|| self.minor.checked_sub(rhs.minor))
+            .flatten()
+            .map(|minor| Self::new(minor, self.currency))
+    }
+
+    /// Takes `percent` off, rounding half away from zero to the nearest minor unit.
+    #[must_use]
+    pub fn checked_percent_off(self, percent: i64) -> Option<Self> {
+        let scaled = self.minor.checked_mul(100_i64.checked_sub(percent)?)?;
+        let (quotient, remainder) = (scaled / 100, scaled % 100);
+        let minor = if remainder.abs() * 2 >= 100 {
+            quotient.checked_add(scaled.signum())?
+        } else {
+            quotient
+        };
+        Some(Self::new(minor, self.currency))
+    }
+
+    #[must_use]
+    pub fn checked_mul(self, n: i64) -> Option<Self> {
+        self.minor
+            .checked_mul(n)
+            .map(|minor| Self::new(minor, self.currency))
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = self.currency.minor_digits();
+        let sign = if self.minor < 0 { "-" } else { "" };
+        let abs = self.minor.unsigned_abs();
+        if digits == 0 {
+            return write!(f, "{sign}{abs} {}", self.currency);
+        }
+        let scale = 10_u64.pow(digits);
+        write!(
+            f,
+            "{sign}{}.{:0width$} {}",
+            abs / scale,
+            abs % scale,
+            self.currency,
+            width = digits as usize
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EUR: Currency = Currency(*b"EUR");
+    const JPY: Currency = Currency(*b"JPY");
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::USD)
+    }
+
+    #[test]
+    fn parses_major_amounts_exactly() {
+        assert_eq!(Money::parse_major("4.99", Currency::USD), Some(usd(499)));
+        assert_eq!(Money::parse_major("4.9", Currency::USD), Some(usd(490)));
+        assert_eq!(Money::parse_major("0.01", Currency::USD), Some(usd(1)));
+        assert_eq!(Money::parse_major("-1.50", Currency::USD), Some(usd(-150)));
+        assert_eq!(Money::parse_major("500", JPY), Some(Money::new(500, JPY)));
+    }
+
+    #[test]
+    fn rejects_malformed_major_amounts() {
+        assert_eq!(Money::parse_major("", Currency::USD), None);
+        assert_eq!(Money::parse_major(".5", Currency::USD), None);
+        assert_eq!(Money::parse_major("4.999", Currency::USD), None);
+        assert_eq!(Money::parse_major("4.5", JPY), None);
+        assert_eq!(Money::parse_major("1e3", Currency::USD), None);
+        assert_eq!(
+            Money::parse_major("92233720368547758.08", Currency::USD),
+            None
+        );
+    }
+
+    #[test]
+    fn adds_and_subtracts_within_one_currency() {
+        assert_eq!(usd(1).checked_add(usd(2)), Some(usd(3)));
+        assert_eq!(usd(1).checked_sub(usd(2)), Some(usd(-1)));
+        assert_eq!(usd(1).checked_add(Money::new(1, EUR)), None);
+        assert_eq!(usd(1).checked_sub(Money::new(1, EUR)), None);
+    }
+
+    #[test]
+    fn refuses_to_overflow() {
+        assert_eq!(usd(i64::MAX).checked_add(usd(1)), None);
+        assert_eq!(usd(i64::MIN).checked_sub(usd(1)), None);
+        assert_eq!(usd(i64::MAX).checked_mul(2), None);
+        assert_eq!(usd(i64::MAX).checked_percent_off(10), None);
+        assert_eq!(usd(3).checked_mul(0), Some(usd(0)));
+    }
+
+    #[test]
+    fn rounds_percentages_half_away_from_zero() {
+        // 5% off 10 cents leaves 9.5 cents
+        assert_eq!(usd(10).checked_percent_off(5), Some(usd(10)));
+        assert_eq!(usd(-10).checked_percent_off(5), Some(usd(-10)));
+        // 50% off one cent leaves half a cent
+        assert_eq!(usd(1).checked_percent_off(50), Some(usd(1)));
+        assert_eq!(usd(1).checked_percent_off(51), Some(usd(0)));
+        assert_eq!(usd(999).checked_percent_off(100), Some(usd(0)));
+        assert_eq!(usd(999).checked_percent_off(0), Some(usd(999)));
+        assert_eq!(usd(0).checked_percent_off(30), Some(usd(0)));
+    }
+
+    #[test]
+    fn displays_minor_digits_of_the_currency() {
+        assert_eq!(usd(499).to_string(), "4.99 USD");
+        assert_eq!(usd(5).to_string(), "0.05 USD");
+        assert_eq!(usd(-150).to_string(), "-1.50 USD");
+        assert_eq!(Money::new(500, JPY).to_string(), "500 JPY");
+        assert_eq!(Money::new(1234, Currency(*b"KWD")).to_string(), "1.234 KWD");
+    }
+}